@@ -0,0 +1,85 @@
+//! Times the degree distribution measurements on a random graph.
+//!
+//! The measurements accumulate the weight of each node, shave the accumulated weights, and count
+//! the resulting indices, and so spend much of their time in the hash maps of the `shave` and
+//! `measure` operators. The benchmark first compares the maps those operators hold under SipHash
+//! and FNV, on the same updates, and then times the measurements themselves.
+//!
+//! Run with `cargo bench --bench degrees -- <nodes> <edges>`.
+
+extern crate fnv;
+extern crate rand;
+extern crate timely;
+extern crate wpinq;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::time::Instant;
+
+use fnv::FnvBuildHasher;
+use rand::Rng;
+use timely::Configuration;
+use timely::dataflow::{InputHandle, ProbeHandle};
+
+use wpinq::Dataset;
+use wpinq::analyses::degrees;
+
+fn main() {
+
+    let nodes: usize = std::env::args().nth(1).and_then(|x| x.parse().ok()).unwrap_or(100_000);
+    let edges: usize = std::env::args().nth(2).and_then(|x| x.parse().ok()).unwrap_or(1_000_000);
+
+    let mut rng = rand::thread_rng();
+    let graph = (0 .. edges).map(|_| (rng.gen_range(0, nodes), rng.gen_range(0, nodes))).collect::<Vec<_>>();
+
+    // the accumulation of node weights, as in `shave`, and of degree indices, as in `measure`.
+    let updates = graph.iter().map(|&(src, _)| src).collect::<Vec<_>>();
+    let sip = accumulate(&updates[..], HashMap::new());
+    let fnv = accumulate(&updates[..], HashMap::with_hasher(FnvBuildHasher::default()));
+    println!("accumulate\tsiphash: {:?}\tfnv: {:?}", sip, fnv);
+
+    let timer = Instant::now();
+    timely::execute(Configuration::Thread, move |worker| {
+
+        let weight = i32::max_value() as i64 / 10;
+        let mut truth = InputHandle::new();
+        let mut synth = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+        let total = Rc::new(RefCell::new(0i64));
+
+        worker.dataflow(|scope| {
+            let dataset = Dataset::from(truth.to_stream(scope), synth.to_stream(scope));
+            let sources = dataset.map(|(src, _dst): (usize, usize)| src);
+            degrees::cdf(sources.clone(), &mut probe, &total, weight, None);
+            degrees::seq(sources, &mut probe, &total, weight, None);
+        });
+
+        for &edge in graph.iter() {
+            truth.send((edge, weight));
+        }
+        truth.close();
+        synth.advance_to(1);
+        while probe.less_than(synth.time()) { worker.step(); }
+
+    }).unwrap();
+    println!("measure\tdegrees: {:?}", timer.elapsed());
+}
+
+// times the accumulation of unit weights for each of `updates` into `map`, and their retraction.
+fn accumulate<S: BuildHasher>(updates: &[usize], mut map: HashMap<usize, i64, S>) -> ::std::time::Duration {
+    let timer = Instant::now();
+    map.reserve(updates.len());
+    for &update in updates.iter() { *map.entry(update).or_insert(0) += 1; }
+    for &update in updates.iter() {
+        let zero = {
+            let weight = map.get_mut(&update).expect("retracted key absent");
+            *weight -= 1;
+            *weight == 0
+        };
+        if zero { map.remove(&update); }
+    }
+    assert!(map.is_empty());
+    timer.elapsed()
+}
@@ -4,14 +4,20 @@ extern crate wpinq;
 
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader};
 use std::fs::File;
 
 use rand::Rng;
 use timely::dataflow::{InputHandle, ProbeHandle};
 
 use wpinq::Dataset;
-use wpinq::analyses::degrees;
+use wpinq::analyses::{cdf, degrees};
+use wpinq::io;
+use wpinq::io::sink::{Csv, Json, Sink};
+use wpinq::synthesis;
+use wpinq::synthesis::{Synthesizer, Table};
+use wpinq::synthesis::synthesizer::Config;
+use wpinq::synthesis::tabular::Move;
 
 fn main() {
 
@@ -19,6 +25,12 @@ fn main() {
 
         let timer = ::std::time::Instant::now();
 
+        // results are written to standard output, as JSON if requested and otherwise as CSV.
+        let sink: Rc<RefCell<Box<dyn Sink>>> = Rc::new(RefCell::new(match ::std::env::var("WPINQ_FORMAT").ok().as_ref().map(|x| x.as_str()) {
+            Some("json") => Box::new(Json::new(::std::io::stdout())),
+            _ => Box::new(Csv::new(::std::io::stdout())),
+        }));
+
         let mut truth = InputHandle::new();
         let mut synth = InputHandle::new();
 
@@ -32,29 +44,27 @@ fn main() {
         // measure the number of edges.
         let mut nodes_measurement = worker.dataflow(|scope| {
             let dataset = Dataset::from(truth.to_stream(scope), synth.to_stream(scope));
-            degrees::cdf(dataset.flat_map(|(src, dst)| Some(src).into_iter().chain(Some(dst))), &mut probe, &total, weight / 2)
+            degrees::cdf(dataset.flat_map(|(src, dst)| Some(src).into_iter().chain(Some(dst))), &mut probe, &total, weight / 2, None)
         });
 
         // measure the number of edges.
         let mut edges_measurement = worker.dataflow(|scope| {
             let dataset = Dataset::from(truth.to_stream(scope), synth.to_stream(scope));
-            dataset.map(|_| ()).measure(&mut probe, &total)
+            dataset.count(&mut probe, &total)
         });
 
         // measure the number of nodes with at least each number of edges.
         let mut measurements1 = worker.dataflow(|scope| {
             let dataset = Dataset::from(truth.to_stream(scope), synth.to_stream(scope));
-            degrees::cdf(dataset.map(|(src, _)| src), &mut probe, &total, weight)
+            degrees::cdf(dataset.map_ref(|&(src, _)| src), &mut probe, &total, weight, None)
         });
 
         // measure the degrees of nodes from large to small.
         let mut measurements2 = worker.dataflow(|scope| {
             let dataset = Dataset::from(truth.to_stream(scope), synth.to_stream(scope));
-            degrees::seq(dataset.map(|(src, _)| src), &mut probe, &total, weight)
+            degrees::seq(dataset.map_ref(|&(src, _)| src), &mut probe, &total, weight, None)
         });
 
-        let mut graph = Vec::new();
-
         // load up the "sensitive" data.
         let filename = std::env::args().nth(1).unwrap();
         let file = BufReader::new(File::open(filename).unwrap());
@@ -64,105 +74,58 @@ fn main() {
                 let mut elts = line[..].split_whitespace();
                 let src: usize = elts.next().unwrap().parse().ok().expect("malformed src");
                 let dst: usize = elts.next().unwrap().parse().ok().expect("malformed dst");
-                // graph.push((src, dst));
                 truth.send(((src, dst), weight));
             }
         }
         truth.close();
 
-        println!("{:?}\tloading complete", timer.elapsed());
+        eprintln!("{:?}\tloading complete", timer.elapsed());
 
         // propagate true data.
         synth.advance_to(1);
         while probe.less_than(synth.time()) { worker.step(); }
 
-        println!("{:?}\tcomputation stable, total error: {:?}", timer.elapsed(), *total.borrow() / weight);
+        eprintln!("{:?}\tcomputation stable, total error: {:?}", timer.elapsed(), *total.borrow() / weight);
 
         // report measurements on nodes, edges, and degree distributions.
         let nodes = nodes_measurement.observe(0) / (weight/2);
         let edges = edges_measurement.observe(()) / weight;
-        println!("nodes: {:?}", nodes);
-        println!("edges: {:?}", edges);
+        sink.borrow_mut().observation("nodes", "", nodes as f64).expect("failed to write results");
+        sink.borrow_mut().observation("edges", "", edges as f64).expect("failed to write results");
 
-        let mut degree_cdf = Vec::new();
-        let mut degree_seq = Vec::new();
-        for i in 0 .. (nodes as usize) {
-            degree_cdf.push((measurements1.observe(i) as f64) / (weight as f64));
-        }
-        for i in 0 .. (nodes as usize) {
-            degree_seq.push((measurements2.observe(i) as f64) / (weight as f64));
-        }
+        let degree_cdf = cdf::counts(&mut measurements1, weight, nodes as usize);
+        let degree_seq = cdf::counts(&mut measurements2, weight, nodes as usize);
 
         // let (fitted_cdf, fitted_seq) = degrees::fit_cdf_seq(&degree_cdf[..], &degree_seq[..], |x,y| (x-y).abs());
         let (fitted_cdf, fitted_seq) = degrees::fit_cdf_seq(&degree_cdf[..], &degree_seq[..], |x,y| (x-y) * (x-y));
-        let limit = fitted_seq[0];
-
-        // for i in 0 .. limit  {
-        //     println!("cdf\t{:?}\t{:?}", i, degree_cdf[i]);
-        // }
 
-        // for i in 0 .. limit  {
-        //     println!("fit\t{:?}\t{:?}", i, fitted_cdf[i]);
-        // }
+        {
+            let mut sink = sink.borrow_mut();
+            let fitted = |values: &[usize]| values.iter().map(|&x| x as f64).collect::<Vec<_>>();
+            sink.sequence("degree_cdf", &degree_cdf[..]).expect("failed to write results");
+            sink.sequence("degree_seq", &degree_seq[..]).expect("failed to write results");
+            sink.sequence("fitted_cdf", &fitted(&fitted_cdf[..])[..]).expect("failed to write results");
+            sink.sequence("fitted_seq", &fitted(&fitted_seq[..])[..]).expect("failed to write results");
+        }
 
         let mut rng = ::rand::thread_rng();
 
-        // synthesize random graph.
-        println!("{:?}\tsynthesizing random graph on {:?} nodes and {:?} edges", timer.elapsed(), nodes, edges);
-        for _ in 0 .. edges {
-            let src = rng.gen_range(0, nodes as usize);
-            let dst = rng.gen_range(0, nodes as usize);
-            graph.push((src, dst));
+        // synthesize a random graph matching the fitted degree sequence.
+        eprintln!("{:?}\tsynthesizing random graph on {:?} nodes and {:?} edges", timer.elapsed(), fitted_seq.len(), fitted_seq.iter().sum::<usize>());
+        let graph = synthesis::graph::from_degree_sequence(&fitted_seq[..], &mut rng);
+
+        // refine the graph by rewiring edges, for as many rounds as requested.
+        let rounds = std::env::args().nth(3).map(|x| x.parse().expect("malformed rounds")).unwrap_or(0);
+        let nodes = nodes as usize;
+        let rewire = Move::<(usize, usize)>::new("rewire", move |_edge, rng| (rng.gen_range(0, nodes), rng.gen_range(0, nodes)));
+        let mut synthesizer = Synthesizer::new(Table::from(graph, weight), synth, &total, vec![rewire]);
+        let report = sink.clone();
+        let config = Config::new(rounds).report(1000000, move |progress| report.borrow_mut().summary("synthesis", progress).expect("failed to write results"));
+        synthesizer.run(worker, &probe, config, &mut rng);
+
+        // export the synthetic graph, in a format determined by the file extension.
+        if let Some(output) = std::env::args().nth(2) {
+            io::graph::save(output, synthesizer.table().records()).expect("failed to write synthetic graph");
         }
-
-        for &(src, dst) in graph.iter() {
-            synth.send(((src, dst), weight));
-        }
-
-        println!("{:?}\tdata synthesized", timer.elapsed());
-
-        synth.advance_to(2);
-        while probe.less_than(synth.time()) { worker.step(); }
-
-        let mut total_error = *total.borrow();
-
-        println!("{:?}\tround {:?}, total error: {:?}", timer.elapsed(), 0, total_error / weight);
-
-        // for round in 3 .. {
-
-        //     if round % 1000000 == 0 {
-        //         println!("{:?}\tround {:?}, total error: {:?}", timer.elapsed(), round, total_error / weight);
-        //     }
-
-        //     if round % 10000000 == 0 {
-        //         let mut file = File::create(format!("output-{}.txt", round)).unwrap();
-        //         for &(src, dst) in graph.iter() {
-        //             file.write_fmt(format_args!("{}\t{}", src, dst)).unwrap();
-        //         }
-        //     }
-
-        //     let index = rng.gen_range(0, graph.len());
-
-        //     let src = rng.gen_range(0, nodes as usize);
-        //     let dst = rng.gen_range(0, nodes as usize);
-        //     let change = (src, dst);
-
-        //     // try out a change
-        //     synth.send((graph[index], -weight));
-        //     synth.send((change, weight));
-        //     synth.advance_to(round);
-        //     while probe.less_than(synth.time()) { worker.step(); }
-
-        //     let new_error = *total.borrow();
-
-        //     if total_error < new_error {
-        //         synth.send((graph[index], weight));
-        //         synth.send((change, -weight))
-        //     }
-        //     else {
-        //         graph[index] = change;
-        //         total_error = new_error;
-        //     }
-        // }
     }).unwrap();
 }
\ No newline at end of file
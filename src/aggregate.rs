@@ -0,0 +1,155 @@
+//! Sample-and-aggregate, for statistics without a natural wPINQ formulation.
+//!
+//! The records are partitioned into disjoint blocks, an arbitrary (non-private) aggregate is
+//! computed for each block, and the block results are combined by a differentially private median.
+//! As each record lands in exactly one block, it influences at most one block result, and so the
+//! private median protects each record regardless of the aggregate, which need only be accurate on
+//! random subsets of the data for the result to be useful.
+//!
+//! The private median, and the quantiles and clipped means built on it, are also useful directly
+//! for numeric attributes, such as the prices and discounts of TPC-H line items.
+
+use std::hash::Hash;
+
+use rand::{Rng, RngCore};
+
+use fnv_hash;
+use noise;
+
+/// Partitions `records` into `blocks` blocks, determined by a random `salt` and each record.
+///
+/// Equal records are always assigned to the same block, and so should be distinguishable if they
+/// describe distinct individuals.
+pub fn partition<D: Hash+Clone>(records: &[D], blocks: usize, salt: u64) -> Vec<Vec<D>> {
+    assert!(blocks > 0);
+    let mut result = vec![Vec::new(); blocks];
+    for record in records.iter() {
+        result[(fnv_hash(&(salt, record)) % (blocks as u64)) as usize].push(record.clone());
+    }
+    result
+}
+
+/// Returns an `epsilon`-differentially private median of `values`, clamped to `[lower, upper]`.
+///
+/// This is the exponential mechanism over the interval: values are clamped and sorted, each gap
+/// between consecutive values is chosen with probability proportional to its width times
+/// `exp(-epsilon * |rank - n/2| / 2)`, and a uniform point of the chosen gap is returned. Changing
+/// one of the values changes each rank by at most one.
+pub fn private_median(values: &[f64], lower: f64, upper: f64, epsilon: f64, rng: &mut dyn RngCore) -> f64 {
+    let weighted = values.iter().map(|&v| (v, 1)).collect::<Vec<_>>();
+    private_median_weighted(&weighted[..], 1, lower, upper, epsilon, rng)
+}
+
+/// Returns an `epsilon`-differentially private median of weighted `values`, clamped to `[lower, upper]`.
+///
+/// As `private_median`, except that ranks are measured in units of weight, and divided by `unit`,
+/// the largest change in weight to protect. Weights should be positive.
+pub fn private_median_weighted(values: &[(f64, i64)], unit: i64, lower: f64, upper: f64, epsilon: f64, rng: &mut dyn RngCore) -> f64 {
+    private_quantile_weighted(values, unit, 0.5, lower, upper, epsilon, rng)
+}
+
+/// Returns an `epsilon`-differentially private `quantile` of `values`, clamped to `[lower, upper]`.
+///
+/// As `private_median`, except that gaps are scored by the distance of their rank from
+/// `quantile * n` rather than from `n/2`.
+pub fn private_quantile(values: &[f64], quantile: f64, lower: f64, upper: f64, epsilon: f64, rng: &mut dyn RngCore) -> f64 {
+    let weighted = values.iter().map(|&v| (v, 1)).collect::<Vec<_>>();
+    private_quantile_weighted(&weighted[..], 1, quantile, lower, upper, epsilon, rng)
+}
+
+/// Returns an `epsilon`-differentially private `quantile` of weighted `values`, clamped to `[lower, upper]`.
+pub fn private_quantile_weighted(values: &[(f64, i64)], unit: i64, quantile: f64, lower: f64, upper: f64, epsilon: f64, rng: &mut dyn RngCore) -> f64 {
+
+    assert!(quantile >= 0.0 && quantile <= 1.0);
+    assert!(lower < upper);
+    assert!(epsilon > 0.0);
+    assert!(unit > 0);
+
+    let mut points = Vec::with_capacity(values.len() + 2);
+    points.push((lower, 0));
+    for &(value, weight) in values.iter() {
+        points.push((if value < lower { lower } else if value > upper { upper } else { value }, weight));
+    }
+    points.push((upper, 0));
+    points.sort_by(|x, y| x.0.partial_cmp(&y.0).expect("values must not be NaN"));
+
+    // work with logarithms of the weights, to avoid underflow for large `epsilon * n`.
+    let middle = (values.iter().map(|x| x.1).sum::<i64>() as f64) * quantile;
+    let mut below = 0;
+    let logs = (0 .. points.len() - 1).map(|index| {
+        below += points[index].1;
+        let width = points[index + 1].0 - points[index].0;
+        width.ln() - epsilon * ((below as f64) - middle).abs() / (2.0 * unit as f64)
+    }).collect::<Vec<_>>();
+    let points = points.iter().map(|x| x.0).collect::<Vec<_>>();
+
+    let max = logs.iter().cloned().fold(::std::f64::NEG_INFINITY, f64::max);
+    let weights = logs.iter().map(|l| (l - max).exp()).collect::<Vec<_>>();
+    let total: f64 = weights.iter().sum();
+
+    let mut target = rng.gen::<f64>() * total;
+    for (index, weight) in weights.iter().enumerate() {
+        if target < *weight || index == weights.len() - 1 {
+            return points[index] + rng.gen::<f64>() * (points[index + 1] - points[index]);
+        }
+        target -= weight;
+    }
+    unreachable!()
+}
+
+/// Returns a private mean of weighted `values`, and the clipping bound used, with a total cost of
+/// `epsilon_bound + epsilon_mean`.
+///
+/// Values are clipped to `[lower, bound]`, where `bound` is a private `quantile` of the values
+/// within `[lower, upper]`, estimated at a cost of `epsilon_bound`. The clipped values are then
+/// summed and counted in units of `unit`, each with half of `epsilon_mean`: the sum with Laplace
+/// noise scaled to the largest magnitude of a clipped value, and the count with Laplace noise
+/// scaled to one. A quantile near one, e.g. `0.95`, clips few values while excluding outliers that
+/// would dominate the noise. This is reached through `Median::observe_mean`, which charges the
+/// cost to an odometer.
+pub(crate) fn noisy_mean_auto(values: &[(f64, i64)], unit: i64, lower: f64, upper: f64, quantile: f64, epsilon_bound: f64, epsilon_mean: f64, rng: &mut dyn RngCore) -> (f64, f64) {
+
+    assert!(epsilon_mean > 0.0);
+
+    let bound = private_quantile_weighted(values, unit, quantile, lower, upper, epsilon_bound, rng);
+    let scale = if lower.abs() > bound.abs() { lower.abs() } else { bound.abs() };
+
+    let sum: f64 = values.iter().map(|&(v, w)| (if v < lower { lower } else if v > bound { bound } else { v }) * (w as f64)).sum();
+    let count: i64 = values.iter().map(|x| x.1).sum();
+    let noisy_sum = sum / (unit as f64) + noise::laplace_scaled(2.0 * scale / epsilon_mean, rng);
+    let noisy_count = (count as f64) / (unit as f64) + noise::laplace_scaled(2.0 / epsilon_mean, rng);
+
+    (noisy_sum / noisy_count.max(1.0), bound)
+}
+
+/// Computes `aggregate` on each of `blocks` disjoint blocks of `records`, and returns an
+/// `epsilon`-differentially private median of the results, clamped to `[lower, upper]`.
+pub fn sample_aggregate<D, F>(records: &[D], blocks: usize, aggregate: F, lower: f64, upper: f64, epsilon: f64, rng: &mut dyn RngCore) -> f64
+where
+    D: Hash+Clone,
+    F: Fn(&[D])->f64,
+{
+    let salt = rng.gen::<u64>();
+    let results = partition(records, blocks, salt).iter().map(|block| aggregate(&block[..])).collect::<Vec<_>>();
+    private_median(&results[..], lower, upper, epsilon, rng)
+}
+
+mod tests {
+
+    #[test]
+    fn test_sample_aggregate() {
+        let records = (0 .. 10000u64).collect::<Vec<_>>();
+        let mean = |block: &[u64]| block.iter().sum::<u64>() as f64 / (block.len() as f64);
+        let result = super::sample_aggregate(&records[..], 100, mean, 0.0, 10000.0, 1.0, &mut ::rand::thread_rng());
+        assert!(result > 4000.0 && result < 6000.0);
+    }
+
+    #[test]
+    fn test_noisy_mean_auto() {
+        let mut values = (0 .. 10000).map(|x| ((x % 100) as f64, 10)).collect::<Vec<_>>();
+        values.push((1000000.0, 10));
+        let (mean, bound) = super::noisy_mean_auto(&values[..], 10, 0.0, 1e9, 0.99, 1.0, 1.0, &mut ::rand::thread_rng());
+        assert!(bound > 90.0 && bound < 110.0);
+        assert!(mean > 48.0 && mean < 51.0);
+    }
+}
@@ -0,0 +1,137 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+
+/// Measures the class priors and class-conditional attribute histograms of labeled records.
+///
+/// Each record is a label and a list of categorical attributes, and its weight is divided evenly
+/// among its label and each of its attribute values, tagged with the label and the position of the
+/// attribute. All histograms are then measured at the cost of a single measurement. Records should
+/// all have the same number of attributes, as the weight of each measured value depends on it.
+pub fn measure<G: Scope, L: ExchangeData+Ord+Hash, V: ExchangeData+Ord+Hash>(
+    dataset: Dataset<G, (L, Vec<V>)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>) -> Measurement<(L, Option<(usize, V)>)> {
+    dataset
+        .flat_map(|(label, values)| {
+            let mut result = Vec::with_capacity(1 + values.len());
+            result.push((label.clone(), None));
+            result.extend(values.into_iter().enumerate().map(|value| (label.clone(), Some(value))));
+            result
+        })
+        .measure(probe, total)
+}
+
+/// A naive Bayes classifier, from noisy measurements of priors and histograms.
+///
+/// The classifier assumes attributes are independent given the label, and scores each label by
+/// the logarithm of its prior plus the logarithms of the conditional probabilities of each of the
+/// attributes. Noisy counts are clamped below at zero and smoothed by adding `smoothing` records to
+/// each count, so that no probability is zero.
+#[derive(Clone, Debug)]
+pub struct NaiveBayes<L, V: Hash+Eq> {
+    labels: Vec<L>,
+    priors: Vec<f64>,
+    conditionals: Vec<Vec<HashMap<V, f64>>>,
+    // the log-probability of attribute values outside the domain, for each label and attribute.
+    unseen: Vec<Vec<f64>>,
+}
+
+impl<L: Clone+Hash+Eq, V: Clone+Hash+Eq> NaiveBayes<L, V> {
+    /// Reads a classifier from a measurement made by `measure`.
+    ///
+    /// The domains of labels and of each attribute are public, and are not learned from the data.
+    /// Here `weight` is the weight of each input record. Reading the measurement is post-processing,
+    /// and costs nothing further.
+    pub fn new(measurement: &mut Measurement<(L, Option<(usize, V)>)>, labels: Vec<L>, domains: Vec<Vec<V>>, weight: i64, smoothing: f64) -> Self {
+        let unit = (weight / (1 + domains.len() as i64)) as f64;
+        NaiveBayes::from_counts(|key| measurement.observe(key) as f64 / unit, labels, domains, smoothing)
+    }
+    // builds a classifier from the counts, in records, reported by `observe`.
+    fn from_counts<F: FnMut((L, Option<(usize, V)>))->f64>(mut observe: F, labels: Vec<L>, domains: Vec<Vec<V>>, smoothing: f64) -> Self {
+        assert!(!labels.is_empty());
+        assert!(smoothing > 0.0);
+        let mut count = |key| observe(key).max(0.0);
+
+        let counts = labels.iter().map(|label| count((label.clone(), None)) + smoothing).collect::<Vec<_>>();
+        let sum = counts.iter().sum::<f64>();
+        let priors = counts.iter().map(|c| (c / sum).ln()).collect();
+
+        let mut conditionals = Vec::with_capacity(labels.len());
+        let mut unseen = Vec::with_capacity(labels.len());
+        for label in labels.iter() {
+            let mut by_attribute = Vec::with_capacity(domains.len());
+            let mut unseen_by_attribute = Vec::with_capacity(domains.len());
+            for (index, domain) in domains.iter().enumerate() {
+                let counts = domain.iter().map(|v| count((label.clone(), Some((index, v.clone())))) + smoothing).collect::<Vec<_>>();
+                // values outside the domain are treated as a further value with only the smoothing.
+                let sum = counts.iter().sum::<f64>() + smoothing;
+                by_attribute.push(domain.iter().cloned().zip(counts.iter().map(|c| (c / sum).ln())).collect());
+                unseen_by_attribute.push((smoothing / sum).ln());
+            }
+            conditionals.push(by_attribute);
+            unseen.push(unseen_by_attribute);
+        }
+
+        NaiveBayes { labels: labels, priors: priors, conditionals: conditionals, unseen: unseen }
+    }
+    /// The labels, in the order of their scores.
+    pub fn labels(&self) -> &[L] { &self.labels[..] }
+    /// The log-probability, up to a common constant, of each label given `values`.
+    pub fn scores(&self, values: &[V]) -> Vec<f64> {
+        (0 .. self.labels.len()).map(|label| {
+            let mut score = self.priors[label];
+            for (index, value) in values.iter().enumerate().take(self.conditionals[label].len()) {
+                score += self.conditionals[label][index].get(value).cloned().unwrap_or(self.unseen[label][index]);
+            }
+            score
+        }).collect()
+    }
+    /// The most likely label given `values`.
+    pub fn classify(&self, values: &[V]) -> L {
+        let scores = self.scores(values);
+        let mut best = 0;
+        for index in 1 .. scores.len() {
+            if scores[index] > scores[best] { best = index; }
+        }
+        self.labels[best].clone()
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_classify() {
+        use std::collections::HashMap;
+
+        // labels 0 and 1, with two binary attributes; the first attribute mostly agrees with the label.
+        let mut counts = HashMap::new();
+        counts.insert((0, None), 60.0);
+        counts.insert((1, None), 40.0);
+        counts.insert((0, Some((0, false))), 50.0);
+        counts.insert((0, Some((0, true))), 10.0);
+        counts.insert((1, Some((0, false))), 5.0);
+        counts.insert((1, Some((0, true))), 35.0);
+        counts.insert((0, Some((1, false))), 30.0);
+        counts.insert((0, Some((1, true))), 30.0);
+        counts.insert((1, Some((1, false))), 20.0);
+        counts.insert((1, Some((1, true))), 20.0);
+
+        let domains = vec![vec![false, true], vec![false, true]];
+        let bayes = super::NaiveBayes::from_counts(|key| counts.get(&key).cloned().unwrap_or(0.0), vec![0, 1], domains, 1.0);
+        assert_eq!(bayes.classify(&[false, true]), 0);
+        assert_eq!(bayes.classify(&[true, false]), 1);
+
+        // with smoothing one, the prior of label zero is 61 / 102, and an unseen value has one share.
+        let scores = bayes.scores(&[]);
+        assert!((scores[0] - (61.0f64 / 102.0).ln()).abs() < 1e-9);
+        let unseen = bayes.scores(&[false, false]);
+        let expected = (61.0f64 / 102.0).ln() + (51.0f64 / 63.0).ln() + (31.0f64 / 63.0).ln();
+        assert!((unseen[0] - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,122 @@
+//! Degree analyses of bipartite graphs, such as customers and their orders, or parts and suppliers.
+//!
+//! A bipartite graph is a dataset of `(left, right)` pairs, one for each edge, whose two sides may
+//! have different node types. Unlike the graphs of `degrees`, each edge appears once rather than in
+//! both directions, and the degrees of each side are measured separately.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::io::Result;
+use std::str::FromStr;
+
+use timely::{Data, ExchangeData};
+use timely::dataflow::{ProbeHandle, Scope};
+use timely::progress::Timestamp;
+use ::{Dataset, DatasetGroup, LoadSpec, Measurement};
+use super::degrees;
+
+/// Reports for each `index` the number of left nodes with degree greater than `index`.
+///
+/// As `degrees::cdf`, for the degrees of the left nodes of `edges`.
+pub fn left<G: Scope, L: ExchangeData+Ord+Hash, R: ExchangeData>(
+    edges: Dataset<G, (L, R)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    width: i64,
+    limit: Option<usize>) -> Measurement<usize> {
+    degrees::cdf(edges.map_ref(|edge| edge.0.clone()), probe, total, width, limit)
+}
+
+/// Reports for each `index` the number of right nodes with degree greater than `index`.
+///
+/// As `degrees::cdf`, for the degrees of the right nodes of `edges`.
+pub fn right<G: Scope, L: ExchangeData, R: ExchangeData+Ord+Hash>(
+    edges: Dataset<G, (L, R)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    width: i64,
+    limit: Option<usize>) -> Measurement<usize> {
+    degrees::cdf(edges.map_ref(|edge| edge.1.clone()), probe, total, width, limit)
+}
+
+/// Reports for each pair of indices `(i, j)` the edges whose left node has degree greater than `i`
+/// and whose right node has degree greater than `j`.
+///
+/// The degrees of each side are shaved into `width`-sized slices, as in `degrees::cdf`, and each
+/// edge is joined with the slices of its left node and then with those of its right node, so that
+/// it contributes to each pair of indices below the degrees of its endpoints. Each join divides the
+/// weight of an edge among the slices of its node, and so the measurement is of the biadjacency
+/// matrix's joint degree distribution as seen through two joins; synthetic graphs are measured
+/// identically, and are fit to it directly. If `limit` is supplied, slices of either side with
+/// index `limit` or greater are discarded.
+pub fn joint<G: Scope, L: ExchangeData+Ord+Hash, R: ExchangeData+Ord+Hash>(
+    edges: Dataset<G, (L, R)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    width: i64,
+    limit: Option<usize>) -> Measurement<(usize, usize)> {
+
+    let lefts = slices(edges.clone().map_ref(|edge| edge.0.clone()), width, limit);
+    let rights = slices(edges.clone().map_ref(|edge| edge.1.clone()), width, limit);
+
+    edges
+        .join_by(lefts, |edge| edge.0.clone(), |slice| slice.0.clone())
+        .map_ref(|pair| ((pair.0).1.clone(), (pair.1).1))
+        .join_by(rights, |half| half.0.clone(), |slice| slice.0.clone())
+        .map_ref(|pair| ((pair.0).1, (pair.1).1))
+        .measure(probe, total)
+}
+
+// the `width`-sized slices of each node's degree, less than `limit` if supplied.
+fn slices<G: Scope, N: ExchangeData+Ord+Hash>(nodes: Dataset<G, N>, width: i64, limit: Option<usize>) -> Dataset<G, (N, usize)> {
+    nodes
+        .shave(width)
+        .filter(move |slice| limit.map(|limit| slice.1 < limit).unwrap_or(true))
+}
+
+/// Parses the `columns` of a delimited line as a bipartite edge.
+///
+/// Fields are separated by `|`, as in TPC-H tables, by commas, or by whitespace, and `columns`
+/// names the fields of the left and right nodes, counting from zero.
+///
+/// # Panics
+///
+/// Panics if either field is missing or does not parse.
+pub fn parse<L: FromStr, R: FromStr>(line: &str, columns: (usize, usize)) -> (L, R) {
+    let fields =
+    if line.contains('|') { line.split('|').map(|x| x.trim()).collect::<Vec<_>>() }
+    else if line.contains(',') { line.split(',').map(|x| x.trim()).collect::<Vec<_>>() }
+    else { line.split_whitespace().collect::<Vec<_>>() };
+
+    let field = |column: usize| *fields.get(column).unwrap_or_else(|| panic!("missing column {} in {:?}", column, line));
+    let left = field(columns.0).parse().ok().unwrap_or_else(|| panic!("malformed left node in {:?}", line));
+    let right = field(columns.1).parse().ok().unwrap_or_else(|| panic!("malformed right node in {:?}", line));
+    (left, right)
+}
+
+/// Loads the `columns` of a two-column relation, or any delimited file, as the edges named `name`.
+///
+/// Each line is parsed by `parse`, and loaded as in `DatasetGroup::load`. For example, the columns
+/// `(1, 0)` of the TPC-H orders table are edges from customers to their orders.
+pub fn load<T, L, R>(group: &mut DatasetGroup<T>, name: &str, spec: &LoadSpec, columns: (usize, usize)) -> Result<usize>
+where
+    T: Timestamp,
+    L: Data+FromStr,
+    R: Data+FromStr,
+{
+    group.load::<(L, R), _>(name, spec, |line| parse(line, columns))
+}
+
+mod tests {
+
+    #[test]
+    fn test_parse() {
+        let order: (u32, u64) = super::parse("7|3|O|173665.47|1996-01-02|5-LOW|", (1, 0));
+        assert_eq!(order, (3, 7));
+        let supply: (String, u32) = super::parse("part7, 12", (0, 1));
+        assert_eq!(supply, ("part7".to_owned(), 12));
+        let edge: (u32, u32) = super::parse("4\t9", (0, 1));
+        assert_eq!(edge, (4, 9));
+    }
+}
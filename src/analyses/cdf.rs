@@ -0,0 +1,71 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+
+/// Reports for each `index` the number of records whose weight exceeds `index * width`.
+///
+/// Each record's accumulated weight is treated as a numeric attribute: a record with weight `w`
+/// contributes `width` to each index less than `w / width`, and the remainder to the next index.
+/// Dividing the measured value at `index` by `width` estimates the number of records whose
+/// attribute is at least `upper(index, width, scale)`.
+///
+/// Vertex degrees are one example, where the records are vertex identifiers and each edge adds
+/// a fixed weight to its vertex. Quantities or balances work the same way once each record carries
+/// weight proportional to its value.
+pub fn measure<G: Scope, D: ExchangeData+Ord+Hash>(
+    dataset: Dataset<G, D>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    width: i64) -> Measurement<usize> {
+    indices(dataset, width, None)
+        .measure(probe, total)
+}
+
+/// Shaves each record into `width`-sized slices and retains only the slice indices.
+///
+/// If `limit` is supplied, slices with index `limit` or greater are discarded, which truncates
+/// each record's contribution to at most `limit * width`. Discarding weight is a stable
+/// transformation, so truncation has no privacy cost; it only bounds the domain of the result.
+pub fn indices<G: Scope, D: ExchangeData+Ord+Hash>(
+    dataset: Dataset<G, D>,
+    width: i64,
+    limit: Option<usize>) -> Dataset<G, usize> {
+    dataset
+        .shave(width)
+        .filter_map(move |(_datum, idx)| if limit.map(|limit| idx < limit).unwrap_or(true) { Some(idx) } else { None })
+}
+
+/// The range `[lower, upper)` of attribute values associated with `index`.
+///
+/// Here `scale` is the weight corresponding to one unit of the attribute, so that a record with
+/// attribute value `v` carries weight `v * scale`.
+pub fn range(index: usize, width: i64, scale: i64) -> (f64, f64) {
+    (lower(index, width, scale), upper(index, width, scale))
+}
+
+/// The least attribute value whose records contribute any weight to `index`.
+pub fn lower(index: usize, width: i64, scale: i64) -> f64 {
+    ((index as i64) * width) as f64 / (scale as f64)
+}
+
+/// The least attribute value whose records contribute the full `width` to `index`.
+pub fn upper(index: usize, width: i64, scale: i64) -> f64 {
+    ((index as i64 + 1) * width) as f64 / (scale as f64)
+}
+
+/// The index to which a record with attribute value `value` contributes its last unit of weight.
+pub fn index_of(value: f64, width: i64, scale: i64) -> usize {
+    let weight = value * (scale as f64);
+    if weight <= 0.0 { 0 } else { ((weight / width as f64).ceil() as usize).saturating_sub(1) }
+}
+
+/// Reads the first `limit` measured values, normalized by `width` to record counts.
+pub fn counts(measurement: &mut Measurement<usize>, width: i64, limit: usize) -> Vec<f64> {
+    (0 .. limit)
+        .map(|index| (measurement.observe(index) as f64) / (width as f64))
+        .collect()
+}
@@ -0,0 +1,78 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+use analyses::cdf;
+
+/// Measures, for each `index`, the nodes whose connected components have more than `index` nodes.
+///
+/// The edges should contain each edge in both directions. Each node is labeled with itself, and in
+/// each of `rounds` rounds sends its labels to its neighbors, so that after the last round each node
+/// holds the labels of the nodes at most `rounds` hops away, which are those of its component when
+/// its diameter is at most `rounds`. Each round reads the labels twice, once to retain them and once
+/// to send them, and is attenuated by two; the labels are then `1 + rounds / 2` stable in the edges.
+/// Each pair of node and label contributes at most `width` to its label, and the nodes reached by
+/// each label are counted by shaving, as in `degrees::cdf`.
+///
+/// The weight of each label is halved in each round and divided among neighbors by each `join`, and
+/// so labels of distant or high degree nodes may carry less than `width`; such nodes are counted
+/// only in part. The `width` should be small compared to `weight >> rounds`.
+pub fn measure<G: Scope, N: ExchangeData+Ord+Hash>(
+    edges: Dataset<G, (N, N)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    rounds: usize,
+    width: i64,
+    limit: usize) -> Measurement<usize> {
+
+    let labels =
+    edges
+        .clone()
+        .map_ref(|edge| (edge.0.clone(), edge.0.clone()))
+        .iterate(rounds, 2, move |labels| {
+            let edges = edges.enter_scope(&labels.scope());
+            let sent =
+            labels
+                .clone()
+                .join_by(edges, |label| label.0.clone(), |edge| edge.0.clone())
+                .map_ref(|pair| ((pair.1).1.clone(), (pair.0).1.clone()));
+            labels.concat(sent)
+        });
+
+    // each label receives at most `width` from each node it reached.
+    let reached =
+    labels
+        .shave(width)
+        .filter_map(|((_node, label), index)| if index == 0 { Some(label) } else { None });
+
+    cdf::indices(reached, width, Some(limit))
+        .measure(probe, total)
+}
+
+/// Estimates the number of components with each number of nodes `1 .. limit`.
+///
+/// The arguments are those given to `measure`. The nodes in components of exactly `size` nodes
+/// are the difference of the measured counts at `size - 1` and `size`, and each such component
+/// accounts for `size` of them. Reading the measurement is post-processing, and costs nothing
+/// further. The result at position `size - 1` is the estimate for `size`.
+pub fn sizes(measurement: &mut Measurement<usize>, width: i64, limit: usize) -> Vec<f64> {
+    from_counts(&cdf::counts(measurement, width, limit)[..])
+}
+
+// the components of each size, from the counts of nodes in components larger than each index.
+fn from_counts(counts: &[f64]) -> Vec<f64> {
+    (1 .. counts.len()).map(|size| (counts[size - 1] - counts[size]) / size as f64).collect()
+}
+
+mod tests {
+
+    #[test]
+    fn test_from_counts() {
+        // one component of one node, two of two nodes, and one of three nodes.
+        let counts = [8.0, 7.0, 3.0, 0.0];
+        assert_eq!(super::from_counts(&counts[..]), vec![1.0, 2.0, 1.0]);
+    }
+}
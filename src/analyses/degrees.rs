@@ -5,6 +5,9 @@ use std::hash::Hash;
 use timely::ExchangeData;
 use timely::dataflow::{ProbeHandle, Scope};
 use ::{Dataset, Measurement};
+use budget::{self, Exhausted, Odometer};
+use postprocess;
+use super::cdf;
 
 // Reports for each `index` the number of nodes with degree greater than `index`.
 //
@@ -12,14 +15,18 @@ use ::{Dataset, Measurement};
 // vertex contributes one unit to each integral value less or equal to its degree. Each count
 // is subjected to Laplace noise, but each are relatively accurate measurements of the number
 // of nodes with at least the given degree.
+//
+// If `limit` is supplied, each node's degree is truncated to at most `limit` before measurement,
+// which bounds the domain of the measurement (and the operator state) on heavy-tailed graphs.
+// The truncation only discards weight, and so costs no privacy; it should be read as a choice
+// to not learn about degrees beyond `limit`.
 pub fn cdf<G: Scope, D: ExchangeData+Ord+Hash>(
     dataset: Dataset<G, D>,
     probe: &mut ProbeHandle<G::Timestamp>,
     total: &Rc<RefCell<i64>>,
-    width: i64) -> Measurement<usize> {
-    dataset
-        .shave(width)
-        .map(|(_src, idx)| idx)
+    width: i64,
+    limit: Option<usize>) -> Measurement<usize> {
+    cdf::indices(dataset, width, limit)
         .measure(probe, total)
 }
 
@@ -29,125 +36,197 @@ pub fn cdf<G: Scope, D: ExchangeData+Ord+Hash>(
 // cumulative density function. The double-transposition has the effect of re-ordering the
 // degrees from largest to smallest; we could also have taken the measurements using the original
 // node identifiers if we had reason to know what they ranged over, but generally we do not.
+//
+// If `limit` is supplied, each node's degree is truncated to at most `limit`, as in `cdf`.
 pub fn seq<G: Scope, D: ExchangeData+Ord+Hash>(
     dataset: Dataset<G, D>,
     probe: &mut ProbeHandle<G::Timestamp>,
     total: &Rc<RefCell<i64>>,
-    width:i64) -> Measurement<usize> {
-    dataset
-        .shave(width)
-        .map(|(_src, idx)| idx)
-        .shave(width)
-        .map(|(_src, idx)| idx)
+    width: i64,
+    limit: Option<usize>) -> Measurement<usize> {
+    seq_indices(dataset, width, limit)
         .measure(probe, total)
 }
 
-/// Fits joint cdf and sequence measurements
+/// The dataset `seq` measures, for callers that measure it themselves.
 ///
-/// This method tries to find the minimum weight grid path connecting the points (0, infinity) and
-/// (infinity, 0), where the cost of an edge corresponds to committing to that measurement. More
-/// specifically, edges are either horizontal or vertical, and their costs are
+/// The indices produced by `cdf::indices` are shaved a second time, which transposes them.
+pub fn seq_indices<G: Scope, D: ExchangeData+Ord+Hash>(dataset: Dataset<G, D>, width: i64, limit: Option<usize>) -> Dataset<G, usize> {
+    cdf::indices(dataset, width, limit)
+        .shave(width)
+        .map_ref(|slice| slice.1)
+}
+
+/// Reports for each `index` the number of nodes with degree greater than `index`, protecting nodes.
 ///
-/// cost((a,b) -> (a+1,b)) : math::abs(b - seqs[a])
-/// cost((a,b+1) -> (a,b)) : math::abs(a - cdfs[b])
+/// As `cdf`, but for `edges` of a graph with one record for each edge in each direction, each of
+/// weight `weight`, whose degrees are first truncated to `degree` by `truncate_degrees`, and with
+/// `width` dividing `weight`. Each node then contributes to at most `degree * weight / width`
+/// indices, and the measurement protects nodes, rather than edges. Its noise provides `epsilon`
+/// privacy to each edge, and `node_factor(degree) * epsilon` is charged to `odometer`.
+pub fn cdf_node<G: Scope, N: ExchangeData+Ord+Hash>(
+    edges: Dataset<G, (N, N)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    width: i64,
+    weight: i64,
+    degree: usize,
+    odometer: &mut Odometer,
+    epsilon: f64) -> Result<Measurement<usize>, Exhausted> {
+    let limit = (degree as i64 * weight / width) as usize;
+    let indices = cdf::indices(edges.truncate_degrees(degree, weight).map_ref(|edge| edge.0.clone()), width, Some(limit));
+    measure_node(indices, probe, total, weight, degree, odometer, epsilon)
+}
+
+/// Reports for each `index` the `index`-th largest degree in the graph, protecting nodes.
 ///
-/// The intuition is that traversing an edge corresponds to committing to that edge in the actual
-/// cdf/seq measurement, and so the cost is the sum of the errors in the corresponding measurements.
-pub fn fit_cdf_seq(horizontal: &[f64], vertical: &[f64], cost: impl Fn(f64,f64)->f64) -> (Vec<usize>, Vec<usize>) {
+/// As `seq`, with the degrees of `edges` truncated and the cost charged as in `cdf_node`.
+pub fn seq_node<G: Scope, N: ExchangeData+Ord+Hash>(
+    edges: Dataset<G, (N, N)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    width: i64,
+    weight: i64,
+    degree: usize,
+    odometer: &mut Odometer,
+    epsilon: f64) -> Result<Measurement<usize>, Exhausted> {
+    let limit = (degree as i64 * weight / width) as usize;
+    let indices = seq_indices(edges.truncate_degrees(degree, weight).map_ref(|edge| edge.0.clone()), width, Some(limit));
+    measure_node(indices, probe, total, weight, degree, odometer, epsilon)
+}
 
-    #[derive(PartialEq)]
-    struct QueueKey(f64);
+// measures `dataset` with `epsilon` privacy for each edge, charging the node privacy cost.
+fn measure_node<G: Scope>(
+    dataset: Dataset<G, usize>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    weight: i64,
+    degree: usize,
+    odometer: &mut Odometer,
+    epsilon: f64) -> Result<Measurement<usize>, Exhausted> {
+    odometer.charge("NodeMeasure", node_factor(degree) * epsilon)?;
+    let scale = budget::scale(dataset.stability(), weight, epsilon);
+    Ok(dataset.measure_scaled(probe, total, scale))
+}
 
-    impl PartialOrd for QueueKey {
-        fn partial_cmp(&self, other: &Self) -> Option<::std::cmp::Ordering> {
-            (other.0).partial_cmp(&self.0)
-        }
-    }
-    impl Ord for QueueKey {
-        fn cmp(&self, other: &Self) -> ::std::cmp::Ordering {
-            self.partial_cmp(other).unwrap()
-        }
-    }
+/// Reports for each pair of indices `(i, j)` the weight of edges from nodes of degree greater than
+/// `i` to nodes of degree greater than `j`, with both less than `limit`.
+///
+/// The `edges` should contain each edge in both directions, each of weight `weight`. The degrees
+/// are shaved into slices of `weight`, one for each edge, and each edge is joined first with the
+/// slices of its source and then with those of its destination. An edge between nodes of degrees
+/// `d1` and `d2` contributes `weight / (3 * d1 * d2)` to each pair of indices below its degrees,
+/// from which `joint_degrees` recovers the joint degree distribution. Pairs of indices are only
+/// discarded after both joins, so that truncation does not change the weights of retained pairs.
+pub fn joint<G: Scope, N: ExchangeData+Ord+Hash>(
+    edges: Dataset<G, (N, N)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    weight: i64,
+    limit: usize) -> Measurement<(usize, usize)> {
+    joint_indices(edges, weight, limit)
+        .measure(probe, total)
+}
 
-    impl Eq for QueueKey { }
+/// The dataset `joint` measures, for callers that measure it themselves.
+pub fn joint_indices<G: Scope, N: ExchangeData+Ord+Hash>(edges: Dataset<G, (N, N)>, weight: i64, limit: usize) -> Dataset<G, (usize, usize)> {
 
-    assert!(!horizontal.is_empty());
-    assert!(!vertical.is_empty());
+    let slices = edges.clone().map_ref(|edge| edge.0.clone()).shave(weight);
 
-    let mut queue = ::std::collections::BinaryHeap::new();
-    let mut dists = ::std::collections::HashMap::new();
+    edges
+        .join_by(slices.clone(), |edge| edge.0.clone(), |slice| slice.0.clone())
+        .map_ref(|pair| ((pair.0).1.clone(), (pair.1).1))
+        .join_by(slices, |half| half.0.clone(), |slice| slice.0.clone())
+        .filter_map(move |((_dst, index1), (_dst2, index2))| {
+            if index1 < limit && index2 < limit { Some((index1, index2)) } else { None }
+        })
+}
 
-    let max_x = ::std::cmp::max(vertical.iter().map(|x| x.round() as i64).max().unwrap(), 0) as usize;
-    let max_y = ::std::cmp::max(horizontal.iter().map(|x| x.round() as i64).max().unwrap(), 0) as usize;
+/// Estimates the number of edges between nodes of each pair of degrees from a `joint` measurement.
+///
+/// The arguments are those given to `joint`. The weight of edges between nodes of degrees exactly
+/// `(d1, d2)` is recovered from the measured weights by inclusion and exclusion, and scaled up by
+/// `3 * d1 * d2 / weight`. The estimates are rounded, and only positive counts are reported, as
+/// suited to `synthesis::graph::from_joint_degrees`. Degrees of `limit` or more are not reported.
+pub fn joint_degrees(measurement: &mut Measurement<(usize, usize)>, weight: i64, limit: usize) -> Vec<((usize, usize), usize)> {
+    let grid =
+    (0 .. limit)
+        .map(|i| (0 .. limit).map(|j| measurement.observe((i, j)) as f64 / weight as f64).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    from_cumulative(&grid[..])
+}
 
-    queue.push((QueueKey(0.0), 0, max_y));
-    while !dists.contains_key(&(max_x, 0)) {
+// the rounded positive counts of each pair of degrees, from the weights of pairs of indices below them.
+fn from_cumulative(grid: &[Vec<f64>]) -> Vec<((usize, usize), usize)> {
+    let cell = |i: usize, j: usize| grid.get(i).and_then(|row| row.get(j)).cloned().unwrap_or(0.0);
+    let mut result = Vec::new();
+    for d1 in 1 .. grid.len() {
+        for d2 in 1 .. grid.len() {
+            let exact = cell(d1 - 1, d2 - 1) - cell(d1, d2 - 1) - cell(d1 - 1, d2) + cell(d1, d2);
+            let count = (3.0 * (d1 * d2) as f64 * exact).round();
+            if count > 0.0 { result.push(((d1, d2), count as usize)); }
+        }
+    }
+    result
+}
 
-        if let Some((QueueKey(d), x, y)) = queue.pop() {
-            if !dists.contains_key(&(x,y)) {
-                dists.insert((x,y), d);
-                // consider (x,y) -> (x+1,y); costs additional abs(h[x] - y)
-                if x + 1 <= max_x {
-                    queue.push((QueueKey(d + cost(horizontal[x], y as f64)), x+1, y));
-                }
+/// The factor by which node privacy multiplies the cost of measurements of degrees truncated to `degree`.
+///
+/// Removing a node from a graph of degree at most `degree` removes its own edges and one edge of
+/// each of its neighbors, at most `2 * degree` edge records, and so a measurement providing
+/// epsilon privacy to each edge provides `node_factor(degree)` times epsilon to each node. The
+/// guarantee is exact among graphs of degree at most `degree`, which truncation leaves unchanged;
+/// for other graphs truncation bounds each node's own contribution, but not its effect on the
+/// truncation of its neighbors, and so the guarantee is that of restricted sensitivity.
+pub fn node_factor(degree: usize) -> f64 {
+    2.0 * degree as f64
+}
 
-                // consider (x,y) -> (x,y-1); costs additional abs(v[y-1] - x)
-                if y > 0 {
-                    queue.push((QueueKey(d + cost(vertical[y-1], x as f64)), x, y-1));
-                }
-            }
-        }
-        else {
-            panic!("ran out of reachable states; mysterious!");
-        }
+/// Fits joint cdf and sequence measurements
+///
+/// This is `postprocess::fit::Fit` for two non-increasing sequences with unit confidence in each
+/// measurement: the cdf counts nodes with degree greater than each index, and the sequence lists
+/// the degrees from largest to smallest.
+pub fn fit_cdf_seq(horizontal: &[f64], vertical: &[f64], cost: impl Fn(f64,f64)->f64) -> (Vec<usize>, Vec<usize>) {
+    postprocess::fit::Fit::new(cost).fit(horizontal, vertical)
+}
+
+/// Fits expected degrees to a noisy degree cdf, for the Chung-Lu model.
+///
+/// Here `counts[i]` is the noisy number of nodes with degree greater than `i`, as read from a `cdf`
+/// measurement by `cdf::counts`. The counts are projected onto the nearest non-increasing sequence,
+/// clamped to `[0, nodes]`, and transposed: the `k`-th largest expected degree is the sum over `i`
+/// of the amount by which `counts[i]` exceeds `k`, clamped to `[0, 1]`. The fractional degrees are
+/// suited to `synthesis::graph::chung_lu`, which samples a graph in one pass rather than fitting
+/// the measurements by refinement.
+pub fn expected_degrees(counts: &[f64], nodes: usize) -> Vec<f64> {
+    let fitted = postprocess::isotonic_decreasing(counts);
+    (0 .. nodes)
+        .map(|k| fitted.iter().map(|&c| (c.min(nodes as f64) - k as f64).max(0.0).min(1.0)).sum())
+        .collect()
+}
+
+mod tests {
+    #[test]
+    fn test_joint_from_cumulative() {
+        // a star with three leaves, in both directions: three edges of degrees (3, 1) and (1, 3).
+        let unit = 1.0 / 9.0;
+        let mut grid = vec![vec![0.0; 4]; 4];
+        for i in 0 .. 3 { grid[i][0] += 3.0 * unit; grid[0][i] += 3.0 * unit; }
+        let joint = super::from_cumulative(&grid[..]);
+        assert_eq!(joint, vec![((1, 3), 3), ((3, 1), 3)]);
     }
 
-    // now we walk backwards from (max_x, 0) to find the minimum path
-    let mut current = (max_x, 0);
-
-    let mut result_h = vec![0; max_x];
-    let mut result_v = vec![0; max_y];
-
-    while current != (0, max_y) {
-
-        let (x,y) = current;
-        let dist1 = dists.get(&(x-1,y));
-        let dist2 = dists.get(&(x,y+1));
-
-        match (dist1, dist2) {
-            (None, None) => { panic!("backwards tracing failed!") }
-            (Some(_), None) => {
-                // edge (x-1,y) -> (x,y)
-                current = (x-1, y);
-                result_h[x-1] = y;
-            },
-            (None, Some(_)) => {
-                // edge (x,y+1) -> (x,y)
-                current = (x, y+1);
-                result_v[y] = x;
-            },
-            (Some(d1), Some(d2)) => {
-                let d1 = d1 + cost(horizontal[x-1], y as f64);
-                let d2 = d2 + cost(vertical[y], x as f64);
-
-                if d1 <= d2 {
-                    // edge (x-1,y) -> (x,y)
-                    current = (x-1, y);
-                    result_h[x-1] = y;
-                }
-                else {
-                    // edge (x,y+1) -> (x,y)
-                    current = (x, y+1);
-                    result_v[y] = x;
-                }
-            }
+    #[test]
+    fn test_expected_degrees() {
+        let counts = vec![3.0, 2.2, 0.6, 0.9, -0.5];
+        let degrees = super::expected_degrees(&counts[..], 3);
+        for (degree, expected) in degrees.iter().zip([3.5, 2.0, 1.2].iter()) {
+            assert!((degree - expected).abs() < 1e-9);
         }
     }
 
-    (result_h, result_v)
-}
 
-mod tests {
     #[test]
     fn test_fit1() {
         let h = vec![10, 4, 2, 1, 1];
@@ -156,7 +235,7 @@ mod tests {
         let hf = h.iter().map(|&x| x as f64).collect::<Vec<_>>();
         let vf = v.iter().map(|&x| x as f64).collect::<Vec<_>>();
 
-        let (hn, vn) = super::fit_cdf_seq(&hf[..], &vf[..]);
+        let (hn, vn) = super::fit_cdf_seq(&hf[..], &vf[..], |x,y| (x-y).abs());
 
         assert_eq!(h, hn);
         assert_eq!(v, vn);
@@ -0,0 +1,126 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+use budget::{Exhausted, Odometer};
+use noise;
+
+/// Measures the weights of all prefixes of each key, for discovering heavy hitters.
+///
+/// Each key is a 64 bit value, read as a sequence of `64 / bits` digits of `bits` bits each. The
+/// weight of each key is divided evenly among its prefixes, one of each number of digits, and so
+/// all levels of the prefix tree are measured under the privacy cost of a single measurement,
+/// `epsilon`, charged to `odometer`. Here `weight` is the weight of each input record. Keys from
+/// domains too large to enumerate (identifiers, tokens) are typically hashed to 64 bits.
+pub fn measure<G: Scope>(
+    dataset: Dataset<G, u64>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    bits: usize,
+    odometer: &mut Odometer,
+    weight: i64,
+    epsilon: f64) -> Result<Measurement<(usize, u64)>, Exhausted> {
+    assert!(bits > 0 && bits < 64 && 64 % bits == 0);
+    let levels = 64 / bits;
+    dataset
+        .flat_map(move |key| (1 .. levels + 1).map(move |level| (level, prefix(key, level * bits))))
+        .measure_epsilon(probe, total, odometer, weight, epsilon)
+}
+
+/// The first `length` bits of `key`.
+pub fn prefix(key: u64, length: usize) -> u64 {
+    if length >= 64 { key } else { key >> (64 - length) }
+}
+
+/// A key discovered to have a large count.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeavyHitter {
+    /// The key.
+    pub key: u64,
+    /// The noisy count of the key, in units of record weight.
+    pub count: f64,
+    /// The probability, under the noise distribution, that the true count is at least the threshold.
+    pub confidence: f64,
+}
+
+/// Discovers keys whose noisy count is at least `threshold`, by descending the prefix tree.
+///
+/// Starting from the empty prefix, the children of each prefix whose noisy count reaches
+/// `threshold` are examined in turn. Here `bits` and `weight` are as given to `measure`. As all
+/// prefixes were measured at once, exploring the tree incurs no further privacy cost; the
+/// threshold should nonetheless be several times the noise scale, in records, of the measurement,
+/// to prevent noise from admitting (exponentially many) spurious prefixes.
+pub fn discover(measurement: &mut Measurement<(usize, u64)>, bits: usize, weight: i64, threshold: f64) -> Vec<HeavyHitter> {
+
+    let levels = 64 / bits;
+    let unit = (weight / levels as i64) as f64;
+    let scale = measurement.scale() * (noise::SCALE as f64) / unit;
+
+    let mut frontier = vec![0u64];
+    let mut counts = Vec::new();
+
+    for level in 1 .. levels + 1 {
+        counts.clear();
+        for &parent in frontier.iter() {
+            for digit in 0 .. (1u64 << bits) {
+                let child = (parent << bits) | digit;
+                let count = measurement.observe((level, child)) as f64 / unit;
+                if count >= threshold {
+                    counts.push((child, count));
+                }
+            }
+        }
+        frontier = counts.iter().map(|x| x.0).collect();
+    }
+
+    counts
+        .into_iter()
+        .map(|(key, count)| HeavyHitter {
+            key: key,
+            count: count,
+            confidence: 1.0 - 0.5 * (-(count - threshold) / scale).exp(),
+        })
+        .collect()
+}
+
+mod tests {
+
+    #[test]
+    fn test_discover() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use timely::communication::allocator::Thread;
+        use timely::dataflow::ProbeHandle;
+        use timely::dataflow::scopes::Root;
+        use budget::Odometer;
+        use DatasetHandle;
+
+        let mut worker = Root::new(Thread);
+        let mut input = DatasetHandle::new();
+        let mut probe = ProbeHandle::new();
+        let total = Rc::new(RefCell::new(0i64));
+        let mut odometer = Odometer::new(2e9);
+
+        // a large epsilon makes the noise negligible, so that the counts are exact.
+        let mut measurement = worker.dataflow(|scope| {
+            super::measure(input.enter(scope), &mut probe, &total, 8, &mut odometer, 4096, 1e9)
+        }).unwrap();
+        assert_eq!(odometer.spent(), 1e9);
+
+        let heavy = 0xABCD_0000_0000_0001u64;
+        let light = 0xABCD_0000_0000_0002u64;
+        for _ in 0 .. 3 { input.truth.send((heavy, 4096)); }
+        input.truth.send((light, 4096));
+        input.truth.send((0x1234_0000_0000_0000, 4096));
+        input.close();
+        while worker.step() { }
+
+        // the shared prefixes of `heavy` and `light` are explored, but only `heavy` reaches the threshold.
+        let found = super::discover(&mut measurement, 8, 4096, 2.0);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].key, heavy);
+        assert!((found[0].count - 3.0).abs() < 1e-3);
+        assert!(found[0].confidence > 0.99);
+    }
+}
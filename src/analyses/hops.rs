@@ -0,0 +1,77 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+use analyses::cdf;
+
+/// Measures, for each number of hops up to `rounds`, the nodes within that many hops of `seeds`.
+///
+/// The seed nodes are public, and the edges should contain each edge in both directions. Each seed
+/// with edges is reached in zero hops, and in each of `rounds` rounds every reached node sends its
+/// number of hops, plus one, to its neighbors. Each round reads the reached nodes twice, once to
+/// retain them and once to send them, and is attenuated by two, as in `components::measure`. A node
+/// reached in `hops` hops is then counted for each of `hops ..= rounds`, with its weight divided
+/// evenly among them, and each node contributes at most `width` to each count.
+///
+/// Weights are halved in each round and divided among neighbors by each `join`, and so nodes far
+/// from the seeds, or reached only through high degree nodes, may carry less than `width` and be
+/// counted only in part. The `width` should be small compared to `weight >> rounds`.
+pub fn measure<G: Scope, N: ExchangeData+Ord+Hash>(
+    edges: Dataset<G, (N, N)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    seeds: Vec<N>,
+    rounds: usize,
+    width: i64) -> Measurement<usize> {
+
+    let seeds = seeds.into_iter().collect::<HashSet<_>>();
+    let reached =
+    edges
+        .clone()
+        .filter(move |edge| seeds.contains(&edge.0))
+        .map_ref(|edge| (edge.0.clone(), 0))
+        .iterate(rounds, 2, move |reached| {
+            let edges = edges.enter_scope(&reached.scope());
+            let sent =
+            reached
+                .clone()
+                .join_by(edges, |node| node.0.clone(), |edge| edge.0.clone())
+                .map_ref(|pair| ((pair.1).1.clone(), (pair.0).1 + 1));
+            reached.concat(sent)
+        });
+
+    // each node contributes at most `width` to the count of each number of hops it is within.
+    reached
+        .flat_map(move |(node, hops)| (hops .. rounds + 1).map(move |within| (node.clone(), within)))
+        .shave(width)
+        .filter_map(|((_node, within), index)| if index == 0 { Some(within) } else { None })
+        .measure(probe, total)
+}
+
+/// Estimates the number of nodes at each distance `0 ..= rounds` from the seeds.
+///
+/// The arguments are those given to `measure`. The nodes at exactly `hops` hops are the difference
+/// of the measured counts within `hops` and within `hops - 1` hops. Reading the measurement is
+/// post-processing, and costs nothing further.
+pub fn distribution(measurement: &mut Measurement<usize>, width: i64, rounds: usize) -> Vec<f64> {
+    from_cumulative(&cdf::counts(measurement, width, rounds + 1)[..])
+}
+
+// the nodes at each distance, from the counts of nodes within each distance.
+fn from_cumulative(counts: &[f64]) -> Vec<f64> {
+    (0 .. counts.len()).map(|hops| if hops == 0 { counts[0] } else { counts[hops] - counts[hops - 1] }).collect()
+}
+
+mod tests {
+
+    #[test]
+    fn test_from_cumulative() {
+        // two seeds, three nodes one hop away, and one node two hops away.
+        let counts = [2.0, 5.0, 6.0];
+        assert_eq!(super::from_cumulative(&counts[..]), vec![2.0, 3.0, 1.0]);
+    }
+}
@@ -0,0 +1,147 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+
+/// Measures the supports of all itemsets of at most `size` items, for discovering frequent itemsets.
+///
+/// Each record is a transaction, whose distinct items are sorted and truncated to the first
+/// `limit`. The weight of each transaction is divided evenly among the sizes `1 .. size + 1`, and
+/// within each size `k` evenly among the `choose(limit, k)` itemsets a transaction of `limit` items
+/// would contain; shares of smaller transactions that correspond to no itemset go to the empty
+/// itemset, which is not reported. Every itemset of size `k` then receives the same weight from each
+/// transaction containing it, and all sizes are measured under the cost of a single measurement.
+pub fn measure<G: Scope, I: ExchangeData+Ord+Hash>(
+    dataset: Dataset<G, Vec<I>>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    size: usize,
+    limit: usize) -> Measurement<Vec<I>> {
+    assert!(size > 0 && size <= limit);
+    let common = (1 .. size + 1).fold(1, |l, k| lcm(l, choose(limit, k)));
+    dataset
+        .flat_map_weighted(move |mut items| {
+            items.sort();
+            items.dedup();
+            items.truncate(limit);
+            let mut result = Vec::new();
+            let mut padding = 0;
+            for k in 1 .. size + 1 {
+                let share = common / choose(limit, k);
+                let before = result.len();
+                subsets(&items[..], k, &mut Vec::new(), &mut |subset| result.push((subset.to_vec(), share)));
+                padding += (choose(limit, k) - (result.len() - before) as i64) * share;
+            }
+            result.push((Vec::new(), padding));
+            result
+        })
+        .measure(probe, total)
+}
+
+/// Discovers the itemsets whose noisy support is at least `threshold` transactions.
+///
+/// The arguments `size` and `limit` are those given to `measure`, and `weight` is the weight of
+/// each transaction. Itemsets of one item are drawn from the public `domain`, and candidates of
+/// each further size are those whose subsets one item smaller were all discovered, as in the
+/// apriori algorithm. As all sizes were measured at once, the search incurs no further privacy
+/// cost. Each itemset of size `k` carries `weight / (size * choose(limit, k))` of each transaction,
+/// and so its noise grows with `choose(limit, k)`, and the threshold should be several times this
+/// noise to prevent spurious itemsets from admitting many more candidates.
+pub fn discover<I: Ord+Hash+Clone>(
+    measurement: &mut Measurement<Vec<I>>,
+    domain: &[I],
+    size: usize,
+    limit: usize,
+    weight: i64,
+    threshold: f64) -> Vec<(Vec<I>, f64)> {
+
+    let mut result = Vec::new();
+    let mut candidates = domain.iter().map(|item| vec![item.clone()]).collect::<Vec<_>>();
+    candidates.sort();
+    candidates.dedup();
+
+    for k in 1 .. size + 1 {
+        let unit = weight as f64 / (size as f64 * choose(limit, k) as f64);
+        let mut frequent = Vec::new();
+        for candidate in candidates.drain(..) {
+            let support = measurement.observe(candidate.clone()) as f64 / unit;
+            if support >= threshold {
+                frequent.push((candidate, support));
+            }
+        }
+        candidates = extend(&frequent.iter().map(|x| x.0.clone()).collect::<Vec<_>>()[..]);
+        result.extend(frequent);
+    }
+
+    result
+}
+
+// the sorted itemsets one item larger than `frequent`, all of whose subsets are in `frequent`.
+fn extend<I: Ord+Hash+Clone>(frequent: &[Vec<I>]) -> Vec<Vec<I>> {
+    let known = frequent.iter().cloned().collect::<HashSet<_>>();
+    let mut result = Vec::new();
+    for (index, first) in frequent.iter().enumerate() {
+        for second in frequent[index + 1 ..].iter() {
+            let length = first.len();
+            if first[.. length - 1] == second[.. length - 1] {
+                let mut candidate = first.clone();
+                candidate.push(second[length - 1].clone());
+                candidate.sort();
+                let closed = (0 .. candidate.len()).all(|skip| {
+                    let subset = candidate.iter().enumerate().filter(|x| x.0 != skip).map(|x| x.1.clone()).collect::<Vec<_>>();
+                    known.contains(&subset)
+                });
+                if closed { result.push(candidate); }
+            }
+        }
+    }
+    result.sort();
+    result.dedup();
+    result
+}
+
+// calls `action` with each subset of `items` of size `k`, in lexicographic order.
+fn subsets<I: Clone, F: FnMut(&[I])>(items: &[I], k: usize, prefix: &mut Vec<I>, action: &mut F) {
+    if k == 0 {
+        action(&prefix[..]);
+    }
+    else {
+        for index in 0 .. items.len() {
+            if items.len() - index < k { break; }
+            prefix.push(items[index].clone());
+            subsets(&items[index + 1 ..], k - 1, prefix, action);
+            prefix.pop();
+        }
+    }
+}
+
+// the binomial coefficient `n` choose `k`.
+fn choose(n: usize, k: usize) -> i64 {
+    (0 .. k).fold(1, |c, i| c * (n - i) as i64 / (i + 1) as i64)
+}
+
+fn lcm(a: i64, b: i64) -> i64 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 { let t = x % y; x = y; y = t; }
+    a / x * b
+}
+
+mod tests {
+
+    #[test]
+    fn test_candidates() {
+        let mut found = Vec::new();
+        super::subsets(&[1, 2, 3, 4], 2, &mut Vec::new(), &mut |s| found.push(s.to_vec()));
+        assert_eq!(found, vec![vec![1, 2], vec![1, 3], vec![1, 4], vec![2, 3], vec![2, 4], vec![3, 4]]);
+        assert_eq!(super::choose(4, 2), 6);
+        assert_eq!(super::lcm(4, 6), 12);
+
+        // `[1, 3, 4]` is excluded, as `[3, 4]` is not frequent.
+        let frequent = vec![vec![1, 2], vec![1, 3], vec![1, 4], vec![2, 3]];
+        assert_eq!(super::extend(&frequent[..]), vec![vec![1, 2, 3]]);
+    }
+}
@@ -0,0 +1,105 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+use budget::{Exhausted, Odometer};
+
+/// Measures the counts and coordinate sums of the clusters of `centroids`, for one iteration.
+///
+/// Each record is a point whose coordinates are clamped to `[-bound, bound]`, and is assigned to
+/// its nearest centroid. The weight of each record is divided among its cluster's statistics in
+/// fixed proportions: a share `bound` to the count, and for each coordinate `x` the shares
+/// `bound + x` and `bound - x` to a pair of statistics whose difference is proportional to `x`.
+/// The shares sum to the same total for every record, and so all statistics of all clusters are
+/// measured at the cost of a single measurement, `epsilon`, charged to `odometer`. Here `weight` is
+/// the weight of each input record.
+///
+/// The centroids are public, typically those produced by `centroids` from the previous iteration,
+/// and so each iteration is a new measurement over the same data, for example replayed from an
+/// `arrange::Cache`, and the iterations together cost `epsilon` times their number.
+pub fn measure<G: Scope>(
+    dataset: Dataset<G, Vec<i64>>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    centroids: &[Vec<f64>],
+    bound: i64,
+    odometer: &mut Odometer,
+    weight: i64,
+    epsilon: f64) -> Result<Measurement<(usize, usize)>, Exhausted> {
+    assert!(bound > 0);
+    assert!(!centroids.is_empty());
+    let centroids = centroids.to_vec();
+    dataset
+        .flat_map_weighted(move |point| shares(&point[..], &centroids[..], bound))
+        .measure_epsilon(probe, total, odometer, weight, epsilon)
+}
+
+/// Reads the noisy centroids of the clusters of `previous` from `measurement`.
+///
+/// The arguments are those given to `measure`. Each centroid is the noisy coordinate sum of its
+/// cluster divided by its noisy count, clamped to `[-bound, bound]`. Clusters whose noisy count is
+/// less than one record, and whose centroids the noise would dominate, retain their previous
+/// centroid. Reading the measurement is post-processing, and costs nothing further.
+pub fn centroids(measurement: &mut Measurement<(usize, usize)>, previous: &[Vec<f64>], bound: i64, weight: i64) -> Vec<Vec<f64>> {
+    let mut result = Vec::with_capacity(previous.len());
+    for (cluster, centroid) in previous.iter().enumerate() {
+        let total = (bound * (1 + 2 * centroid.len() as i64)) as f64;
+        let count = (measurement.observe((cluster, 0)) as f64) * total / ((weight * bound) as f64);
+        if count < 1.0 {
+            result.push(centroid.clone());
+        }
+        else {
+            result.push((0 .. centroid.len()).map(|index| {
+                let above = measurement.observe((cluster, 2 * index + 1));
+                let below = measurement.observe((cluster, 2 * index + 2));
+                let sum = ((above - below) as f64) * total / (2 * weight) as f64;
+                let mean = sum / count;
+                mean.max(-bound as f64).min(bound as f64)
+            }).collect());
+        }
+    }
+    result
+}
+
+/// The index of the centroid nearest to `point`, in Euclidean distance.
+pub fn nearest(point: &[f64], centroids: &[Vec<f64>]) -> usize {
+    let distance = |centroid: &Vec<f64>| point.iter().zip(centroid.iter()).map(|(x, c)| (x - c) * (x - c)).sum::<f64>();
+    let mut best = 0;
+    for index in 1 .. centroids.len() {
+        if distance(&centroids[index]) < distance(&centroids[best]) {
+            best = index;
+        }
+    }
+    best
+}
+
+// the statistics of the nearest cluster to `point`, with their shares of its weight.
+fn shares(point: &[i64], centroids: &[Vec<f64>], bound: i64) -> Vec<((usize, usize), i64)> {
+    let point = point.iter().map(|x| if *x < -bound { -bound } else if *x > bound { bound } else { *x }).collect::<Vec<_>>();
+    let cluster = nearest(&point.iter().map(|x| *x as f64).collect::<Vec<_>>()[..], centroids);
+    let dimension = centroids[cluster].len();
+    let mut result = Vec::with_capacity(1 + 2 * dimension);
+    result.push(((cluster, 0), bound));
+    for index in 0 .. dimension {
+        let x = point.get(index).cloned().unwrap_or(0);
+        result.push(((cluster, 2 * index + 1), bound + x));
+        result.push(((cluster, 2 * index + 2), bound - x));
+    }
+    result.retain(|x| x.1 > 0);
+    result
+}
+
+mod tests {
+
+    #[test]
+    fn test_shares() {
+        let centroids = vec![vec![0.0, 0.0], vec![10.0, 10.0]];
+        assert_eq!(super::nearest(&[6.0, 7.0], &centroids[..]), 1);
+
+        // coordinates are clamped to the bound, and shares sum to `bound * (1 + 2 * dimension)`.
+        let shares = super::shares(&[3, 20], &centroids[..], 10);
+        assert_eq!(shares, vec![((1, 0), 10), ((1, 1), 13), ((1, 2), 7), ((1, 3), 20)]);
+        assert_eq!(shares.iter().map(|x| x.1).sum::<i64>(), 50);
+    }
+}
@@ -1 +1,11 @@
-pub mod degrees;
\ No newline at end of file
+pub mod bayes;
+pub mod bipartite;
+pub mod cdf;
+pub mod components;
+pub mod degrees;
+pub mod heavy_hitters;
+pub mod hops;
+pub mod itemsets;
+pub mod kmeans;
+pub mod pca;
+pub mod triangles;
@@ -0,0 +1,161 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+use budget::{Exhausted, Odometer};
+
+/// Measures the clipped second moments of points, for their covariance.
+///
+/// Each record is a point of `dimension` coordinates, each clamped to `[-bound, bound]`, and is
+/// extended by a constant coordinate `bound`. For each pair `i <= j` of coordinates of the extended
+/// point, with product `p`, the weight of the record is divided among a pair of statistics with
+/// shares `bound^2 + p` and `bound^2 - p`, whose difference is proportional to `p`. The shares sum
+/// to the same total for every record, and so the count, the sums, and the sums of products are
+/// all measured at the cost of a single measurement, `epsilon`, charged to `odometer`. Here
+/// `weight` is the weight of each input record.
+pub fn measure<G: Scope>(
+    dataset: Dataset<G, Vec<i64>>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    dimension: usize,
+    bound: i64,
+    odometer: &mut Odometer,
+    weight: i64,
+    epsilon: f64) -> Result<Measurement<((usize, usize), bool)>, Exhausted> {
+    assert!(bound > 0);
+    dataset
+        .flat_map_weighted(move |point| shares(&point[..], dimension, bound))
+        .measure_epsilon(probe, total, odometer, weight, epsilon)
+}
+
+/// Noisy estimates of the count, mean, and covariance of points.
+#[derive(Clone, Debug)]
+pub struct Covariance {
+    /// The number of points.
+    pub count: f64,
+    /// The mean of each coordinate.
+    pub means: Vec<f64>,
+    /// The covariance of each pair of coordinates.
+    pub matrix: Vec<Vec<f64>>,
+}
+
+impl Covariance {
+    /// Reads the covariance from a measurement made by `measure`.
+    ///
+    /// The arguments are those given to `measure`. The noisy matrix is symmetric, but need not be
+    /// positive semi-definite. Reading the measurement is post-processing, and costs nothing further.
+    pub fn new(measurement: &mut Measurement<((usize, usize), bool)>, dimension: usize, bound: i64, weight: i64) -> Self {
+        let square = (bound * bound) as f64;
+        let total = square * ((dimension + 1) * (dimension + 2)) as f64;
+        // the sum of products of coordinates `i` and `j` of the extended points.
+        let mut moment = |i: usize, j: usize| {
+            let (i, j) = if i <= j { (i, j) } else { (j, i) };
+            let above = measurement.observe(((i, j), true));
+            let below = measurement.observe(((i, j), false));
+            ((above - below) as f64) * total / (2 * weight) as f64
+        };
+
+        let count = (moment(dimension, dimension) / square).max(1.0);
+        let means = (0 .. dimension).map(|i| moment(i, dimension) / (bound as f64 * count)).collect::<Vec<_>>();
+        let mut matrix = vec![vec![0.0; dimension]; dimension];
+        for i in 0 .. dimension {
+            for j in i .. dimension {
+                let covariance = moment(i, j) / count - means[i] * means[j];
+                matrix[i][j] = covariance;
+                matrix[j][i] = covariance;
+            }
+        }
+
+        Covariance { count: count, means: means, matrix: matrix }
+    }
+    /// The `k` principal components, as pairs of variance and unit direction, by decreasing variance.
+    pub fn components(&self, k: usize) -> Vec<(f64, Vec<f64>)> {
+        let mut pairs = eigenpairs(&self.matrix);
+        pairs.truncate(k);
+        pairs
+    }
+}
+
+/// The eigenvalues and unit eigenvectors of the symmetric `matrix`, by decreasing eigenvalue.
+///
+/// This is the cyclic Jacobi method, which rotates pairs of coordinates to eliminate off-diagonal
+/// entries until they are negligible, accumulating the rotations as the eigenvectors.
+pub fn eigenpairs(matrix: &[Vec<f64>]) -> Vec<(f64, Vec<f64>)> {
+    let n = matrix.len();
+    let mut a = matrix.to_vec();
+    let mut v = (0 .. n).map(|i| (0 .. n).map(|j| if i == j { 1.0 } else { 0.0 }).collect::<Vec<_>>()).collect::<Vec<_>>();
+    let scale = a.iter().map(|row| row.iter().map(|x| x * x).sum::<f64>()).sum::<f64>();
+    for _sweep in 0 .. 100 {
+        let off = (0 .. n).map(|i| (0 .. n).filter(|&j| j != i).map(|j| a[i][j] * a[i][j]).sum::<f64>()).sum::<f64>();
+        if off <= 1e-24 * scale { break; }
+        for p in 0 .. n {
+            for q in p + 1 .. n {
+                if a[p][q] == 0.0 { continue; }
+                // the rotation by angle `theta` zeroes the entry at `(p, q)`.
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0 .. n {
+                    let (akp, akq) = (a[k][p], a[k][q]);
+                    a[k][p] = c * akp - s * akq;
+                    a[k][q] = s * akp + c * akq;
+                }
+                for k in 0 .. n {
+                    let (apk, aqk) = (a[p][k], a[q][k]);
+                    a[p][k] = c * apk - s * aqk;
+                    a[q][k] = s * apk + c * aqk;
+                }
+                for k in 0 .. n {
+                    let (vkp, vkq) = (v[k][p], v[k][q]);
+                    v[k][p] = c * vkp - s * vkq;
+                    v[k][q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    let mut pairs = (0 .. n).map(|i| (a[i][i], (0 .. n).map(|k| v[k][i]).collect::<Vec<_>>())).collect::<Vec<_>>();
+    pairs.sort_by(|x, y| y.0.partial_cmp(&x.0).expect("eigenvalues must not be NaN"));
+    pairs
+}
+
+// the statistics of the products of coordinates of `point`, with their shares of its weight.
+fn shares(point: &[i64], dimension: usize, bound: i64) -> Vec<(((usize, usize), bool), i64)> {
+    let mut extended = (0 .. dimension).map(|i| {
+        let x = point.get(i).cloned().unwrap_or(0);
+        if x < -bound { -bound } else if x > bound { bound } else { x }
+    }).collect::<Vec<_>>();
+    extended.push(bound);
+    let square = bound * bound;
+    let mut result = Vec::with_capacity(extended.len() * (extended.len() + 1));
+    for i in 0 .. extended.len() {
+        for j in i .. extended.len() {
+            let product = extended[i] * extended[j];
+            result.push((((i, j), true), square + product));
+            result.push((((i, j), false), square - product));
+        }
+    }
+    result
+}
+
+mod tests {
+
+    #[test]
+    fn test_shares() {
+        let shares = super::shares(&[3, -20], 2, 10);
+        assert_eq!(shares.len(), 12);
+        assert_eq!(shares.iter().map(|x| x.1).sum::<i64>(), 100 * 3 * 4);
+        assert!(shares.contains(&(((0, 1), true), 70)));
+        assert!(shares.contains(&(((1, 2), false), 200)));
+    }
+
+    #[test]
+    fn test_eigenpairs() {
+        let pairs = super::eigenpairs(&[vec![2.0, 1.0], vec![1.0, 2.0]]);
+        assert!((pairs[0].0 - 3.0).abs() < 1e-9 && (pairs[1].0 - 1.0).abs() < 1e-9);
+        let direction = &pairs[0].1;
+        assert!((direction[0].abs() - 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((direction[0] - direction[1]).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,39 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{ProbeHandle, Scope};
+use ::{Dataset, Measurement};
+
+// Reports the weight of directed triangles `(a, b, c)` with edges `a -> b -> c` and `a -> c`.
+//
+// Each triangle is found by extending edges to paths of length two, and then joining paths with
+// the edges that close them. Each `join` scales weights down by the total weight associated with
+// the key, and so the measured weight is not a count of triangles: triangles among high degree
+// nodes contribute less weight than those among low degree nodes. Synthetic graphs should be fit
+// to the measured weight itself, rather than to an estimated count.
+pub fn measure<G: Scope, N: ExchangeData+Ord+Hash>(
+    edges: Dataset<G, (N, N)>,
+    probe: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>) -> Measurement<()> {
+    weights(edges)
+        .measure(probe, total)
+}
+
+/// The weight of directed triangles, as a dataset with the single element `()`.
+///
+/// This is the dataset `measure` measures, for callers that measure it themselves.
+pub fn weights<G: Scope, N: ExchangeData+Ord+Hash>(edges: Dataset<G, (N, N)>) -> Dataset<G, ()> {
+
+    // paths (a, b, c) keyed by their endpoints (a, c).
+    let paths =
+    edges
+        .clone()
+        .join_by(edges.clone(), |edge| edge.1.clone(), |edge| edge.0.clone())
+        .map_ref(|pair| (((pair.0).0.clone(), (pair.1).1.clone()), ()));
+
+    paths
+        .join(edges.map(|edge| (edge, ())))
+        .total_weight()
+}
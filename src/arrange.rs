@@ -0,0 +1,181 @@
+//! Shared arrangements of input data across dataflows.
+//!
+//! Each call to `DatasetHandle::enter` introduces the input into a dataflow independently, and
+//! each dataflow then exchanges and accumulates the records itself. An `Arrangement` instead
+//! ingests a dataset once, in its own dataflow, where the records are partitioned by their hash
+//! and consolidated at each timestamp. The resulting streams are captured and may be replayed into
+//! any number of downstream dataflows, which then share the work of ingestion.
+//!
+//! A `Cache` does the same for a dataset computed within a dataflow, for example a filtered and
+//! keyed table used by several analyses, so that the shared prefix is computed only once. Replays
+//! of a cache are attributed to the same plan node, and so share its stability.
+//!
+//! Both retain every consolidated batch since they were created, so that dataflows attached later
+//! observe the full history, and their memory grows with the number of updates rather than with
+//! the size of the dataset; a dataset whose records are repeatedly inserted and retracted will
+//! accumulate each change. Once no further dataflows will be attached, `release` discards the
+//! retained history, which is then held only until the dataflows already attached replay it.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use timely::{ExchangeData, Allocate};
+use timely::progress::Timestamp;
+use timely::progress::nested::product::Product;
+use timely::progress::timestamp::RootTimestamp;
+use timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::operators::capture::{Capture, Replay, EventLink};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::scopes::{Child, Root};
+
+use {Dataset, DatasetHandle, Rounding, consolidate, fnv_hash, plan};
+
+type Link<T, D> = Rc<EventLink<Product<RootTimestamp, T>, (D, i64)>>;
+
+/// A dataset ingested once and shared by several dataflows.
+///
+/// The arrangement retains the consolidated history of the dataset, so that dataflows may be
+/// attached with `enter` at any point and will observe all records sent so far. The history holds
+/// one batch for each timestamp at which records were sent, until `release` is called.
+pub struct Arrangement<T: Timestamp, D: ExchangeData> {
+    truth: Link<T, D>,
+    synth: Link<T, D>,
+}
+
+impl<T: Timestamp, D: ExchangeData+Ord+Hash> Arrangement<T, D> {
+    /// Ingests the records sent to `handle` in a new dataflow of `worker`.
+    pub fn new<A: Allocate>(handle: &mut DatasetHandle<T, D>, worker: &mut Root<A>) -> Self {
+        let truth = Rc::new(EventLink::new());
+        let synth = Rc::new(EventLink::new());
+        worker.dataflow(|scope| {
+            arrange(&handle.truth.to_stream(scope)).capture_into(truth.clone());
+            arrange(&handle.synth.to_stream(scope)).capture_into(synth.clone());
+        });
+        Arrangement { truth: truth, synth: synth }
+    }
+    /// Introduces the arranged dataset into a dataflow scope, for computation.
+    pub fn enter<'a, A: Allocate>(&self, scope: &mut Child<'a, Root<A>, T>) -> Dataset<Child<'a, Root<A>, T>, D> {
+        Dataset::from(Some(self.truth.clone()).replay_into(scope), Some(self.synth.clone()).replay_into(scope))
+    }
+    /// Discards the retained history, after which no further dataflows may be attached.
+    ///
+    /// Dataflows already attached continue to receive all records, and each batch is freed once
+    /// every one of them has replayed it.
+    pub fn release(self) {
+        release(self.truth);
+        release(self.synth);
+    }
+}
+
+/// A dataset computed once, whose consolidated records may be replayed into several dataflows.
+///
+/// A cache is created by `Dataset::cache`, and may be entered into scopes of the dataflow in which
+/// it was created, or of any other dataflow with the same timestamp. As for `Arrangement`, the
+/// history of the dataset is retained until `release` is called.
+pub struct Cache<T: Timestamp, D: ExchangeData> {
+    truth: Rc<EventLink<T, (D, i64)>>,
+    synth: Rc<EventLink<T, (D, i64)>>,
+    node: usize,
+    rounding: Rounding,
+}
+
+impl<T: Timestamp, D: ExchangeData+Ord+Hash> Cache<T, D> {
+    /// Consolidates `dataset` at each timestamp, and captures the result.
+    pub fn new<G: Scope<Timestamp=T>>(dataset: Dataset<G, D>) -> Self {
+        let truth = Rc::new(EventLink::new());
+        let synth = Rc::new(EventLink::new());
+        arrange(dataset.truth.expose()).capture_into(truth.clone());
+        arrange(&dataset.synth).capture_into(synth.clone());
+        let node = plan::record("Arrange", 1.0, true, &[dataset.node]);
+        Cache { truth: truth, synth: synth, node: node, rounding: dataset.rounding }
+    }
+    /// Replays the cached dataset into `scope`, for further computation.
+    pub fn enter<S: Scope<Timestamp=T>>(&self, scope: &mut S) -> Dataset<S, D> {
+        let truth = Some(self.truth.clone()).replay_into(scope);
+        let synth = Some(self.synth.clone()).replay_into(scope);
+        Dataset::derived(truth, synth, self.node, self.rounding)
+    }
+    /// Discards the retained history, after which no further scopes may enter the cache.
+    ///
+    /// Scopes already entered continue to receive all records, and each batch is freed once every
+    /// one of them has replayed it.
+    pub fn release(self) {
+        release(self.truth);
+        release(self.synth);
+    }
+}
+
+// frees the batches from `link` on that no replayer holds, one at a time rather than recursively.
+fn release<T, D>(link: Rc<EventLink<T, D>>) {
+    let mut link = link;
+    while let Ok(owned) = Rc::try_unwrap(link) {
+        match owned.next.into_inner() {
+            Some(next) => { link = next; },
+            None => { return; },
+        }
+    }
+}
+
+// partitions records by their hash, and consolidates them at each timestamp.
+fn arrange<G: Scope, D: ExchangeData+Ord+Hash>(stream: &Stream<G, (D, i64)>) -> Stream<G, (D, i64)> {
+
+    let mut stash = HashMap::new();
+
+    stream.unary_notify(Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), "Arrange", vec![], move |input, output, notificator| {
+
+        input.for_each(|time, data| {
+            stash.entry(time.time().clone()).or_insert(Vec::new()).extend(data.drain(..));
+            notificator.notify_at(time.retain());
+        });
+
+        notificator.for_each(|time, _, _| {
+            if let Some(mut list) = stash.remove(time.time()) {
+                consolidate(&mut list);
+                output.session(&time).give_iterator(list.into_iter());
+            }
+        });
+    })
+}
+
+mod tests {
+
+    #[test]
+    fn test_release() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use timely::communication::allocator::Thread;
+        use timely::dataflow::operators::Inspect;
+        use timely::dataflow::scopes::Root;
+        use DatasetHandle;
+
+        let mut worker = Root::new(Thread);
+        let mut handle = DatasetHandle::new();
+        let arrangement = super::Arrangement::new(&mut handle, &mut worker);
+        let received = Rc::new(RefCell::new(0));
+        let counter = received.clone();
+        worker.dataflow(|scope| {
+            arrangement.enter(scope).truth.expose().inspect(move |x| *counter.borrow_mut() += x.1);
+        });
+
+        for round in 0 .. 10u64 {
+            handle.truth.send((round, 1));
+            handle.truth.advance_to(round as usize + 1);
+            handle.synth.advance_to(round as usize + 1);
+            while *received.borrow() <= round as i64 { worker.step(); }
+        }
+
+        // the attached dataflow has replayed every batch, and so none are held once released.
+        let head = Rc::downgrade(&arrangement.truth);
+        let first = Rc::downgrade(arrangement.truth.next.borrow().as_ref().unwrap());
+        arrangement.release();
+        assert!(head.upgrade().is_none());
+        assert!(first.upgrade().is_none());
+
+        handle.truth.send((10, 1));
+        handle.close();
+        while worker.step() { }
+        assert_eq!(*received.borrow(), 11);
+    }
+}
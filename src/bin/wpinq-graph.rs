@@ -0,0 +1,280 @@
+//! Runs standard private graph analyses over an edge list, and synthesizes a graph.
+//!
+//! Usage: `wpinq-graph <edges> <epsilon> <analysis>[,<analysis>..] [output-prefix]`
+//!
+//! The edge file contains whitespace separated pairs of node identifiers, with lines starting
+//! with `#` ignored. Each measurement is performed with privacy parameter `epsilon` times the
+//! number of times it reads the edges. Supported analyses, and their costs, are `edges` and
+//! `nodes` (`epsilon` each), `degrees` (two measurements, `2 * epsilon`), and `triangles` and
+//! `joint-degrees` (three reads of the edges, `3 * epsilon` each); the total cost is printed and
+//! recorded with the results. Noisy results are written to `<output-prefix>.results.tsv`, and a
+//! synthetic graph to `<output-prefix>.graph.txt`: from the joint degree distribution if measured,
+//! otherwise from the fitted degree sequence, and otherwise matching the node and edge counts.
+//!
+//! Timely arguments, such as `-w <workers>`, follow the output prefix, which must then be given.
+//! Each worker reads its share of the lines of the edge file, and all measurements are gathered
+//! at worker zero, which reports the results.
+
+extern crate rand;
+extern crate timely;
+extern crate wpinq;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, Write};
+use std::fs::File;
+use std::sync::Arc;
+
+use rand::Rng;
+use timely::Configuration;
+use timely::dataflow::ProbeHandle;
+
+use wpinq::{DatasetHandle, Measurement};
+use wpinq::analyses::{cdf, degrees, triangles};
+use wpinq::io;
+use wpinq::noise;
+use wpinq::synthesis;
+
+// the analyses supported, with the multiple of `epsilon` that each costs.
+const ANALYSES: &[(&str, f64)] = &[("edges", 1.0), ("nodes", 1.0), ("degrees", 2.0), ("triangles", 3.0), ("joint-degrees", 3.0)];
+
+// the public bound on degrees reported by the joint degree distribution.
+const JOINT_LIMIT: usize = 64;
+
+fn main() {
+
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 4 {
+        eprintln!("usage: {} <edges> <epsilon> <analysis>[,<analysis>..] [output-prefix]", args[0]);
+        std::process::exit(1);
+    }
+
+    let analyses = args[3].split(',').map(|x| x.to_owned()).collect::<Vec<_>>();
+    let mut cost = 0.0;
+    for analysis in analyses.iter() {
+        match ANALYSES.iter().find(|x| x.0 == analysis.as_str()) {
+            Some(&(_name, multiple)) => { cost += multiple; },
+            None => {
+                eprintln!("unsupported analysis: {:?}; expected edges, nodes, degrees, triangles, or joint-degrees", analysis);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let filename = args[1].clone();
+    let epsilon: f64 = args[2].parse().expect("malformed epsilon");
+    let prefix = args.get(4).cloned().unwrap_or_else(|| "wpinq".to_owned());
+    let configuration = Configuration::from_args(std::env::args()).expect("malformed timely arguments");
+
+    let results = run(filename, epsilon, analyses, configuration, None);
+
+    let mut file = File::create(format!("{}.results.tsv", prefix)).expect("failed to create results");
+
+    eprintln!("total privacy cost: epsilon = {}", cost * epsilon);
+    writeln!(file, "epsilon\t\t{}", cost * epsilon).unwrap();
+
+    if let Some(edges) = results.edges {
+        writeln!(file, "edges\t\t{}", edges).unwrap();
+    }
+    if let Some(nodes) = results.nodes {
+        writeln!(file, "nodes\t\t{}", nodes).unwrap();
+    }
+    if let Some(ref degree_cdf) = results.degree_cdf {
+        for (index, value) in degree_cdf.iter().enumerate() {
+            writeln!(file, "degree_cdf\t{}\t{}", index, value).unwrap();
+        }
+    }
+    if let Some(ref degree_seq) = results.degree_seq {
+        for (index, value) in degree_seq.iter().enumerate() {
+            writeln!(file, "degree_seq\t{}\t{}", index, value).unwrap();
+        }
+    }
+    if let Some((ref fitted_cdf, ref fitted_seq)) = results.fitted {
+        for (index, value) in fitted_cdf.iter().enumerate() {
+            writeln!(file, "fitted_cdf\t{}\t{}", index, value).unwrap();
+        }
+        for (index, value) in fitted_seq.iter().enumerate() {
+            writeln!(file, "fitted_seq\t{}\t{}", index, value).unwrap();
+        }
+    }
+    if let Some(ref joint) = results.joint {
+        for &((degree1, degree2), count) in joint.iter() {
+            writeln!(file, "joint_degrees\t{},{}\t{}", degree1, degree2, count).unwrap();
+        }
+    }
+    if let Some(triangles) = results.triangles {
+        writeln!(file, "triangles\t\t{}", triangles).unwrap();
+    }
+
+    // synthesize a graph from the most detailed measurements available.
+    let mut rng = rand::thread_rng();
+    let graph =
+    if let Some(ref joint) = results.joint {
+        Some(synthesis::graph::from_joint_degrees(&joint[..], &mut rng))
+    }
+    else if let Some((_, ref fitted_seq)) = results.fitted {
+        Some(synthesis::graph::from_degree_sequence(&fitted_seq[..], &mut rng))
+    }
+    else if let (Some(nodes), Some(edges)) = (results.nodes, results.edges) {
+        let nodes = nodes.max(1) as usize;
+        Some((0 .. edges.max(0)).map(|_| (rng.gen_range(0, nodes), rng.gen_range(0, nodes))).collect::<Vec<_>>())
+    }
+    else { None };
+
+    if let Some(graph) = graph {
+        io::graph::save(format!("{}.graph.txt", prefix), &graph[..]).expect("failed to write graph");
+    }
+}
+
+// the noisy results of the analyses, as observed at worker zero.
+#[derive(Clone, Debug, PartialEq)]
+struct Results {
+    edges: Option<i64>,
+    nodes: Option<i64>,
+    degree_cdf: Option<Vec<f64>>,
+    degree_seq: Option<Vec<f64>>,
+    fitted: Option<(Vec<usize>, Vec<usize>)>,
+    joint: Option<Vec<((usize, usize), usize)>>,
+    triangles: Option<f64>,
+}
+
+// performs `analyses` on the edges in `filename`, with noise seeded by `seed` if supplied.
+fn run(filename: String, epsilon: f64, analyses: Vec<String>, configuration: Configuration, seed: Option<[u8; 32]>) -> Results {
+
+    let analyses = Arc::new(analyses);
+    let guards = timely::execute(configuration, move |worker| {
+
+        if let Some(seed) = seed { noise::seed(seed); }
+        let index = worker.index();
+        let peers = worker.peers();
+
+        // noise has scale `i32::max_value()`, so records of this weight provide `epsilon`-DP.
+        let weight = (epsilon * (i32::max_value() as f64)) as i64;
+        assert!(weight > 0, "epsilon too small");
+
+        let mut edges = DatasetHandle::new();
+        let mut probe = ProbeHandle::new();
+        let total = Rc::new(RefCell::new(0i64));
+
+        let mut edge_count = None;
+        let mut node_count = None;
+        let mut degree_cdf = None;
+        let mut degree_seq = None;
+        let mut triangle_weight = None;
+        let mut joint_weight = None;
+
+        // each measurement is gathered at worker zero, which observes and reports them all.
+        for analysis in analyses.iter() {
+            match analysis.as_str() {
+                "edges" => {
+                    edge_count = Some(worker.dataflow(|scope| {
+                        edges.enter(scope).total_weight().measure_gathered(&mut probe, &total)
+                    }));
+                },
+                "nodes" => {
+                    node_count = Some(worker.dataflow(|scope| {
+                        let nodes = edges.enter(scope).flat_map(|(src, dst)| Some(src).into_iter().chain(Some(dst)));
+                        cdf::indices(nodes, weight / 2, Some(1)).measure_gathered(&mut probe, &total)
+                    }));
+                },
+                "degrees" => {
+                    degree_cdf = Some(worker.dataflow(|scope| {
+                        cdf::indices(edges.enter(scope).map_ref(|&(src, _)| src), weight, None).measure_gathered(&mut probe, &total)
+                    }));
+                    degree_seq = Some(worker.dataflow(|scope| {
+                        degrees::seq_indices(edges.enter(scope).map_ref(|&(src, _)| src), weight, None).measure_gathered(&mut probe, &total)
+                    }));
+                },
+                "triangles" => {
+                    triangle_weight = Some(worker.dataflow(|scope| {
+                        triangles::weights(edges.enter(scope)).measure_gathered(&mut probe, &total)
+                    }));
+                },
+                "joint-degrees" => {
+                    joint_weight = Some(worker.dataflow(|scope| {
+                        degrees::joint_indices(edges.enter(scope), weight, JOINT_LIMIT).measure_gathered(&mut probe, &total)
+                    }));
+                },
+                _ => unreachable!(),
+            }
+        }
+
+        // load the sensitive edges, each worker reading its share of the lines.
+        let file = BufReader::new(File::open(&filename).expect("failed to open edges"));
+        for (number, readline) in file.lines().enumerate() {
+            let line = readline.expect("read error");
+            if number % peers == index && !line.starts_with('#') {
+                let mut elts = line[..].split_whitespace();
+                let src: usize = elts.next().unwrap().parse().expect("malformed src");
+                let dst: usize = elts.next().unwrap().parse().expect("malformed dst");
+                edges.truth.send(((src, dst), weight));
+            }
+        }
+        edges.close();
+
+        while worker.step() { }
+
+        if index != 0 { return None; }
+
+        let edges = edge_count.as_mut().map(|m: &mut Measurement<()>| m.observe(()) / weight);
+        let nodes = node_count.as_mut().map(|m: &mut Measurement<usize>| m.observe(0) / (weight / 2));
+
+        let mut counts = None;
+        let mut fitted = None;
+        if let (Some(cdf_m), Some(seq_m)) = (degree_cdf.as_mut(), degree_seq.as_mut()) {
+            let limit = nodes.unwrap_or(1000).max(1) as usize;
+            let degree_cdf = cdf::counts(cdf_m, weight, limit);
+            let degree_seq = cdf::counts(seq_m, weight, limit);
+            fitted = Some(degrees::fit_cdf_seq(&degree_cdf[..], &degree_seq[..], |x,y| (x-y) * (x-y)));
+            counts = Some((degree_cdf, degree_seq));
+        }
+
+        Some(Results {
+            edges: edges,
+            nodes: nodes,
+            degree_cdf: counts.as_ref().map(|x| x.0.clone()),
+            degree_seq: counts.map(|x| x.1),
+            fitted: fitted,
+            joint: joint_weight.as_mut().map(|m| degrees::joint_degrees(m, weight, JOINT_LIMIT)),
+            triangles: triangle_weight.as_mut().map(|m| (m.observe(()) as f64) / (weight as f64)),
+        })
+
+    }).expect("did not complete cleanly.");
+
+    guards
+        .join()
+        .into_iter()
+        .filter_map(|result| result.expect("worker failed"))
+        .next()
+        .expect("no results from worker zero")
+}
+
+mod tests {
+
+    #[test]
+    fn test_workers() {
+        use std::io::Write;
+        use timely::Configuration;
+
+        // two triangles sharing an edge, with each edge in both directions.
+        let filename = ::std::env::temp_dir().join(format!("wpinq-graph-{}.txt", ::std::process::id()));
+        {
+            let mut file = ::std::fs::File::create(&filename).unwrap();
+            writeln!(file, "# src dst").unwrap();
+            for &(src, dst) in [(0, 1), (1, 2), (2, 0), (1, 3), (3, 2)].iter() {
+                writeln!(file, "{} {}\n{} {}", src, dst, dst, src).unwrap();
+            }
+        }
+
+        let analyses = super::ANALYSES.iter().map(|x| x.0.to_owned()).collect::<Vec<_>>();
+        let run = |configuration| super::run(filename.to_str().unwrap().to_owned(), 1000.0, analyses.clone(), configuration, Some([5u8; 32]));
+        let single = run(Configuration::Thread);
+        let multiple = run(Configuration::Process(2));
+        ::std::fs::remove_file(&filename).unwrap();
+
+        // with the same noise, the counts are the same however the edges are partitioned.
+        assert_eq!(single, multiple);
+        assert!((single.edges.unwrap() - 10).abs() <= 1);
+        assert!((single.nodes.unwrap() - 4).abs() <= 1);
+    }
+}
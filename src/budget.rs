@@ -0,0 +1,322 @@
+//! Accounting of privacy budget.
+//!
+//! An `Odometer` records the privacy cost of each measurement and query as it is made, and
+//! refuses any charge that would exceed its configured limit. Charges are refused before any
+//! measurement is constructed or any noisy value released, so that an exhausted budget halts the
+//! analysis rather than silently overspending.
+//!
+//! Each charge is the epsilon of a pure differentially private measurement. By default the total
+//! cost is their sum, but an odometer may instead use a `Composition` that yields a tighter
+//! (epsilon, delta) guarantee for many measurements, at the expense of a non-zero delta. Analyses
+//! over several input datasets may give each its own odometer, through `Accounts`.
+
+use std::error::Error;
+use std::fmt;
+
+use noise;
+
+/// A rule for composing the costs of several pure differentially private measurements.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Composition {
+    /// The sum of the epsilons, with delta zero.
+    Basic,
+    /// The advanced composition theorem, for the given delta.
+    Advanced(f64),
+    /// Zero-concentrated differential privacy, converted to epsilon for the given delta.
+    Concentrated(f64),
+    /// Renyi differential privacy of the Laplace mechanism over a range of orders, converted to
+    /// epsilon for the given delta.
+    Renyi(f64),
+}
+
+impl Composition {
+    /// The delta of the resulting guarantee.
+    pub fn delta(&self) -> f64 {
+        match *self {
+            Composition::Basic => 0.0,
+            Composition::Advanced(delta) => delta,
+            Composition::Concentrated(delta) => delta,
+            Composition::Renyi(delta) => delta,
+        }
+    }
+
+    /// The epsilon guaranteed by composing measurements with epsilons `costs`.
+    ///
+    /// Each rule falls back to the basic sum when that is smaller, as it is for few measurements.
+    pub fn epsilon(&self, costs: &[f64]) -> f64 {
+        let basic: f64 = costs.iter().sum();
+        let composed = match *self {
+            Composition::Basic => basic,
+            Composition::Advanced(delta) => {
+                // heterogeneous advanced composition (Kairouz, Oh, and Viswanath).
+                let squares: f64 = costs.iter().map(|e| e * e).sum();
+                let expected: f64 = costs.iter().map(|e| e * (e.exp() - 1.0) / (e.exp() + 1.0)).sum();
+                (2.0 * (1.0 / delta).ln() * squares).sqrt() + expected
+            },
+            Composition::Concentrated(delta) => {
+                // each epsilon-DP measurement is (epsilon^2 / 2)-zCDP.
+                let rho: f64 = costs.iter().map(|e| e * e / 2.0).sum();
+                rho + 2.0 * (rho * (1.0 / delta).ln()).sqrt()
+            },
+            Composition::Renyi(delta) => {
+                let mut best = ::std::f64::INFINITY;
+                for &alpha in RENYI_ORDERS.iter() {
+                    let total: f64 = costs.iter().map(|&e| laplace_renyi(alpha, e)).sum();
+                    let epsilon = total + (1.0 / delta).ln() / (alpha - 1.0);
+                    if epsilon < best { best = epsilon; }
+                }
+                best
+            },
+        };
+        if composed < basic { composed } else { basic }
+    }
+}
+
+// the orders at which Renyi divergences are evaluated.
+const RENYI_ORDERS: [f64; 14] = [1.25, 1.5, 1.75, 2.0, 2.5, 3.0, 4.0, 6.0, 8.0, 12.0, 16.0, 24.0, 32.0, 64.0];
+
+// the Renyi divergence of order `alpha` of the Laplace mechanism providing `epsilon`-DP.
+fn laplace_renyi(alpha: f64, epsilon: f64) -> f64 {
+    if epsilon == 0.0 { return 0.0; }
+    let term1 = (alpha / (2.0 * alpha - 1.0)) * ((alpha - 1.0) * epsilon).exp();
+    let term2 = ((alpha - 1.0) / (2.0 * alpha - 1.0)) * (-alpha * epsilon).exp();
+    let divergence = (term1 + term2).ln() / (alpha - 1.0);
+    // the divergence never exceeds that of pure epsilon-DP.
+    if divergence < epsilon { divergence } else { epsilon }
+}
+
+/// Tracks privacy budget spent against a global limit.
+#[derive(Clone, Debug)]
+pub struct Odometer {
+    limit: f64,
+    composition: Composition,
+    ledger: Vec<(String, f64)>,
+    delta_limit: f64,
+    deltas: Vec<(String, f64)>,
+}
+
+impl Odometer {
+    /// Creates an odometer permitting a total cost of `limit`, under basic composition.
+    pub fn new(limit: f64) -> Self {
+        Odometer::with_composition(limit, Composition::Basic)
+    }
+    /// Creates an odometer permitting a total epsilon of `limit`, under `composition`.
+    pub fn with_composition(limit: f64, composition: Composition) -> Self {
+        Odometer { limit: limit, composition: composition, ledger: Vec::new(), delta_limit: 0.0, deltas: Vec::new() }
+    }
+    /// The configured limit.
+    pub fn limit(&self) -> f64 { self.limit }
+    /// The composition rule used to total charges.
+    pub fn composition(&self) -> Composition { self.composition }
+    /// The total cost charged so far, under the composition rule.
+    pub fn spent(&self) -> f64 { self.total(None) }
+    /// The cost that may still be charged.
+    pub fn remaining(&self) -> f64 { self.limit - self.spent() }
+    /// The name and cost of each charge, in the order made.
+    pub fn ledger(&self) -> &[(String, f64)] { &self.ledger[..] }
+    /// Indicates whether a charge of `cost` for `name` would be accepted, without making it.
+    pub fn check(&self, name: &str, cost: f64) -> Result<(), Exhausted> {
+        assert!(cost >= 0.0);
+        // permit rounding error in the accumulated costs.
+        if self.total(Some(cost)) > self.limit * (1.0 + 1e-9) {
+            Err(Exhausted { name: name.to_owned(), requested: cost, remaining: self.remaining() })
+        }
+        else {
+            Ok(())
+        }
+    }
+    /// Charges `cost` for `name`, or returns an error and charges nothing if the limit would be exceeded.
+    pub fn charge(&mut self, name: &str, cost: f64) -> Result<(), Exhausted> {
+        self.check(name, cost)?;
+        self.ledger.push((name.to_owned(), cost));
+        Ok(())
+    }
+    /// Permits mechanisms with a total delta of `limit`, beyond that required by the composition rule.
+    ///
+    /// Odometers permit no delta unless otherwise specified.
+    pub fn set_delta_limit(&mut self, limit: f64) { self.delta_limit = limit; }
+    /// The total delta charged so far, including that required by the composition rule.
+    pub fn delta_spent(&self) -> f64 {
+        self.composition.delta() + self.deltas.iter().map(|x| x.1).sum::<f64>()
+    }
+    /// Charges `delta` for `name`, or returns an error and charges nothing if the limit would be exceeded.
+    pub fn charge_delta(&mut self, name: &str, delta: f64) -> Result<(), Exhausted> {
+        assert!(delta >= 0.0);
+        let spent = self.deltas.iter().map(|x| x.1).sum::<f64>();
+        if spent + delta > self.delta_limit * (1.0 + 1e-9) {
+            return Err(Exhausted { name: name.to_owned(), requested: delta, remaining: self.delta_limit - spent });
+        }
+        self.deltas.push((name.to_owned(), delta));
+        Ok(())
+    }
+    // the total cost of the ledger, and `extra` if supplied.
+    fn total(&self, extra: Option<f64>) -> f64 {
+        let costs = self.ledger.iter().map(|x| x.1).chain(extra).collect::<Vec<_>>();
+        self.composition.epsilon(&costs[..])
+    }
+}
+
+/// Separate privacy budgets for several protected input datasets.
+///
+/// Each input is identified by the plan node of the dataset entered from its handle, and has its
+/// own odometer. A measurement charges each input in proportion to the factor by which that input
+/// may change it, as reported by `plan::factors`, and public inputs are not charged.
+#[derive(Clone, Debug)]
+pub struct Accounts {
+    odometers: Vec<(usize, Odometer)>,
+}
+
+impl Accounts {
+    /// Creates accounts with no inputs.
+    pub fn new() -> Self {
+        Accounts { odometers: Vec::new() }
+    }
+    /// Charges costs for `input` to `odometer`, replacing any previous odometer for the input.
+    pub fn open(&mut self, input: usize, odometer: Odometer) {
+        self.odometers.retain(|x| x.0 != input);
+        self.odometers.push((input, odometer));
+    }
+    /// The odometer charged for `input`, if any.
+    pub fn odometer(&self, input: usize) -> Option<&Odometer> {
+        self.odometers.iter().find(|x| x.0 == input).map(|x| &x.1)
+    }
+    /// Charges each `(input, cost)` pair to the odometer of the input, for `name`.
+    ///
+    /// If any odometer cannot afford its cost, an error is returned and nothing is charged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an input with a non-zero cost has no odometer.
+    pub fn charge(&mut self, name: &str, costs: &[(usize, f64)]) -> Result<(), Exhausted> {
+        for &(input, cost) in costs.iter().filter(|x| x.1 > 0.0) {
+            let odometer = self.odometer(input).unwrap_or_else(|| panic!("no budget for input node {}", input));
+            odometer.check(name, cost)?;
+        }
+        for &(input, cost) in costs.iter().filter(|x| x.1 > 0.0) {
+            let odometer = self.odometers.iter_mut().find(|x| x.0 == input).map(|x| &mut x.1).unwrap();
+            odometer.charge(name, cost)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error returned when a charge would exceed the limit of an `Odometer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exhausted {
+    /// The name of the refused charge.
+    pub name: String,
+    /// The cost requested.
+    pub requested: f64,
+    /// The cost that remained.
+    pub remaining: f64,
+}
+
+impl fmt::Display for Exhausted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "insufficient budget: {} requires {}, {} remains", self.name, self.requested, self.remaining)
+    }
+}
+
+impl Error for Exhausted {
+    fn description(&self) -> &str { "insufficient privacy budget" }
+}
+
+/// The threshold for releasing noisy counts of keys not known in advance, in units of weight.
+///
+/// A key present with weight at most `weight` in one dataset and absent from a neighbor has noisy
+/// count (with noise of scale `noise::SCALE`) exceeding this threshold with probability at most
+/// `delta`. A record contributing to at most `keys` keys may make that many keys present, and so
+/// releasing only counts above the threshold provides (epsilon, `keys * delta`)-differential
+/// privacy, and `keys * delta` is what should be charged by `Odometer::charge_delta`.
+pub fn threshold(weight: i64, delta: f64) -> i64 {
+    assert!(delta > 0.0 && delta < 0.5);
+    weight + ((noise::SCALE as f64) * (1.0 / (2.0 * delta)).ln()).ceil() as i64
+}
+
+/// The privacy cost of measuring a dataset of the given `stability`, for input records of `weight`.
+///
+/// Measurements add Laplace noise of scale `noise::SCALE`, and so a change of `weight` to an input
+/// record, amplified by `stability`, is protected with epsilon `stability * weight / SCALE`.
+pub fn epsilon(stability: f64, weight: i64) -> f64 {
+    stability * (weight as f64) / (noise::SCALE as f64)
+}
+
+/// The noise scale, as a multiple of `noise::SCALE`, at which a measurement costs `epsilon`.
+///
+/// This inverts `epsilon`: noise of scale `scale(stability, weight, epsilon) * SCALE` protects a
+/// change of `weight` to an input record, amplified by `stability`, with epsilon `epsilon`.
+pub fn scale(stability: f64, weight: i64, epsilon: f64) -> f64 {
+    assert!(epsilon > 0.0);
+    self::epsilon(stability, weight) / epsilon
+}
+
+/// Divides `epsilon` among measurements to minimize their expected weighted error.
+///
+/// Each demand is the `(stability, importance)` of a measurement, whose expected absolute error
+/// when it costs `e` is proportional to `stability / e`. The sum of these errors, each multiplied
+/// by its importance, is minimized subject to the costs summing to `epsilon` by costs proportional
+/// to `sqrt(stability * importance)`.
+pub fn allocate(epsilon: f64, demands: &[(f64, f64)]) -> Vec<f64> {
+    assert!(epsilon > 0.0);
+    assert!(demands.iter().all(|&(s, i)| s > 0.0 && i > 0.0));
+    let roots = demands.iter().map(|&(s, i)| (s * i).sqrt()).collect::<Vec<_>>();
+    let sum: f64 = roots.iter().sum();
+    roots.iter().map(|r| epsilon * r / sum).collect()
+}
+
+mod tests {
+
+    #[test]
+    fn test_odometer() {
+        let mut odometer = super::Odometer::new(1.0);
+        assert!(odometer.charge("a", 0.5).is_ok());
+        assert!(odometer.charge("b", 0.5).is_ok());
+        assert!(odometer.charge("c", 0.1).is_err());
+        assert_eq!(odometer.ledger().len(), 2);
+    }
+
+    #[test]
+    fn test_accounts() {
+        let mut accounts = super::Accounts::new();
+        accounts.open(0, super::Odometer::new(1.0));
+        accounts.open(2, super::Odometer::new(0.5));
+        assert!(accounts.charge("a", &[(0, 0.5), (2, 0.25)]).is_ok());
+        assert!(accounts.charge("b", &[(0, 0.25), (2, 0.5)]).is_err());
+        assert_eq!(accounts.odometer(0).unwrap().spent(), 0.5);
+        assert!(accounts.charge("c", &[(0, 0.5), (1, 0.0)]).is_ok());
+    }
+
+    #[test]
+    fn test_composition() {
+        use super::Composition;
+        let costs = vec![0.01; 100];
+        for &rule in [Composition::Advanced(1e-6), Composition::Concentrated(1e-6), Composition::Renyi(1e-6)].iter() {
+            // many small measurements compose to much less than their sum.
+            assert!(rule.epsilon(&costs[..]) < 0.75);
+            // a single measurement costs no more than its epsilon.
+            assert!(rule.epsilon(&[0.5]) <= 0.5);
+        }
+        assert_eq!(Composition::Basic.epsilon(&costs[..]), costs.iter().sum());
+        // k identical measurements cost `sqrt(2 k ln(1/delta)) e + k e (e^e - 1) / (e^e + 1)`.
+        assert!((Composition::Advanced(1e-6).epsilon(&costs[..]) - 0.5306521353).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_scale() {
+        let weight = ::noise::SCALE as i64;
+        assert_eq!(super::scale(2.0, weight, 0.5), 4.0);
+        assert_eq!(super::epsilon(2.0, weight) / super::scale(2.0, weight, 0.5), 0.5);
+    }
+
+    #[test]
+    fn test_allocate() {
+        let allocation = super::allocate(1.0, &[(1.0, 1.0), (1.0, 4.0), (4.0, 4.0)]);
+        assert!((allocation.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((allocation[1] - 2.0 * allocation[0]).abs() < 1e-9);
+        assert!((allocation[2] - 4.0 * allocation[0]).abs() < 1e-9);
+        // moving budget between measurements only increases the weighted error.
+        let error = |a: &[f64]| 1.0 / a[0] + 4.0 / a[1] + 16.0 / a[2];
+        let shifted = vec![allocation[0] + 0.01, allocation[1] - 0.01, allocation[2]];
+        assert!(error(&allocation[..]) < error(&shifted[..]));
+    }
+}
@@ -0,0 +1,189 @@
+//! A C ABI for driving standard graph measurements.
+//!
+//! This module is compiled with the `capi` feature, and exposes functions to create a
+//! single-threaded session, register standard analyses of an edge dataset, load sensitive edges,
+//! step the computation, and observe noisy measurements. The functions are intended to be called
+//! through a foreign function interface, for example from Python via `ctypes`:
+//!
+//! ```text
+//! lib = ctypes.CDLL("libwpinq.so")
+//! lib.wpinq_new.restype = ctypes.c_void_p
+//! session = ctypes.c_void_p(lib.wpinq_new())
+//! cdf = lib.wpinq_register(session, b"out_degree_cdf", weight)
+//! lib.wpinq_load_edges(session, srcs, dsts, len(edges), weight)
+//! lib.wpinq_step(session)
+//! count = lib.wpinq_observe(session, cdf, 3) / weight
+//! lib.wpinq_free(session)
+//! ```
+//!
+//! Analyses must be registered before any edges are loaded, as edges are only delivered to the
+//! dataflows that exist when they are sent.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use timely::communication::allocator::Thread;
+use timely::dataflow::ProbeHandle;
+use timely::progress::nested::product::Product;
+use timely::progress::timestamp::RootTimestamp;
+use timely::dataflow::scopes::Root;
+
+use ::{DatasetHandle, Measurement};
+use analyses::degrees;
+
+/// A registered measurement, by the type of its keys.
+enum Analysis {
+    Count(Measurement<()>),
+    Degree(Measurement<usize>),
+}
+
+/// A single-threaded worker with an edge dataset and its registered analyses.
+pub struct Session {
+    worker: Root<Thread>,
+    edges: DatasetHandle<usize, (u64, u64)>,
+    probe: ProbeHandle<Product<RootTimestamp, usize>>,
+    total: Rc<RefCell<i64>>,
+    analyses: Vec<Analysis>,
+    loaded: bool,
+    round: usize,
+}
+
+/// Creates a new session, to be released with `wpinq_free`.
+#[no_mangle]
+pub extern "C" fn wpinq_new() -> *mut Session {
+    let session = Session {
+        worker: Root::new(Thread),
+        edges: DatasetHandle::new(),
+        probe: ProbeHandle::new(),
+        total: Rc::new(RefCell::new(0)),
+        analyses: Vec::new(),
+        loaded: false,
+        round: 0,
+    };
+    Box::into_raw(Box::new(session))
+}
+
+/// Releases a session created by `wpinq_new`.
+///
+/// # Safety
+///
+/// `session` must be null or a live pointer returned by `wpinq_new`.
+#[no_mangle]
+pub unsafe extern "C" fn wpinq_free(session: *mut Session) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// Registers a standard analysis by name, returning its identifier or -1 on error.
+///
+/// The supported analyses are `edges` (the number of edges), and `out_degree_cdf`,
+/// `out_degree_seq`, `in_degree_cdf`, and `in_degree_seq`, each shaved by `width`.
+///
+/// # Safety
+///
+/// `session` must be null or a live pointer returned by `wpinq_new`, and `analysis` must be null
+/// or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn wpinq_register(session: *mut Session, analysis: *const c_char, width: i64) -> i64 {
+
+    if session.is_null() || analysis.is_null() { return -1; }
+    let session = &mut *session;
+    if session.loaded || width <= 0 { return -1; }
+
+    let name = match CStr::from_ptr(analysis).to_str() {
+        Ok(name) => name,
+        Err(_) => { return -1; }
+    };
+
+    let Session { ref mut worker, ref mut edges, ref mut probe, ref total, .. } = *session;
+
+    let analysis = match name {
+        "edges" => Analysis::Count(worker.dataflow(|scope| {
+            edges.enter(scope).count(probe, total)
+        })),
+        "out_degree_cdf" => Analysis::Degree(worker.dataflow(|scope| {
+            degrees::cdf(edges.enter(scope).map(|(src, _)| src), probe, total, width, None)
+        })),
+        "out_degree_seq" => Analysis::Degree(worker.dataflow(|scope| {
+            degrees::seq(edges.enter(scope).map(|(src, _)| src), probe, total, width, None)
+        })),
+        "in_degree_cdf" => Analysis::Degree(worker.dataflow(|scope| {
+            degrees::cdf(edges.enter(scope).map(|(_, dst)| dst), probe, total, width, None)
+        })),
+        "in_degree_seq" => Analysis::Degree(worker.dataflow(|scope| {
+            degrees::seq(edges.enter(scope).map(|(_, dst)| dst), probe, total, width, None)
+        })),
+        _ => { return -1; }
+    };
+
+    session.analyses.push(analysis);
+    (session.analyses.len() - 1) as i64
+}
+
+/// Loads `len` sensitive edges `(src[i], dst[i])`, each with weight `weight`.
+///
+/// Returns zero on success and -1 on error.
+///
+/// # Safety
+///
+/// `session` must be null or a live pointer returned by `wpinq_new`, and `src` and `dst` must each
+/// be null or point to `len` values.
+#[no_mangle]
+pub unsafe extern "C" fn wpinq_load_edges(session: *mut Session, src: *const u64, dst: *const u64, len: usize, weight: i64) -> i64 {
+    if session.is_null() || src.is_null() || dst.is_null() { return -1; }
+    let session = &mut *session;
+    let src = ::std::slice::from_raw_parts(src, len);
+    let dst = ::std::slice::from_raw_parts(dst, len);
+    session.edges.truth_from(src.iter().zip(dst.iter()).map(|(&s, &d)| ((s, d), weight)));
+    session.loaded = true;
+    0
+}
+
+/// Completes the computation on all loaded edges, so that measurements may be observed.
+///
+/// # Safety
+///
+/// `session` must be null or a live pointer returned by `wpinq_new`.
+#[no_mangle]
+pub unsafe extern "C" fn wpinq_step(session: *mut Session) {
+    if session.is_null() { return; }
+    let session = &mut *session;
+    session.round += 1;
+    session.edges.truth.advance_to(session.round);
+    session.edges.synth.advance_to(session.round);
+    while session.probe.less_than(session.edges.truth.time()) {
+        session.worker.step();
+    }
+}
+
+/// Observes the noisy count of `key` in the analysis `analysis`, or zero for unknown analyses.
+///
+/// The key is ignored by the `edges` analysis.
+///
+/// # Safety
+///
+/// `session` must be null or a live pointer returned by `wpinq_new`.
+#[no_mangle]
+pub unsafe extern "C" fn wpinq_observe(session: *mut Session, analysis: i64, key: u64) -> i64 {
+    if session.is_null() || analysis < 0 { return 0; }
+    let session = &mut *session;
+    match session.analyses.get_mut(analysis as usize) {
+        Some(&mut Analysis::Count(ref mut measurement)) => measurement.observe(()),
+        Some(&mut Analysis::Degree(ref mut measurement)) => measurement.observe(key as usize),
+        None => 0,
+    }
+}
+
+/// Reports the total error of synthetic data across all registered analyses.
+///
+/// # Safety
+///
+/// `session` must be null or a live pointer returned by `wpinq_new`.
+#[no_mangle]
+pub unsafe extern "C" fn wpinq_total_error(session: *const Session) -> i64 {
+    if session.is_null() { return 0; }
+    *(*session).total.borrow()
+}
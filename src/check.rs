@@ -0,0 +1,101 @@
+//! Runtime checks of the stability claimed by each operator.
+//!
+//! Each plan node records a stability constant, which bounds the weight its output may change by
+//! in response to changes in its inputs, and privacy costs are computed from these constants. An
+//! operator that emits more weight than it claims, for example a new reduction with an error in
+//! its retraction logic, silently voids the privacy guarantee. With the `stability-check` feature,
+//! the true records produced by each node are passed through an operator that totals their
+//! absolute weight at each timestamp, and once a timestamp completes, the worker panics if a node's
+//! total exceeds its stability times the totals of its inputs at that timestamp. Each output record
+//! is allowed one further unit of weight, for rounding.
+//!
+//! The check compares weights counted on one worker, and so is only made for dataflows run by a
+//! single worker. Consumers of replayed datasets, such as those of an `arrange::Cache` entered in
+//! several dataflows, are not checked, as the replays complete independently. The totals of every
+//! timestamp are retained, and so the check is meant for development rather than production.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use timely::Data;
+use timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Pipeline;
+
+use plan;
+
+thread_local! {
+    // the absolute weight and number of records produced by each node, at each timestamp.
+    static WEIGHTS: RefCell<HashMap<(usize, String), (i64, usize)>> = RefCell::new(HashMap::new());
+    // the nodes whose records are totalled, and whether they are replayed.
+    static NODES: RefCell<HashMap<usize, bool>> = RefCell::new(HashMap::new());
+}
+
+/// Totals the weights of `stream`, produced by `node`, and checks them against its inputs.
+pub(crate) fn stream<G: Scope, D: Data>(stream: Stream<G, (D, i64)>, node: usize) -> Stream<G, (D, i64)> {
+
+    if stream.scope().peers() > 1 || !begin(node) {
+        return stream;
+    }
+
+    stream.unary_notify(Pipeline, "CheckStability", vec![], move |input, output, notificator| {
+        input.for_each(|time, data| {
+            let key = format!("{:?}", time.time());
+            let weight = data.iter().map(|x| x.1.abs()).sum::<i64>();
+            add(node, key, weight, data.len());
+            output.session(&time).give_iterator(data.drain(..));
+            notificator.notify_at(time.retain());
+        });
+        notificator.for_each(|time, _, _| check(node, time.time()));
+    })
+}
+
+// starts totalling `node`, returning false and marking it replayed if it is already totalled.
+fn begin(node: usize) -> bool {
+    NODES.with(|nodes| {
+        let mut nodes = nodes.borrow_mut();
+        if nodes.contains_key(&node) {
+            nodes.insert(node, true);
+            false
+        }
+        else {
+            nodes.insert(node, false);
+            true
+        }
+    })
+}
+
+fn add(node: usize, key: String, weight: i64, records: usize) {
+    WEIGHTS.with(|weights| {
+        let mut weights = weights.borrow_mut();
+        let entry = weights.entry((node, key)).or_insert((0, 0));
+        entry.0 += weight;
+        entry.1 += records;
+    })
+}
+
+// panics if the weight of `node` at `time` exceeds its stability times that of its inputs.
+fn check<T: Debug>(node: usize, time: &T) {
+    let claimed = match plan::nodes().into_iter().find(|x| x.0 == node) { Some((_, claimed)) => claimed, None => return };
+    if claimed.inputs.is_empty() { return; }
+    // inputs that are replayed, or that were not totalled, cannot be compared.
+    let checked = NODES.with(|nodes| {
+        let nodes = nodes.borrow();
+        claimed.inputs.iter().all(|input| nodes.get(input) == Some(&false))
+    });
+    if !checked { return; }
+
+    let key = format!("{:?}", time);
+    WEIGHTS.with(|weights| {
+        let weights = weights.borrow();
+        let get = |id: usize| weights.get(&(id, key.clone())).cloned().unwrap_or((0, 0));
+        let (output, records) = get(node);
+        let input = claimed.inputs.iter().map(|&id| get(id).0).sum::<i64>();
+        let bound = claimed.stability * input as f64 + records as f64;
+        if output as f64 > bound {
+            panic!("node {} ({}) produced weight {} at {:?}, exceeding stability {} times the weight {} of its inputs {:?}",
+                node, claimed.name, output, time, claimed.stability, input, claimed.inputs);
+        }
+    })
+}
@@ -0,0 +1,125 @@
+//! Conversion between datasets and differential dataflow collections.
+//!
+//! Non-private preprocessing, such as joining with public reference data or computing derived
+//! attributes, may be easier with differential dataflow's incremental operators. A `Dataset` is a
+//! pair of weighted streams, of true and of synthetic records, and corresponds to a pair of
+//! collections whose differences are the weights, so that the preprocessed collections can be
+//! measured privately in the same timely computation.
+//!
+//! Differential operators do not track stability, and so a dataset formed from collections must
+//! be told the stability of the preprocessing that produced them. The same preprocessing should be
+//! applied to the true and to the synthetic records. The true collection of a dataset is returned
+//! in a `ProtectedCollection`, which offers a few record-at-a-time differential operators and a
+//! join with public collections, and is only released by forming a dataset from it again.
+
+use std::hash::Hash;
+
+use timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Pipeline;
+
+use differential_dataflow::{Collection, Data};
+use differential_dataflow::hashable::Hashable;
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::operators::Join;
+
+use {Dataset, Rounding, plan};
+
+impl<G: Scope, D: Data+Hash> Dataset<G, D> {
+
+    /// Forms a dataset from collections of true and synthetic records, with differences as weights.
+    ///
+    /// The `stability` bounds the factor by which the preprocessing producing the collections may
+    /// change them, for a change to its inputs; it is one for preprocessing that only maps and
+    /// filters each record. Each record is produced at the time of its difference, which may be
+    /// later than the time at which differential dataflow sends it.
+    pub fn from_collections(truth: &Collection<G, D, i64>, synth: &Collection<G, D, i64>, stability: f64) -> Self {
+        let node = plan::record("Collection", stability, false, &[]);
+        let truth = untimestamped(&truth.inner);
+        let synth = untimestamped(&synth.inner);
+        Dataset::derived(truth, synth, node, Rounding::default())
+    }
+
+    /// Forms a dataset from a protected collection of true records and a collection of synthetic records.
+    ///
+    /// As `from_collections`, where `stability` bounds the factor by which the preprocessing applied
+    /// to the protected collection may change it. The dataset is recorded as consuming the dataset
+    /// from which the protected collection was formed, so that its stability accumulates.
+    pub fn from_protected_collections(truth: ProtectedCollection<G, D>, synth: &Collection<G, D, i64>, stability: f64) -> Self {
+        let node = plan::record("Collection", stability, false, &[truth.node]);
+        let truth = untimestamped(&truth.collection.inner);
+        let synth = untimestamped(&synth.inner);
+        Dataset::derived(truth, synth, node, Rounding::default())
+    }
+
+    /// Converts the dataset into collections of true and synthetic records, with weights as differences.
+    ///
+    /// The true collection is sensitive, and is protected as the true stream of a dataset is. It
+    /// may be preprocessed by the methods of `ProtectedCollection`, and then measured once it is
+    /// returned to a dataset by `from_protected_collections`.
+    pub fn into_collections(self) -> (ProtectedCollection<G, D>, Collection<G, D, i64>) {
+        let truth = ProtectedCollection { collection: Collection::new(timestamped(self.truth.expose())), node: self.node };
+        (truth, Collection::new(timestamped(&self.synth)))
+    }
+}
+
+/// A collection of true records, accessible only within this crate.
+///
+/// The collection itself is never released, as any differential operator (for example `inspect`)
+/// could copy its records out. Instead, a few operators are applied on its behalf. As with the
+/// methods of `Dataset`, their closures see the true records; see the `protected` module.
+pub struct ProtectedCollection<G: Scope, D: Data> {
+    collection: Collection<G, D, i64>,
+    node: usize,
+}
+
+impl<G: Scope, D: Data> ProtectedCollection<G, D> {
+    /// Transforms each true record with `logic`.
+    pub fn map<R: Data, L: Fn(D)->R+'static>(self, logic: L) -> ProtectedCollection<G, R> {
+        ProtectedCollection { collection: self.collection.map(logic), node: self.node }
+    }
+    /// Retains the true records satisfying `predicate`.
+    pub fn filter<L: Fn(&D)->bool+'static>(self, predicate: L) -> ProtectedCollection<G, D> {
+        ProtectedCollection { collection: self.collection.filter(predicate), node: self.node }
+    }
+    /// Transforms each true record into any number of records with `logic`.
+    ///
+    /// Each record is produced with the weight of the record producing it, so that the stability
+    /// of the preprocessing is at most the largest number of records `logic` produces.
+    pub fn flat_map<I: IntoIterator, L: Fn(D)->I+'static>(self, logic: L) -> ProtectedCollection<G, I::Item> where I::Item: Data {
+        ProtectedCollection { collection: self.collection.flat_map(logic), node: self.node }
+    }
+}
+
+impl<G: Scope, K: Data+Hashable, V: Data> ProtectedCollection<G, (K, V)> where G::Timestamp: Lattice+Ord {
+    /// Joins the keyed true records with a public collection, combining matches with `logic`.
+    ///
+    /// The `public` collection is not protected, and its contents are revealed through any
+    /// measurement of the result. Each true record is produced once for each match, with its weight
+    /// multiplied by the match's difference, so that the stability of the preprocessing is at most
+    /// the largest total difference of any key in `public`.
+    pub fn join_public<V2: Data, R: Data, L: Fn(&K, &V, &V2)->R+'static>(self, public: &Collection<G, (K, V2), i64>, logic: L) -> ProtectedCollection<G, R> {
+        ProtectedCollection { collection: self.collection.join_map(public, logic), node: self.node }
+    }
+}
+
+// attaches to each record the timestamp at which it is produced.
+fn timestamped<G: Scope, D: Data>(stream: &Stream<G, (D, i64)>) -> Stream<G, (D, G::Timestamp, i64)> {
+    stream.unary(Pipeline, "Timestamp", |_,_| move |input, output| {
+        input.for_each(|time, data| {
+            let stamp = time.time().clone();
+            output.session(&time).give_iterator(data.drain(..).map(|(datum, weight)| (datum, stamp.clone(), weight)));
+        });
+    })
+}
+
+// produces each record at its timestamp, which is no earlier than the time at which it is sent.
+fn untimestamped<G: Scope, D: Data>(stream: &Stream<G, (D, G::Timestamp, i64)>) -> Stream<G, (D, i64)> {
+    stream.unary(Pipeline, "Untimestamp", |_,_| move |input, output| {
+        input.for_each(|time, data| {
+            for (datum, stamp, weight) in data.drain(..) {
+                output.session(&time.delayed(&stamp)).give((datum, weight));
+            }
+        });
+    })
+}
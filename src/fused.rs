@@ -0,0 +1,64 @@
+//! Fusion of chains of record-at-a-time transformations into single operators.
+//!
+//! Each `map` and `filter` of a `Dataset` creates an operator for the true records and another for
+//! the synthetic records, and long pipelines pay for a channel between each pair of them. A `Fused`
+//! dataset instead composes its transformations into one closure, and only creates operators once
+//! it is converted back into a dataset, typically just before an operator that exchanges records.
+//! Each transformation has stability one, as does their composition, and so the plan records one
+//! node of stability one for the whole chain.
+
+use std::rc::Rc;
+
+use timely::Data;
+use timely::dataflow::Scope;
+use timely::dataflow::operators::Map;
+
+use {Dataset, plan};
+
+/// A dataset with a pending chain of transformations of its records.
+pub struct Fused<G: Scope, D: Data, R> {
+    dataset: Dataset<G, D>,
+    logic: Rc<dyn Fn(D)->Option<R>>,
+}
+
+impl<G: Scope, D: Data> Fused<G, D, D> {
+    /// Starts an empty chain of transformations of `dataset`.
+    pub fn new(dataset: Dataset<G, D>) -> Self {
+        Fused { dataset: dataset, logic: Rc::new(Some) }
+    }
+}
+
+impl<G: Scope, D: Data, R: Data> Fused<G, D, R> {
+    /// Transforms each record using `function`.
+    pub fn map<S: Data, F: Fn(R)->S+'static>(self, function: F) -> Fused<G, D, S> {
+        let logic = self.logic;
+        Fused { dataset: self.dataset, logic: Rc::new(move |d| logic(d).map(&function)) }
+    }
+    /// Transforms each record using `function`, which borrows rather than consumes the record.
+    pub fn map_ref<S: Data, F: Fn(&R)->S+'static>(self, function: F) -> Fused<G, D, S> {
+        let logic = self.logic;
+        Fused { dataset: self.dataset, logic: Rc::new(move |d| logic(d).map(|r| function(&r))) }
+    }
+    /// Restricts the records to those satisfying `predicate`, with the defect of `Dataset::filter`.
+    pub fn filter<P: Fn(&R)->bool+'static>(self, predicate: P) -> Fused<G, D, R> {
+        let logic = self.logic;
+        Fused { dataset: self.dataset, logic: Rc::new(move |d| logic(d).filter(&predicate)) }
+    }
+    /// Transforms each record using `function`, retaining only the records it returns results for.
+    pub fn filter_map<S: Data, F: Fn(R)->Option<S>+'static>(self, function: F) -> Fused<G, D, S> {
+        let logic = self.logic;
+        Fused { dataset: self.dataset, logic: Rc::new(move |d| logic(d).and_then(&function)) }
+    }
+    /// Applies the chain of transformations, with one operator for each of the true and synthetic
+    /// records.
+    pub fn dataset(self) -> Dataset<G, R> {
+        let logic1 = self.logic;
+        let logic2 = logic1.clone();
+        Dataset::derived(
+            self.dataset.truth.expose().flat_map(move |(d,w)| logic1(d).map(|r| (r,w))),
+            self.dataset.synth.flat_map(move |(d,w)| logic2(d).map(|r| (r,w))),
+            plan::record("Fused", 1.0, false, &[self.dataset.node]),
+            self.dataset.rounding
+        )
+    }
+}
@@ -0,0 +1,124 @@
+//! Coordinated management of several input datasets.
+//!
+//! Programs over several tables create, load, advance, and close a `DatasetHandle` for each table,
+//! repeating the same few lines per table. A `DatasetGroup` owns the handles, by name, and applies
+//! each of these operations to all of them at once.
+
+use std::any::Any;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Result};
+use std::path::{Path, PathBuf};
+
+use timely::Data;
+use timely::progress::Timestamp;
+
+use DatasetHandle;
+
+/// A file of records to load into a dataset, one record per line.
+pub struct LoadSpec {
+    path: PathBuf,
+    weight: i64,
+    index: usize,
+    peers: usize,
+}
+
+impl LoadSpec {
+    /// Loads the records at `path`, each with weight `weight`.
+    pub fn new<P: AsRef<Path>>(path: P, weight: i64) -> Self {
+        LoadSpec { path: path.as_ref().to_path_buf(), weight: weight, index: 0, peers: 1 }
+    }
+    /// Loads only every `peers`-th record, starting from the `index`-th.
+    ///
+    /// With `index` and `peers` from the worker, each record is loaded by exactly one worker.
+    pub fn partition(mut self, index: usize, peers: usize) -> Self {
+        assert!(index < peers);
+        self.index = index;
+        self.peers = peers;
+        self
+    }
+}
+
+// the operations a group applies to each of its handles, independent of their record types.
+trait Member<T: Timestamp> {
+    fn advance_to(&mut self, time: T);
+    fn close(self: Box<Self>);
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Timestamp, D: Data> Member<T> for DatasetHandle<T, D> {
+    fn advance_to(&mut self, time: T) {
+        self.truth.advance_to(time.clone());
+        self.synth.advance_to(time);
+    }
+    fn close(self: Box<Self>) { (*self).close(); }
+    fn as_any(&mut self) -> &mut dyn Any { self }
+}
+
+/// A collection of named dataset handles, with a common timestamp.
+pub struct DatasetGroup<T: Timestamp> {
+    members: Vec<(String, Box<dyn Member<T>>)>,
+}
+
+impl<T: Timestamp> DatasetGroup<T> {
+
+    /// Creates an empty group.
+    pub fn new() -> Self {
+        DatasetGroup { members: Vec::new() }
+    }
+
+    /// The names of the handles in the group, in order of creation.
+    pub fn names(&self) -> Vec<&str> {
+        self.members.iter().map(|x| x.0.as_str()).collect()
+    }
+
+    /// Returns the handle named `name`, creating it if it does not exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle named `name` has records of a type other than `D`.
+    pub fn handle<D: Data>(&mut self, name: &str) -> &mut DatasetHandle<T, D> {
+        let position = match self.members.iter().position(|x| x.0 == name) {
+            Some(position) => position,
+            None => {
+                self.members.push((name.to_owned(), Box::new(DatasetHandle::<T, D>::new())));
+                self.members.len() - 1
+            }
+        };
+        self.members[position].1
+            .as_any()
+            .downcast_mut::<DatasetHandle<T, D>>()
+            .unwrap_or_else(|| panic!("dataset {:?} has a different record type", name))
+    }
+
+    /// Loads the true records of the handle named `name` from the file described by `spec`.
+    ///
+    /// Each line is converted to a record by `parse`, except for empty lines and lines starting
+    /// with `#`, which are skipped. Returns the number of records loaded by this worker.
+    pub fn load<D: Data, F: Fn(&str)->D>(&mut self, name: &str, spec: &LoadSpec, parse: F) -> Result<usize> {
+        let handle = self.handle::<D>(name);
+        let reader = BufReader::new(File::open(&spec.path)?);
+        let mut loaded = 0;
+        for (count, line) in reader.lines().enumerate() {
+            let line = line?;
+            if count % spec.peers == spec.index && !line.is_empty() && !line.starts_with('#') {
+                handle.truth.send((parse(&line), spec.weight));
+                loaded += 1;
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Advances the true and synthetic inputs of all handles to `time`.
+    pub fn advance_all(&mut self, time: T) {
+        for member in self.members.iter_mut() {
+            member.1.advance_to(time.clone());
+        }
+    }
+
+    /// Closes the true and synthetic inputs of all handles.
+    pub fn close_all(self) {
+        for (_name, member) in self.members.into_iter() {
+            member.close();
+        }
+    }
+}
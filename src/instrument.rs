@@ -0,0 +1,123 @@
+//! Privacy-safe instrumentation of operators.
+//!
+//! When enabled, operators record the number of batches and records they receive, the records and
+//! bytes they receive through exchanges, and an estimate of the size of their retained state. Only
+//! counts are recorded, never records themselves.
+//!
+//! Even counts of the true records are sensitive: the number of records a join receives, or the
+//! number of keys it retains, discloses the cardinalities of the true data without noise. Only the
+//! operators on synthetic records are instrumented, and the operators on true records are
+//! registered but never record anything. Operators are taken to act on true records unless they
+//! are constructed within `synth`, so that an operator that is not marked is never disclosed. The
+//! synthetic operators mirror the true ones, and so reveal the same performance characteristics
+//! for data of the synthetic data's size. Statistics are kept per worker thread, for each
+//! instrumented operator instance in construction order.
+
+use std::cell::{Cell, RefCell};
+use std::io::{Result, Write};
+use std::mem::size_of;
+
+use abomonation::Abomonation;
+
+/// Statistics recorded for one operator instance.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OperatorStats {
+    /// The operator name, for example `"Join"`.
+    pub name: String,
+    /// The number of input batches received.
+    pub batches: usize,
+    /// The number of input records received, across all inputs.
+    pub records: usize,
+    /// The number of input records received through exchanges, from this or other workers.
+    pub exchanged_records: usize,
+    /// The number of bytes of the records received through exchanges, as they would be serialized.
+    pub exchanged_bytes: usize,
+    /// The number of entries in the operator's state, as of its last activation.
+    pub state_entries: usize,
+    /// An estimate of the bytes in the operator's state, as of its last activation.
+    ///
+    /// This counts only the inline size of each entry, and not any heap allocations it owns.
+    pub state_bytes: usize,
+}
+
+// the statistics of an operator instance, and whether its inputs are exchanged; `None` for operators
+// on true records, which are never recorded.
+type Entry = Option<(OperatorStats, bool)>;
+
+thread_local! {
+    static ENABLED: Cell<bool> = Cell::new(false);
+    static SYNTH: Cell<bool> = Cell::new(false);
+    static STATS: RefCell<Vec<Entry>> = RefCell::new(Vec::new());
+}
+
+/// Enables recording of statistics on this worker thread.
+pub fn enable() { ENABLED.with(|x| x.set(true)); }
+
+/// Disables recording of statistics on this worker thread.
+pub fn disable() { ENABLED.with(|x| x.set(false)); }
+
+/// Indicates whether statistics are recorded on this worker thread.
+pub fn enabled() -> bool { ENABLED.with(|x| x.get()) }
+
+/// Constructs operators on synthetic records with `logic`, so that they are instrumented.
+pub(crate) fn synth<R, F: FnOnce()->R>(logic: F) -> R {
+    let prior = SYNTH.with(|x| x.replace(true));
+    let result = logic();
+    SYNTH.with(|x| x.set(prior));
+    result
+}
+
+/// Registers a new operator instance, whose inputs are exchanged if `exchange` is set.
+///
+/// Returns an identifier for the instance, which records nothing unless constructed within `synth`.
+pub(crate) fn register(name: &str, exchange: bool) -> usize {
+    let synth = SYNTH.with(|x| x.get());
+    STATS.with(|stats| {
+        let mut stats = stats.borrow_mut();
+        stats.push(if synth { Some((OperatorStats { name: name.to_owned(), ..Default::default() }, exchange)) } else { None });
+        stats.len() - 1
+    })
+}
+
+/// Records the receipt of the batch `records` by operator `id`.
+pub(crate) fn batch<T: Abomonation>(id: usize, records: &[T]) {
+    if ENABLED.with(|x| x.get()) {
+        STATS.with(|stats| {
+            if let Some((ref mut stats, exchange)) = stats.borrow_mut()[id] {
+                stats.batches += 1;
+                stats.records += records.len();
+                if exchange {
+                    stats.exchanged_records += records.len();
+                    stats.exchanged_bytes += records.iter().map(|x| size_of::<T>() + x.extent()).sum::<usize>();
+                }
+            }
+        });
+    }
+}
+
+/// Records that operator `id` retains `entries` entries of type `T`.
+pub(crate) fn state<T>(id: usize, entries: usize) {
+    if ENABLED.with(|x| x.get()) {
+        STATS.with(|stats| {
+            if let Some((ref mut stats, _exchange)) = stats.borrow_mut()[id] {
+                stats.state_entries = entries;
+                stats.state_bytes = entries * size_of::<T>();
+            }
+        });
+    }
+}
+
+/// Returns the statistics of the instrumented operator instances on this worker thread.
+pub fn snapshot() -> Vec<OperatorStats> {
+    STATS.with(|stats| stats.borrow().iter().filter_map(|x| x.as_ref().map(|y| y.0.clone())).collect())
+}
+
+/// Writes the statistics of the instrumented operator instances on this worker thread as
+/// tab-separated text.
+pub fn dump<W: Write>(mut writer: W) -> Result<()> {
+    writeln!(writer, "id\tname\tbatches\trecords\texchanged_records\texchanged_bytes\tstate_entries\tstate_bytes")?;
+    for (id, stats) in snapshot().iter().enumerate() {
+        writeln!(writer, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", id, stats.name, stats.batches, stats.records, stats.exchanged_records, stats.exchanged_bytes, stats.state_entries, stats.state_bytes)?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,82 @@
+//! Interning of categorical values as small integer codes.
+//!
+//! Categorical keys such as order priorities or ship modes are often short strings, or fixed byte
+//! arrays, repeated across many records, and are copied and hashed by every operator that
+//! exchanges them. A `Dictionary` assigns each value of a declared domain a `Code`, so that
+//! operators shuffle codes rather than values, and measurements are keyed by code.
+//!
+//! The domain must be public, as are the domains of histograms, and is never learned from the
+//! true records: a dictionary built from the values present in the data would disclose them.
+//! Values outside the domain all receive the code `other()`, so that interning retains all weight.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+use Measurement;
+
+/// The code of an interned value.
+pub type Code = u32;
+
+/// An assignment of codes to the values of a declared domain.
+#[derive(Clone, Debug)]
+pub struct Dictionary<T: Hash+Eq> {
+    values: Vec<T>,
+    codes: HashMap<T, Code>,
+}
+
+impl<T: Hash+Eq+Clone> Dictionary<T> {
+    /// Assigns codes to the values of `domain`, in order, ignoring repeated values.
+    pub fn new(domain: Vec<T>) -> Self {
+        let mut values = Vec::with_capacity(domain.len());
+        let mut codes = HashMap::with_capacity(domain.len());
+        for value in domain.into_iter() {
+            if !codes.contains_key(&value) {
+                codes.insert(value.clone(), values.len() as Code);
+                values.push(value);
+            }
+        }
+        assert!(values.len() < Code::max_value() as usize, "domain too large to intern");
+        Dictionary { values: values, codes: codes }
+    }
+    /// The code of `value`, or `other()` if it is outside the domain.
+    pub fn encode<Q: ?Sized+Hash+Eq>(&self, value: &Q) -> Code where T: Borrow<Q> {
+        self.codes.get(value).cloned().unwrap_or_else(|| self.other())
+    }
+    /// The value with code `code`, or `None` for `other()` and unassigned codes.
+    pub fn decode(&self, code: Code) -> Option<&T> {
+        self.values.get(code as usize)
+    }
+    /// The code shared by all values outside the domain.
+    pub fn other(&self) -> Code { self.values.len() as Code }
+    /// The codes of the domain values, in domain order.
+    pub fn codes(&self) -> Range<Code> { 0 .. self.other() }
+    /// The domain values, in order of their codes.
+    pub fn values(&self) -> &[T] { &self.values[..] }
+    /// The number of domain values.
+    pub fn len(&self) -> usize { self.values.len() }
+    /// Indicates whether the domain is empty.
+    pub fn is_empty(&self) -> bool { self.values.is_empty() }
+    /// Observes the noisy count of each domain value from a measurement keyed by code.
+    ///
+    /// Every domain value is observed, whether or not it is present, and the count of values
+    /// outside the domain is not reported.
+    pub fn counts(&self, measurement: &mut Measurement<Code>) -> Vec<(T, i64)> {
+        self.codes().map(|code| (self.values[code as usize].clone(), measurement.observe(code))).collect()
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_dictionary() {
+        let dictionary = super::Dictionary::new(vec!["AIR".to_owned(), "MAIL".to_owned(), "AIR".to_owned(), "SHIP".to_owned()]);
+        assert_eq!(dictionary.len(), 3);
+        assert_eq!(dictionary.encode("MAIL"), 1);
+        assert_eq!(dictionary.encode("RAIL"), dictionary.other());
+        assert_eq!(dictionary.decode(2).map(|x| x.as_str()), Some("SHIP"));
+        assert_eq!(dictionary.decode(dictionary.other()), None);
+        assert_eq!(dictionary.codes().collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,88 @@
+//! Export of synthetic graphs as edge lists, CSV, and GraphML.
+//!
+//! Each writer takes a list of directed edges `(src, dst)` whose node identifiers implement
+//! `Display`. The formats are those read by common graph libraries (NetworkX, igraph).
+
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::Path;
+
+/// Supported graph output formats.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Whitespace-separated `src dst` pairs, one per line.
+    EdgeList,
+    /// Comma-separated `source,target` pairs, with a header line.
+    Csv,
+    /// GraphML XML, with a node element for each distinct identifier.
+    GraphML,
+}
+
+impl Format {
+    /// Selects a format from a file extension, defaulting to `EdgeList`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|x| x.to_str()) {
+            Some("csv") => Format::Csv,
+            Some("graphml") | Some("xml") => Format::GraphML,
+            _ => Format::EdgeList,
+        }
+    }
+}
+
+/// Writes `edges` to the file at `path`, in the format indicated by its extension.
+pub fn save<P: AsRef<Path>, N: Display+Ord>(path: P, edges: &[(N, N)]) -> Result<()> {
+    let format = Format::from_path(&path);
+    let writer = BufWriter::new(File::create(path)?);
+    write(writer, edges, format)
+}
+
+/// Writes `edges` to `writer` in `format`.
+pub fn write<W: Write, N: Display+Ord>(writer: W, edges: &[(N, N)], format: Format) -> Result<()> {
+    match format {
+        Format::EdgeList => write_edge_list(writer, edges),
+        Format::Csv => write_csv(writer, edges),
+        Format::GraphML => write_graphml(writer, edges),
+    }
+}
+
+/// Writes `edges` as tab-separated pairs, one per line.
+pub fn write_edge_list<W: Write, N: Display>(mut writer: W, edges: &[(N, N)]) -> Result<()> {
+    for &(ref src, ref dst) in edges.iter() {
+        writeln!(writer, "{}\t{}", src, dst)?;
+    }
+    writer.flush()
+}
+
+/// Writes `edges` as comma-separated pairs, preceded by a `source,target` header.
+pub fn write_csv<W: Write, N: Display>(mut writer: W, edges: &[(N, N)]) -> Result<()> {
+    writeln!(writer, "source,target")?;
+    for &(ref src, ref dst) in edges.iter() {
+        writeln!(writer, "{},{}", src, dst)?;
+    }
+    writer.flush()
+}
+
+/// Writes `edges` as a directed GraphML document.
+pub fn write_graphml<W: Write, N: Display+Ord>(mut writer: W, edges: &[(N, N)]) -> Result<()> {
+
+    let mut nodes = BTreeSet::new();
+    for &(ref src, ref dst) in edges.iter() {
+        nodes.insert(src);
+        nodes.insert(dst);
+    }
+
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">")?;
+    writeln!(writer, "  <graph id=\"G\" edgedefault=\"directed\">")?;
+    for node in nodes {
+        writeln!(writer, "    <node id=\"n{}\"/>", node)?;
+    }
+    for &(ref src, ref dst) in edges.iter() {
+        writeln!(writer, "    <edge source=\"n{}\" target=\"n{}\"/>", src, dst)?;
+    }
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")?;
+    writer.flush()
+}
@@ -0,0 +1,4 @@
+//! Writers for exporting synthetic data and noisy results.
+
+pub mod graph;
+pub mod sink;
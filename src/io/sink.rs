@@ -0,0 +1,128 @@
+//! Structured output of noisy results, as CSV or JSON.
+//!
+//! Every result is written as a record with the same four fields: its `kind`, such as an
+//! observation, a fitted sequence, or a synthesis summary; the `name` of the query or analysis
+//! producing it; a `key` within that result, such as a group or an index; and a numeric `value`.
+//! The schema does not depend on the results written, and so output can be read by other tools
+//! without knowing which analyses produced it.
+
+use std::io::{Result, Write};
+
+use synthesis::synthesizer::Progress;
+
+/// A destination for result records.
+pub trait Sink {
+    /// Writes one record.
+    fn record(&mut self, kind: &str, name: &str, key: &str, value: f64) -> Result<()>;
+
+    /// Writes a noisy observation of `key` in the measurement `name`.
+    fn observation(&mut self, name: &str, key: &str, value: f64) -> Result<()> {
+        self.record("observation", name, key, value)
+    }
+    /// Writes a sequence, such as a fitted cdf, keyed by index.
+    fn sequence(&mut self, name: &str, values: &[f64]) -> Result<()> {
+        for (index, value) in values.iter().enumerate() {
+            self.record("sequence", name, &index.to_string(), *value)?;
+        }
+        Ok(())
+    }
+    /// Writes the progress of a synthesis run, one record for each quantity.
+    fn summary(&mut self, name: &str, progress: &Progress) -> Result<()> {
+        let elapsed = progress.elapsed.as_secs() as f64 + progress.elapsed.subsec_nanos() as f64 / 1e9;
+        self.record("summary", name, "rounds", progress.rounds as f64)?;
+        self.record("summary", name, "accepted", progress.accepted as f64)?;
+        self.record("summary", name, "error", progress.error as f64)?;
+        self.record("summary", name, "elapsed", elapsed)
+    }
+}
+
+/// Writes records as comma-separated values, after a header line.
+pub struct Csv<W: Write> {
+    writer: W,
+    header: bool,
+}
+
+impl<W: Write> Csv<W> {
+    /// Writes records to `writer`.
+    pub fn new(writer: W) -> Self {
+        Csv { writer: writer, header: false }
+    }
+}
+
+impl<W: Write> Sink for Csv<W> {
+    fn record(&mut self, kind: &str, name: &str, key: &str, value: f64) -> Result<()> {
+        if !self.header {
+            writeln!(self.writer, "kind,name,key,value")?;
+            self.header = true;
+        }
+        writeln!(self.writer, "{},{},{},{}", csv_field(kind), csv_field(name), csv_field(key), value)?;
+        self.writer.flush()
+    }
+}
+
+/// Writes records as JSON objects, one per line.
+pub struct Json<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Json<W> {
+    /// Writes records to `writer`.
+    pub fn new(writer: W) -> Self {
+        Json { writer: writer }
+    }
+}
+
+impl<W: Write> Sink for Json<W> {
+    fn record(&mut self, kind: &str, name: &str, key: &str, value: f64) -> Result<()> {
+        // JSON has no representation of infinite or undefined numbers.
+        let value = if value.is_finite() { value.to_string() } else { "null".to_owned() };
+        writeln!(self.writer, "{{\"kind\":{},\"name\":{},\"key\":{},\"value\":{}}}", json_string(kind), json_string(name), json_string(key), value)?;
+        self.writer.flush()
+    }
+}
+
+// quotes `field` if it contains a separator, quote, or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }
+    else {
+        field.to_owned()
+    }
+}
+
+// quotes and escapes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+mod tests {
+
+    #[test]
+    fn test_formats() {
+        use super::{Sink, Csv, Json};
+
+        let mut csv = Csv::new(Vec::new());
+        csv.observation("Q04", "1-URGENT", 2.5).unwrap();
+        csv.sequence("cdf", &[3.0, 1.0]).unwrap();
+        assert_eq!(String::from_utf8(csv.writer).unwrap(), "kind,name,key,value\nobservation,Q04,1-URGENT,2.5\nsequence,cdf,0,3\nsequence,cdf,1,1\n");
+
+        let mut json = Json::new(Vec::new());
+        json.observation("Q01", "(1, \"a\")", ::std::f64::NAN).unwrap();
+        assert_eq!(String::from_utf8(json.writer).unwrap(), "{\"kind\":\"observation\",\"name\":\"Q01\",\"key\":\"(1, \\\"a\\\")\",\"value\":null}\n");
+    }
+}
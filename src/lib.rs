@@ -12,25 +12,102 @@
 //! on the timely dataflow runtime. Its goals are to reproduce the original work, and also
 //! to serve as a basis for experimentation.
 
+extern crate abomonation;
+extern crate arrayvec;
 extern crate fnv;
 extern crate rand;
 extern crate timely;
+extern crate wpinq_derive;
+#[cfg(feature = "differential")]
+extern crate differential_dataflow;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::hash::{Hash, Hasher};
 
+use rand::Rng;
 use timely::{Data, ExchangeData, Allocate};
 use timely::progress::Timestamp;
 use timely::dataflow::{Scope, Stream, ProbeHandle, InputHandle};
-use timely::dataflow::operators::{Map, Filter, Concat};
+use timely::dataflow::operators::{Map, Filter, Concat, Enter, Leave, LoopVariable, ConnectLoop, Inspect, Operator};
+use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::scopes::{Child, Root};
 
 mod operators;
 pub mod analyses;
+pub mod postprocess;
+pub mod io;
+pub mod synthesis;
+pub mod workload;
+pub mod sql;
+pub mod registry;
+pub mod repl;
+pub mod instrument;
+pub mod plan;
+pub mod noise;
+pub mod budget;
+pub mod aggregate;
+pub mod arrange;
+pub mod group;
+pub mod source;
+pub mod protected;
+pub mod record;
+pub mod intern;
+pub mod fused;
+pub mod testing;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "verify")]
+pub mod verify;
+#[cfg(feature = "stability-check")]
+mod check;
+#[cfg(feature = "differential")]
+pub mod differential;
 mod merge_sort;
+mod radix;
 
-pub use operators::measure::Measurement;
+pub use operators::measure::{Measurement, NoisyHistogram, Negative, error};
+pub use operators::rounding::Rounding;
+pub use operators::median::Median;
+pub use operators::sample::Sample;
+pub use operators::continual::Continual;
+pub use operators::window::Windowed;
+#[cfg(feature = "columnar")]
+pub use operators::columnar;
+pub use group::{DatasetGroup, LoadSpec};
+pub use protected::ProtectedStream;
+pub use wpinq_derive::WpinqRecord;
+pub use record::Record;
+
+/// A scope into which inputs with timestamp `T` can be introduced.
+///
+/// This is the top-level scope of a dataflow with timestamp `T`, where inputs are created, or any
+/// scope nested within it, whose timestamps refine `T` and into which inputs are entered.
+pub trait InputScope<T: Timestamp> : Scope {
+    /// Introduces the records of `input` into the scope.
+    fn input_stream<D: Data>(&mut self, input: &mut InputHandle<T, D>) -> Stream<Self, D>;
+}
+
+impl<'a, A: Allocate, T: Timestamp> InputScope<T> for Child<'a, Root<A>, T> {
+    fn input_stream<D: Data>(&mut self, input: &mut InputHandle<T, D>) -> Stream<Self, D> {
+        input.to_stream(self)
+    }
+}
+
+// nested scopes are matched as children of children, so that this impl cannot overlap the one above.
+impl<'a, 'b, S: Scope, T: Timestamp, T1: Timestamp, T2: Timestamp> InputScope<T> for Child<'a, Child<'b, S, T1>, T2>
+where
+    Child<'b, S, T1>: InputScope<T>,
+{
+    fn input_stream<D: Data>(&mut self, input: &mut InputHandle<T, D>) -> Stream<Self, D> {
+        let mut parent = self.parent.clone();
+        parent.input_stream(input).enter(self)
+    }
+}
 
 /// A dataflow-agnostic handle to input data.
 ///
@@ -38,9 +115,13 @@ pub use operators::measure::Measurement;
 /// the raw and sensitive data requiring protection, and "synth",
 /// which is the synthetic data that may be tested against results
 /// of computation applied to the true data.
+///
+/// A public dataset, such as a lookup table of names for codes, requires no protection: its true
+/// records are also its synthetic records, and measurements are not charged for it.
 pub struct DatasetHandle<T: Timestamp, D: Data> {
     pub truth: InputHandle<T, (D, i64)>,
     pub synth: InputHandle<T, (D, i64)>,
+    public: bool,
 }
 
 impl<T: Timestamp, D: Data> DatasetHandle<T, D> {
@@ -49,11 +130,30 @@ impl<T: Timestamp, D: Data> DatasetHandle<T, D> {
         DatasetHandle {
             truth: InputHandle::new(),
             synth: InputHandle::new(),
+            public: false,
         }
     }
+    /// Create a new empty handle for a public dataset, whose records are sent to `truth`.
+    ///
+    /// Nothing sent to `synth` is used.
+    pub fn public() -> Self {
+        DatasetHandle { public: true, .. DatasetHandle::new() }
+    }
+    /// Indicates whether the dataset is public.
+    pub fn is_public(&self) -> bool { self.public }
     /// Introduce the dataset into a dataflow scope, for computation.
-    pub fn enter<'a, A: Allocate>(&mut self, scope: &mut Child<'a, Root<A>, T>) -> Dataset<Child<'a, Root<A>, T>, D> {
-        Dataset::from(self.truth.to_stream(scope), self.synth.to_stream(scope))
+    ///
+    /// The scope may be the top-level scope of a dataflow, or any scope nested within it. The
+    /// plan node of the resulting dataset identifies the input in `plan::factors` and in
+    /// `budget::Accounts`; a public input is recorded with stability zero.
+    pub fn enter<S: InputScope<T>>(&mut self, scope: &mut S) -> Dataset<S, D> {
+        if self.public {
+            let truth = scope.input_stream(&mut self.truth);
+            Dataset::derived(truth.clone(), truth, plan::record("Input", 0.0, false, &[]), Rounding::default())
+        }
+        else {
+            Dataset::from(scope.input_stream(&mut self.truth), scope.input_stream(&mut self.synth))
+        }
     }
     /// Initialize the dataset's data from a supplied iterator.
     pub fn truth_from<I: Iterator<Item=(D,i64)>>(&mut self, iter: I) {
@@ -78,27 +178,156 @@ impl<T: Timestamp, D: Data> DatasetHandle<T, D> {
 /// The two member streams correspond to the stream of sensitive data, and to the stream
 /// of synthetic data.
 pub struct Dataset<G: Scope, D: Data> {
-    truth: Stream<G, (D, i64)>,
+    truth: ProtectedStream<G, D>,
     synth: Stream<G, (D, i64)>,
+    node: usize,
+    rounding: Rounding,
+}
+
+impl<G: Scope, D: Data> Clone for Dataset<G, D> {
+    fn clone(&self) -> Self {
+        // the streams are already instrumented, and are shared rather than derived again.
+        Dataset { truth: self.truth.clone(), synth: self.synth.clone(), node: self.node, rounding: self.rounding }
+    }
 }
 
 impl<G: Scope, D: Data> Dataset<G, D> {
 
     // Constructs a new `Dataset` from a stream of weighted elements.
     pub fn from(truth: Stream<G, (D, i64)>, synth: Stream<G, (D, i64)>) -> Self {
-        Dataset { truth: truth, synth: synth }
+        Dataset::derived(truth, synth, plan::record("Input", 1.0, false, &[]), Rounding::default())
+    }
+
+    // Constructs a `Dataset` produced by the plan node `node`.
+    fn derived(truth: Stream<G, (D, i64)>, mut synth: Stream<G, (D, i64)>, node: usize, rounding: Rounding) -> Self {
+        // record the operators producing each stream, at the addresses timely's logging reports.
+        let address = |stream: &Stream<G, (D, i64)>| { let mut address = stream.scope().addr(); address.push(stream.name().index); address };
+        plan::place(node, address(&truth), address(&synth));
+        // count the synthetic records of each node once, if instrumentation is enabled; the true
+        // records are not counted, as their number is not protected by any measurement.
+        if instrument::enabled() && plan::begin_count(node) {
+            synth = synth.inspect_batch(move |_, data| plan::count(node, data.len()));
+        }
+        // check the weight of each node against its claimed stability, if enabled.
+        #[cfg(feature = "stability-check")]
+        let truth = check::stream(truth, node);
+        Dataset { truth: ProtectedStream::new(truth), synth: synth, node: node, rounding: rounding }
+    }
+
+    /// The identifier of the plan node producing this dataset.
+    pub fn node(&self) -> usize { self.node }
+
+    /// The scope containing the dataset.
+    pub fn scope(&self) -> G { self.synth.scope() }
+
+    /// Brings the dataset into a scope nested within its own, such as the loop of `iterate`.
+    pub fn enter_scope<'a, T: Timestamp>(&self, scope: &Child<'a, G, T>) -> Dataset<Child<'a, G, T>, D> {
+        Dataset::derived(
+            self.truth.expose().enter(scope),
+            self.synth.enter(scope),
+            plan::record("Enter", 1.0, false, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// The factor by which a change to each protected input may change this dataset, by input node.
+    pub fn inputs(&self) -> Vec<(usize, f64)> { plan::factors(self.node) }
+
+    /// The factor by which a change to the input data may change this dataset.
+    ///
+    /// A measurement of this dataset with noise of scale `b` provides `stability / b` differential
+    /// privacy with respect to weight in the inputs. See `plan::stability`.
+    pub fn stability(&self) -> f64 { plan::stability(self.node) }
+
+    /// Sets the rounding used when weights are divided, for this dataset and those derived from it.
+    ///
+    /// Datasets use `Rounding::Truncate` unless otherwise specified. When two datasets are combined,
+    /// the result uses the rounding of the dataset whose method is called.
+    pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
     }
 
+    /// The rounding used when weights are divided.
+    pub fn rounding(&self) -> Rounding { self.rounding }
+
     // Transform each record using `function`.
     pub fn map<R: Data, F: Fn(D)->R+'static>(self, function: F) -> Dataset<G, R> {
         let function1 = Rc::new(function);
         let function2 = function1.clone();
-        Dataset::from(
-            self.truth.map(move |(d,w)| (function1(d), w)),
-            self.synth.map(move |(d,w)| (function2(d), w))
+        Dataset::derived(
+            self.truth.expose().map(move |(d,w)| (function1(d), w)),
+            self.synth.map(move |(d,w)| (function2(d), w)),
+            plan::record("Map", 1.0, false, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// Transforms each record using `function`, which borrows rather than consumes the record.
+    ///
+    /// Projections of a few fields out of wide records need not move, or clone, the whole record
+    /// into the function; the records of each batch are instead dropped together once it is read.
+    pub fn map_ref<R: Data, F: Fn(&D)->R+'static>(self, function: F) -> Dataset<G, R> {
+        let function1 = Rc::new(function);
+        let function2 = function1.clone();
+        Dataset::derived(
+            map_ref(self.truth.expose(), move |d| function1(d)),
+            map_ref(&self.synth, move |d| function2(d)),
+            plan::record("Map", 1.0, false, &[self.node]),
+            self.rounding
         )
     }
 
+    /// Starts a chain of transformations of the records, to be applied by a single operator.
+    ///
+    /// The transformations of the result are composed rather than applied, until its `dataset`
+    /// method applies them all with one operator for each of the true and synthetic records.
+    pub fn fuse(self) -> fused::Fused<G, D, D> {
+        fused::Fused::new(self)
+    }
+
+    /// Joins two collections, pairing records whose keys, computed by `key1` and `key2`, are equal.
+    ///
+    /// This method behaves as `join` on the records keyed by `key1` and `key2`, and so scales the
+    /// weight of each pair down by the total weight of the key, but forms the keys within the join
+    /// and discards them from its output, rather than requiring keyed records.
+    pub fn join_by<D2, K, F1, F2>(self, other: Dataset<G, D2>, key1: F1, key2: F2) -> Dataset<G, (D, D2)>
+    where
+        D: ExchangeData+Ord,
+        D2: ExchangeData+Ord,
+        K: ExchangeData+Eq+Hash,
+        F1: Fn(&D)->K+'static,
+        F2: Fn(&D2)->K+'static,
+    {
+        let key1a = Rc::new(key1);
+        let key1b = key1a.clone();
+        let key2a = Rc::new(key2);
+        let key2b = key2a.clone();
+        let truth1 = self.truth.expose().map(move |(d,w)| ((key1a(&d), d), w));
+        let truth2 = other.truth.expose().map(move |(d,w)| ((key2a(&d), d), w));
+        let synth1 = self.synth.map(move |(d,w)| ((key1b(&d), d), w));
+        let synth2 = other.synth.map(move |(d,w)| ((key2b(&d), d), w));
+        Dataset::derived(
+            operators::join::join(&truth1, &truth2, None, self.rounding).map(|((_k, pair), w)| (pair, w)),
+            instrument::synth(|| operators::join::join(&synth1, &synth2, None, self.rounding)).map(|((_k, pair), w)| (pair, w)),
+            plan::record("JoinBy", 1.0, true, &[self.node, other.node]),
+            self.rounding
+        )
+    }
+
+    /// Replaces each element by its code in `dictionary`, whose domain must be public.
+    ///
+    /// Elements outside the domain all receive the code `dictionary.other()`.
+    pub fn intern(self, dictionary: &Rc<intern::Dictionary<D>>) -> Dataset<G, intern::Code> where D: Hash+Eq {
+        let dictionary = dictionary.clone();
+        self.map(move |d| dictionary.encode(&d))
+    }
+
+    /// Pairs each element with a key computed by `function`, for use with keyed operations.
+    pub fn key_by<K: Data, F: Fn(&D)->K+'static>(self, function: F) -> Dataset<G, (K, D)> {
+        self.map(move |d| (function(&d), d))
+    }
+
     /// Restrict the collection to elements satisfying `predicate`.
     ///
     /// This has the defect that it simply drops some elements, where they should
@@ -106,31 +335,94 @@ impl<G: Scope, D: Data> Dataset<G, D> {
     pub fn filter<P: Fn(&D)->bool+'static>(self, predicate: P) -> Dataset<G, D> {
         let predicate1 = Rc::new(predicate);
         let predicate2 = predicate1.clone();
-        Dataset::from(
-            self.truth.filter(move |&(ref d,_)| (predicate1)(d)),
-            self.synth.filter(move |&(ref d,_)| (predicate2)(d))
+        Dataset::derived(
+            self.truth.expose().filter(move |&(ref d,_)| (predicate1)(d)),
+            self.synth.filter(move |&(ref d,_)| (predicate2)(d)),
+            plan::record("Filter", 1.0, false, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// Transforms each record using `function`, retaining only the records it returns results for.
+    ///
+    /// This is `filter` and `map` in a single operator, and has the same defect as `filter`, in that
+    /// the weight of records without results is simply dropped.
+    pub fn filter_map<R: Data, F: Fn(D)->Option<R>+'static>(self, function: F) -> Dataset<G, R> {
+        let function1 = Rc::new(function);
+        let function2 = function1.clone();
+        Dataset::derived(
+            self.truth.expose().flat_map(move |(d,w)| function1(d).map(|r| (r,w))),
+            self.synth.flat_map(move |(d,w)| function2(d).map(|r| (r,w))),
+            plan::record("FilterMap", 1.0, false, &[self.node]),
+            self.rounding
         )
     }
 
     /// Merges two datasets, accumulating their weights.
     pub fn concat(self, other: Self) -> Self {
-        Dataset::from(
-            self.truth.concat(&other.truth),
-            self.synth.concat(&other.synth)
+        Dataset::derived(
+            self.truth.expose().concat(other.truth.expose()),
+            self.synth.concat(&other.synth),
+            plan::record("Concat", 1.0, false, &[self.node, other.node]),
+            self.rounding
         )
     }
 
     /// Merges two datasets, subtracting their weights.
     pub fn except(self, other: Self) -> Self {
-        Dataset::from(
-            self.truth.concat(&other.truth.map(|(d,w)| (d,-w))),
-            self.synth.concat(&other.synth.map(|(d,w)| (d,-w)))
+        Dataset::derived(
+            self.truth.expose().concat(&other.truth.expose().map(|(d,w)| (d,-w))),
+            self.synth.concat(&other.synth.map(|(d,w)| (d,-w))),
+            plan::record("Except", 1.0, false, &[self.node, other.node]),
+            self.rounding
+        )
+    }
+
+    /// The total weight of the dataset, as a dataset with the single element `()`.
+    pub fn total_weight(self) -> Dataset<G, ()> {
+        Dataset::derived(
+            self.truth.expose().map(|(_,w)| ((),w)),
+            self.synth.map(|(_,w)| ((),w)),
+            plan::record("TotalWeight", 1.0, false, &[self.node]),
+            self.rounding
         )
     }
+
+    /// Measures the total weight of the dataset, observed with `observe(())`.
+    ///
+    /// A change in the weight of any records changes the total by at most the same amount, and so
+    /// the total receives the same noise, and costs the same, as a single element of `measure`.
+    /// As all weight is measured by one worker, only that worker observes the true total.
+    pub fn count(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>) -> operators::measure::Measurement<()> {
+        self.total_weight().measure(handle, total)
+    }
+
+    /// Continually releases the running total weight of the dataset, over epochs.
+    ///
+    /// Rather than measuring the total anew at each epoch, at a cost for each, the counts of
+    /// epochs are combined by the binary tree mechanism, whose running count through any epoch
+    /// has error polylogarithmic in `horizon`, the largest number of epochs that will be counted.
+    /// The epoch of each timestamp is given by `epoch`; all counts together cost the same as one
+    /// measurement with noise of scale `scale * SCALE`. Only the true data are counted.
+    pub fn count_continual<F>(self, handle: &mut ProbeHandle<G::Timestamp>, epoch: F, horizon: usize, scale: f64) -> Continual
+    where
+        F: Fn(&G::Timestamp)->usize+'static,
+    {
+        plan::record("Continual", 1.0, true, &[self.node]);
+        operators::continual::continual(&self.truth.expose().map(|(_,w)| w), handle, epoch, horizon, scale)
+    }
 }
 
 impl<G: Scope, D: ExchangeData+Ord+Hash> Dataset<G, D> {
 
+    /// Computes the dataset once, for replay into several consumers within or across dataflows.
+    ///
+    /// The records are consolidated at each timestamp and captured. Each call to `Cache::enter`
+    /// replays them, rather than repeating the computation that produced them.
+    pub fn cache(self) -> arrange::Cache<G::Timestamp, D> {
+        arrange::Cache::new(self)
+    }
+
     // Maps each element into a list of elements, distributing weight among them.
     pub fn flat_map<I, F>(self, function: F) -> Dataset<G, I::Item>
     where
@@ -140,9 +432,53 @@ impl<G: Scope, D: ExchangeData+Ord+Hash> Dataset<G, D> {
     {
         let function1 = Rc::new(function);
         let function2 = function1.clone();
-        Dataset::from(
-            operators::flat_map::flat_map(&self.truth, move |x| (*function1)(x)),
-            operators::flat_map::flat_map(&self.synth, move |x| (*function2)(x))
+        Dataset::derived(
+            operators::flat_map::flat_map(self.truth.expose(), move |x| (*function1)(x), true, self.rounding),
+            instrument::synth(|| operators::flat_map::flat_map(&self.synth, move |x| (*function2)(x), true, self.rounding)),
+            plan::record("FlatMap", 1.0, true, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// Maps each element into a list of elements with integer shares, distributing weight in
+    /// proportion to the shares.
+    ///
+    /// Each result receives the fraction `share / sum |share|` of the weight of its element, with
+    /// its sign flipped if the share is negative, so that a change in the input weight results in a
+    /// change of at most the same magnitude in the output.
+    pub fn flat_map_weighted<R, I, F>(self, function: F) -> Dataset<G, R>
+    where
+        R: Data+Eq+Hash+Clone,
+        I: IntoIterator<Item=(R, i64)>,
+        F: Fn(D)->I+'static,
+    {
+        let function1 = Rc::new(function);
+        let function2 = function1.clone();
+        Dataset::derived(
+            operators::flat_map::flat_map_weighted(self.truth.expose(), move |x| (*function1)(x), true, self.rounding),
+            instrument::synth(|| operators::flat_map::flat_map_weighted(&self.synth, move |x| (*function2)(x), true, self.rounding)),
+            plan::record("FlatMap", 1.0, true, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// As `flat_map`, but processes each record on the worker that holds it.
+    ///
+    /// This avoids a redundant shuffle when records are already distributed across workers, for
+    /// example immediately after a `join`.
+    pub fn flat_map_local<I, F>(self, function: F) -> Dataset<G, I::Item>
+    where
+        I: IntoIterator,
+        I::Item: Data+Eq+Hash+Clone,
+        F: Fn(D)->I+'static,
+    {
+        let function1 = Rc::new(function);
+        let function2 = function1.clone();
+        Dataset::derived(
+            operators::flat_map::flat_map(self.truth.expose(), move |x| (*function1)(x), false, self.rounding),
+            instrument::synth(|| operators::flat_map::flat_map(&self.synth, move |x| (*function2)(x), false, self.rounding)),
+            plan::record("FlatMap", 1.0, false, &[self.node]),
+            self.rounding
         )
     }
 
@@ -156,9 +492,167 @@ impl<G: Scope, D: ExchangeData+Ord+Hash> Dataset<G, D> {
     /// values of `index` are produced for `0 .. weight / width`, where the last `index` value
     /// may have a weight less than `width` if `weight` is not a multiple of `width`.
     pub fn shave(self, width: i64) -> Dataset<G, (D, usize)> {
-        Dataset::from(
-            operators::shave::shave(&self.truth, width),
-            operators::shave::shave(&self.synth, width)
+        Dataset::derived(
+            operators::shave::shave(self.truth.expose(), width, true),
+            instrument::synth(|| operators::shave::shave(&self.synth, width, true)),
+            plan::record("Shave", 1.0, true, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// As `shave`, but with a width for each element determined by `width`.
+    ///
+    /// Elements known to carry much weight can be shaved more coarsely, producing fewer slices,
+    /// while others are shaved finely, trading resolution for volume within a single dataset.
+    /// The width of an element must not depend on anything but the element, and must be positive.
+    pub fn shave_by<W: Fn(&D)->i64+'static>(self, width: W) -> Dataset<G, (D, usize)> {
+        let width1 = Rc::new(width);
+        let width2 = width1.clone();
+        Dataset::derived(
+            operators::shave::shave_by(self.truth.expose(), move |x| (*width1)(x), true),
+            instrument::synth(|| operators::shave::shave_by(&self.synth, move |x| (*width2)(x), true)),
+            plan::record("Shave", 1.0, true, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// As `shave`, but without exchanging records between workers.
+    ///
+    /// The dataset must already be partitioned so that all records with equal data reside on the
+    /// same worker, for example when the data are a function of the key of a preceding `join`. If
+    /// this is not the case, weights are accumulated separately on each worker and the result is
+    /// incorrect.
+    pub fn shave_local(self, width: i64) -> Dataset<G, (D, usize)> {
+        Dataset::derived(
+            operators::shave::shave(self.truth.expose(), width, false),
+            instrument::synth(|| operators::shave::shave(&self.synth, width, false)),
+            plan::record("Shave", 1.0, false, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// Divides the weight of each element by `k`, so that guarantees hold for groups of `k` records.
+    ///
+    /// When one individual may contribute up to `k` records, a change to the individual changes
+    /// `k` records, and a measurement protecting each record with epsilon protects the individual
+    /// only with `k` times epsilon. Dividing weights by `k` bounds the change of `k` records by the
+    /// change of one, and so the node is recorded with stability one, as a factor of `k` for the
+    /// group size times `1 / k` for the division: the costs accounted for measurements of derived
+    /// datasets are then those for groups. This should be applied to an input before other
+    /// operators, so that all paths from the input are accounted for groups, and weights should be
+    /// large multiples of `k`, as the divided weights are rounded by `rounding`.
+    pub fn group_privacy(self, k: i64) -> Dataset<G, D> {
+        assert!(k > 0);
+        let rounding = self.rounding;
+        let divide = move |(d,w): (D,i64)| { let w = rounding.divide(fnv_hash(&d), w as i128, k as i128); (d,w) };
+        Dataset::derived(
+            self.truth.expose().map(divide),
+            self.synth.map(divide),
+            plan::record("GroupPrivacy", 1.0, false, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// Divides the weight of each element by `k`, so that the result is `1 / k`-stable.
+    ///
+    /// Unlike `group_privacy`, the division is accounted in the plan, and reduces the cost of
+    /// measurements of derived datasets. Divided weights are rounded by `rounding`.
+    pub fn attenuate(self, k: i64) -> Dataset<G, D> {
+        assert!(k > 0);
+        let rounding = self.rounding;
+        let divide = move |(d,w): (D,i64)| { let w = rounding.divide(fnv_hash(&d), w as i128, k as i128); (d,w) };
+        Dataset::derived(
+            self.truth.expose().map(divide),
+            self.synth.map(divide),
+            plan::record("Attenuate", 1.0 / k as f64, false, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// Applies `logic` to the dataset `rounds` times, attenuating the result of each round by `k`.
+    ///
+    /// The rounds are a timely loop, in a scope nested within the dataset's, into which `logic` may
+    /// bring other datasets with `enter_scope`. The dataset at each round accumulates the records of
+    /// the rounds before it: the input, and for each round the change from its input to its result,
+    /// which is fed back to the next round. The result of the last round leaves the loop.
+    ///
+    /// The plan records the operators of `logic` once, consuming an `Iterate` node that stands for
+    /// the dataset entering each round. If a round is `a`-stable in that dataset, and has factor `b`
+    /// for other inputs, the stability of the result is accumulated over the rounds as `a` applied
+    /// `rounds` times to the input, plus `b` for each round, attenuated by the rounds after it. A
+    /// round that reads its input twice doubles the stability of its input, and an attenuation of
+    /// `2` keeps stability from growing geometrically in the number of rounds, at the cost of
+    /// halving weights each round.
+    ///
+    /// Operators apply the updates of each timestamp as it completes, and the rounds of a timestamp
+    /// are incomparable with later input timestamps. Records should only be sent at a later
+    /// timestamp once the result of the loop is complete for earlier timestamps.
+    pub fn iterate<F>(self, rounds: usize, k: i64, logic: F) -> Dataset<G, D>
+    where
+        F: for<'a> FnOnce(Dataset<Child<'a, G, u64>, D>)->Dataset<Child<'a, G, u64>, D>,
+    {
+        if rounds == 0 { return self; }
+        let rounding = self.rounding;
+        let mut scope = self.synth.scope();
+        let (truth, synth, variable, factors) = scope.scoped::<u64,_,_>(|inner| {
+            // records fed back from a round reach the next, up to the last round.
+            let (truth_handle, truth_cycle) = inner.loop_variable(rounds as u64, 1);
+            let (synth_handle, synth_cycle) = inner.loop_variable(rounds as u64, 1);
+            let truth = self.truth.expose().enter(inner).concat(&truth_cycle);
+            let synth = self.synth.enter(inner).concat(&synth_cycle);
+            // the dataset entering each round is recorded as an input, so that its factor is that of one round.
+            let variable = plan::record("Iterate", 1.0, false, &[]);
+            let result = logic(Dataset::derived(truth.clone(), synth.clone(), variable, rounding)).attenuate(k);
+            // feed back the change from each round's input to its result.
+            result.truth.expose().concat(&truth.map(|(d,w)| (d,-w))).connect_loop(truth_handle);
+            result.synth.concat(&synth.map(|(d,w)| (d,-w))).connect_loop(synth_handle);
+            (truth.leave(), synth.leave(), variable, plan::factors(result.node))
+        });
+        // the factors of the last round's result, by the recurrence `f(i+1) = a * f(i) + b`.
+        let a: f64 = factors.iter().filter(|x| x.0 == variable).map(|x| x.1).sum();
+        let attenuated: f64 = (0 .. rounds).map(|round| a.powi(round as i32)).sum();
+        let mut inputs = vec![plan::record("IterateRounds", a.powi(rounds as i32), false, &[self.node])];
+        for &(input, factor) in factors.iter().filter(|x| x.0 != variable) {
+            inputs.push(plan::record("IterateRounds", factor * attenuated, false, &[input]));
+        }
+        Dataset::derived(truth, synth, plan::record("Leave", 1.0, false, &inputs[..]), rounding)
+    }
+
+    /// Perturbs each element by randomized response over `domain`, providing `epsilon` local privacy.
+    ///
+    /// Each element is retained with probability `e^epsilon / (e^epsilon + k - 1)`, where `k` is the
+    /// size of `domain`, and is otherwise replaced by a uniformly chosen other element of `domain`.
+    /// Elements not in `domain` are replaced by a uniformly chosen element. The random choices are
+    /// derived from a secret seed and the element, so that retractions perturb exactly as the
+    /// records they retract; as a consequence, equal elements are perturbed identically, and data
+    /// about distinct individuals should be distinguishable (e.g. by an identifier that is then
+    /// discarded with `map`).
+    pub fn randomized_response(self, domain: Vec<D>, epsilon: f64) -> Dataset<G, D> {
+        assert!(!domain.is_empty());
+        assert!(epsilon >= 0.0);
+        let source = noise::next_source();
+        let keep = epsilon.exp() / (epsilon.exp() + (domain.len() as f64) - 1.0);
+        let perturb = Rc::new(move |datum: D| {
+            let mut rng = source.rng(&datum);
+            match domain.iter().position(|d| d == &datum) {
+                Some(index) => {
+                    if domain.len() == 1 || rng.gen::<f64>() < keep { datum }
+                    else {
+                        // choose uniformly among the other elements.
+                        let other = rng.gen_range(0, domain.len() - 1);
+                        domain[if other < index { other } else { other + 1 }].clone()
+                    }
+                },
+                None => domain[rng.gen_range(0, domain.len())].clone(),
+            }
+        });
+        let perturb1 = perturb.clone();
+        let perturb2 = perturb;
+        Dataset::derived(
+            self.truth.expose().map(move |(d,w)| (perturb1(d), w)),
+            self.synth.map(move |(d,w)| (perturb2(d), w)),
+            plan::record("RandomizedResponse", 1.0, false, &[self.node]),
+            self.rounding
         )
     }
 
@@ -167,14 +661,90 @@ impl<G: Scope, D: ExchangeData+Ord+Hash> Dataset<G, D> {
     /// This method is useful for finding the intersection or union, but by consuming the inputs both are
     /// produced at no additional cost.
     pub fn min_max(self, other: Self) -> (Self, Self) {
-        let (min_truth, max_truth) = operators::min_max::min_max(&self.truth, &other.truth);
-        let (min_synth, max_synth) = operators::min_max::min_max(&self.synth, &other.synth);
-        (Dataset::from(min_truth, min_synth), Dataset::from(max_truth, max_synth))
+        let (min_truth, max_truth) = operators::min_max::min_max(self.truth.expose(), other.truth.expose());
+        let (min_synth, max_synth) = instrument::synth(|| operators::min_max::min_max(&self.synth, &other.synth));
+        let min_node = plan::record("Min", 1.0, true, &[self.node, other.node]);
+        let max_node = plan::record("Max", 1.0, true, &[self.node, other.node]);
+        (Dataset::derived(min_truth, min_synth, min_node, self.rounding), Dataset::derived(max_truth, max_synth, max_node, self.rounding))
+    }
+}
+
+impl<G: Scope> Dataset<G, usize> {
+
+    /// Gathers the sensitive count of each domain element, for release by `Mwem` and `Pmw`.
+    ///
+    /// Records outside `0 .. domain` are never consulted. The counts are in units of input
+    /// records of weight `weight`, accounting for the stability of the dataset, and are only
+    /// revealed through the noisy mechanisms of the `Histogram`, each charged to an odometer. The
+    /// histogram may be used once the probe indicates completion, at worker zero.
+    pub fn histogram(self, domain: usize, weight: i64, handle: &mut ProbeHandle<G::Timestamp>) -> synthesis::mwem::Histogram {
+        let unit = (weight as f64) * self.stability();
+        plan::record("Histogram", 1.0, true, &[self.node]);
+        let weights = operators::gather::gather(self.truth.expose(), handle, "Histogram");
+        synthesis::mwem::Histogram::new(weights, domain, unit)
+    }
+}
+
+impl<G: Scope, D: Data> Dataset<G, (D, usize)> {
+
+    /// Discards the indices of shaved elements, the inverse of `shave`.
+    ///
+    /// The weights of the slices of each element accumulate back into the weight of the element,
+    /// so that a dataset may be shaved, transformed by index, and returned to weighted elements
+    /// for further joins. Slices removed by the transformation are absent from the accumulation.
+    pub fn unshave(self) -> Dataset<G, D> {
+        Dataset::derived(
+            self.truth.expose().map(|((d,_i),w)| (d,w)),
+            self.synth.map(|((d,_i),w)| (d,w)),
+            plan::record("Unshave", 1.0, false, &[self.node]),
+            self.rounding
+        )
     }
 }
 
 impl<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord> Dataset<G, (K, V1)> {
 
+    /// Discards the keys of a keyed collection, the inverse of `key_by`.
+    pub fn unkey(self) -> Dataset<G, V1> {
+        self.map(|(_k, v)| v)
+    }
+
+    /// Bounds the total weight contributed by each key, treated as a user, by `limit`.
+    ///
+    /// Each user whose records have total absolute weight exceeding `limit` has the weights of
+    /// all of its records scaled down by the same factor, so that their total is `limit`. This is
+    /// the usual preprocessing for protecting users who contribute many records, such as the
+    /// orders of a customer: protecting changes of `limit` to the bounded dataset then protects
+    /// whole users. A change to one record may change the output by twice its weight, once for
+    /// the record and once for the rescaling of the user's other records, and so the node is
+    /// recorded with stability two.
+    pub fn bound_contributions(self, limit: i64) -> Dataset<G, (K, V1)> {
+        Dataset::derived(
+            operators::bound::bound_contributions(self.truth.expose(), limit, self.rounding),
+            instrument::synth(|| operators::bound::bound_contributions(&self.synth, limit, self.rounding)),
+            plan::record("BoundContributions", 2.0, true, &[self.node]),
+            self.rounding
+        )
+    }
+
+    /// Truncates the degree of each node of a graph to `degree` edges of weight `weight`.
+    ///
+    /// The keys are nodes and the values their neighbors, one record for each edge in each
+    /// direction. Each node whose edges have total absolute weight exceeding `degree * weight` has
+    /// the weights of all of its edges scaled down by the same factor, as for `bound_contributions`,
+    /// and so each node contributes at most `degree` edges. Graphs whose degrees are at most
+    /// `degree` are unchanged, and among these a node is removed by changing at most `2 * degree`
+    /// edge records, its own and one for each neighbor. See `analyses::degrees::node_factor`.
+    pub fn truncate_degrees(self, degree: usize, weight: i64) -> Dataset<G, (K, V1)> {
+        let limit = degree as i64 * weight;
+        Dataset::derived(
+            operators::bound::bound_contributions(self.truth.expose(), limit, self.rounding),
+            instrument::synth(|| operators::bound::bound_contributions(&self.synth, limit, self.rounding)),
+            plan::record("TruncateDegrees", 2.0, true, &[self.node]),
+            self.rounding
+        )
+    }
+
     /// Joins two keyed collections, pairing values with the same keys.
     ///
     /// This method produces pairs whose weights are proportional to the product of the weights
@@ -183,9 +753,97 @@ impl<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord> Dataset<G, (K, V1)
     /// either input has a correspondingly bounded change in the output, independent of the total
     /// weight of elements in the other input.
     pub fn join<V2: ExchangeData+Ord>(self, other: Dataset<G, (K, V2)>) -> Dataset<G, (K, (V1, V2))> {
-        Dataset::from(
-            operators::join::join(&self.truth, &other.truth),
-            operators::join::join(&self.synth, &other.synth)
+        Dataset::derived(
+            operators::join::join(self.truth.expose(), other.truth.expose(), None, self.rounding),
+            instrument::synth(|| operators::join::join(&self.synth, &other.synth, None, self.rounding)),
+            plan::record("Join", 1.0, true, &[self.node, other.node]),
+            self.rounding
+        )
+    }
+
+    /// Joins two keyed collections, also producing records for values without matches.
+    ///
+    /// This method behaves as `join`, but additionally pairs each value `v1` with `None`, with weight
+    /// reflecting how far the total absolute weight of `other` for its key falls short of `unit`.
+    /// A key with no matching values produces `(k, (v1, None))` with weight `w1 * unit / (W1 + unit)`,
+    /// where `W1` is the total absolute weight of the key in `self`, and these records vanish once
+    /// `other` holds at least `unit` weight for the key. Typically `unit` is the weight of one record,
+    /// so that "unmatched" means "without any matching record".
+    ///
+    /// A change in `other` may change the output by up to twice its weight, and the operator is
+    /// accounted as 2-stable.
+    pub fn join_left<V2: ExchangeData+Ord>(self, other: Dataset<G, (K, V2)>, unit: i64) -> Dataset<G, (K, (V1, Option<V2>))> {
+        Dataset::derived(
+            operators::join::join_left(self.truth.expose(), other.truth.expose(), unit, None, self.rounding),
+            instrument::synth(|| operators::join::join_left(&self.synth, &other.synth, unit, None, self.rounding)),
+            plan::record("JoinLeft", 2.0, true, &[self.node, other.node]),
+            self.rounding
+        )
+    }
+
+    /// Combines the values of each key in two keyed collections using `logic`.
+    ///
+    /// For each key, `logic` is called with the key, and the values and weights of the key in each
+    /// collection, and should push weighted results to its output vector. The operator exchanges
+    /// records by key, maintains the values of each key, and whenever they change retracts the
+    /// prior results of `logic` and introduces the new results; the `logic` must be deterministic.
+    ///
+    /// The declared `stability` should bound the ratio of the change in output weight to the change
+    /// in input weight, and is used to account for the privacy cost of measurements; `join` is an
+    /// example with stability one. The `logic` is also given the rounding mode of this dataset and
+    /// a salt identifying the key, for use with `Rounding::divide`.
+    pub fn cogroup<V2, R, L>(self, other: Dataset<G, (K, V2)>, stability: f64, logic: L) -> Dataset<G, (K, R)>
+    where
+        V2: ExchangeData+Ord,
+        R: Data+Ord,
+        L: Fn(&K, &[(V1,i64)], &[(V2,i64)], u64, Rounding, &mut Vec<(R,i64)>)+'static,
+    {
+        let logic1 = Rc::new(logic);
+        let logic2 = logic1.clone();
+        Dataset::derived(
+            operators::join::cogroup(self.truth.expose(), other.truth.expose(), "Cogroup", None, self.rounding, move |k, l1, l2, s, r, o| logic1(k, l1, l2, s, r, o)),
+            instrument::synth(|| operators::join::cogroup(&self.synth, &other.synth, "Cogroup", None, self.rounding, move |k, l1, l2, s, r, o| logic2(k, l1, l2, s, r, o))),
+            plan::record("Cogroup", stability, true, &[self.node, other.node]),
+            self.rounding
+        )
+    }
+
+    /// Joins two keyed collections, holding the state of at most `resident` keys in memory.
+    ///
+    /// This method behaves as `join`, except that each join operator spills the state of its least
+    /// recently updated keys to a temporary file once more than `resident` keys are present, and
+    /// restores them when they are next updated. This bounds memory use for large inputs, at the
+    /// cost of disk traffic for keys that are updated after having been spilled.
+    pub fn join_with_budget<V2: ExchangeData+Ord>(self, other: Dataset<G, (K, V2)>, resident: usize) -> Dataset<G, (K, (V1, V2))> {
+        Dataset::derived(
+            operators::join::join(self.truth.expose(), other.truth.expose(), Some(resident), self.rounding),
+            instrument::synth(|| operators::join::join(&self.synth, &other.synth, Some(resident), self.rounding)),
+            plan::record("Join", 1.0, true, &[self.node, other.node]),
+            self.rounding
+        )
+    }
+}
+
+impl<G: Scope, K: ExchangeData+Ord, V1: ExchangeData+Ord> Dataset<G, (K, V1)> {
+
+    /// Joins two keyed collections whose keys are ordered, but need not be hashable.
+    ///
+    /// This method behaves as `join`, except that records are routed to workers by `partition`,
+    /// which must map equal keys to equal values, and each worker holds its keys in sorted order
+    /// and merges each sorted batch of updates into them. Keys with equal partitions share the
+    /// salts used to round their weights, and so partitions should rarely collide.
+    pub fn join_sorted<V2, P>(self, other: Dataset<G, (K, V2)>, partition: P) -> Dataset<G, (K, (V1, V2))>
+    where
+        V2: ExchangeData+Ord,
+        P: Fn(&K)->u64+'static,
+    {
+        let partition1 = Rc::new(partition);
+        let partition2 = partition1.clone();
+        Dataset::derived(
+            operators::join::join_sorted(self.truth.expose(), other.truth.expose(), move |k| partition1(k), self.rounding),
+            instrument::synth(|| operators::join::join_sorted(&self.synth, &other.synth, move |k| partition2(k), self.rounding)),
+            plan::record("JoinSorted", 1.0, true, &[self.node, other.node]),
+            self.rounding
         )
     }
 }
@@ -207,8 +865,174 @@ impl<G: Scope, D: ExchangeData+Ord+Hash> Dataset<G, D> {
     /// This method uses `handle` to communicate when results are completely populated, and interaction with
     /// the resulting measurement may not provide differential privacy if not all updates have been applied.
     pub fn measure(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>) -> operators::measure::Measurement<D> {
-        operators::measure::measure(self.truth, self.synth, handle, total)
+        self.measure_with(handle, total, error::absolute)
+    }
+
+    /// Performs a Laplace-based noisy measurement, scoring synthetic data with `error`.
+    ///
+    /// The `error` function is applied to the discrepancy `synth - truth` of each element, and the
+    /// results are accumulated in `total`. The `error` module provides absolute, squared, and Huber
+    /// objectives; `measure` uses absolute error.
+    pub fn measure_with<E>(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>, error: E) -> operators::measure::Measurement<D>
+    where
+        E: Fn(i64)->i64+'static,
+    {
+        plan::record("Measure", 1.0, true, &[self.node]);
+        operators::measure::measure(self.truth.into_stream(), self.synth, handle, total, error, 1.0, true)
     }
+
+    /// As `measure`, but with noise of scale `scale * noise::SCALE`.
+    ///
+    /// Measurements of different accuracy can share a total: coarse statistics can use a large
+    /// scale and spend little privacy, while fine statistics use a small scale. Discrepancies are
+    /// divided by `scale` before they are scored, so that more accurate measurements are weighted
+    /// more heavily in `total`.
+    pub fn measure_scaled(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>, scale: f64) -> operators::measure::Measurement<D> {
+        plan::record("Measure", 1.0, true, &[self.node]);
+        operators::measure::measure(self.truth.into_stream(), self.synth, handle, total, error::absolute, scale, true)
+    }
+
+    /// As `measure_with`, but without exchanging records between workers.
+    ///
+    /// The dataset must already be partitioned so that all true and synthetic records with equal
+    /// data reside on the same worker. Otherwise an element would be measured, with independent
+    /// noise, by several workers, which weakens the privacy guarantee. Synthetic data are scored
+    /// with `error`, for example `error::absolute` as `measure` does.
+    pub fn measure_local<E>(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>, error: E) -> operators::measure::Measurement<D>
+    where
+        E: Fn(i64)->i64+'static,
+    {
+        plan::record("Measure", 1.0, false, &[self.node]);
+        operators::measure::measure(self.truth.into_stream(), self.synth, handle, total, error, 1.0, false)
+    }
+
+    /// As `measure`, but observable from any worker of a computation spanning several processes.
+    ///
+    /// Each worker sends the elements it wants to observe as `queries`, and reads their noisy
+    /// counts with `Measurement::answer` once `handle` passes the time of the query. The error
+    /// accumulated in `total` is that of all workers, rather than of this worker's elements, and
+    /// so each worker sees the same total once `handle` passes a timestamp.
+    pub fn measure_cluster(self, queries: &Stream<G, D>, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>) -> operators::measure::Measurement<D> {
+        plan::record("Measure", 1.0, true, &[self.node]);
+        operators::measure::measure_cluster(self.truth.into_stream(), self.synth, queries, handle, total, error::absolute, 1.0)
+    }
+
+    /// As `measure`, but with every element measured at worker zero, where all may be observed.
+    ///
+    /// Programs that report their results from a single worker can observe any element there,
+    /// whichever workers held the records. Other workers observe only noise, and the error in their
+    /// `total` remains zero.
+    pub fn measure_gathered(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>) -> operators::measure::Measurement<D> {
+        plan::record("Measure", 1.0, true, &[self.node]);
+        operators::measure::measure_gathered(self.truth.into_stream(), self.synth, handle, total, error::absolute, 1.0)
+    }
+
+    /// Performs a Laplace-based noisy measurement, charging its privacy cost to `odometer`.
+    ///
+    /// The cost is `budget::epsilon(self.stability(), weight)`, where `weight` is the largest weight
+    /// of any input record. If the odometer cannot afford the cost, no measurement is constructed
+    /// and an error is returned.
+    pub fn measure_charged(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>, odometer: &mut budget::Odometer, weight: i64) -> Result<operators::measure::Measurement<D>, budget::Exhausted> {
+        odometer.charge("Measure", budget::epsilon(self.stability(), weight))?;
+        Ok(self.measure(handle, total))
+    }
+
+    /// Performs a noisy measurement costing `epsilon`, charged to `odometer`.
+    ///
+    /// The noise scale is chosen by `budget::scale` from `epsilon`, `self.stability()`, and
+    /// `weight`, the largest weight of any input record. If the odometer cannot afford `epsilon`,
+    /// no measurement is constructed and an error is returned.
+    pub fn measure_epsilon(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>, odometer: &mut budget::Odometer, weight: i64, epsilon: f64) -> Result<operators::measure::Measurement<D>, budget::Exhausted> {
+        odometer.charge("Measure", epsilon)?;
+        let scale = budget::scale(self.stability(), weight, epsilon);
+        Ok(self.measure_scaled(handle, total, scale))
+    }
+
+    /// Performs a noisy measurement accurate to `accuracy` input records, charged to `odometer`.
+    ///
+    /// The noise has scale `accuracy` times `weight`, the largest weight of any input record, and
+    /// the epsilon this requires is determined by the stability of the pipeline, by
+    /// `plan::epsilon`. If the odometer cannot afford it, no measurement is constructed and an
+    /// error naming the plan node, its stability, and the requested accuracy is returned, rather
+    /// than a measurement too noisy to be of use.
+    pub fn measure_accurate(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>, odometer: &mut budget::Odometer, weight: i64, accuracy: f64) -> Result<operators::measure::Measurement<D>, budget::Exhausted> {
+        let stability = self.stability();
+        let epsilon = plan::epsilon(self.node, accuracy);
+        let name = format!("Measure of node {} (stability {}, accuracy {})", self.node, stability, accuracy);
+        odometer.charge(&name, epsilon)?;
+        let scale = budget::scale(stability, weight, epsilon);
+        Ok(self.measure_scaled(handle, total, scale))
+    }
+
+    /// Performs a noisy measurement with noise scale `scale`, charging each protected input.
+    ///
+    /// Each input is charged `budget::epsilon(factor, weight) / scale` to its odometer in
+    /// `accounts`, where `factor` is as reported by `inputs` and `weight` is the largest weight of
+    /// any of its records, so that inputs with different budgets are each charged only for their
+    /// own contribution, and public inputs are not charged. If any odometer cannot afford its
+    /// cost, nothing is charged, no measurement is constructed, and an error is returned.
+    pub fn measure_accounted(self, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>, accounts: &mut budget::Accounts, weight: i64, scale: f64) -> Result<operators::measure::Measurement<D>, budget::Exhausted> {
+        assert!(scale > 0.0);
+        let costs = self.inputs().into_iter().map(|(input, factor)| (input, budget::epsilon(factor, weight) / scale)).collect::<Vec<_>>();
+        let name = format!("Measure of node {}", self.node);
+        accounts.charge(&name, &costs[..])?;
+        Ok(self.measure_scaled(handle, total, scale))
+    }
+
+    /// Measures the weights of elements over sliding windows of recent epochs.
+    ///
+    /// The epoch of each timestamp is given by `epoch`, and windows of up to `window` complete
+    /// epochs may be observed, with noise of scale `scale * SCALE`; the contributions of older
+    /// epochs are retired, so that the state is bounded for continuously arriving data. Elements
+    /// are exchanged by hash, and each is only observed correctly at the worker that holds it.
+    /// Only the true data are measured. See `Windowed` for the cost of repeated observations.
+    pub fn measure_window<F>(self, handle: &mut ProbeHandle<G::Timestamp>, epoch: F, window: usize, scale: f64) -> Windowed<D>
+    where
+        F: Fn(&G::Timestamp)->usize+'static,
+    {
+        plan::record("Window", 1.0, true, &[self.node]);
+        operators::window::window(self.truth.expose(), handle, epoch, window, scale)
+    }
+
+    /// Prepares a private median of the sensitive data, by the exponential mechanism over gaps.
+    ///
+    /// Medians of skewed numeric data are poorly served by noisy histograms, whose counts near the
+    /// median are small relative to the noise. The resulting `Median` may be observed once the probe
+    /// indicates completion, with a function from records to numbers, and each observation costs
+    /// the epsilon it is given. It also supports clipped means, by `Median::observe_mean`. The
+    /// synthetic data are not consulted.
+    pub fn median(self, handle: &mut ProbeHandle<G::Timestamp>) -> Median<D> {
+        plan::record("Median", 1.0, true, &[self.node]);
+        operators::median::median(self.truth.expose(), handle)
+    }
+
+    /// Accumulates the synthetic records, for drawing weighted samples of them.
+    ///
+    /// This allows a smaller extract of the synthetic data to be published, without tracking the
+    /// synthetic records separately. The resulting `Sample` reflects the synthetic records once the
+    /// probe indicates completion. The true data are not consulted, and so sampling costs nothing.
+    pub fn sample_synthetic(self, handle: &mut ProbeHandle<G::Timestamp>) -> Sample<D> {
+        plan::record("Sample", 0.0, true, &[self.node]);
+        instrument::synth(|| operators::sample::sample(&self.synth, handle))
+    }
+
+    /// Performs a Laplace-based noisy measurement over an explicit, public domain.
+    ///
+    /// The resulting histogram is indexed by position in `domain`, and can export the noisy counts
+    /// of all domain elements. This is appropriate when the domain is small and publicly
+    /// known, for example a set of status flags or priorities.
+    pub fn measure_histogram(self, domain: Vec<D>, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>) -> NoisyHistogram<D> {
+        NoisyHistogram::new(self.measure(handle, total), domain)
+    }
+}
+
+/// Applies `function` to references to the records of `stream`.
+fn map_ref<G: Scope, D: Data, R: Data, F: Fn(&D)->R+'static>(stream: &Stream<G, (D,i64)>, function: F) -> Stream<G, (R,i64)> {
+    stream.unary(Pipeline, "MapRef", |_,_| move |input, output| {
+        input.for_each(|time, data| {
+            output.session(&time).give_iterator(data.iter().map(|&(ref d, w)| (function(d), w)));
+        });
+    })
 }
 
 /// Compute a FNV hash of an `element` implementing `Hash`.
@@ -219,8 +1043,12 @@ fn fnv_hash<T: Hash>(element: &T) -> u64 {
 }
 
 /// Consolidates a disordered collection of `(T, i64)` pairs.
-fn consolidate<T: Ord>(list: &mut Vec<(T,i64)>) {
-    list.sort_unstable_by(|x,y| x.0.cmp(&y.0));
+///
+/// Lists of unsigned integers are radix sorted, and others sorted by comparison.
+fn consolidate<T: Ord+'static>(list: &mut Vec<(T,i64)>) {
+    if !radix::sort(list) {
+        list.sort_unstable_by(|x,y| x.0.cmp(&y.0));
+    }
     for index in 1 .. list.len() {
         if list[index-1].0 == list[index].0 {
             list[index].1 += list[index-1].1;
@@ -60,15 +60,20 @@ unsafe fn push_unchecked<T>(vec: &mut Vec<T>, element: T) {
     vec.set_len(len + 1);
 }
 
+// batches with at least this many records are sorted, and lists merged, in parallel.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 1 << 16;
+
 pub struct MergeSorter<T: Ord> {
     queue: Vec<Vec<Vec<(T, i64)>>>,    // each power-of-two length list of allocations.
     stash: Vec<Vec<(T, i64)>>,
+    lists: Vec<Vec<Vec<(T, i64)>>>,    // emptied lists of allocations, for reuse.
 }
 
-impl<T: Ord> MergeSorter<T> {
+impl<T: Ord+Send> MergeSorter<T> {
 
     #[inline]
-    pub fn new() -> Self { MergeSorter { queue: Vec::new(), stash: Vec::new() } }
+    pub fn new() -> Self { MergeSorter { queue: Vec::new(), stash: Vec::new(), lists: Vec::new() } }
 
     #[inline(never)]
     pub fn _sort(&mut self, list: &mut Vec<Vec<(T, i64)>>) {
@@ -89,7 +94,7 @@ impl<T: Ord> MergeSorter<T> {
         };
 
         if batch.len() > 0 {
-            batch.sort_unstable_by(|x,y| x.0.cmp(&y.0));
+            sort(&mut batch);
             for index in 1 .. batch.len() {
                 if batch[index].0 == batch[index - 1].0 {
                     batch[index].1 = batch[index].1 + batch[index - 1].1;
@@ -122,6 +127,14 @@ impl<T: Ord> MergeSorter<T> {
 
     #[inline(never)]
     pub fn finish_into(&mut self, target: &mut Vec<Vec<(T, i64)>>) {
+        #[cfg(feature = "parallel")]
+        {
+            let records: usize = self.queue.iter().flat_map(|list| list.iter()).map(|batch| batch.len()).sum();
+            if self.queue.len() > 2 && records >= PARALLEL_THRESHOLD {
+                let queue = ::std::mem::replace(&mut self.queue, Vec::new());
+                self.queue.push(merge_parallel(queue));
+            }
+        }
         while self.queue.len() > 1 {
             let list1 = self.queue.pop().unwrap();
             let list2 = self.queue.pop().unwrap();
@@ -140,8 +153,8 @@ impl<T: Ord> MergeSorter<T> {
 
         use std::cmp::Ordering;
 
-        // TODO: `list1` and `list2` get dropped; would be better to reuse?
-        let mut output = Vec::with_capacity(list1.len() + list2.len());
+        let mut output = self.lists.pop().unwrap_or_else(Vec::new);
+        output.reserve(list1.len() + list2.len());
         let mut result = self.stash.pop().unwrap_or_else(|| Vec::with_capacity(1024));
 
         let mut list1 = VecQueue::from(list1);
@@ -212,6 +225,36 @@ impl<T: Ord> MergeSorter<T> {
             output.push(list2.pop());
         }
 
+        // retain emptied lists for reuse as outputs; each merge requires only one.
+        if self.lists.len() < 2 {
+            self.lists.push(list1.done());
+            self.lists.push(list2.done());
+        }
+
         output
     }
 }
+
+// sorts `batch` by its data, in parallel if it is large and the `parallel` feature is enabled.
+#[cfg(feature = "parallel")]
+fn sort<T: Ord+Send>(batch: &mut Vec<(T, i64)>) {
+    use rayon::slice::ParallelSliceMut;
+    if batch.len() >= PARALLEL_THRESHOLD { batch.par_sort_unstable_by(|x,y| x.0.cmp(&y.0)); }
+    else { batch.sort_unstable_by(|x,y| x.0.cmp(&y.0)); }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn sort<T: Ord+Send>(batch: &mut Vec<(T, i64)>) {
+    batch.sort_unstable_by(|x,y| x.0.cmp(&y.0));
+}
+
+// merges sorted lists by recursively merging each half of `lists` in parallel.
+#[cfg(feature = "parallel")]
+fn merge_parallel<T: Ord+Send>(mut lists: Vec<Vec<Vec<(T, i64)>>>) -> Vec<Vec<(T, i64)>> {
+    if lists.len() == 1 {
+        return lists.pop().unwrap();
+    }
+    let other = lists.split_off(lists.len() / 2);
+    let (list1, list2) = ::rayon::join(|| merge_parallel(lists), || merge_parallel(other));
+    MergeSorter::new().merge_by(list1, list2)
+}
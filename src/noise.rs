@@ -0,0 +1,234 @@
+//! Sources of noise for measurements.
+//!
+//! Noise is derived deterministically from a secret seed and the serialized measured element,
+//! rather than drawn and stored when an element is first observed. Repeated observations of an
+//! element see the same noise, and elements that have never been observed or updated require no
+//! storage at all.
+//!
+//! By default each measurement draws a fresh random seed. A run-level seed may instead be set on
+//! each worker with `seed`, from which each measurement derives its own seed by its position in
+//! the order of construction. As workers construct the same dataflows in the same order, the noise
+//! for an element is then the same regardless of which worker owns it, and runs with the same seed
+//! report the same noisy values for any number of workers.
+
+use std::cell::Cell;
+use std::hash::{Hash, Hasher};
+
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+
+/// The scale of the Laplace noise added to measurements.
+pub const SCALE: i64 = ::std::i32::MAX as i64;
+
+thread_local! {
+    static SEED: Cell<Option<[u8; 32]>> = Cell::new(None);
+    static MEASUREMENTS: Cell<u64> = Cell::new(0);
+}
+
+/// Sets the run-level seed for measurements subsequently constructed on this worker thread.
+///
+/// All workers should supply the same secret seed, before constructing any dataflows.
+pub fn seed(seed: [u8; 32]) {
+    SEED.with(|x| x.set(Some(seed)));
+    MEASUREMENTS.with(|x| x.set(0));
+}
+
+/// Clears the run-level seed, so that each measurement draws a fresh random seed.
+pub fn unseed() {
+    SEED.with(|x| x.set(None));
+}
+
+/// The noise source for the next measurement constructed on this worker thread.
+pub(crate) fn next_source() -> NoiseSource {
+    let index = MEASUREMENTS.with(|x| { let index = x.get(); x.set(index + 1); index });
+    match SEED.with(|x| x.get()) {
+        Some(seed) => NoiseSource::from_seed(seed).derive(index),
+        None => NoiseSource::new(),
+    }
+}
+
+/// A keyed source of per-element noise.
+///
+/// Noise for each element is drawn from a cryptographically secure generator, whose seed is derived
+/// from the secret seed and the element by a keyed cascade over the element's serialization: the
+/// bytes its `Hash` implementation writes, followed by their length. Each 32 byte block of these
+/// is combined with the running seed, which is replaced by the first output of a generator seeded
+/// with the combination. Distinct elements therefore receive independent noise, unless their
+/// serializations are equal, rather than whenever a 64 bit hash of them collides. The seed must
+/// remain secret: anyone who knows it can subtract the noise from measurements.
+#[derive(Clone)]
+pub struct NoiseSource {
+    seed: [u8; 32],
+}
+
+impl NoiseSource {
+    /// Creates a noise source with a freshly drawn random seed.
+    pub fn new() -> Self {
+        let mut seed = [0u8; 32];
+        ::rand::thread_rng().fill_bytes(&mut seed);
+        NoiseSource::from_seed(seed)
+    }
+    /// Creates a noise source from a secret `seed`.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        NoiseSource { seed: seed }
+    }
+    /// An independent noise source determined by this source and `index`.
+    pub fn derive(&self, index: u64) -> NoiseSource {
+        let mut seed = [0u8; 32];
+        self.rng(&("derive", index)).fill_bytes(&mut seed);
+        NoiseSource::from_seed(seed)
+    }
+    /// A generator determined by the seed and `element`.
+    pub fn rng<T: Hash>(&self, element: &T) -> StdRng {
+        let mut bytes = Serializer { bytes: Vec::new() };
+        element.hash(&mut bytes);
+        let bytes = bytes.bytes;
+        let mut length = [0u8; 32];
+        for index in 0 .. 8 {
+            length[index] = ((bytes.len() as u64) >> (8 * index)) as u8;
+        }
+        let mut seed = self.seed;
+        for block in bytes.chunks(32).chain(Some(&length[..])) {
+            for (seed, byte) in seed.iter_mut().zip(block.iter()) { *seed ^= *byte; }
+            StdRng::from_seed(seed).fill_bytes(&mut seed);
+        }
+        StdRng::from_seed(seed)
+    }
+    /// Laplace noise for `element`, with scale `SCALE`.
+    pub fn laplace<T: Hash>(&self, element: &T) -> i64 {
+        laplace_from(&mut self.rng(element))
+    }
+}
+
+// collects the bytes an element writes as it is hashed, as its serialization.
+struct Serializer {
+    bytes: Vec<u8>,
+}
+
+impl Hasher for Serializer {
+    fn write(&mut self, bytes: &[u8]) { self.bytes.extend_from_slice(bytes); }
+    fn finish(&self) -> u64 { panic!("serializers do not hash") }
+}
+
+/// Generates a sample from the Laplace distribution with scale `SCALE`.
+pub fn laplace() -> i64 {
+    laplace_from(&mut ::rand::thread_rng())
+}
+
+/// Generates a sample from the Laplace distribution with scale `SCALE`, using `rng`.
+pub fn laplace_from<R: Rng+?Sized>(rng: &mut R) -> i64 {
+    // TODO: Replace with independent bit flipping.
+    let logarithm: f64 = rng.gen::<f64>().ln();
+    let result = (logarithm * (SCALE as f64)) as i64;
+    if rng.gen() { result } else { -result }
+}
+
+/// Generates a sample from the Laplace distribution with scale `scale`, using `rng`.
+pub fn laplace_scaled<R: Rng+?Sized>(scale: f64, rng: &mut R) -> f64 {
+    (laplace_from(rng) as f64) * scale / (SCALE as f64)
+}
+
+/// Generates a sample from the discrete Gaussian distribution with variance parameter `num / den`.
+///
+/// The sample is exact, following Canonne, Kamath, and Steinke: integers are proposed from a
+/// discrete Laplace distribution and accepted with a probability evaluated by rational arithmetic,
+/// and so the sampler uses no floating point arithmetic. Each integer `x` is produced with
+/// probability proportional to `exp(-x^2 / (2 * num / den))`.
+pub fn discrete_gaussian_from<R: Rng+?Sized>(num: u64, den: u64, rng: &mut R) -> i64 {
+    assert!(num > 0 && den > 0);
+    let (num, den) = (num as u128, den as u128);
+    // the integer scale of the discrete Laplace proposal, `floor(sigma) + 1`.
+    let mut floor = (num as f64 / den as f64).sqrt() as u128;
+    while floor * floor * den > num { floor -= 1; }
+    while (floor + 1) * (floor + 1) * den <= num { floor += 1; }
+    let t = floor + 1;
+    loop {
+        let y = discrete_laplace_from(t as u64, rng);
+        // accept with probability `exp(-(|y| - sigma^2/t)^2 / (2 sigma^2))`.
+        let offset = (y.abs() as i128) * (den * t) as i128 - num as i128;
+        let exponent_num = (offset * offset) as u128;
+        let exponent_den = 2 * num * den * t * t;
+        if bernoulli_exp(exponent_num, exponent_den, rng) {
+            return y;
+        }
+    }
+}
+
+/// Generates a sample from the discrete Laplace distribution with scale `scale`.
+///
+/// Each integer `x` is produced with probability proportional to `exp(-|x| / scale)`.
+pub fn discrete_laplace_from<R: Rng+?Sized>(scale: u64, rng: &mut R) -> i64 {
+    assert!(scale > 0);
+    let scale = scale as u128;
+    loop {
+        // the remainder of the magnitude modulo `scale`, and then its quotient by `scale`.
+        let remainder = uniform_below(scale, rng);
+        if !bernoulli_exp(remainder, scale, rng) { continue; }
+        let mut quotient = 0;
+        while bernoulli_exp(1, 1, rng) { quotient += 1; }
+        let magnitude = remainder + scale * quotient;
+        let negative = uniform_below(2, rng) == 1;
+        // zero would otherwise be produced with both signs.
+        if negative && magnitude == 0 { continue; }
+        return if negative { -(magnitude as i64) } else { magnitude as i64 };
+    }
+}
+
+// a bernoulli sample with probability `exp(-num / den)`.
+fn bernoulli_exp<R: Rng+?Sized>(num: u128, den: u128, rng: &mut R) -> bool {
+    let mut num = num;
+    // each whole unit of the exponent is an independent trial with probability `exp(-1)`.
+    while num > den {
+        if !bernoulli_exp(1, 1, rng) { return false; }
+        num -= den;
+    }
+    // for `num / den` at most one, the parity of the first failure of trials with probabilities
+    // `gamma / k` for `k = 1, 2, ..`.
+    let mut k = 1;
+    while uniform_below(den * k, rng) < num { k += 1; }
+    k % 2 == 1
+}
+
+// a uniform sample from `0 .. bound`, by rejection from the smallest enclosing power of two.
+fn uniform_below<R: Rng+?Sized>(bound: u128, rng: &mut R) -> u128 {
+    assert!(bound > 0);
+    let bits = 128 - (bound - 1).leading_zeros();
+    loop {
+        let sample = ((rng.next_u64() as u128) << 64) | rng.next_u64() as u128;
+        let sample = if bits == 0 { 0 } else { sample >> (128 - bits) };
+        if sample < bound { return sample; }
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_source() {
+        use super::NoiseSource;
+        let source = NoiseSource::from_seed([3u8; 32]);
+        assert_eq!(source.laplace(&("key", 7u64)), source.laplace(&("key", 7u64)));
+        assert!(source.laplace(&("key", 7u64)) != source.laplace(&("key", 8u64)));
+        // elements whose serializations share 32 byte prefixes are still distinguished.
+        let long = |last: u8| { let mut x = vec![0u8; 100]; x[99] = last; x };
+        assert!(source.laplace(&long(1)) != source.laplace(&long(2)));
+        assert!(source.laplace(&vec![0u8; 32]) != source.laplace(&vec![0u8; 31]));
+    }
+
+    #[test]
+    fn test_discrete_gaussian() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let mut rng = StdRng::from_seed([7u8; 32]);
+        let samples = (0 .. 20000).map(|_| super::discrete_gaussian_from(9, 1, &mut rng)).collect::<Vec<_>>();
+        let mean = samples.iter().sum::<i64>() as f64 / samples.len() as f64;
+        let variance = samples.iter().map(|x| (x * x) as f64).sum::<f64>() / samples.len() as f64;
+        assert!(mean.abs() < 0.1);
+        assert!((variance - 9.0).abs() < 0.5);
+
+        // the probability of zero is `1 / sum_x exp(-x^2 / 2)`, about 0.399.
+        let mut rng = StdRng::from_seed([9u8; 32]);
+        let unit = (0 .. 20000).filter(|_| super::discrete_gaussian_from(1, 1, &mut rng) == 0).count() as f64 / 20000.0;
+        assert!((unit - 0.399).abs() < 0.02);
+    }
+}
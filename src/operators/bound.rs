@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Exchange;
+
+use super::super::{consolidate, fnv_hash};
+use super::rounding::Rounding;
+use instrument;
+
+pub fn bound_contributions<G: Scope, U: ExchangeData+Eq+Hash, D: ExchangeData+Ord>(
+    stream: &Stream<G, ((U, D), i64)>,
+    limit: i64,
+    rounding: Rounding) -> Stream<G, ((U, D), i64)>
+{
+    // Bounds the total absolute weight of the records of each user by `limit`, by scaling the
+    // weights of each user whose total exceeds `limit` by `limit / total`. Each user's records
+    // are held by one worker, and on each change to a user the prior output is retracted and the
+    // new output introduced, as in `cogroup`.
+    //
+    // A change to the weight of one record changes the scaled weights by at most twice as much,
+    // once for the record itself and once for the rescaling of the user's other records.
+    assert!(limit > 0);
+
+    let exchange = Exchange::new(|x: &((U,D),i64)| fnv_hash(&(x.0).0));
+
+    stream.unary(exchange, "BoundContributions", move |_,_| {
+
+        let id = instrument::register("BoundContributions", true);
+        let mut output_stash = Vec::new();
+        let mut state = HashMap::<U, Vec<(D,i64)>>::new();
+
+        move |input, output| {
+            while let Some((time, data)) = input.next() {
+                instrument::batch(id, &data[..]);
+                let mut session = output.session(&time);
+                for ((user, datum), delta) in data.drain(..) {
+                    let salt = fnv_hash(&user);
+                    let list = state.entry(user.clone()).or_insert_with(Vec::new);
+
+                    // compute old output, then negate.
+                    bound_helper(&list[..], limit, salt, rounding, &mut output_stash);
+                    for pair in output_stash.iter_mut() { pair.1 *= -1; }
+
+                    // apply update.
+                    list.push((datum, delta));
+                    consolidate(list);
+
+                    // compute new output, don't negate.
+                    bound_helper(&list[..], limit, salt, rounding, &mut output_stash);
+                    let empty = list.is_empty();
+
+                    consolidate(&mut output_stash);
+                    for (datum, delta) in output_stash.drain(..) {
+                        session.give(((user.clone(), datum), delta));
+                    }
+
+                    // discard state for users with no remaining records.
+                    if empty { state.remove(&user); }
+                }
+            }
+
+            instrument::state::<(U, Vec<(D,i64)>)>(id, state.len());
+        }
+    })
+}
+
+fn bound_helper<D: Ord+Clone>(
+    list: &[(D,i64)],
+    limit: i64,
+    salt: u64,
+    rounding: Rounding,
+    output: &mut Vec<(D,i64)>)
+{
+    let total: i128 = list.iter().map(|x| x.1.abs() as i128).sum();
+    if total <= limit as i128 {
+        output.extend(list.iter().cloned());
+    }
+    else {
+        // Records are identified for rounding by their positions in the (consolidated) list.
+        for (index, &(ref datum, weight)) in list.iter().enumerate() {
+            let salt = fnv_hash(&(salt, index));
+            output.push((datum.clone(), rounding.divide(salt, weight as i128 * limit as i128, total)));
+        }
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_bound_helper() {
+        let mut output = Vec::new();
+        // totals within the limit are unchanged.
+        super::bound_helper(&[(0, 3), (1, -2)], 5, 0, super::Rounding::Truncate, &mut output);
+        assert_eq!(output, vec![(0, 3), (1, -2)]);
+        // totals beyond the limit are scaled down to it.
+        output.clear();
+        super::bound_helper(&[(0, 30), (1, -10)], 8, 0, super::Rounding::Truncate, &mut output);
+        assert_eq!(output, vec![(0, 6), (1, -2)]);
+    }
+}
@@ -0,0 +1,163 @@
+//! Columnar batches of weighted records, for exchange between workers.
+//!
+//! Timely exchanges streams of `(D, i64)` pairs record by record, and for wide records the
+//! weights are interleaved with, and padded to the alignment of, much larger records. A
+//! `Columns` batch holds the records of one destination contiguously, and their weights in a
+//! separate contiguous column, and is exchanged as a single record. Batches sent between
+//! processes are deserialized in place, and iterated without copying their records.
+//!
+//! With the `columnar` feature, `flat_map` and the operators built on it exchange their inputs as
+//! columnar batches.
+
+use std::hash::Hash;
+use std::io::{Result as IOResult, Write};
+
+use abomonation::Abomonation;
+
+use timely::ExchangeData;
+use timely::dataflow::{Scope, Stream};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::{Exchange, Pipeline};
+
+use super::super::fnv_hash;
+use instrument;
+
+/// A batch of weighted records, with the records and their weights held in separate columns.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Columns<D> {
+    records: Vec<D>,
+    weights: Vec<i64>,
+}
+
+impl<D> Columns<D> {
+    /// Creates an empty batch.
+    pub fn new() -> Self { Columns { records: Vec::new(), weights: Vec::new() } }
+    /// Creates an empty batch with space for `capacity` records.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Columns { records: Vec::with_capacity(capacity), weights: Vec::with_capacity(capacity) }
+    }
+    /// Appends `record` with weight `weight`.
+    pub fn push(&mut self, record: D, weight: i64) {
+        self.records.push(record);
+        self.weights.push(weight);
+    }
+    /// The number of records in the batch.
+    pub fn len(&self) -> usize { self.records.len() }
+    /// Indicates whether the batch has no records.
+    pub fn is_empty(&self) -> bool { self.records.is_empty() }
+    /// The records, in order.
+    pub fn records(&self) -> &[D] { &self.records[..] }
+    /// The weights of the records, in order.
+    pub fn weights(&self) -> &[i64] { &self.weights[..] }
+    /// The sum of the absolute weights of the records, read from the weight column alone.
+    pub fn total(&self) -> i64 { self.weights.iter().map(|w| w.abs()).sum() }
+    /// Iterates over references to the records, with their weights.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item=(&'a D, i64)>+'a {
+        self.records.iter().zip(self.weights.iter().cloned())
+    }
+    /// Moves the records and their weights into `target`, leaving the batch empty.
+    pub fn drain_into(&mut self, target: &mut Vec<(D, i64)>) {
+        target.reserve(self.records.len());
+        target.extend(self.records.drain(..).zip(self.weights.drain(..)));
+    }
+}
+
+impl<D> ::std::iter::FromIterator<(D, i64)> for Columns<D> {
+    fn from_iter<I: IntoIterator<Item=(D, i64)>>(iter: I) -> Self {
+        let mut columns = Columns::new();
+        for (record, weight) in iter { columns.push(record, weight); }
+        columns
+    }
+}
+
+impl<D: Abomonation> Abomonation for Columns<D> {
+    unsafe fn entomb<W: Write>(&self, write: &mut W) -> IOResult<()> {
+        self.records.entomb(write)?;
+        self.weights.entomb(write)
+    }
+    unsafe fn exhume<'a, 'b>(&'a mut self, bytes: &'b mut [u8]) -> Option<&'b mut [u8]> {
+        let bytes = self.records.exhume(bytes)?;
+        self.weights.exhume(bytes)
+    }
+    fn extent(&self) -> usize {
+        self.records.extent() + self.weights.extent()
+    }
+}
+
+/// Exchanges `stream` by the hash of each record, as columnar batches.
+///
+/// Records are routed to the same workers as by an `Exchange` on the hash of the record, and so
+/// an operator reading the result with a `Pipeline` contract sees the same records, at the same
+/// timestamps, as one reading `stream` with that exchange.
+pub fn exchange<G: Scope, D: ExchangeData+Hash>(stream: &Stream<G, (D,i64)>) -> Stream<G, (D,i64)> {
+    unpack(&route(&pack(stream)))
+}
+
+/// Splits each batch of `stream` into one columnar batch for each worker, tagged by the worker.
+pub fn pack<G: Scope, D: ExchangeData+Hash>(stream: &Stream<G, (D,i64)>) -> Stream<G, (usize, Columns<D>)> {
+    let peers = stream.scope().peers();
+    stream.unary(Pipeline, "ColumnarPack", |_,_| {
+        let id = instrument::register("ColumnarPack", false);
+        move |input, output| {
+            input.for_each(|time, data| {
+                instrument::batch(id, &data[..]);
+                let mut batches = (0 .. peers).map(|_| Columns::new()).collect::<Vec<_>>();
+                for (datum, delta) in data.drain(..) {
+                    let worker = (fnv_hash(&datum) % peers as u64) as usize;
+                    batches[worker].push(datum, delta);
+                }
+                let mut session = output.session(&time);
+                for (worker, batch) in batches.into_iter().enumerate() {
+                    if !batch.is_empty() { session.give((worker, batch)); }
+                }
+            });
+        }
+    })
+}
+
+/// Sends each tagged columnar batch to the worker it is tagged with.
+pub fn route<G: Scope, D: ExchangeData>(stream: &Stream<G, (usize, Columns<D>)>) -> Stream<G, Columns<D>> {
+    stream.unary(Exchange::new(|x: &(usize, Columns<D>)| x.0 as u64), "ColumnarRoute", |_,_| {
+        let id = instrument::register("ColumnarRoute", true);
+        move |input, output| {
+            input.for_each(|time, data| {
+                instrument::batch(id, &data[..]);
+                output.session(&time).give_iterator(data.drain(..).map(|(_worker, batch)| batch));
+            });
+        }
+    })
+}
+
+/// Flattens columnar batches into weighted records.
+pub fn unpack<G: Scope, D: ExchangeData>(stream: &Stream<G, Columns<D>>) -> Stream<G, (D,i64)> {
+    stream.unary(Pipeline, "ColumnarUnpack", |_,_| {
+        let mut stash = Vec::new();
+        move |input, output| {
+            input.for_each(|time, data| {
+                for mut batch in data.drain(..) {
+                    batch.drain_into(&mut stash);
+                }
+                output.session(&time).give_iterator(stash.drain(..));
+            });
+        }
+    })
+}
+
+mod tests {
+
+    use super::Columns;
+
+    #[test]
+    fn test_columns() {
+        let mut columns = vec![("a", 3), ("b", -5)].into_iter().collect::<Columns<_>>();
+        columns.push("c", 2);
+        assert_eq!(columns.len(), 3);
+        assert_eq!(columns.weights(), &[3, -5, 2]);
+        assert_eq!(columns.total(), 10);
+        assert_eq!(columns.iter().map(|x| (*x.0, x.1)).collect::<Vec<_>>(), vec![("a", 3), ("b", -5), ("c", 2)]);
+        let mut pairs = Vec::new();
+        columns.drain_into(&mut pairs);
+        assert!(columns.is_empty());
+        assert_eq!(pairs, vec![("a", 3), ("b", -5), ("c", 2)]);
+    }
+}
@@ -0,0 +1,138 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use timely::dataflow::{Scope, Stream, ProbeHandle};
+use timely::dataflow::operators::{Operator, Probe};
+use timely::dataflow::channels::pact::Exchange;
+
+use noise::{self, NoiseSource};
+use instrument;
+
+/// Gathers the weights of `stream` at worker zero, to count them continually over epochs.
+///
+/// The epoch of each timestamp is given by `epoch`, and must not decrease as timestamps advance.
+/// Epochs are numbered from zero. Once the frontier passes an epoch, its total weight is added to
+/// a binary tree counter of at most `horizon` epochs, and epochs without any weight are counted as
+/// zero. Each count has noise of scale `scale * SCALE` times the number of levels of the tree.
+pub fn continual<G: Scope, F: Fn(&G::Timestamp)->usize+'static>(
+    stream: &Stream<G, i64>,
+    handle: &mut ProbeHandle<G::Timestamp>,
+    epoch: F,
+    horizon: usize,
+    scale: f64) -> Continual
+{
+    let shared = Rc::new(RefCell::new(Counter::new(noise::next_source(), horizon, scale)));
+    let state = shared.clone();
+
+    stream.unary_frontier::<(),_,_,_>(Exchange::new(|_x: &i64| 0), "Continual", |_,_| {
+
+        let id = instrument::register("Continual", true);
+        let mut stash = HashMap::<usize, i64>::new();
+
+        move |input, _output| {
+            while let Some((time, data)) = input.next() {
+                instrument::batch(id, &data[..]);
+                *stash.entry(epoch(time.time())).or_insert(0) += data.drain(..).sum::<i64>();
+            }
+
+            // release the epochs the frontier has passed, or all stashed epochs once it is empty.
+            let passed = match input.frontier().frontier().iter().map(|t| epoch(t)).min() {
+                Some(epoch) => epoch,
+                None => stash.keys().max().map(|e| e + 1).unwrap_or(0),
+            };
+            let mut counter = state.borrow_mut();
+            while counter.released.len() < passed {
+                let count = stash.remove(&counter.released.len()).unwrap_or(0);
+                counter.push(count);
+            }
+            instrument::state::<(usize, i64)>(id, stash.len());
+        }
+    })
+    .probe_with(handle);
+
+    Continual { shared: shared }
+}
+
+/// A continually released count, by the binary tree mechanism.
+///
+/// The count of each epoch is added to noisy partial sums of dyadic ranges of epochs, and the
+/// running count through an epoch is the sum of at most one partial sum for each level of the
+/// tree. Each epoch's count contributes to one partial sum at each level, and so the noise of
+/// each partial sum is scaled by the number of levels, and the running count at any epoch has
+/// error polylogarithmic in the horizon, rather than linear in the number of epochs.
+///
+/// All weights are gathered at worker zero, and observations should only be made there.
+pub struct Continual {
+    shared: Rc<RefCell<Counter>>,
+}
+
+impl Continual {
+    /// The number of epochs released.
+    pub fn epochs(&self) -> usize { self.shared.borrow().released.len() }
+    /// The noisy running count through `epoch`, if it has been released.
+    pub fn observe(&self, epoch: usize) -> Option<i64> {
+        self.shared.borrow().released.get(epoch).cloned()
+    }
+}
+
+// the partial sums of a binary tree counter over at most `horizon` epochs.
+struct Counter {
+    source: NoiseSource,
+    horizon: usize,
+    scale: f64,
+    // the exact and noisy partial sum at each level.
+    exact: Vec<i64>,
+    noisy: Vec<i64>,
+    // the noisy running count through each epoch.
+    released: Vec<i64>,
+}
+
+impl Counter {
+    fn new(source: NoiseSource, horizon: usize, scale: f64) -> Self {
+        assert!(horizon > 0);
+        assert!(scale >= 0.0);
+        // the number of levels needed to index epochs `1 .. horizon + 1`.
+        let levels = 64 - (horizon as u64).leading_zeros() as usize;
+        Counter {
+            source: source,
+            horizon: horizon,
+            scale: scale,
+            exact: vec![0; levels],
+            noisy: vec![0; levels],
+            released: Vec::new(),
+        }
+    }
+    // adds the count of the next epoch, and releases the noisy running count.
+    fn push(&mut self, count: i64) {
+        let epoch = self.released.len() + 1;
+        assert!(epoch <= self.horizon, "continual count exceeds its horizon");
+        let levels = self.exact.len();
+        // the partial sum for epoch `epoch` absorbs those of all lower levels.
+        let level = epoch.trailing_zeros() as usize;
+        let sum = self.exact[.. level].iter().sum::<i64>() + count;
+        for lower in 0 .. level {
+            self.exact[lower] = 0;
+            self.noisy[lower] = 0;
+        }
+        let noise = noise::laplace_from(&mut self.source.rng(&(level, epoch))) as f64;
+        self.exact[level] = sum;
+        self.noisy[level] = sum + (noise * self.scale * levels as f64) as i64;
+        // the running count sums the partial sums of the levels set in `epoch`.
+        let running = (0 .. levels).filter(|l| epoch & (1 << l) != 0).map(|l| self.noisy[l]).sum();
+        self.released.push(running);
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_counter() {
+        let mut counter = super::Counter::new(::noise::NoiseSource::new(), 10, 0.0);
+        for count in 1 .. 11 {
+            counter.push(count);
+        }
+        let expected = (1 .. 11).scan(0, |sum, count| { *sum += count; Some(*sum) }).collect::<Vec<_>>();
+        assert_eq!(counter.released, expected);
+    }
+}
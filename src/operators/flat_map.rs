@@ -4,33 +4,80 @@ use std::hash::Hash;
 use timely::{Data, ExchangeData};
 use timely::dataflow::{Scope, Stream};
 use timely::dataflow::operators::Operator;
-use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::channels::pact::{Exchange, Pipeline, ParallelizationContract};
 
 use super::super::fnv_hash;
+#[cfg(feature = "columnar")]
+use super::columnar;
+use super::rounding::Rounding;
+use instrument;
 
-pub fn flat_map<D, G, I, F>(stream: &Stream<G, (D,i64)>, function: F) -> Stream<G, (I::Item, i64)>
+pub fn flat_map<D, G, I, F>(stream: &Stream<G, (D,i64)>, function: F, exchange: bool, rounding: Rounding) -> Stream<G, (I::Item, i64)>
 where
     G: Scope,
     D: ExchangeData+Eq+Hash,
     I: IntoIterator,
     I::Item: Data+Eq+Hash+Clone,
     F: Fn(D)->I+'static,
+{
+    flat_map_weighted(stream, move |x| function(x).into_iter().map(|y| (y, 1)), exchange, rounding)
+}
+
+pub fn flat_map_weighted<D, G, R, I, F>(stream: &Stream<G, (D,i64)>, function: F, exchange: bool, rounding: Rounding) -> Stream<G, (R, i64)>
+where
+    G: Scope,
+    D: ExchangeData+Eq+Hash,
+    R: Data+Eq+Hash+Clone,
+    I: IntoIterator<Item=(R, i64)>,
+    F: Fn(D)->I+'static,
+{
+    // Each result receives the fraction `share / sum |share|` of the weight of its source record,
+    // and so has its sign flipped if its share is negative. Results with zero shares are dropped.
+    //
+    // If `exchange` is false, records are processed by the worker that holds them. With the
+    // `columnar` feature, records are exchanged as columnar batches, to the same workers.
+    #[cfg(feature = "columnar")]
+    {
+        if exchange {
+            return flat_map_pact(&columnar::exchange(stream), Pipeline, false, function, rounding);
+        }
+    }
+    if exchange {
+        flat_map_pact(stream, Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), true, function, rounding)
+    }
+    else {
+        flat_map_pact(stream, Pipeline, false, function, rounding)
+    }
+}
+
+fn flat_map_pact<D, G, R, I, F, P>(stream: &Stream<G, (D,i64)>, pact: P, exchange: bool, function: F, rounding: Rounding) -> Stream<G, (R, i64)>
+where
+    G: Scope,
+    D: ExchangeData+Eq+Hash,
+    R: Data+Eq+Hash+Clone,
+    I: IntoIterator<Item=(R, i64)>,
+    F: Fn(D)->I+'static,
+    P: ParallelizationContract<G::Timestamp, (D,i64)>,
 {
     // TODO: Rounding may be an issue here, as dividing by the weight could do surprising things if
     //       we don't see exact negations of records.
 
-    stream.unary(Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), "FlatMap", |_,_| {
+    stream.unary(pact, "FlatMap", |_,_| {
 
+        let id = instrument::register("FlatMap", exchange);
         let mut stash = Vec::new();
 
         move |input, output| {
             while let Some((time, data)) = input.next() {
+                instrument::batch(id, &data[..]);
                 let mut session = output.session(&time);
                 for (datum, delta) in data.drain(..) {
-                    stash.extend(function(datum.clone()));
-                    let length = stash.len() as i64;
-                    for result in stash.drain(..) {
-                        session.give((result, delta / length));
+                    let salt = fnv_hash(&datum);
+                    stash.extend(function(datum).into_iter().filter(|x| x.1 != 0));
+                    let length: i128 = stash.iter().map(|x: &(R, i64)| (x.1 as i128).abs()).sum();
+                    for (index, (result, share)) in stash.drain(..).enumerate() {
+                        let weight = rounding.divide(fnv_hash(&(salt, index)), delta as i128 * share as i128, length);
+                        session.give((result, weight));
                     }
                 }
             }
@@ -0,0 +1,48 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{Scope, Stream, ProbeHandle};
+use timely::dataflow::operators::{Operator, Probe};
+use timely::dataflow::channels::pact::Exchange;
+
+use instrument;
+
+/// Gathers the weighted records of `stream` at worker zero, accumulating the weight of each.
+///
+/// The gathered weights are sensitive, and back mechanisms such as `Median` and `Histogram` that
+/// only reveal them through noise. Records whose weights cancel are removed.
+pub fn gather<G: Scope, D: ExchangeData+Ord+Hash>(
+    stream: &Stream<G, (D,i64)>,
+    handle: &mut ProbeHandle<G::Timestamp>,
+    name: &'static str) -> Rc<RefCell<HashMap<D, i64>>>
+{
+    let shared = Rc::new(RefCell::new(HashMap::new()));
+    let state = shared.clone();
+
+    stream.unary::<(),_,_,_>(Exchange::new(|_x: &(D,i64)| 0), name, |_,_| {
+
+        let id = instrument::register(name, true);
+
+        move |input, _output| {
+            let mut borrow = state.borrow_mut();
+            input.for_each(|_time, data| {
+                instrument::batch(id, &data[..]);
+                for (datum, delta) in data.drain(..) {
+                    let remove = {
+                        let weight = borrow.entry(datum.clone()).or_insert(0);
+                        *weight += delta;
+                        *weight == 0
+                    };
+                    if remove { borrow.remove(&datum); }
+                }
+            });
+            instrument::state::<(D, i64)>(id, borrow.len());
+        }
+    })
+    .probe_with(handle);
+
+    shared
+}
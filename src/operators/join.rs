@@ -1,16 +1,22 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::hash::Hash;
+use std::rc::Rc;
 
-use timely::ExchangeData;
+use timely::{Data, ExchangeData};
 use timely::dataflow::{Scope, Stream};
 use timely::dataflow::operators::Operator;
 use timely::dataflow::channels::pact::Exchange;
 
 use super::super::{consolidate, fnv_hash};
+use super::spill::SpillMap;
+use super::rounding::Rounding;
+use instrument;
 
 pub fn join<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord, V2: ExchangeData+Ord>(
     stream1: &Stream<G, ((K, V1), i64)>,
-    stream2: &Stream<G, ((K, V2), i64)>) -> Stream<G, ((K, (V1, V2)), i64)>
+    stream2: &Stream<G, ((K, V2), i64)>,
+    resident: Option<usize>,
+    rounding: Rounding) -> Stream<G, ((K, (V1, V2)), i64)>
 {
     // The intended behavior of `join` is that it takes a pair of similarly keyed collections
     // to a collection of keyed pairs, whose weights are scaled down so that each input record
@@ -22,17 +28,66 @@ pub fn join<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord, V2: Exchang
     //   (k, (v1_i, v2_j)) with weight = w1_i * w2_j / (sum_i |w1_i| + sum_i |w2_i|)
     //
     // There are several issues related to rounding and such, but this is the intent.
+    //
+    // If `resident` is supplied, at most that many keys have their state held in memory, and
+    // the state of less recently used keys is spilled to disk. Scaled weights are rounded as
+    // indicated by `rounding`.
+    cogroup(stream1, stream2, "Join", resident, rounding, |_key, list1, list2, salt, rounding, output| {
+        join_helper(list1, list2, salt, rounding, output)
+    })
+}
+
+pub fn join_left<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord, V2: ExchangeData+Ord>(
+    stream1: &Stream<G, ((K, V1), i64)>,
+    stream2: &Stream<G, ((K, V2), i64)>,
+    unit: i64,
+    resident: Option<usize>,
+    rounding: Rounding) -> Stream<G, ((K, (V1, Option<V2>)), i64)>
+{
+    // As `join`, except that each key's second list is augmented by a virtual `None` value whose
+    // weight is however much the absolute weight of the list falls short of `unit`. Keys without
+    // any values in the second input produce `(k, (v1_i, None))` with weight
+    //
+    //   w1_i * unit / (sum_i |w1_i| + unit)
+    //
+    // and this weight diminishes as weight is added to the second input, vanishing once it
+    // reaches `unit`. A change to the second input changes the augmented list by at most twice
+    // its weight.
+    assert!(unit >= 0);
+    cogroup(stream1, stream2, "JoinLeft", resident, rounding, move |_key, list1, list2, salt, rounding, output| {
+        join_left_helper(list1, list2, unit, salt, rounding, output)
+    })
+}
 
+pub fn cogroup<G, K, V1, V2, R, H>(
+    stream1: &Stream<G, ((K, V1), i64)>,
+    stream2: &Stream<G, ((K, V2), i64)>,
+    name: &'static str,
+    resident: Option<usize>,
+    rounding: Rounding,
+    helper: H) -> Stream<G, ((K, R), i64)>
+where
+    G: Scope,
+    K: ExchangeData+Eq+Hash,
+    V1: ExchangeData+Ord,
+    V2: ExchangeData+Ord,
+    R: Data+Ord,
+    H: Fn(&K, &[(V1,i64)], &[(V2,i64)], u64, Rounding, &mut Vec<(R,i64)>)+'static,
+{
+    // Maintains the consolidated values of each key in each input, and on each change to a key
+    // retracts the output of `helper` for the prior values and introduces its output for the
+    // new values. The helper is supplied with a salt identifying the key, for use in rounding.
     // let mut input1_stash = Vec::<(V1, i64)>::new();
     // let mut input2_stash = Vec::<(V2, i64)>::new();
 
     let exchange1 = Exchange::new(|x: &((K,V1),i64)| fnv_hash(&(x.0).0));
     let exchange2 = Exchange::new(|x: &((K,V2),i64)| fnv_hash(&(x.0).0));
 
-    stream1.binary(stream2, exchange1, exchange2, "Join", |_,_| {
+    stream1.binary(stream2, exchange1, exchange2, name, |_,_| {
 
+        let id = instrument::register(name, true);
         let mut output_stash = Vec::new();
-        let mut state = HashMap::<K, (Vec<(V1,i64)>, Vec<(V2,i64)>)>::new();
+        let mut state = SpillMap::<K, (Vec<(V1,i64)>, Vec<(V2,i64)>)>::new(resident);
 
         move |input1, input2, output| {
 
@@ -43,12 +98,15 @@ pub fn join<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord, V2: Exchang
 
             // drain the first input.
             while let Some((time, data)) = input1.next() {
+                instrument::batch(id, &data[..]);
+                state.reserve(data.len());
                 let mut session = output.session(&time);
                 for ((key, val), delta) in data.drain(..) {
-                    let entry = state.entry(key.clone()).or_insert((Vec::new(), Vec::new()));
+                    let salt = fnv_hash(&key);
+                    let entry = state.get_or_insert_with(key.clone(), || (Vec::new(), Vec::new()));
 
                     // compute old output, then negate.
-                    join_helper(&entry.0, &entry.1, &mut output_stash);
+                    helper(&key, &entry.0, &entry.1, salt, rounding, &mut output_stash);
                     for pair in output_stash.iter_mut() { pair.1 *= -1; }
 
                     // apply update.
@@ -56,23 +114,30 @@ pub fn join<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord, V2: Exchang
                     consolidate(&mut entry.0);
 
                     // compute new output, don't negate.
-                    join_helper(&entry.0, &entry.1, &mut output_stash);
+                    helper(&key, &entry.0, &entry.1, salt, rounding, &mut output_stash);
+                    let empty = entry.0.is_empty() && entry.1.is_empty();
 
                     consolidate(&mut output_stash);
                     for (result, delta) in output_stash.drain(..) {
                         session.give(((key.clone(), result), delta));
                     }
+
+                    // discard state for keys with no remaining values.
+                    if empty { state.remove(&key); }
                 }
             }
 
             // drain the second input.
             while let Some((time, data)) = input2.next() {
+                instrument::batch(id, &data[..]);
+                state.reserve(data.len());
                 let mut session = output.session(&time);
                 for ((key, val), delta) in data.drain(..) {
-                    let entry = state.entry(key.clone()).or_insert((Vec::new(), Vec::new()));
+                    let salt = fnv_hash(&key);
+                    let entry = state.get_or_insert_with(key.clone(), || (Vec::new(), Vec::new()));
 
                     // compute old output, then negate.
-                    join_helper(&entry.0, &entry.1, &mut output_stash);
+                    helper(&key, &entry.0, &entry.1, salt, rounding, &mut output_stash);
                     for pair in output_stash.iter_mut() { pair.1 *= -1; }
 
                     // apply update.
@@ -80,14 +145,151 @@ pub fn join<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord, V2: Exchang
                     consolidate(&mut entry.1);
 
                     // compute new output, don't negate.
-                    join_helper(&entry.0, &entry.1, &mut output_stash);
+                    helper(&key, &entry.0, &entry.1, salt, rounding, &mut output_stash);
+                    let empty = entry.0.is_empty() && entry.1.is_empty();
+
+                    consolidate(&mut output_stash);
+                    for (result, delta) in output_stash.drain(..) {
+                        session.give(((key.clone(), result), delta));
+                    }
+
+                    // discard state for keys with no remaining values.
+                    if empty { state.remove(&key); }
+                }
+            }
+
+            instrument::state::<(K, (Vec<(V1,i64)>, Vec<(V2,i64)>))>(id, state.resident());
+        }
+    })
+}
+
+pub fn join_sorted<G, K, V1, V2, P>(
+    stream1: &Stream<G, ((K, V1), i64)>,
+    stream2: &Stream<G, ((K, V2), i64)>,
+    partition: P,
+    rounding: Rounding) -> Stream<G, ((K, (V1, V2)), i64)>
+where
+    G: Scope,
+    K: ExchangeData+Ord,
+    V1: ExchangeData+Ord,
+    V2: ExchangeData+Ord,
+    P: Fn(&K)->u64+'static,
+{
+    // As `join`, for keys that are ordered but need not be hashable. Records are routed to workers
+    // by `partition`, which must map equal keys to equal values, and each worker holds the values
+    // of its keys in key order. Each batch of updates is sorted by key and merged with the state,
+    // so that the output of each key is re-evaluated once per batch rather than once per update.
+    cogroup_sorted(stream1, stream2, "JoinSorted", partition, rounding, |_key, list1, list2, salt, rounding, output| {
+        join_helper(list1, list2, salt, rounding, output)
+    })
+}
+
+pub fn cogroup_sorted<G, K, V1, V2, R, P, H>(
+    stream1: &Stream<G, ((K, V1), i64)>,
+    stream2: &Stream<G, ((K, V2), i64)>,
+    name: &'static str,
+    partition: P,
+    rounding: Rounding,
+    helper: H) -> Stream<G, ((K, R), i64)>
+where
+    G: Scope,
+    K: ExchangeData+Ord,
+    V1: ExchangeData+Ord,
+    V2: ExchangeData+Ord,
+    R: Data+Ord,
+    P: Fn(&K)->u64+'static,
+    H: Fn(&K, &[(V1,i64)], &[(V2,i64)], u64, Rounding, &mut Vec<(R,i64)>)+'static,
+{
+    // As `cogroup`, except that records are routed by `partition` and state is held in key order.
+    // Keys need not be hashable, and so the salt identifying each key for rounding is derived from
+    // its partition; keys with equal partitions share salts.
+    let partition = Rc::new(partition);
+    let partition1 = partition.clone();
+    let partition2 = partition.clone();
+    let exchange1 = Exchange::new(move |x: &((K,V1),i64)| partition1(&(x.0).0));
+    let exchange2 = Exchange::new(move |x: &((K,V2),i64)| partition2(&(x.0).0));
+
+    stream1.binary(stream2, exchange1, exchange2, name, |_,_| {
+
+        let id = instrument::register(name, true);
+        let mut output_stash = Vec::new();
+        let mut state = BTreeMap::<K, (Vec<(V1,i64)>, Vec<(V2,i64)>)>::new();
+
+        move |input1, input2, output| {
+
+            // drain the first input.
+            while let Some((time, data)) = input1.next() {
+                instrument::batch(id, &data[..]);
+                let mut updates = data.drain(..).collect::<Vec<_>>();
+                updates.sort_by(|x, y| (x.0).0.cmp(&(y.0).0));
+                let mut session = output.session(&time);
+                let mut updates = updates.into_iter().peekable();
+                while let Some(((key, val), delta)) = updates.next() {
+                    let salt = fnv_hash(&partition(&key));
+                    let mut entry = state.remove(&key).unwrap_or_else(|| (Vec::new(), Vec::new()));
+
+                    // compute old output, then negate.
+                    helper(&key, &entry.0, &entry.1, salt, rounding, &mut output_stash);
+                    for pair in output_stash.iter_mut() { pair.1 *= -1; }
+
+                    // apply all updates to the key.
+                    entry.0.push((val, delta));
+                    while updates.peek().map(|x| (x.0).0 == key).unwrap_or(false) {
+                        let ((_key, val), delta) = updates.next().unwrap();
+                        entry.0.push((val, delta));
+                    }
+                    consolidate(&mut entry.0);
+
+                    // compute new output, don't negate.
+                    helper(&key, &entry.0, &entry.1, salt, rounding, &mut output_stash);
+
+                    consolidate(&mut output_stash);
+                    for (result, delta) in output_stash.drain(..) {
+                        session.give(((key.clone(), result), delta));
+                    }
+
+                    // retain state only for keys with remaining values.
+                    if !entry.0.is_empty() || !entry.1.is_empty() { state.insert(key, entry); }
+                }
+            }
+
+            // drain the second input.
+            while let Some((time, data)) = input2.next() {
+                instrument::batch(id, &data[..]);
+                let mut updates = data.drain(..).collect::<Vec<_>>();
+                updates.sort_by(|x, y| (x.0).0.cmp(&(y.0).0));
+                let mut session = output.session(&time);
+                let mut updates = updates.into_iter().peekable();
+                while let Some(((key, val), delta)) = updates.next() {
+                    let salt = fnv_hash(&partition(&key));
+                    let mut entry = state.remove(&key).unwrap_or_else(|| (Vec::new(), Vec::new()));
+
+                    // compute old output, then negate.
+                    helper(&key, &entry.0, &entry.1, salt, rounding, &mut output_stash);
+                    for pair in output_stash.iter_mut() { pair.1 *= -1; }
+
+                    // apply all updates to the key.
+                    entry.1.push((val, delta));
+                    while updates.peek().map(|x| (x.0).0 == key).unwrap_or(false) {
+                        let ((_key, val), delta) = updates.next().unwrap();
+                        entry.1.push((val, delta));
+                    }
+                    consolidate(&mut entry.1);
+
+                    // compute new output, don't negate.
+                    helper(&key, &entry.0, &entry.1, salt, rounding, &mut output_stash);
 
                     consolidate(&mut output_stash);
                     for (result, delta) in output_stash.drain(..) {
                         session.give(((key.clone(), result), delta));
                     }
+
+                    // retain state only for keys with remaining values.
+                    if !entry.0.is_empty() || !entry.1.is_empty() { state.insert(key, entry); }
                 }
             }
+
+            instrument::state::<(K, (Vec<(V1,i64)>, Vec<(V2,i64)>))>(id, state.len());
         }
     })
 }
@@ -95,16 +297,88 @@ pub fn join<G: Scope, K: ExchangeData+Eq+Hash, V1: ExchangeData+Ord, V2: Exchang
 fn join_helper<V1:Ord+Clone, V2:Ord+Clone>(
     list1: &[(V1,i64)],
     list2: &[(V2,i64)],
+    salt: u64,
+    rounding: Rounding,
     output: &mut Vec<((V1,V2),i64)>)
 {
-    let total1: i64 = list1.iter().map(|x| x.1.abs()).sum();
-    let total2: i64 = list2.iter().map(|x| x.1.abs()).sum();
+    // Products of weights readily exceed the range of an `i64`, and so are computed as `i128`.
+    // Each quotient is at most the smaller of the two weights in magnitude, and so fits an `i64`.
+    let total1: i128 = list1.iter().map(|x| x.1.abs() as i128).sum();
+    let total2: i128 = list2.iter().map(|x| x.1.abs() as i128).sum();
     let total = total1 + total2;
 
-    for &(ref datum1, weight1) in list1.iter() {
-        for &(ref datum2, weight2) in list2.iter() {
-            output.push(((datum1.clone(), datum2.clone()), (weight1 * weight2) / total));
+    // Pairs are identified for rounding by their positions in the (consolidated) lists.
+    for (index1, &(ref datum1, weight1)) in list1.iter().enumerate() {
+        for (index2, &(ref datum2, weight2)) in list2.iter().enumerate() {
+            let salt = fnv_hash(&(salt, index1, index2));
+            let weight = rounding.divide(salt, weight1 as i128 * weight2 as i128, total);
+            output.push(((datum1.clone(), datum2.clone()), weight));
+        }
+    }
+}
+
+fn join_left_helper<V1:Ord+Clone, V2:Ord+Clone>(
+    list1: &[(V1,i64)],
+    list2: &[(V2,i64)],
+    unit: i64,
+    salt: u64,
+    rounding: Rounding,
+    output: &mut Vec<((V1,Option<V2>),i64)>)
+{
+    let total1: i128 = list1.iter().map(|x| x.1.abs() as i128).sum();
+    let total2: i128 = list2.iter().map(|x| x.1.abs() as i128).sum();
+    let missing = if total2 < unit as i128 { unit as i128 - total2 } else { 0 };
+    let total = total1 + total2 + missing;
+
+    for (index1, &(ref datum1, weight1)) in list1.iter().enumerate() {
+        for (index2, &(ref datum2, weight2)) in list2.iter().enumerate() {
+            let salt = fnv_hash(&(salt, index1, index2));
+            let weight = rounding.divide(salt, weight1 as i128 * weight2 as i128, total);
+            output.push(((datum1.clone(), Some(datum2.clone())), weight));
+        }
+        if missing > 0 {
+            let salt = fnv_hash(&(salt, index1, list2.len()));
+            let weight = rounding.divide(salt, weight1 as i128 * missing, total);
+            output.push(((datum1.clone(), None), weight));
         }
     }
 }
 
+mod tests {
+
+    #[test]
+    fn test_join_helper_large() {
+        // the weight used by the examples.
+        let weight = i32::max_value() as i64 / 10;
+        let mut output = Vec::new();
+        super::join_helper(&[(0, weight)], &[(1, weight)], 0, super::Rounding::Truncate, &mut output);
+        assert_eq!(output, vec![((0, 1), weight / 2)]);
+    }
+
+    #[test]
+    fn test_join_helper_larger() {
+        // weights whose products exceed an i64 many times over.
+        let weight = i64::max_value() / 4;
+        let mut output = Vec::new();
+        super::join_helper(&[(0, weight), (1, -weight)], &[(2, weight), (3, weight)], 0, super::Rounding::Truncate, &mut output);
+        assert_eq!(output, vec![
+            ((0, 2), weight / 4),
+            ((0, 3), weight / 4),
+            ((1, 2), -weight / 4),
+            ((1, 3), -weight / 4),
+        ]);
+    }
+
+    #[test]
+    fn test_join_left_helper() {
+        let weight = i32::max_value() as i64 / 10;
+        let mut output = Vec::new();
+        // no matches: the unmatched weight is scaled by unit / (weight + unit).
+        super::join_left_helper::<_, usize>(&[(0, weight)], &[], weight, 0, super::Rounding::Truncate, &mut output);
+        assert_eq!(output, vec![((0, None), weight / 2)]);
+        // matches of at least `unit` weight leave nothing unmatched.
+        output.clear();
+        super::join_left_helper(&[(0, weight)], &[(1, weight)], weight, 0, super::Rounding::Truncate, &mut output);
+        assert_eq!(output, vec![((0, Some(1)), weight / 2)]);
+    }
+}
@@ -1,14 +1,22 @@
+use std::any::Any;
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::collections::HashMap;
 use std::hash::Hash;
+use std::ops::Range;
+
+use fnv::FnvHashMap;
 
 use timely::ExchangeData;
+use timely::order::PartialOrder;
+use timely::progress::Timestamp;
 use timely::dataflow::{Scope, Stream, ProbeHandle};
-use timely::dataflow::operators::{Operator, Probe};
-use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{Operator, Probe, Concat, Sink, Map, Broadcast};
+use timely::dataflow::channels::pact::{Exchange, Pipeline, ParallelizationContract};
 
 use super::super::{consolidate, fnv_hash};
+use noise::{self, NoiseSource};
+use budget::{self, Exhausted, Odometer};
+use instrument;
 // use super::super::merge_sort::MergeSorter;
 
 /// Performs a Laplace-based noisy measurement.
@@ -19,122 +27,469 @@ use super::super::{consolidate, fnv_hash};
 /// and if no measurement yet exists one is made and recorded.
 ///
 /// The supplied probe handle is used to indicate whether all measurements have been updated for an
-/// indicated timestamp.
-pub fn measure<G: Scope, D: ExchangeData+Ord+Hash>(
+/// indicated timestamp. Updates are only applied once their timestamp is complete, and so once the
+/// probe passes a timestamp the measurement reflects exactly the inputs up to that timestamp.
+///
+/// The `error` function scores the discrepancy `synth - truth` of each measured element, and the
+/// sum of these scores is accumulated in `total`.
+///
+/// The noise has scale `scale * noise::SCALE`, and discrepancies are divided by `scale` before they
+/// are scored, so that less noisy measurements count for more in the total.
+///
+/// If `exchange` is false, records are not exchanged before measurement, and the caller must ensure
+/// that all true and synthetic records for each element reside on the same worker.
+pub fn measure<G: Scope, D: ExchangeData+Ord+Hash, E: Fn(i64)->i64+'static>(
+    stream1: Stream<G, (D,i64)>,
+    stream2: Stream<G, (D,i64)>,
+    handle: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    error: E,
+    scale: f64,
+    exchange: bool) -> Measurement<D>
+{
+    assert!(scale > 0.0);
+    let shared = Rc::new(RefCell::new(MeasurementState::new(total, error, scale)));
+    let (truth, synth) = if exchange {
+        (measure_truth(&stream1, Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), true, shared.clone(), handle),
+         instrument::synth(|| measure_synth(&stream2, Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), true, shared.clone(), handle)))
+    }
+    else {
+        (measure_truth(&stream1, Pipeline, false, shared.clone(), handle),
+         instrument::synth(|| measure_synth(&stream2, Pipeline, false, shared.clone(), handle)))
+    };
+    measure_stable(&truth.concat(&synth), shared.clone());
+    Measurement { shared: shared }
+}
+
+/// How a measurement treats counts that retractions have taken below zero.
+///
+/// Counts accumulate signed updates, and a dataset that retracts more weight than it introduced
+/// for an element, through a bug or through records whose retractions arrive before them, leaves
+/// a negative count. The policy applies to both the true and the synthetic counts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Negative {
+    /// Counts are tracked and reported signed; this is the default.
+    Signed,
+    /// Counts are tracked signed, so that later insertions restore them, but are observed and
+    /// scored as if clamped at zero. Clamping changes no count by more than the signed count
+    /// changes, and so the privacy guarantee is unaffected.
+    Clamp,
+    /// The worker panics when an update leaves a count negative. The panic depends on the true
+    /// records, and discloses that a count went negative; the policy is meant for development.
+    Panic,
+}
+
+/// Performs a Laplace-based noisy measurement that may be observed from any worker in a cluster.
+///
+/// As `measure`, each element is measured by the worker its hash selects, which need not be the
+/// worker, or even the process, that wants to observe it. The elements of `queries` are sent to
+/// the workers that own them, and their noisy counts returned to the worker that sent them, where
+/// they are available from `Measurement::answer` once the probe passes the time of the query.
+///
+/// The changes in each worker's error are broadcast to all workers, so that `total` accumulates
+/// the error of the measurement across the cluster, rather than of this worker's elements.
+pub fn measure_cluster<G: Scope, D: ExchangeData+Ord+Hash, E: Fn(i64)->i64+'static>(
+    stream1: Stream<G, (D,i64)>,
+    stream2: Stream<G, (D,i64)>,
+    queries: &Stream<G, D>,
+    handle: &mut ProbeHandle<G::Timestamp>,
+    total: &Rc<RefCell<i64>>,
+    error: E,
+    scale: f64) -> Measurement<D>
+{
+    assert!(scale > 0.0);
+    let local = Rc::new(RefCell::new(0i64));
+    let shared = Rc::new(RefCell::new(MeasurementState::new(&local, error, scale)));
+    let truth = measure_truth(&stream1, Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), true, shared.clone(), handle);
+    let synth = instrument::synth(|| measure_synth(&stream2, Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), true, shared.clone(), handle));
+    let applied = truth.concat(&synth);
+    measure_stable(&applied, shared.clone());
+    measure_total(&applied, local, total.clone(), handle);
+    measure_queries(queries, &truth, shared.clone(), handle);
+    Measurement { shared: shared }
+}
+
+/// Performs a Laplace-based noisy measurement whose every element is observed at worker zero.
+///
+/// As `measure`, but all true and synthetic records are sent to worker zero, which measures each
+/// element rather than the worker its hash selects. This suits computations whose results are read
+/// and reported by a single worker, at the expense of holding the whole measurement there. Other
+/// workers hold no counts and observe only noise, and their `total` remains zero.
+pub fn measure_gathered<G: Scope, D: ExchangeData+Ord+Hash, E: Fn(i64)->i64+'static>(
     stream1: Stream<G, (D,i64)>,
     stream2: Stream<G, (D,i64)>,
     handle: &mut ProbeHandle<G::Timestamp>,
-    total: &Rc<RefCell<i64>>) -> Measurement<D>
+    total: &Rc<RefCell<i64>>,
+    error: E,
+    scale: f64) -> Measurement<D>
 {
-    let shared = Rc::new(RefCell::new(MeasurementState::new(total)));
-    measure_truth(&stream1, shared.clone(), handle);
-    measure_synth(&stream2, shared.clone(), handle);
+    assert!(scale > 0.0);
+    let shared = Rc::new(RefCell::new(MeasurementState::new(total, error, scale)));
+    let truth = measure_truth(&stream1, Exchange::new(|_x: &(D,i64)| 0), true, shared.clone(), handle);
+    let synth = instrument::synth(|| measure_synth(&stream2, Exchange::new(|_x: &(D,i64)| 0), true, shared.clone(), handle));
+    measure_stable(&truth.concat(&synth), shared.clone());
     Measurement { shared: shared }
 }
 
-fn measure_truth<G: Scope, D: ExchangeData+Ord+Hash>(
+// broadcasts changes in the `local` error of this worker, and accumulates all changes in `total`.
+fn measure_total<G: Scope>(applied: &Stream<G, ()>, local: Rc<RefCell<i64>>, total: Rc<RefCell<i64>>, handle: &mut ProbeHandle<G::Timestamp>) {
+
+    let mut reported = 0;
+
+    applied
+        .unary_notify(Pipeline, "MeasureError", vec![], move |input, output, notificator| {
+            input.for_each(|time, _data| notificator.notify_at(time.retain()));
+            notificator.for_each(|time, _, _| {
+                let current = *local.borrow();
+                if current != reported {
+                    output.session(&time).give(current - reported);
+                    reported = current;
+                }
+            });
+        })
+        .broadcast()
+        .unary::<(),_,_,_>(Pipeline, "MeasureTotal", |_,_| move |input, _output| {
+            input.for_each(|_time, data| {
+                for change in data.drain(..) {
+                    *total.borrow_mut() += change;
+                }
+            });
+        })
+        .probe_with(handle);
+}
+
+// answers queries at the workers owning the queried elements, once their true updates are applied.
+fn measure_queries<G: Scope, D: ExchangeData+Ord+Hash>(queries: &Stream<G, D>, truth: &Stream<G, ()>, shared: Rc<RefCell<MeasurementState<D>>>, handle: &mut ProbeHandle<G::Timestamp>) {
+
+    let index = queries.scope().index();
+    let owner = shared.clone();
+    let mut stash = FnvHashMap::default();
+
+    queries
+        .map(move |datum| (index, datum))
+        .binary_notify(truth, Exchange::new(|x: &(usize,D)| fnv_hash(&x.1)), Pipeline, "MeasureQuery", vec![], move |input1, input2, output, notificator| {
+            input1.for_each(|time, data| {
+                stash.entry(time.time().clone()).or_insert(Vec::new()).extend(data.drain(..));
+                notificator.notify_at(time.retain());
+            });
+            input2.for_each(|_time, _data| { });
+            notificator.for_each(|time, _, _| {
+                if let Some(requests) = stash.remove(time.time()) {
+                    let borrow = owner.borrow();
+                    output.session(&time).give_iterator(requests.into_iter().map(|(index, datum)| {
+                        let count = borrow.observe(datum.clone());
+                        (index, datum, count)
+                    }));
+                }
+            });
+        })
+        .unary::<(),_,_,_>(Exchange::new(|x: &(usize,D,i64)| x.0 as u64), "MeasureAnswer", |_,_| move |input, _output| {
+            input.for_each(|_time, data| {
+                let mut borrow = shared.borrow_mut();
+                for (_index, datum, count) in data.drain(..) {
+                    borrow.answers.insert(datum, count);
+                }
+            });
+        })
+        .probe_with(handle);
+}
+
+// invokes the callbacks waiting on timestamps that the frontier of `stream` has passed.
+fn measure_stable<G: Scope, D: Hash+Eq+'static>(stream: &Stream<G, ()>, shared: Rc<RefCell<MeasurementState<D>>>) {
+    stream.sink(Pipeline, "MeasureStable", move |input| {
+        while input.next().is_some() { }
+        // callbacks may observe the measurement, and so are invoked once the state is released.
+        let ready = shared.borrow_mut().take_ready(|time: &G::Timestamp| !input.frontier().less_equal(time));
+        let mut measurement = Measurement { shared: shared.clone() };
+        for mut callback in ready.into_iter() {
+            callback(&mut measurement);
+        }
+    });
+}
+
+fn measure_truth<G: Scope, D: ExchangeData+Ord+Hash, P: ParallelizationContract<G::Timestamp, (D,i64)>>(
     stream: &Stream<G, (D,i64)>,
+    pact: P,
+    exchange: bool,
     shared: Rc<RefCell<MeasurementState<D>>>,
-    handle: &mut ProbeHandle<G::Timestamp>)
+    handle: &mut ProbeHandle<G::Timestamp>) -> Stream<G, ()>
 {
-    stream.unary::<(),_,_,_>(Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), "MeasureTruth", |_,_| {
+    stream.unary_notify::<(),_,_>(pact, "MeasureTruth", vec![], {
 
-        let mut buffer = Vec::new();
+        let id = instrument::register("MeasureTruth", exchange);
+        let mut stash = FnvHashMap::default();
 
-        move |input, _output| {
+        move |input, output, notificator| {
 
-            input.for_each(|_time, data| {
-                buffer.extend(data.drain(..));
+            // retired measurements discard their inputs, but continue to announce timestamps.
+            let retired = shared.borrow().retired;
+            if retired { stash.clear(); }
+
+            input.for_each(|time, data| {
+                instrument::batch(id, &data[..]);
+                if !retired {
+                    stash.entry(time.time().clone()).or_insert(Vec::new()).extend(data.drain(..));
+                }
+                notificator.notify_at(time.retain());
             });
 
-            let mut borrow = shared.borrow_mut();
-            consolidate(&mut buffer);
-            for (datum, delta) in buffer.drain(..) {
-                borrow.update_truth(datum, delta);
-            }
+            // apply updates only once their timestamp is complete, in timestamp order.
+            notificator.for_each(|time, _, _| {
+                if let Some(mut buffer) = stash.remove(time.time()) {
+                    let mut borrow = shared.borrow_mut();
+                    consolidate(&mut buffer);
+                    borrow.reserve(buffer.len());
+                    for &(ref datum, delta) in buffer.iter() {
+                        borrow.update_truth(datum.clone(), delta);
+                    }
+                    borrow.record(time.time().clone(), buffer);
+                    instrument::state::<(D, (i64, i64))>(id, borrow.measurements.len());
+                }
+                // announce the application of updates, for operators downstream.
+                output.session(&time).give(());
+            });
         }
     })
-    .probe_with(handle);
+    .probe_with(handle)
 }
 
-fn measure_synth<G: Scope, D: ExchangeData+Ord+Hash>(
+fn measure_synth<G: Scope, D: ExchangeData+Ord+Hash, P: ParallelizationContract<G::Timestamp, (D,i64)>>(
     stream: &Stream<G, (D,i64)>,
+    pact: P,
+    exchange: bool,
     shared: Rc<RefCell<MeasurementState<D>>>,
-    handle: &mut ProbeHandle<G::Timestamp>)
+    handle: &mut ProbeHandle<G::Timestamp>) -> Stream<G, ()>
 {
-    stream.unary::<(),_,_,_>(Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), "MeasureSynth", |_,_| move |input, _output| {
+    stream.unary_notify::<(),_,_>(pact, "MeasureSynth", vec![], {
 
-        let mut buffer = Vec::new();
+        let id = instrument::register("MeasureSynth", exchange);
+        let mut stash = FnvHashMap::default();
 
-        input.for_each(|_time, data| {
-            buffer.extend(data.drain(..));
-        });
+        move |input, output, notificator| {
+
+            // retired measurements discard their inputs, but continue to announce timestamps.
+            let retired = shared.borrow().retired;
+            if retired { stash.clear(); }
 
-        let mut borrow = shared.borrow_mut();
-        consolidate(&mut buffer);
-        for (datum, delta) in buffer.drain(..) {
-            borrow.update_synth(datum, delta);
+            input.for_each(|time, data| {
+                instrument::batch(id, &data[..]);
+                if !retired {
+                    stash.entry(time.time().clone()).or_insert(Vec::new()).extend(data.drain(..));
+                }
+                notificator.notify_at(time.retain());
+            });
+
+            notificator.for_each(|time, _, _| {
+                if let Some(mut buffer) = stash.remove(time.time()) {
+                    let mut borrow = shared.borrow_mut();
+                    consolidate(&mut buffer);
+                    borrow.reserve(buffer.len());
+                    for (datum, delta) in buffer.drain(..) {
+                        borrow.update_synth(datum, delta);
+                    }
+                }
+                output.session(&time).give(());
+            });
         }
     })
-    .probe_with(handle);
+    .probe_with(handle)
 }
 
 /// The state required to back measurements made of sensitive data.
 ///
 /// This state tracks both the accumulated counts for the sensitive and the synthetic data.
-/// It allows one to query the sensitive data, which binds and returns the measurement, and
-/// to assess the fit of synthetic data by reporting the sum of absolute values in error for
-/// the measurements.
+/// It allows one to query the sensitive data, which returns the count with noise added, and
+/// to assess the fit of synthetic data by reporting the sum of errors for the measurements.
+///
+/// Noise is derived from `noise` and each element, rather than stored, and multiplied by `scale`.
+/// Discrepancies are divided by `scale` before they are scored by `error`. Only elements with
+/// non-zero counts are retained, and each contributes to `total_error` the error of its discrepancy
+/// while retained, so that the total is the absolute error of the retained elements, and is never
+/// negative for a non-negative `error`. Elements with zero counts contribute nothing.
+///
+/// The consolidated true updates of each completed timestamp are retained in `history`, with the
+/// timestamp type-erased, so that counts can be observed as of earlier timestamps. Callbacks
+/// awaiting the completion of a timestamp are held in `waiting`, also with type-erased timestamps.
+/// The noisy counts returned for queries made from this worker, in a cluster, are held in `answers`.
+///
+/// The error this measurement has added to `total_error` is tracked in `contribution`, so that it
+/// can be withdrawn when the measurement is retired.
+///
+/// Counts are stored signed, and `negative` determines how negative counts are observed and scored.
 struct MeasurementState<D: Hash+Eq> {
     total_error: Rc<RefCell<i64>>,
-    measurements: HashMap<D, (i64, i64)>,
+    error: Box<dyn Fn(i64)->i64>,
+    scale: f64,
+    negative: Negative,
+    noise: NoiseSource,
+    measurements: FnvHashMap<D, (i64, i64)>,
+    history: Vec<(Box<dyn Any>, FnvHashMap<D, i64>)>,
+    waiting: Vec<(Box<dyn Any>, Callback<D>)>,
+    answers: FnvHashMap<D, i64>,
+    contribution: i64,
+    retired: bool,
 }
 
+/// A function invoked with a measurement once it is stable at a timestamp.
+type Callback<D> = Box<dyn FnMut(&mut Measurement<D>)>;
+
 impl<D: Hash+Eq> MeasurementState<D> {
 
-    pub fn new(total: &Rc<RefCell<i64>>) -> Self {
+    pub fn new<E: Fn(i64)->i64+'static>(total: &Rc<RefCell<i64>>, error: E, scale: f64) -> Self {
         MeasurementState {
             total_error: total.clone(),
-            measurements: HashMap::new(),
+            error: Box::new(error),
+            scale: scale,
+            negative: Negative::Signed,
+            noise: noise::next_source(),
+            measurements: FnvHashMap::default(),
+            history: Vec::new(),
+            waiting: Vec::new(),
+            answers: FnvHashMap::default(),
+            contribution: 0,
+            retired: false,
         }
     }
 
     pub fn update_truth(&mut self, element: D, delta: i64) {
-        let entry =
-        self.measurements
-            .entry(element)
-            .or_insert((0, laplace()));
-
-        // update total error measurements.
-        *self.total_error.borrow_mut() -= (entry.1 - entry.0).abs();
-        entry.1 += delta;
-        *self.total_error.borrow_mut() += (entry.1 - entry.0).abs();
+        self.update(element, 0, delta);
     }
 
     pub fn update_synth(&mut self, element: D, delta: i64) {
-        let entry =
-        self.measurements
-            .entry(element)
-            .or_insert((0, laplace()));
+        self.update(element, delta, 0);
+    }
+
+    // applies changes to the synthetic and true counts of `element`.
+    fn update(&mut self, element: D, synth: i64, truth: i64) {
+
+        if self.retired { return; }
+
+        let noise = self.noise(&element);
+        let old = self.measurements.get(&element).cloned().unwrap_or((0, 0));
+        let new = (old.0 + synth, old.1 + truth);
+
+        if self.negative == Negative::Panic {
+            if new.0 < 0 { panic!("synthetic count of a measured element is negative ({})", new.0); }
+            if new.1 < 0 { panic!("true count of a measured element is negative"); }
+        }
+
+        // update total error measurements; elements with zero counts are absent, and contribute nothing.
+        let scored = |counts: (i64, i64)| {
+            if counts == (0, 0) { 0 }
+            else { (self.error)(self.normalize(self.count(counts.0) - self.count(counts.1) - noise)) }
+        };
+        let change = scored(new) - scored(old);
+        *self.total_error.borrow_mut() += change;
+        self.contribution += change;
+
+        if new == (0, 0) { self.measurements.remove(&element); }
+        else { self.measurements.insert(element, new); }
+    }
+
+    /// Withdraws the error of this measurement from the total, and releases its counts, history,
+    /// answers, and waiting callbacks.
+    ///
+    /// Subsequent updates are ignored.
+    pub fn retire(&mut self) {
+        *self.total_error.borrow_mut() -= self.contribution;
+        self.contribution = 0;
+        self.retired = true;
+        self.measurements = FnvHashMap::default();
+        self.history = Vec::new();
+        self.waiting = Vec::new();
+        self.answers = FnvHashMap::default();
+    }
+
+    /// Reserves memory for `additional` further measured elements.
+    pub fn reserve(&mut self, additional: usize) {
+        if !self.retired { self.measurements.reserve(additional); }
+    }
+
+    /// Retains the consolidated true `updates` of the completed timestamp `time`.
+    pub fn record<T: Timestamp>(&mut self, time: T, updates: Vec<(D, i64)>) {
+        if !updates.is_empty() && !self.retired {
+            self.history.push((Box::new(time), updates.into_iter().collect()));
+        }
+    }
+
+    /// Removes and returns the callbacks waiting on timestamps for which `stable` holds.
+    pub fn take_ready<T: Timestamp, F: Fn(&T)->bool>(&mut self, stable: F) -> Vec<Callback<D>> {
+        let (ready, waiting): (Vec<_>, Vec<_>) = self.waiting.drain(..).partition(|x| {
+            stable(x.0.downcast_ref::<T>().expect("timestamp of a different type"))
+        });
+        self.waiting = waiting;
+        ready.into_iter().map(|x| x.1).collect()
+    }
+
+    /// Sets the scale of the noise, as a multiple of `noise::SCALE`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any element has been updated, as its noise would change.
+    pub fn set_scale(&mut self, scale: f64) {
+        assert!(scale > 0.0);
+        assert!(self.measurements.is_empty(), "cannot rescale a measurement after updates");
+        self.scale = scale;
+    }
+
+    /// Sets the policy for negative counts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any element has been updated, as its accounted error would change.
+    pub fn set_negative(&mut self, negative: Negative) {
+        assert!(self.measurements.is_empty(), "cannot change the negative policy of a measurement after updates");
+        self.negative = negative;
+    }
+
+    // a count as it is observed and scored, under the negative policy.
+    fn count(&self, count: i64) -> i64 {
+        if self.negative == Negative::Clamp { count.max(0) } else { count }
+    }
 
-        // update total error measurements.
-        *self.total_error.borrow_mut() -= (entry.1 - entry.0).abs();
-        entry.0 += delta;
-        *self.total_error.borrow_mut() += (entry.1 - entry.0).abs();
+    // the noise for `element`, with scale `scale * noise::SCALE`.
+    fn noise(&self, element: &D) -> i64 {
+        ((self.noise.laplace(element) as f64) * self.scale) as i64
+    }
+
+    // a discrepancy in units of noise of scale `noise::SCALE`.
+    fn normalize(&self, discrepancy: i64) -> i64 {
+        ((discrepancy as f64) / self.scale) as i64
     }
 
     /// Observes the noisy count associated with an element.
     ///
-    /// This method also binds the current measurement as "truth", so that future
-    /// updates to the counts are reflected in `self.total_error`. This is important
-    /// for tracking how well subsequent candidate datasets match observed counts.
+    /// The noise is a deterministic function of the element, and so repeated observations
+    /// return the same value until the true count changes.
+    pub fn observe(&self, element: D) -> i64 {
+        let count = self.measurements.get(&element).map(|x| x.1).unwrap_or(0);
+        self.count(count) + self.noise(&element)
+    }
+
+    /// Observes the noisy count of an element, reflecting only true updates at times up to `time`.
+    ///
+    /// # Panics
     ///
-    /// This method binds the observation as truth, from which
-    pub fn observe(&mut self, element: D) -> i64 {
+    /// Panics if `time` is not of the timestamp type of the measured dataflow.
+    pub fn observe_at<T: Timestamp>(&self, time: &T, element: D) -> i64 {
+        let mut count = 0;
+        for &(ref stamp, ref updates) in self.history.iter() {
+            let stamp = stamp.downcast_ref::<T>().expect("timestamp of a different type");
+            if stamp.less_equal(time) {
+                count += updates.get(&element).cloned().unwrap_or(0);
+            }
+        }
+        self.count(count) + self.noise(&element)
+    }
+
+    /// Returns the elements with non-zero true counts whose noisy counts exceed `threshold`.
+    pub fn above(&self, threshold: i64) -> Vec<(D, i64)> where D: Clone {
         self.measurements
-            .entry(element)
-            .or_insert((0, laplace()))
-            .1
+            .iter()
+            .filter(|x| self.count((x.1).1) != 0)
+            .map(|(element, counts)| (element.clone(), self.count(counts.1) + self.noise(element)))
+            .filter(|x| x.1 > threshold)
+            .collect()
     }
 }
 
@@ -145,21 +500,285 @@ pub struct Measurement<D: Hash+Eq> {
 impl<D: Hash+Eq> Measurement<D> {
     /// Observes the noised count associated with `data`.
     ///
-    /// This method inserts noise if the key is not yet present, so that repeated
-    /// queries do not risk disclosing its absence.
+    /// Keys that are not present receive noise just as those that are, so that repeated
+    /// queries do not risk disclosing their absence.
     pub fn observe(&mut self, data: D) -> i64 {
-        self.shared.borrow_mut().observe(data)
+        self.shared.borrow().observe(data)
+    }
+
+    /// Observes the noised count associated with `data`, as of the timestamp `time`.
+    ///
+    /// The count reflects exactly the true records at timestamps less or equal to `time`, provided
+    /// the probe has passed `time`, even if later timestamps have since completed. The noise is that
+    /// of `observe`, and so observations at different times differ only by changes in the data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time` is not of the timestamp type of the measured dataflow.
+    pub fn observe_at<T: Timestamp>(&mut self, time: &T, data: D) -> i64 {
+        self.shared.borrow().observe_at(time, data)
+    }
+
+    /// The most recent noisy count returned for a query of `data` from this worker.
+    ///
+    /// Only measurements made by `Dataset::measure_cluster` receive answers, and only for the
+    /// elements sent as queries.
+    pub fn answer(&self, data: &D) -> Option<i64> {
+        self.shared.borrow().answers.get(data).cloned()
+    }
+
+    /// Retires the measurement, withdrawing its error from the total and releasing its counts.
+    ///
+    /// This allows an analysis to abandon a measurement mid-run, for example one found not to be
+    /// worth its budget before it is observed, without stopping the computation. The measured
+    /// counts, their history, and any answers are released, and callbacks awaiting the measurement
+    /// are dropped without being called. The operators of the measurement remain in the dataflow,
+    /// as timely cannot remove them, and discard their inputs from now on. Only the measurement's
+    /// own state is released: the operators producing the measured dataset are unaffected, and
+    /// continue to hold whatever state they maintain, for example the records of a join.
+    /// For measurements made by `Dataset::measure_cluster` each worker should retire its own, and
+    /// the withdrawal reaches the totals of other workers once further updates are applied.
+    pub fn retire(self) {
+        self.shared.borrow_mut().retire();
+    }
+
+    /// Calls `callback` with the measurement once it reflects all inputs up to `time`.
+    ///
+    /// The callback is invoked as the worker steps, once neither the true nor the synthetic
+    /// records can change at timestamps less or equal to `time`, which is when a probe of the
+    /// measurement would pass `time`. This allows event-driven code to react to measurements
+    /// without itself polling a probe; the worker must still be stepped for the callback to fire.
+    ///
+    /// # Panics
+    ///
+    /// The worker panics if `time` is not of the timestamp type of the measured dataflow.
+    pub fn when_stable<T: Timestamp, F: FnOnce(&mut Measurement<D>)+'static>(&self, time: T, callback: F) where D: 'static {
+        let mut callback = Some(callback);
+        let callback: Callback<D> = Box::new(move |measurement| (callback.take().unwrap())(measurement));
+        self.shared.borrow_mut().waiting.push((Box::new(time), callback));
+    }
+
+    /// The scale of the noise, as a multiple of `noise::SCALE`.
+    pub fn scale(&self) -> f64 {
+        self.shared.borrow().scale
+    }
+
+    /// Sets the scale of the noise, as a multiple of `noise::SCALE`, before any updates arrive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the measurement has already received updates.
+    pub fn set_scale(&mut self, scale: f64) {
+        self.shared.borrow_mut().set_scale(scale);
+    }
+
+    /// The policy for negative counts.
+    pub fn negative(&self) -> Negative {
+        self.shared.borrow().negative
+    }
+
+    /// Sets the policy for negative counts, before any updates arrive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the measurement has already received updates.
+    pub fn set_negative(&mut self, negative: Negative) {
+        self.shared.borrow_mut().set_negative(negative);
+    }
+
+    /// Exports the keys whose noisy counts exceed `threshold`, with their noisy counts.
+    ///
+    /// Only keys with non-zero true counts are considered, on this worker. With a threshold from
+    /// `budget::threshold(weight, delta)`, where `weight` bounds the weight any one input record
+    /// contributes to a key, the export reveals which keys are common at the additional cost of
+    /// `keys * delta`, where `keys` bounds the number of keys to which any one input record
+    /// contributes. The cost is not charged to any odometer; `release` charges it. The keys are not
+    /// sorted, and their order should not be relied upon.
+    pub fn release_above(&self, threshold: i64) -> Vec<(D, i64)> where D: Clone {
+        self.shared.borrow().above(threshold)
+    }
+
+    /// Exports the keys whose noisy counts exceed a threshold calibrated to `weight` and `delta`,
+    /// charging `keys * delta` to `odometer`.
+    ///
+    /// Here `weight` bounds the weight any one input record contributes to a key, and `keys` the
+    /// number of keys to which it contributes; each such key may be released with probability up
+    /// to `delta` were the record absent. The threshold accounts for the scale of the measurement's
+    /// noise.
+    pub fn release(&self, weight: i64, keys: usize, delta: f64, odometer: &mut Odometer) -> Result<Vec<(D, i64)>, Exhausted> where D: Clone {
+        assert!(keys > 0);
+        odometer.charge_delta("Release", (keys as f64) * delta)?;
+        let margin = (budget::threshold(0, delta) as f64) * self.scale();
+        Ok(self.release_above(weight + margin.ceil() as i64))
+    }
+}
+
+impl Measurement<usize> {
+    /// Observes the sum of the noisy counts of the indices in `range`, with the standard deviation
+    /// of its noise.
+    ///
+    /// The noise of each index is independent Laplace noise of scale `scale * noise::SCALE`, and
+    /// so the standard deviation of the sum grows with the square root of the length of the range.
+    /// The difference of two sums, for example of cumulative counts, has variance the sum of their
+    /// variances when their ranges are disjoint, and the variance of their symmetric difference
+    /// otherwise.
+    pub fn observe_range(&mut self, range: Range<usize>) -> (i64, f64) {
+        let state = self.shared.borrow();
+        let length = range.end.saturating_sub(range.start);
+        let sum = range.map(|index| state.observe(index)).sum();
+        let deviation = (2.0 * length as f64).sqrt() * state.scale * (noise::SCALE as f64);
+        (sum, deviation)
+    }
+}
+
+/// A noisy measurement over an explicitly enumerated, public domain.
+///
+/// Counts are addressed by their position in the domain, and may be exported in domain order.
+pub struct NoisyHistogram<D: Hash+Eq> {
+    domain: Vec<D>,
+    shared: Rc<RefCell<MeasurementState<D>>>,
+}
+
+impl<D: Hash+Eq+Clone> NoisyHistogram<D> {
+    /// Binds `measurement` to `domain`.
+    pub fn new(measurement: Measurement<D>, domain: Vec<D>) -> Self {
+        NoisyHistogram {
+            domain: domain,
+            shared: measurement.shared,
+        }
+    }
+    /// The domain elements, in the order used for indexing.
+    pub fn domain(&self) -> &[D] { &self.domain[..] }
+    /// The number of domain elements.
+    pub fn len(&self) -> usize { self.domain.len() }
+    /// Indicates whether the domain is empty.
+    pub fn is_empty(&self) -> bool { self.domain.is_empty() }
+    /// Returns the noisy count of the `index`-th domain element.
+    pub fn get(&self, index: usize) -> i64 {
+        self.shared.borrow().observe(self.domain[index].clone())
+    }
+    /// Returns the noisy counts of all domain elements, in domain order.
+    pub fn counts(&self) -> Vec<i64> {
+        let borrow = self.shared.borrow();
+        self.domain.iter().map(|d| borrow.observe(d.clone())).collect()
+    }
+    /// Returns pairs of domain elements and their noisy counts, in domain order.
+    pub fn export(&self) -> Vec<(D, i64)> {
+        let borrow = self.shared.borrow();
+        self.domain.iter().map(|d| (d.clone(), borrow.observe(d.clone()))).collect()
     }
 }
 
-// generates a sample from the Laplace distribution
-fn laplace() -> i64 {
+/// Error functions for scoring synthetic data against measurements.
+///
+/// Each function maps the discrepancy `synth - truth` of one measured element to a non-negative
+/// cost, in the same units as the total error passed to `measure`.
+pub mod error {
+
+    /// The absolute error, `|x|`; this is the default objective.
+    pub fn absolute(x: i64) -> i64 { x.abs() }
 
-    use rand::Rng;
+    /// The squared error, `x^2`, saturating rather than overflowing.
+    ///
+    /// Weights are commonly large (e.g. `i32::max_value() / 10` per record), and the squares of
+    /// discrepancies of even a few records exceed the range of an `i64`.
+    pub fn squared(x: i64) -> i64 { x.saturating_mul(x) }
 
-    // TODO: Replace with independent bit flipping.
-    let mut rng = ::rand::thread_rng();
-    let logarithm: f64 = rng.gen::<f64>().ln();
-    let result = (logarithm * (i32::max_value() as f64)) as i64;
-    if rng.gen() { result } else { -result }
-}
\ No newline at end of file
+    /// The Huber loss with threshold `delta`, scaled to agree with `absolute` for large errors.
+    ///
+    /// Discrepancies smaller than `delta` are penalized quadratically, as `x^2 / (2 delta)`, and
+    /// larger discrepancies linearly, as `|x| - delta / 2`.
+    pub fn huber(delta: i64) -> impl Fn(i64)->i64 {
+        assert!(delta > 0);
+        move |x| {
+            let x = x.abs();
+            if x <= delta { ((x as i128 * x as i128) / (2 * delta as i128)) as i64 }
+            else { x - delta / 2 }
+        }
+    }
+}
+
+mod tests {
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use super::{MeasurementState, Negative, error};
+
+    // a measurement whose noise truncates to zero, with its total error; each unit of discrepancy
+    // is scored as `1 << 40`.
+    fn state(negative: Negative) -> (MeasurementState<u8>, Rc<RefCell<i64>>) {
+        let total = Rc::new(RefCell::new(0));
+        let mut state = MeasurementState::new(&total, error::absolute, 1.0 / (1u64 << 40) as f64);
+        state.set_negative(negative);
+        (state, total)
+    }
+
+    // inserts five records, then retracts three at a time, eight times over.
+    fn retract(state: &mut MeasurementState<u8>) {
+        state.update_truth(0, 5);
+        for _ in 0 .. 8 { state.update_truth(0, -3); }
+    }
+
+    #[test]
+    fn test_negative_signed() {
+        let (mut state, total) = state(Negative::Signed);
+        retract(&mut state);
+        assert_eq!(state.observe(0), -19);
+        assert_eq!(*total.borrow(), 19 << 40);
+        state.update_synth(0, -19);
+        assert_eq!(*total.borrow(), 0);
+    }
+
+    #[test]
+    fn test_negative_clamp() {
+        let (mut state, total) = state(Negative::Clamp);
+        retract(&mut state);
+        assert_eq!(state.observe(0), 0);
+        assert_eq!(*total.borrow(), 0);
+        assert!(state.above(-1).is_empty());
+        // the signed count is retained, and further insertions must first restore it.
+        state.update_truth(0, 20);
+        assert_eq!(state.observe(0), 1);
+        assert_eq!(*total.borrow(), 1 << 40);
+        state.update_truth(0, -20);
+        state.update_synth(0, -4);
+        assert_eq!(*total.borrow(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_negative_panic() {
+        let (mut state, _total) = state(Negative::Panic);
+        retract(&mut state);
+    }
+
+    #[test]
+    fn test_observe_range() {
+        use super::Measurement;
+        let total = Rc::new(RefCell::new(0));
+        let mut state = MeasurementState::new(&total, error::absolute, 1.0 / (1u64 << 40) as f64);
+        for &(index, count) in [(0usize, 3), (1, 5), (2, -2), (4, 7)].iter() {
+            state.update_truth(index, count);
+        }
+        let mut measurement = Measurement { shared: Rc::new(RefCell::new(state)) };
+        assert_eq!(measurement.observe_range(0 .. 3).0, 6);
+        assert_eq!(measurement.observe_range(1 .. 5).0, 10);
+        assert_eq!(measurement.observe_range(3 .. 3), (0, 0.0));
+    }
+
+    #[test]
+    fn test_observe_range_deviation() {
+        use super::Measurement;
+        use noise::SCALE;
+        let total = Rc::new(RefCell::new(0));
+        let state = MeasurementState::new(&total, error::absolute, 1.0);
+        let mut measurement = Measurement { shared: Rc::new(RefCell::new(state)) };
+        assert_eq!(measurement.observe_range(0 .. 8).1, 4.0 * SCALE as f64);
+
+        // the sums of disjoint ranges of four empty indices are pure noise, with the reported deviation.
+        let sums = (0 .. 2000).map(|range| measurement.observe_range(4 * range .. 4 * range + 4)).collect::<Vec<_>>();
+        let deviation = sums[0].1;
+        let variance = sums.iter().map(|x| (x.0 as f64) * (x.0 as f64)).sum::<f64>() / sums.len() as f64;
+        assert!((variance.sqrt() / deviation - 1.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,56 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rand::RngCore;
+
+use timely::ExchangeData;
+use timely::dataflow::{Scope, Stream, ProbeHandle};
+
+use aggregate::{private_median_weighted, noisy_mean_auto};
+use budget::{Exhausted, Odometer};
+use super::gather::gather;
+
+/// Gathers the weighted values of `stream` at worker zero, to back a private median.
+pub fn median<G: Scope, D: ExchangeData+Ord+Hash>(
+    stream: &Stream<G, (D,i64)>,
+    handle: &mut ProbeHandle<G::Timestamp>) -> Median<D>
+{
+    Median { shared: gather(stream, handle, "Median") }
+}
+
+/// The state backing a private median of sensitive data.
+///
+/// All values are gathered at worker zero, and observations should only be made there; other
+/// workers hold no values.
+pub struct Median<D: Hash+Eq> {
+    shared: Rc<RefCell<HashMap<D, i64>>>,
+}
+
+impl<D: Hash+Eq> Median<D> {
+    /// Observes an `epsilon`-differentially private median of `value` applied to the data.
+    ///
+    /// The result is clamped to `[lower, upper]`, and `unit` is the largest weight of any input
+    /// record, amplified by the stability of the pipeline. Each observation consumes `epsilon`.
+    pub fn observe<F: Fn(&D)->f64>(&self, value: F, unit: i64, lower: f64, upper: f64, epsilon: f64, rng: &mut dyn RngCore) -> f64 {
+        let values = self.shared.borrow().iter().filter(|x| *x.1 > 0).map(|(d, w)| (value(d), *w)).collect::<Vec<_>>();
+        private_median_weighted(&values[..], unit, lower, upper, epsilon, rng)
+    }
+
+    /// Observes a private mean of `value` applied to the data, and the clipping bound used,
+    /// charging `epsilon_bound + epsilon_mean` to `odometer`.
+    ///
+    /// Values are clipped to `[lower, bound]`, where `bound` is a private `quantile` of the values
+    /// within `[lower, upper]` costing `epsilon_bound`, and the clipped values are summed and
+    /// counted with noise costing `epsilon_mean`. As for `observe`, `unit` is the largest weight of
+    /// any input record amplified by the stability of the pipeline. Nothing is observed or charged
+    /// if the odometer cannot afford both.
+    pub fn observe_mean<F: Fn(&D)->f64>(&self, value: F, unit: i64, lower: f64, upper: f64, quantile: f64, epsilon_bound: f64, epsilon_mean: f64, odometer: &mut Odometer, rng: &mut dyn RngCore) -> Result<(f64, f64), Exhausted> {
+        odometer.check("MeanBound", epsilon_bound + epsilon_mean)?;
+        odometer.charge("MeanBound", epsilon_bound)?;
+        odometer.charge("Mean", epsilon_mean)?;
+        let values = self.shared.borrow().iter().filter(|x| *x.1 > 0).map(|(d, w)| (value(d), *w)).collect::<Vec<_>>();
+        Ok(noisy_mean_auto(&values[..], unit, lower, upper, quantile, epsilon_bound, epsilon_mean, rng))
+    }
+}
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
 use std::hash::Hash;
 
+use fnv::FnvHashMap;
+
 use timely::ExchangeData;
 use timely::dataflow::{Scope, Stream};
 use timely::dataflow::channels::pact::Exchange;
@@ -9,12 +10,14 @@ use timely::dataflow::operators::generic::FrontieredInputHandle;
 use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
 
 use super::super::fnv_hash;
+use instrument;
 
 pub fn min_max<G: Scope, D: ExchangeData+Eq+Hash>(
     stream1: &Stream<G, (D, i64)>,
     stream2: &Stream<G, (D, i64)>) -> (Stream<G, (D, i64)>, Stream<G, (D, i64)>)
 {
-    let mut state = HashMap::<D, (i64, i64)>::new();
+    let id = instrument::register("MinMax", true);
+    let mut state = FnvHashMap::<D, (i64, i64)>::default();
 
     let exchange1 = Exchange::new(|x: &(D,i64)| fnv_hash(&x.0));
     let exchange2 = Exchange::new(|x: &(D,i64)| fnv_hash(&x.0));
@@ -37,12 +40,15 @@ pub fn min_max<G: Scope, D: ExchangeData+Eq+Hash>(
 
             while let Some((time, data)) = input_handle1.next() {
 
+                instrument::batch(id, &data[..]);
+                state.reserve(data.len());
+
                 let mut session1 = output_handle1.session(&time);
                 let mut session2 = output_handle2.session(&time);
 
                 for (key, delta) in data.drain(..) {
 
-                    let mut entry = state.entry(key.clone()).or_insert((0, 0));
+                    let entry = state.entry(key.clone()).or_insert((0, 0));
 
                     let mut min_change = ::std::cmp::min(entry.0, entry.1);
                     let mut max_change = ::std::cmp::max(entry.0, entry.1);
@@ -50,6 +56,11 @@ pub fn min_max<G: Scope, D: ExchangeData+Eq+Hash>(
                     min_change -= ::std::cmp::min(entry.0, entry.1);
                     max_change -= ::std::cmp::max(entry.0, entry.1);
 
+                    // discard state for records with no remaining weight.
+                    if *entry == (0, 0) {
+                        state.remove(&key);
+                    }
+
                     if min_change != 0 {
                         session1.give((key.clone(), min_change));
                     }
@@ -62,12 +73,15 @@ pub fn min_max<G: Scope, D: ExchangeData+Eq+Hash>(
 
             while let Some((time, data)) = input_handle2.next() {
 
+                instrument::batch(id, &data[..]);
+                state.reserve(data.len());
+
                 let mut session1 = output_handle1.session(&time);
                 let mut session2 = output_handle2.session(&time);
 
                 for (key, delta) in data.drain(..) {
 
-                    let mut entry = state.entry(key.clone()).or_insert((0, 0));
+                    let entry = state.entry(key.clone()).or_insert((0, 0));
 
                     let mut min_change = ::std::cmp::min(entry.0, entry.1);
                     let mut max_change = ::std::cmp::max(entry.0, entry.1);
@@ -75,6 +89,11 @@ pub fn min_max<G: Scope, D: ExchangeData+Eq+Hash>(
                     min_change -= ::std::cmp::min(entry.0, entry.1);
                     max_change -= ::std::cmp::max(entry.0, entry.1);
 
+                    // discard state for records with no remaining weight.
+                    if *entry == (0, 0) {
+                        state.remove(&key);
+                    }
+
                     if min_change != 0 {
                         session1.give((key.clone(), min_change));
                     }
@@ -83,6 +102,8 @@ pub fn min_max<G: Scope, D: ExchangeData+Eq+Hash>(
                     }
                 }
             }
+
+            instrument::state::<(D, (i64, i64))>(id, state.len());
         }
     });
 
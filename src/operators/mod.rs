@@ -2,4 +2,14 @@ pub mod flat_map;
 pub mod join;
 pub mod measure;
 pub mod shave;
-pub mod min_max;
\ No newline at end of file
+pub mod min_max;
+pub mod spill;
+pub mod rounding;
+pub mod median;
+pub mod gather;
+pub mod bound;
+pub mod continual;
+pub mod window;
+pub mod sample;
+#[cfg(feature = "columnar")]
+pub mod columnar;
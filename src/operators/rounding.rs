@@ -0,0 +1,78 @@
+use super::super::fnv_hash;
+
+/// The rounding applied when operators divide weights.
+///
+/// Operators like `join` and `flat_map` scale weights down, and must round the results to integers.
+/// Truncation is simple, but biases weights towards zero, which can matter when weights are later
+/// shaved near bucket boundaries.
+///
+/// All modes round negative quantities as the negation of the corresponding positive quantity, and
+/// are deterministic functions of their inputs, so that retractions exactly cancel the records
+/// they retract.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Rounds towards zero.
+    Truncate,
+    /// Rounds to the nearest integer, with ties rounded away from zero.
+    Nearest,
+    /// Rounds up or down at random, with probabilities that make the expected result exact.
+    ///
+    /// The random choice is determined by the seed, the quantity, and a salt identifying the
+    /// record, so that the same record and quantity always round the same way.
+    Stochastic(u64),
+}
+
+impl Default for Rounding {
+    fn default() -> Self { Rounding::Truncate }
+}
+
+impl Rounding {
+    /// Divides `numerator` by the positive `denominator`, rounding the result.
+    pub fn divide(&self, salt: u64, numerator: i128, denominator: i128) -> i64 {
+        assert!(denominator > 0);
+        let magnitude = numerator.abs();
+        let quotient = magnitude / denominator;
+        let remainder = magnitude % denominator;
+        let rounded = match *self {
+            Rounding::Truncate => quotient,
+            Rounding::Nearest => if 2 * remainder >= denominator { quotient + 1 } else { quotient },
+            Rounding::Stochastic(seed) => {
+                // a uniform draw from 0 .. denominator, determined by the inputs.
+                let hash = fnv_hash(&(seed, salt, magnitude, denominator)) as u128;
+                let draw = ((hash * denominator as u128) >> 64) as i128;
+                if draw < remainder { quotient + 1 } else { quotient }
+            },
+        };
+        (if numerator < 0 { -rounded } else { rounded }) as i64
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_rounding_symmetric() {
+        use super::Rounding;
+        for &mode in [Rounding::Truncate, Rounding::Nearest, Rounding::Stochastic(0)].iter() {
+            for numerator in -20 .. 20 {
+                assert_eq!(mode.divide(7, numerator, 6), -mode.divide(7, -numerator, 6));
+            }
+        }
+    }
+
+    #[test]
+    fn test_rounding_nearest() {
+        use super::Rounding;
+        assert_eq!(Rounding::Nearest.divide(0, 7, 3), 2);
+        assert_eq!(Rounding::Nearest.divide(0, 8, 3), 3);
+        assert_eq!(Rounding::Nearest.divide(0, -3, 2), -2);
+        assert_eq!(Rounding::Truncate.divide(0, 8, 3), 2);
+    }
+
+    #[test]
+    fn test_rounding_stochastic() {
+        use super::Rounding;
+        // the average over many salts should be close to the exact quotient.
+        let total: i64 = (0 .. 10000).map(|salt| Rounding::Stochastic(3).divide(salt, 1, 4)).sum();
+        assert!(total > 2000 && total < 3000);
+    }
+}
@@ -0,0 +1,89 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rand::Rng;
+
+use timely::ExchangeData;
+use timely::dataflow::{Scope, Stream, ProbeHandle};
+use timely::dataflow::operators::{Operator, Probe};
+use timely::dataflow::channels::pact::Exchange;
+
+use noise::NoiseSource;
+use instrument;
+
+/// Gathers the weighted records of `stream` at worker zero, to draw samples from.
+pub fn sample<G: Scope, D: ExchangeData+Ord+Hash>(
+    stream: &Stream<G, (D,i64)>,
+    handle: &mut ProbeHandle<G::Timestamp>) -> Sample<D>
+{
+    let shared = Rc::new(RefCell::new(HashMap::new()));
+    let state = shared.clone();
+
+    stream.unary::<(),_,_,_>(Exchange::new(|_x: &(D,i64)| 0), "Sample", |_,_| {
+
+        let id = instrument::register("Sample", true);
+
+        move |input, _output| {
+            let mut borrow = state.borrow_mut();
+            input.for_each(|_time, data| {
+                instrument::batch(id, &data[..]);
+                for (datum, delta) in data.drain(..) {
+                    let remove = {
+                        let weight = borrow.entry(datum.clone()).or_insert(0);
+                        *weight += delta;
+                        *weight == 0
+                    };
+                    if remove { borrow.remove(&datum); }
+                }
+            });
+            instrument::state::<(D, i64)>(id, borrow.len());
+        }
+    })
+    .probe_with(handle);
+
+    Sample { shared: shared }
+}
+
+/// The accumulated synthetic records of a dataset, from which weighted samples may be drawn.
+///
+/// All records are gathered at worker zero, and samples should only be drawn there; other workers
+/// hold no records.
+pub struct Sample<D: Hash+Eq> {
+    shared: Rc<RefCell<HashMap<D, i64>>>,
+}
+
+impl<D: Ord+Hash+Clone> Sample<D> {
+    /// The number of distinct records with non-zero weight.
+    pub fn len(&self) -> usize { self.shared.borrow().len() }
+    /// Indicates whether there are no records with non-zero weight.
+    pub fn is_empty(&self) -> bool { self.shared.borrow().is_empty() }
+    /// The records with non-zero weight, with their weights, in order of record.
+    pub fn records(&self) -> Vec<(D, i64)> {
+        let mut records = self.shared.borrow().iter().map(|(d, w)| (d.clone(), *w)).collect::<Vec<_>>();
+        records.sort();
+        records
+    }
+    /// Draws up to `count` distinct records without replacement, with probability by weight.
+    ///
+    /// Records are drawn in turn, each with probability proportional to its weight among those not
+    /// yet drawn, which is done by giving each record the key `u^(1/weight)` for a uniform `u` and
+    /// keeping those with the largest keys. The value `u` for each record is determined by `seed`
+    /// and the record, and so the same seed draws the same sample from the same records, however
+    /// they were distributed or ordered. Records with negative weight are not drawn.
+    pub fn draw(&self, count: usize, seed: [u8; 32]) -> Vec<D> {
+        let source = NoiseSource::from_seed(seed);
+        let mut keyed = self.shared.borrow()
+            .iter()
+            .filter(|x| *x.1 > 0)
+            .map(|(datum, weight)| {
+                let uniform: f64 = source.rng(datum).gen();
+                (uniform.ln() / (*weight as f64), datum.clone())
+            })
+            .collect::<Vec<_>>();
+        keyed.sort_by(|x, y| y.0.partial_cmp(&x.0).expect("keys must not be NaN").then_with(|| x.1.cmp(&y.1)));
+        keyed.truncate(count);
+        keyed.into_iter().map(|x| x.1).collect()
+    }
+}
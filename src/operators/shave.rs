@@ -1,27 +1,57 @@
 use std::cmp::{min, max};
-use std::collections::HashMap;
 use std::hash::Hash;
 
+use fnv::FnvHashMap;
+
 use timely::ExchangeData;
 use timely::dataflow::{Scope, Stream};
 use timely::dataflow::operators::Operator;
-use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::channels::pact::{Exchange, Pipeline, ParallelizationContract};
 
 use super::super::fnv_hash;
 use super::super::merge_sort::MergeSorter;
+use instrument;
 
 use std::ops::DerefMut;
 
-pub fn shave<G: Scope, D: ExchangeData+Ord+Hash>(stream: &Stream<G, (D,i64)>, width: i64) -> Stream<G, ((D, usize), i64)> {
+pub fn shave<G: Scope, D: ExchangeData+Ord+Hash>(stream: &Stream<G, (D,i64)>, width: i64, exchange: bool) -> Stream<G, ((D, usize), i64)> {
+    shave_by(stream, move |_| width, exchange)
+}
+
+/// Shaves each datum into slices of a width determined by the datum.
+pub fn shave_by<G, D, W>(stream: &Stream<G, (D,i64)>, width: W, exchange: bool) -> Stream<G, ((D, usize), i64)>
+where
+    G: Scope,
+    D: ExchangeData+Ord+Hash,
+    W: Fn(&D)->i64+'static,
+{
+    // If `exchange` is false, records must already be partitioned so that all updates to each
+    // datum reside on one worker, as the accumulated weight of each datum is held locally.
+    if exchange {
+        shave_pact(stream, Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), true, width)
+    }
+    else {
+        shave_pact(stream, Pipeline, false, width)
+    }
+}
 
-    stream.unary(Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), "Shave", |_,_| {
+fn shave_pact<G, D, P, W>(stream: &Stream<G, (D,i64)>, pact: P, exchange: bool, width: W) -> Stream<G, ((D, usize), i64)>
+where
+    G: Scope,
+    D: ExchangeData+Ord+Hash,
+    P: ParallelizationContract<G::Timestamp, (D,i64)>,
+    W: Fn(&D)->i64+'static,
+{
+    stream.unary(pact, "Shave", |_,_| {
 
-        let mut state = HashMap::new();
-        let mut sorters = HashMap::new();
+        let id = instrument::register("Shave", exchange);
+        let mut state = FnvHashMap::default();
+        let mut sorters = FnvHashMap::default();
 
         move |input, output| {
 
             while let Some((time, data)) = input.next() {
+                instrument::batch(id, &data[..]);
                 sorters
                     .entry(time.retain())
                     .or_insert(MergeSorter::new())
@@ -36,9 +66,13 @@ pub fn shave<G: Scope, D: ExchangeData+Ord+Hash>(stream: &Stream<G, (D,i64)>, wi
 
                 let mut session = output.session(&time);
 
+                state.reserve(dataz.iter().map(|x| x.len()).sum());
+
                 for data in dataz.into_iter() {
                 for (datum, mut delta) in data.into_iter() {
 
+                    let width = width(&datum);
+                    assert!(width > 0, "shave width must be positive");
                     let weight = state.entry(datum.clone()).or_insert(0);
 
                     // increment `weight`.
@@ -58,9 +92,16 @@ pub fn shave<G: Scope, D: ExchangeData+Ord+Hash>(stream: &Stream<G, (D,i64)>, wi
                         *weight += change;
                         session.give(((datum.clone(), index as usize), change));
                     }
+
+                    // discard state for records with no remaining weight.
+                    if *weight == 0 {
+                        state.remove(&datum);
+                    }
                 }
                 }
             }
+
+            instrument::state::<(D, i64)>(id, state.len());
         }
     })
 }
\ No newline at end of file
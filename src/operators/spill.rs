@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use abomonation::{Abomonation, encode, decode};
+use fnv::FnvHashMap;
+
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A map that retains at most `limit` entries in memory, spilling the rest to disk.
+///
+/// Entries are spilled in least-recently-used order to a temporary file, and are restored to memory
+/// when next accessed. The temporary file is only created once an entry is first spilled, readable
+/// only by its owner, and on unix is unlinked as soon as it is opened; elsewhere it is removed when
+/// the map is dropped. Spilled entries are appended to the file, and the file is compacted once the
+/// space of restored and removed entries exceeds that of the entries it still holds. With no limit,
+/// recency is not tracked and the map behaves as a `HashMap`.
+pub struct SpillMap<K: Hash+Eq, V> {
+    resident: FnvHashMap<K, (V, u64)>,
+    recency: BTreeMap<u64, K>,
+    spilled: FnvHashMap<K, (u64, usize)>,
+    clock: u64,
+    limit: Option<usize>,
+    file: Option<Spill>,
+}
+
+// a spill file, with the path to remove if it is not unlinked, its length, and its live bytes.
+struct Spill {
+    file: File,
+    path: Option<PathBuf>,
+    end: u64,
+    live: u64,
+}
+
+impl Spill {
+
+    fn create() -> Self {
+        let mut path = ::std::env::temp_dir();
+        path.push(format!("wpinq-spill-{}-{}", ::std::process::id(), SPILL_FILES.fetch_add(1, Ordering::SeqCst)));
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+        #[cfg(unix)]
+        { use std::os::unix::fs::OpenOptionsExt; options.mode(0o600); }
+        let file = options.open(&path).expect("failed to create spill file");
+        // on unix the open file remains usable, and is reclaimed when closed, even by a crash.
+        #[cfg(unix)]
+        let path = { ::std::fs::remove_file(&path).expect("failed to unlink spill file"); None };
+        #[cfg(not(unix))]
+        let path = Some(path);
+        Spill { file: file, path: path, end: 0, live: 0 }
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> u64 {
+        let offset = self.end;
+        self.file.seek(SeekFrom::Start(offset)).expect("spill seek failed");
+        self.file.write_all(bytes).expect("spill write failed");
+        self.end += bytes.len() as u64;
+        self.live += bytes.len() as u64;
+        offset
+    }
+
+    // reads `length` bytes at `offset` into a buffer aligned for any abomonated type.
+    fn fetch(&mut self, offset: u64, length: usize) -> Vec<u64> {
+        let mut buffer = vec![0u64; (length + 7) / 8];
+        {
+            let bytes = unsafe { ::std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, length) };
+            self.file.seek(SeekFrom::Start(offset)).expect("spill seek failed");
+            self.file.read_exact(bytes).expect("spill read failed");
+        }
+        buffer
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.path {
+            let _ = ::std::fs::remove_file(path);
+        }
+    }
+}
+
+impl<K: Abomonation+Hash+Eq+Clone, V: Abomonation+Clone> SpillMap<K, V> {
+
+    /// Creates a map retaining at most `limit` entries in memory, or all entries if `None`.
+    pub fn new(limit: Option<usize>) -> Self {
+        assert!(limit != Some(0));
+        SpillMap {
+            resident: FnvHashMap::default(),
+            recency: BTreeMap::new(),
+            spilled: FnvHashMap::default(),
+            clock: 0,
+            limit: limit,
+            file: None,
+        }
+    }
+
+    /// The number of entries retained in memory.
+    pub fn resident(&self) -> usize { self.resident.len() }
+
+    /// Reserves memory for `additional` further resident entries, up to the limit.
+    pub fn reserve(&mut self, additional: usize) {
+        let additional = match self.limit {
+            Some(limit) => ::std::cmp::min(additional, limit.saturating_sub(self.resident.len())),
+            None => additional,
+        };
+        self.resident.reserve(additional);
+    }
+
+    /// Returns the value for `key`, restoring it from disk or inserting `default()` if needed.
+    pub fn get_or_insert_with<F: FnOnce()->V>(&mut self, key: K, default: F) -> &mut V {
+
+        self.clock += 1;
+        let clock = self.clock;
+        let tracking = self.limit.is_some();
+
+        if !self.resident.contains_key(&key) {
+            let value = match self.spilled.remove(&key) {
+                Some(location) => self.read(location),
+                None => default(),
+            };
+            self.resident.insert(key.clone(), (value, clock));
+            if tracking { self.recency.insert(clock, key.clone()); }
+            self.evict();
+        }
+        else if tracking {
+            let entry = self.resident.get_mut(&key).unwrap();
+            self.recency.remove(&entry.1);
+            entry.1 = clock;
+            self.recency.insert(clock, key.clone());
+        }
+
+        &mut self.resident.get_mut(&key).unwrap().0
+    }
+
+    /// Removes the entry for `key`, whether resident or spilled.
+    pub fn remove(&mut self, key: &K) {
+        if let Some((_, stamp)) = self.resident.remove(key) {
+            self.recency.remove(&stamp);
+        }
+        if let Some((_, length)) = self.spilled.remove(key) {
+            self.release(length);
+        }
+    }
+
+    // spills least recently used entries until at most `limit` remain resident.
+    fn evict(&mut self) {
+        if let Some(limit) = self.limit {
+            while self.resident.len() > limit {
+                let stamp = *self.recency.keys().next().unwrap();
+                let key = self.recency.remove(&stamp).unwrap();
+                let (value, _) = self.resident.remove(&key).unwrap();
+                let location = self.write(&value);
+                self.spilled.insert(key, location);
+            }
+        }
+    }
+
+    fn write(&mut self, value: &V) -> (u64, usize) {
+        let mut bytes = Vec::new();
+        unsafe { encode(value, &mut bytes).expect("spill encoding failed"); }
+        if self.file.is_none() { self.file = Some(Spill::create()); }
+        let offset = self.file.as_mut().unwrap().append(&bytes[..]);
+        (offset, bytes.len())
+    }
+
+    fn read(&mut self, (offset, length): (u64, usize)) -> V {
+        let mut buffer = self.file.as_mut().unwrap().fetch(offset, length);
+        let value = {
+            let bytes = unsafe { ::std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, length) };
+            let decoded = unsafe { decode::<V>(bytes) };
+            decoded.expect("spill decoding failed").0.clone()
+        };
+        self.release(length);
+        value
+    }
+
+    // notes that `length` spilled bytes are no longer live, compacting the file if mostly garbage.
+    fn release(&mut self, length: usize) {
+        let compact = {
+            let spill = self.file.as_mut().unwrap();
+            spill.live -= length as u64;
+            // with nothing live, later entries may simply overwrite the file.
+            if spill.live == 0 { spill.end = 0; }
+            spill.end - spill.live > spill.live
+        };
+        if compact { self.compact(); }
+    }
+
+    // copies the live spilled entries to a new file, replacing the current one.
+    fn compact(&mut self) {
+        let mut old = self.file.take().unwrap();
+        let mut new = Spill::create();
+        for location in self.spilled.values_mut() {
+            let buffer = old.fetch(location.0, location.1);
+            let bytes = unsafe { ::std::slice::from_raw_parts(buffer.as_ptr() as *const u8, location.1) };
+            location.0 = new.append(bytes);
+        }
+        self.file = Some(new);
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_spill() {
+        use super::SpillMap;
+        let mut map = SpillMap::<u64, Vec<u64>>::new(Some(2));
+        for round in 0 .. 10 {
+            for key in 0 .. 10 {
+                map.get_or_insert_with(key, || Vec::new()).push(key * round);
+            }
+        }
+        map.remove(&3);
+        assert_eq!(map.resident(), 2);
+        for key in 0 .. 10 {
+            let expected = if key == 3 { vec![] } else { (0 .. 10).map(|round| key * round).collect() };
+            assert_eq!(map.get_or_insert_with(key, || Vec::new()), &expected);
+        }
+        // the file holds at most twice the bytes of the entries still spilled.
+        let spill = map.file.as_ref().unwrap();
+        assert!(spill.end <= 2 * spill.live);
+    }
+}
@@ -0,0 +1,127 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use timely::ExchangeData;
+use timely::dataflow::{Scope, Stream, ProbeHandle};
+use timely::dataflow::operators::{Operator, Probe};
+use timely::dataflow::channels::pact::Exchange;
+
+use super::super::fnv_hash;
+use noise::{self, NoiseSource};
+use instrument;
+
+/// Accumulates the weights of `stream` by epoch, retaining only the most recent `window` epochs.
+///
+/// The epoch of each timestamp is given by `epoch`, and must not decrease as timestamps advance.
+/// Epochs are complete once the frontier passes them, and the weights of epochs more than
+/// `window` epochs before the first incomplete epoch are retired.
+pub fn window<G, D, F>(
+    stream: &Stream<G, (D,i64)>,
+    handle: &mut ProbeHandle<G::Timestamp>,
+    epoch: F,
+    window: usize,
+    scale: f64) -> Windowed<D>
+where
+    G: Scope,
+    D: ExchangeData+Hash+Eq,
+    F: Fn(&G::Timestamp)->usize+'static,
+{
+    assert!(window > 0);
+    let shared = Rc::new(RefCell::new(WindowState {
+        source: noise::next_source(),
+        scale: scale,
+        window: window,
+        epochs: VecDeque::new(),
+        complete: 0,
+    }));
+    let state = shared.clone();
+
+    stream.unary_frontier::<(),_,_,_>(Exchange::new(|x: &(D,i64)| fnv_hash(&x.0)), "Window", |_,_| {
+
+        let id = instrument::register("Window", true);
+
+        move |input, _output| {
+            let mut state = state.borrow_mut();
+            while let Some((time, data)) = input.next() {
+                instrument::batch(id, &data[..]);
+                let epoch = epoch(time.time());
+                let weights = state.epoch_mut(epoch);
+                for (datum, delta) in data.drain(..) {
+                    let remove = {
+                        let weight = weights.entry(datum.clone()).or_insert(0);
+                        *weight += delta;
+                        *weight == 0
+                    };
+                    if remove { weights.remove(&datum); }
+                }
+            }
+
+            // complete the epochs the frontier has passed, and retire those outside the window.
+            let complete = input.frontier().frontier().iter().map(|t| epoch(t)).min();
+            let complete = complete.unwrap_or_else(|| state.epochs.back().map(|x| x.0 + 1).unwrap_or(0));
+            if complete > state.complete {
+                state.complete = complete;
+                let window = state.window;
+                while state.epochs.front().map(|x| x.0 + window < complete).unwrap_or(false) {
+                    state.epochs.pop_front();
+                }
+            }
+            let entries = state.epochs.iter().map(|x| x.1.len()).sum();
+            instrument::state::<(D, i64)>(id, entries);
+        }
+    })
+    .probe_with(handle);
+
+    Windowed { shared: shared }
+}
+
+// the weights of each retained epoch, in order of epoch.
+struct WindowState<D> {
+    source: NoiseSource,
+    scale: f64,
+    window: usize,
+    epochs: VecDeque<(usize, HashMap<D, i64>)>,
+    complete: usize,
+}
+
+impl<D: Hash+Eq> WindowState<D> {
+    // the weights of `epoch`, which should not precede retained epochs.
+    fn epoch_mut(&mut self, epoch: usize) -> &mut HashMap<D, i64> {
+        let position = match self.epochs.iter().position(|x| x.0 >= epoch) {
+            Some(position) if self.epochs[position].0 == epoch => position,
+            Some(position) => { self.epochs.insert(position, (epoch, HashMap::new())); position },
+            None => { self.epochs.push_back((epoch, HashMap::new())); self.epochs.len() - 1 },
+        };
+        &mut self.epochs[position].1
+    }
+}
+
+/// Noisy measurements of the weights of elements over windows of recent epochs.
+///
+/// Each observation sums the weight of an element over the last `k` complete epochs, for `k` at
+/// most the window, and adds noise determined by the element, the epoch, and `k`, so that
+/// repeated observations agree. An update to one epoch is included in the windows of the `k`
+/// epochs starting from it, and so observing windows of `k` epochs at every epoch costs `k` times
+/// as much as one measurement with noise of scale `scale * SCALE`.
+pub struct Windowed<D: Hash+Eq> {
+    shared: Rc<RefCell<WindowState<D>>>,
+}
+
+impl<D: Hash+Eq> Windowed<D> {
+    /// The number of complete epochs.
+    pub fn epochs(&self) -> usize { self.shared.borrow().complete }
+    /// The noisy weight of `element` over the last `k` complete epochs.
+    pub fn observe(&self, element: D, k: usize) -> i64 {
+        let state = self.shared.borrow();
+        assert!(k > 0 && k <= state.window, "window of {} epochs exceeds the retained {}", k, state.window);
+        let last = state.complete;
+        let weight = state.epochs.iter()
+            .filter(|x| x.0 < last && x.0 + k >= last)
+            .map(|x| x.1.get(&element).cloned().unwrap_or(0))
+            .sum::<i64>();
+        let noise = state.source.laplace(&(&element, last, k)) as f64;
+        weight + (noise * state.scale) as i64
+    }
+}
@@ -0,0 +1,299 @@
+//! A record of the wPINQ operators constructed on this worker.
+//!
+//! Each `Dataset` operator records a node in a per-worker plan, with the name of the timely
+//! operator(s) it constructs, its stability constant, and the nodes it consumes. The plan can be
+//! written in DOT format for visualization, or explained as text, with the accumulated stability
+//! of each node, so that the source of a query's cost is visible.
+//!
+//! Each node also records the timely addresses of the operators producing its true and synthetic
+//! records. These are the addresses timely's logging reports for each operator it constructs (in
+//! `OperatesEvent`s), and `locate` maps a logged address back to its plan node, so that timely's
+//! scheduling and messaging events can be attributed to plan nodes.
+//!
+//! When instrumentation is enabled as a dataset is constructed, the synthetic records it produces
+//! are counted, and the counts are included in both forms. The true records are never counted, as
+//! their number at each operator is exactly the sensitive cardinality that measurement protects.
+//!
+//! Node identifiers are never reused on a worker: `clear` discards the recorded nodes but not their
+//! numbering, and a discarded node cannot be consulted, rather than being mistaken for another.
+
+use std::cell::RefCell;
+use std::io::{Result, Write};
+use std::ops::Range;
+
+/// An operator in the plan.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    /// The name of the operator, as reported to timely.
+    pub name: &'static str,
+    /// The factor by which a change in any one input may change the output.
+    pub stability: f64,
+    /// Indicates whether the operator exchanges records between workers.
+    pub exchange: bool,
+    /// The identifiers of the nodes this operator consumes.
+    pub inputs: Vec<usize>,
+    /// The number of synthetic records produced, if they are counted.
+    pub records: Option<usize>,
+    /// The timely addresses of the operators producing the true and the synthetic records, once
+    /// the node's dataset is constructed.
+    pub addresses: Option<(Vec<usize>, Vec<usize>)>,
+}
+
+// the recorded nodes, and the identifier of the first of them.
+struct Plan {
+    first: usize,
+    nodes: Vec<Node>,
+}
+
+impl Plan {
+    // the node with identifier `id`, which must not have been discarded.
+    fn get_mut(&mut self, id: usize) -> Option<&mut Node> {
+        let first = self.first;
+        assert!(id >= first, "plan node {} was discarded by plan::clear", id);
+        self.nodes.get_mut(id - first)
+    }
+}
+
+thread_local! {
+    static PLAN: RefCell<Plan> = RefCell::new(Plan { first: 0, nodes: Vec::new() });
+}
+
+/// Records a new node, returning its identifier.
+pub(crate) fn record(name: &'static str, stability: f64, exchange: bool, inputs: &[usize]) -> usize {
+    PLAN.with(|plan| {
+        let mut plan = plan.borrow_mut();
+        plan.nodes.push(Node { name: name, stability: stability, exchange: exchange, inputs: inputs.to_vec(), records: None, addresses: None });
+        plan.first + plan.nodes.len() - 1
+    })
+}
+
+/// Records the timely addresses of the operators producing the true and synthetic records of `id`.
+pub(crate) fn place(id: usize, truth: Vec<usize>, synth: Vec<usize>) {
+    PLAN.with(|plan| {
+        if let Some(node) = plan.borrow_mut().get_mut(id) {
+            node.addresses = Some((truth, synth));
+        }
+    })
+}
+
+/// Starts counting the records produced by node `id`, returning false if they are already counted.
+pub(crate) fn begin_count(id: usize) -> bool {
+    PLAN.with(|plan| {
+        match plan.borrow_mut().get_mut(id) {
+            Some(ref mut node) if node.records.is_none() => { node.records = Some(0); true },
+            _ => false,
+        }
+    })
+}
+
+/// Adds `records` to the count of synthetic records produced by node `id`.
+pub(crate) fn count(id: usize, records: usize) {
+    PLAN.with(|plan| {
+        if let Some(node) = plan.borrow_mut().get_mut(id) {
+            node.records = Some(node.records.unwrap_or(0) + records);
+        }
+    })
+}
+
+/// Returns the node with identifier `id`.
+///
+/// # Panics
+///
+/// Panics if the node was discarded by `clear`.
+pub fn node(id: usize) -> Option<Node> {
+    PLAN.with(|plan| plan.borrow_mut().get_mut(id).map(|node| node.clone()))
+}
+
+/// Returns the nodes recorded on this worker thread since the last `clear`, with their identifiers.
+pub fn nodes() -> Vec<(usize, Node)> {
+    PLAN.with(|plan| {
+        let plan = plan.borrow();
+        plan.nodes.iter().cloned().enumerate().map(|(index, node)| (plan.first + index, node)).collect()
+    })
+}
+
+/// The identifier the next recorded node will receive.
+pub fn next() -> usize {
+    PLAN.with(|plan| { let plan = plan.borrow(); plan.first + plan.nodes.len() })
+}
+
+/// The node whose true or synthetic records are produced by the timely operator at `address`.
+///
+/// The address is as reported by timely's logging for the operator.
+pub fn locate(address: &[usize]) -> Option<usize> {
+    nodes()
+        .into_iter()
+        .find(|x| x.1.addresses.as_ref().map(|a| &a.0[..] == address || &a.1[..] == address).unwrap_or(false))
+        .map(|x| x.0)
+}
+
+/// The factor by which a change to the input data may change the output of node `id`.
+///
+/// Each node multiplies its stability constant by the sum of the factors of its inputs, as a change
+/// reaching a node through several inputs (for example, a dataset joined with itself) may change
+/// each of them. All protected input nodes are treated as one dataset, with factor one, which is
+/// conservative when distinct inputs hold unrelated records; `factors` separates them. Public
+/// inputs are recorded with stability zero, and so contribute nothing.
+pub fn stability(id: usize) -> f64 {
+    factors(id).iter().map(|x| x.1).sum()
+}
+
+/// The factor by which a change to each protected input node may change the output of node `id`.
+///
+/// Each input node (one consuming no other nodes) has factor one with respect to itself, times its
+/// stability constant, and other nodes combine the factors of their inputs as in `stability`.
+/// Inputs with factor zero, such as public inputs, are omitted. The result is sorted by input.
+///
+/// # Panics
+///
+/// Panics if `id`, or any node it depends on, was discarded by `clear`.
+pub fn factors(id: usize) -> Vec<(usize, f64)> {
+    PLAN.with(|plan| {
+        let plan = plan.borrow();
+        let first = plan.first;
+        assert!(id >= first, "plan node {} was discarded by plan::clear", id);
+        // nodes only consume earlier nodes, and so may be evaluated in order.
+        let mut factors: Vec<Vec<(usize, f64)>> = Vec::with_capacity(id + 1 - first);
+        for (index, node) in plan.nodes[.. id + 1 - first].iter().enumerate() {
+            for &input in node.inputs.iter() {
+                assert!(input >= first, "plan node {} consumes node {}, which was discarded by plan::clear", first + index, input);
+            }
+            let mut combined = if node.inputs.is_empty() { vec![(first + index, 1.0)] } else { node.inputs.iter().flat_map(|&i| factors[i - first].iter().cloned()).collect() };
+            combined.sort_by_key(|x| x.0);
+            let mut result: Vec<(usize, f64)> = Vec::with_capacity(combined.len());
+            for (input, factor) in combined {
+                if result.last().map(|x| x.0) == Some(input) { result.last_mut().unwrap().1 += factor; }
+                else { result.push((input, factor)); }
+            }
+            result.iter_mut().for_each(|x| x.1 *= node.stability);
+            result.retain(|x| x.1 != 0.0);
+            factors.push(result);
+        }
+        factors.pop().unwrap()
+    })
+}
+
+/// The epsilon required to measure node `id` with noise of scale `accuracy` input records.
+///
+/// A measurement whose noise has scale `accuracy` times the weight of an input record protects
+/// that record with epsilon `stability(id) / accuracy`. See `budget::scale` for the corresponding
+/// scale of the noise.
+pub fn epsilon(id: usize, accuracy: f64) -> f64 {
+    assert!(accuracy > 0.0);
+    stability(id) / accuracy
+}
+
+/// Discards all recorded nodes.
+///
+/// Identifiers are not reused, and datasets and measurements constructed before the call may not be
+/// consulted for their stability afterwards, nor used to construct further datasets whose
+/// stability is consulted; doing so panics rather than reporting the stability of another node.
+pub fn clear() {
+    PLAN.with(|plan| {
+        let mut plan = plan.borrow_mut();
+        plan.first += plan.nodes.len();
+        plan.nodes.clear();
+    });
+}
+
+/// The key by which `node` exchanges records between workers, if it does.
+pub fn exchange_key(node: &Node) -> &'static str {
+    match (node.exchange, node.name) {
+        (false, _) => "none",
+        (true, "Join") | (true, "JoinBy") | (true, "JoinLeft") | (true, "Cogroup") | (true, "Min") | (true, "Max") | (true, "BoundContributions") | (true, "TruncateDegrees") => "key",
+        (true, "JoinSorted") => "partition",
+        (true, "Continual") | (true, "Median") | (true, "Histogram") | (true, "Sample") => "worker 0",
+        (true, _) => "record",
+    }
+}
+
+/// Writes the recorded nodes with identifiers in `ids` as text, one per line.
+///
+/// Each line lists the node's identifier and name, its stability constant, the factor by which a
+/// change to the input data may change its output, the key by which it exchanges records, the
+/// number of synthetic records it produced if counted, the timely address of the operator producing
+/// its synthetic records, and the nodes it consumes.
+pub fn explain<W: Write>(mut writer: W, ids: Range<usize>) -> Result<()> {
+    for id in ids {
+        let node = node(id).unwrap_or_else(|| panic!("no plan node {}", id));
+        let records = node.records.map(|x| x.to_string()).unwrap_or("-".to_owned());
+        let address = node.addresses.as_ref().map(|x| format!("{:?}", x.1)).unwrap_or("-".to_owned());
+        writeln!(writer, "n{}\t{}\tstability {}\tfactor {}\texchange {}\tsynthetic records {}\toperator {}\tinputs {:?}", id, node.name, node.stability, stability(id), exchange_key(&node), records, address, node.inputs)?;
+    }
+    writer.flush()
+}
+
+/// Writes the recorded nodes as a DOT graph.
+///
+/// Each node is labeled with its name, stability constant and factor, exchange key, and number of
+/// synthetic records if counted, and the timely address of the operator producing its synthetic
+/// records. Exchanging operators are drawn with a double border.
+pub fn dot<W: Write>(mut writer: W) -> Result<()> {
+    writeln!(writer, "digraph wpinq {{")?;
+    for &(id, ref node) in nodes().iter() {
+        let peripheries = if node.exchange { 2 } else { 1 };
+        let records = node.records.map(|x| format!("\\nsynthetic records {}", x)).unwrap_or(String::new());
+        let address = node.addresses.as_ref().map(|x| format!("\\noperator {:?}", x.1)).unwrap_or(String::new());
+        writeln!(writer, "  n{} [label=\"{}\\nstability {} (factor {})\\nexchange {}{}{}\", peripheries={}];", id, node.name, node.stability, stability(id), exchange_key(node), records, address, peripheries)?;
+        for input in node.inputs.iter() {
+            writeln!(writer, "  n{} -> n{};", input, id)?;
+        }
+    }
+    writeln!(writer, "}}")?;
+    writer.flush()
+}
+
+mod tests {
+
+    #[test]
+    fn test_stability() {
+        use super::{record, stability};
+        let input = record("Input", 1.0, false, &[]);
+        let map = record("Map", 1.0, false, &[input]);
+        let join = record("Join", 1.0, true, &[input, map]);
+        let halve = record("Halve", 0.5, false, &[join]);
+        assert_eq!(stability(map), 1.0);
+        assert_eq!(stability(join), 2.0);
+        assert_eq!(stability(halve), 1.0);
+        assert_eq!(super::epsilon(join, 4.0), 0.5);
+    }
+
+    #[test]
+    fn test_factors() {
+        use super::{record, stability, factors};
+        let orders = record("Input", 1.0, false, &[]);
+        let nations = record("Input", 0.0, false, &[]);
+        let customers = record("Input", 1.0, false, &[]);
+        let join1 = record("Join", 1.0, true, &[orders, nations]);
+        let join2 = record("Join", 1.0, true, &[join1, customers]);
+        let join3 = record("Join", 1.0, true, &[join2, orders]);
+        assert_eq!(factors(nations), vec![]);
+        assert_eq!(factors(join1), vec![(orders, 1.0)]);
+        assert_eq!(factors(join3), vec![(orders, 2.0), (customers, 1.0)]);
+        assert_eq!(stability(join3), 3.0);
+    }
+
+    #[test]
+    fn test_explain() {
+        use super::{record, explain};
+        let input = record("Input", 1.0, false, &[]);
+        let join = record("Join", 1.0, true, &[input, input]);
+        let mut text = Vec::new();
+        explain(&mut text, join .. join + 1).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("factor 2"));
+        assert!(text.contains("exchange key"));
+    }
+
+    #[test]
+    fn test_clear() {
+        use super::{record, clear, next, node, stability};
+        let input = record("Input", 1.0, false, &[]);
+        clear();
+        let other = record("Input", 1.0, false, &[]);
+        assert!(other > input);
+        assert_eq!(next(), other + 1);
+        assert_eq!(stability(other), 1.0);
+        assert!(::std::panic::catch_unwind(|| node(input)).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+//! Corrections for counts of distinct elements made by shaving.
+//!
+//! Distinct elements are counted by shaving a dataset into slices of width `width` and keeping only
+//! the first slice of each element, as `shave(width).filter(|x| x.1 == 0)`. An element of weight
+//! `v` then contributes `min(v, width)` rather than `width`, and dividing the measured total by
+//! `width` undercounts the elements lighter than `width`. When each record carries weight `unit`,
+//! an element with `k` records contributes `min(k * unit, width)`, and the measured total can be
+//! corrected by the average contribution of an element, given the fraction of elements with each
+//! number of records. These fractions may come from a noisy measurement, such as the cumulative
+//! counts of `shave(unit)` indices, in which case the correction is also post-processing.
+
+use std::cmp::min;
+
+/// The contributions of elements to a distinct count by shaving.
+#[derive(Copy, Clone, Debug)]
+pub struct Distinct {
+    width: i64,
+    unit: i64,
+}
+
+impl Distinct {
+    /// Corrects counts made with slices of width `width`, from records of weight `unit`.
+    pub fn new(width: i64, unit: i64) -> Self {
+        assert!(width > 0 && unit > 0);
+        Distinct { width: width, unit: unit }
+    }
+    /// The weight contributed by an element with `records` records.
+    pub fn contribution(&self, records: usize) -> i64 {
+        min(records as i64 * self.unit, self.width)
+    }
+    /// The number of records beyond which an element contributes the full width.
+    pub fn saturation(&self) -> usize {
+        ((self.width + self.unit - 1) / self.unit) as usize
+    }
+    /// Estimates the number of distinct elements from the measured total `observed`.
+    ///
+    /// Here `fractions[k]` is the fraction of elements with `k + 1` records, except the last, which
+    /// is the fraction with at least as many records. Fractions need not sum to one, and those of
+    /// elements with more than `saturation()` records may be combined without changing the result.
+    pub fn estimate(&self, observed: f64, fractions: &[f64]) -> f64 {
+        assert!(!fractions.is_empty());
+        let total: f64 = fractions.iter().sum();
+        let weighted: f64 = fractions.iter().enumerate().map(|(k, f)| f * self.contribution(k + 1) as f64).sum();
+        observed * total / weighted
+    }
+    /// Bounds the number of distinct elements from the measured total `observed`.
+    ///
+    /// The lower bound assumes every element contributes the full width, and the upper bound that
+    /// every element has a single record.
+    pub fn bounds(&self, observed: f64) -> (f64, f64) {
+        (observed / self.width as f64, observed / self.contribution(1) as f64)
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_distinct() {
+        use std::cmp::min;
+
+        // elements with these numbers of records, each of weight 3, shaved at width 10.
+        let records = [1, 1, 2, 3, 5];
+        let observed = records.iter().map(|k| min(k * 3, 10)).sum::<i64>() as f64;
+        let distinct = super::Distinct::new(10, 3);
+        assert_eq!(distinct.saturation(), 4);
+
+        // fractions of elements with 1, 2, 3, and 4 or more records.
+        assert_eq!(distinct.estimate(observed, &[0.4, 0.2, 0.2, 0.2]), 5.0);
+        assert_eq!(distinct.estimate(observed, &[2.0, 1.0, 1.0, 0.0, 1.0]), 5.0);
+        let (lower, upper) = distinct.bounds(observed);
+        assert!(lower <= 5.0 && 5.0 <= upper);
+
+        // records at least as heavy as the width need no correction.
+        let distinct = super::Distinct::new(10, 10);
+        assert_eq!(distinct.estimate(50.0, &[1.0]), 5.0);
+        assert_eq!(distinct.bounds(50.0), (5.0, 5.0));
+    }
+}
@@ -0,0 +1,208 @@
+//! Joint fitting of a sequence and its transpose to noisy measurements of each.
+//!
+//! A non-increasing sequence of counts, such as the number of nodes with degree greater than each
+//! value, determines its transpose, such as the degree of each node from largest to smallest. When
+//! both are measured with noise, neither measurement need be consistent with the other, nor even
+//! monotone. A `Fit` finds the pair of transposed sequences minimizing the total cost of their
+//! disagreement with the measurements, as a minimum weight path through a grid.
+
+/// The order of a measured sequence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// Values do not increase with the index, as for counts of records greater than the index.
+    NonIncreasing,
+    /// Values do not decrease with the index, as for counts of records at most the index.
+    NonDecreasing,
+}
+
+/// Fits a pair of transposed sequences to noisy measurements of each.
+///
+/// The cost of fitting a value `y` to a measurement `m` is `weight * cost(m, y)`, where `weight`
+/// is the confidence in the measurement, one by default.
+pub struct Fit<C: Fn(f64,f64)->f64> {
+    cost: C,
+    orders: (Order, Order),
+    weights: (Option<Vec<f64>>, Option<Vec<f64>>),
+}
+
+impl<C: Fn(f64,f64)->f64> Fit<C> {
+
+    /// Fits non-increasing sequences, with unit confidence in each measurement.
+    pub fn new(cost: C) -> Self {
+        Fit {
+            cost: cost,
+            orders: (Order::NonIncreasing, Order::NonIncreasing),
+            weights: (None, None),
+        }
+    }
+
+    /// Sets the orders of the horizontal and vertical sequences.
+    ///
+    /// A non-decreasing sequence is fit as the reverse of a non-increasing sequence, and its
+    /// transpose is unchanged by the reversal.
+    pub fn order(mut self, horizontal: Order, vertical: Order) -> Self {
+        self.orders = (horizontal, vertical);
+        self
+    }
+
+    /// Sets the confidence in each horizontal and vertical measurement.
+    ///
+    /// Weights should be non-negative, and a weight of zero ignores its measurement.
+    pub fn weights(mut self, horizontal: Vec<f64>, vertical: Vec<f64>) -> Self {
+        assert!(horizontal.iter().chain(vertical.iter()).all(|&w| w >= 0.0));
+        self.weights = (Some(horizontal), Some(vertical));
+        self
+    }
+
+    /// Sets the confidence in each measurement to the inverse of its noise scale.
+    ///
+    /// With an absolute cost, the fit then maximizes the likelihood of Laplace noise of these
+    /// scales; a squared cost would instead use `weights` with inverse squared scales.
+    pub fn scales(self, horizontal: &[f64], vertical: &[f64]) -> Self {
+        let horizontal = horizontal.iter().map(|s| 1.0 / s).collect();
+        let vertical = vertical.iter().map(|s| 1.0 / s).collect();
+        self.weights(horizontal, vertical)
+    }
+
+    /// Fits sequences to the `horizontal` and `vertical` measurements, in the orders set.
+    ///
+    /// A non-increasing result has as many elements as the largest value of the other result,
+    /// and a non-decreasing result has as many elements as its measurements.
+    pub fn fit(&self, horizontal: &[f64], vertical: &[f64]) -> (Vec<usize>, Vec<usize>) {
+
+        let ones = |len| vec![1.0; len];
+        let horizontal_weights = self.weights.0.clone().unwrap_or_else(|| ones(horizontal.len()));
+        let vertical_weights = self.weights.1.clone().unwrap_or_else(|| ones(vertical.len()));
+        assert_eq!(horizontal.len(), horizontal_weights.len());
+        assert_eq!(vertical.len(), vertical_weights.len());
+
+        let horizontal = oriented(horizontal, self.orders.0);
+        let vertical = oriented(vertical, self.orders.1);
+        let horizontal_weights = oriented(&horizontal_weights[..], self.orders.0);
+        let vertical_weights = oriented(&vertical_weights[..], self.orders.1);
+
+        let (mut result_h, mut result_v) = fit_weighted(&horizontal[..], &horizontal_weights[..], &vertical[..], &vertical_weights[..], &self.cost);
+
+        if self.orders.0 == Order::NonDecreasing {
+            result_h.resize(horizontal.len(), 0);
+            result_h.reverse();
+        }
+        if self.orders.1 == Order::NonDecreasing {
+            result_v.resize(vertical.len(), 0);
+            result_v.reverse();
+        }
+
+        (result_h, result_v)
+    }
+}
+
+// copies `values`, reversed if they are non-decreasing.
+fn oriented(values: &[f64], order: Order) -> Vec<f64> {
+    match order {
+        Order::NonIncreasing => values.to_vec(),
+        Order::NonDecreasing => values.iter().rev().cloned().collect(),
+    }
+}
+
+// Finds the minimum weight grid path connecting the points (0, infinity) and (infinity, 0),
+// where edges are either horizontal or vertical, and their costs are
+//
+// cost((a,b) -> (a+1,b)) : hw[a] * cost(h[a], b)
+// cost((a,b+1) -> (a,b)) : vw[b] * cost(v[b], a)
+//
+// Traversing an edge corresponds to committing to that edge in the fitted sequences, and so the
+// cost of a path is the weighted sum of the errors in the corresponding measurements.
+fn fit_weighted<C: Fn(f64,f64)->f64>(horizontal: &[f64], horizontal_weights: &[f64], vertical: &[f64], vertical_weights: &[f64], cost: &C) -> (Vec<usize>, Vec<usize>) {
+
+    assert!(!horizontal.is_empty());
+    assert!(!vertical.is_empty());
+
+    let max_x = ::std::cmp::max(vertical.iter().map(|x| x.round() as i64).max().unwrap(), 0) as usize;
+    let max_y = ::std::cmp::max(horizontal.iter().map(|x| x.round() as i64).max().unwrap(), 0) as usize;
+
+    let cost_h = |x: usize, y: usize| horizontal_weights[x] * cost(horizontal[x], y as f64);
+    let cost_v = |y: usize, x: usize| vertical_weights[y] * cost(vertical[y], x as f64);
+
+    // Paths only move right or down, so we can fill in minimum distances from (0, max_y) in a
+    // single pass over the grid, in order of increasing x and decreasing y. Distances are stored
+    // in one preallocated array, where `(x, y)` is found at `x * (max_y + 1) + y`.
+    let stride = max_y + 1;
+    let mut dists = vec![0.0f64; (max_x + 1) * stride];
+
+    for x in 0 .. (max_x + 1) {
+        for y in (0 .. (max_y + 1)).rev() {
+            // consider (x-1,y) -> (x,y); costs additional hw[x-1] * cost(h[x-1], y)
+            let dist1 = if x > 0 { Some(dists[(x-1) * stride + y] + cost_h(x-1, y)) } else { None };
+            // consider (x,y+1) -> (x,y); costs additional vw[y] * cost(v[y], x)
+            let dist2 = if y < max_y { Some(dists[x * stride + y + 1] + cost_v(y, x)) } else { None };
+            dists[x * stride + y] = match (dist1, dist2) {
+                (None, None) => 0.0,
+                (Some(d1), None) => d1,
+                (None, Some(d2)) => d2,
+                (Some(d1), Some(d2)) => if d1 <= d2 { d1 } else { d2 },
+            };
+        }
+    }
+
+    // now we walk backwards from (max_x, 0) to find the minimum path
+    let mut current = (max_x, 0);
+
+    let mut result_h = vec![0; max_x];
+    let mut result_v = vec![0; max_y];
+
+    while current != (0, max_y) {
+
+        let (x,y) = current;
+        let dist1 = if x > 0 { Some(dists[(x-1) * stride + y] + cost_h(x-1, y)) } else { None };
+        let dist2 = if y < max_y { Some(dists[x * stride + y + 1] + cost_v(y, x)) } else { None };
+
+        let horizontal_edge = match (dist1, dist2) {
+            (None, None) => { panic!("backwards tracing failed!") }
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (Some(d1), Some(d2)) => d1 <= d2,
+        };
+
+        if horizontal_edge {
+            // edge (x-1,y) -> (x,y)
+            current = (x-1, y);
+            result_h[x-1] = y;
+        }
+        else {
+            // edge (x,y+1) -> (x,y)
+            current = (x, y+1);
+            result_v[y] = x;
+        }
+    }
+
+    (result_h, result_v)
+}
+
+mod tests {
+
+    #[test]
+    fn test_fit_orders() {
+        use super::{Fit, Order};
+
+        let h = vec![10, 4, 2, 1, 1];
+        let v = vec![5, 3, 2, 2, 1, 1, 1, 1, 1, 1];
+        let hf = h.iter().rev().map(|&x| x as f64).collect::<Vec<_>>();
+        let vf = v.iter().map(|&x| x as f64).collect::<Vec<_>>();
+
+        let (hn, vn) = Fit::new(|x: f64, y: f64| (x-y).abs()).order(Order::NonDecreasing, Order::NonIncreasing).fit(&hf[..], &vf[..]);
+        assert_eq!(h.iter().rev().cloned().collect::<Vec<_>>(), hn);
+        assert_eq!(v, vn);
+    }
+
+    #[test]
+    fn test_fit_weights() {
+        use super::Fit;
+
+        // the measurements disagree about the second horizontal value; the more confident prevails.
+        let h = vec![1.0, 0.0];
+        let v = vec![2.0];
+        let fit = |weights: (Vec<f64>, Vec<f64>)| Fit::new(|x: f64, y: f64| (x-y).abs()).weights(weights.0, weights.1).fit(&h[..], &v[..]);
+        assert_eq!(fit((vec![1.0, 4.0], vec![1.0])), (vec![1, 0], vec![1]));
+        assert_eq!(fit((vec![1.0, 1.0], vec![4.0])), (vec![1, 1], vec![2]));
+    }
+}
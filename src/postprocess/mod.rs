@@ -0,0 +1,120 @@
+//! Post-processing of noisy measurements.
+//!
+//! The methods in this module consume only noisy measurement values, and so introduce no
+//! additional privacy cost. They exist to recover structure that the true values are known to
+//! have, but which noise destroys.
+
+pub mod distinct;
+pub mod fit;
+
+/// Projects `values` onto the nearest non-increasing sequence, in squared error.
+///
+/// Noisy cumulative density functions need not be monotone, even though the true functions are.
+/// This method applies the pool-adjacent-violators algorithm: adjacent values that violate the
+/// ordering are merged into blocks and replaced by the block average.
+pub fn isotonic_decreasing(values: &[f64]) -> Vec<f64> {
+    isotonic_decreasing_weighted(values, &vec![1.0; values.len()][..])
+}
+
+/// Projects `values` onto the nearest non-increasing sequence, in weighted squared error.
+///
+/// Each value is weighted by the corresponding element of `weights`, which should be positive.
+/// A natural choice is the inverse variance of each measurement.
+pub fn isotonic_decreasing_weighted(values: &[f64], weights: &[f64]) -> Vec<f64> {
+
+    assert_eq!(values.len(), weights.len());
+
+    // blocks of (mean, total weight, count), maintained non-increasing in mean.
+    let mut blocks: Vec<(f64, f64, usize)> = Vec::with_capacity(values.len());
+    for (&value, &weight) in values.iter().zip(weights.iter()) {
+        blocks.push((value, weight, 1));
+        while blocks.len() > 1 && blocks[blocks.len()-2].0 < blocks[blocks.len()-1].0 {
+            let (mean1, weight1, count1) = blocks.pop().unwrap();
+            let (mean2, weight2, count2) = blocks.pop().unwrap();
+            let weight = weight1 + weight2;
+            blocks.push(((mean1 * weight1 + mean2 * weight2) / weight, weight, count1 + count2));
+        }
+    }
+
+    let mut result = Vec::with_capacity(values.len());
+    for (mean, _weight, count) in blocks {
+        for _ in 0 .. count {
+            result.push(mean);
+        }
+    }
+    result
+}
+
+/// Produces least-squares estimates for a hierarchy of measurements that are consistent.
+///
+/// The hierarchy is a tree rooted at `root`, where `children[node]` lists the nodes whose true
+/// values sum to the true value of `node`; for example, a yearly count whose children are monthly
+/// counts. Each `values[node]` is a noisy measurement with variance `variances[node]`.
+///
+/// The result minimizes the variance-weighted squared error to `values`, subject to each parent
+/// equaling the sum of its children. It is computed in two passes: the first combines each node's
+/// measurement with the sum of its children's estimates by inverse variance weighting, and the
+/// second distributes any remaining discrepancy from each parent to its children in proportion to
+/// their variances. Nodes not reachable from `root` are returned unchanged.
+pub fn hierarchical_consistency(values: &[f64], variances: &[f64], children: &[Vec<usize>], root: usize) -> Vec<f64> {
+
+    assert_eq!(values.len(), variances.len());
+    assert_eq!(values.len(), children.len());
+
+    // nodes in an order where each parent precedes its children.
+    let mut order = vec![root];
+    let mut cursor = 0;
+    while cursor < order.len() {
+        let node = order[cursor];
+        order.extend(children[node].iter().cloned());
+        cursor += 1;
+    }
+
+    // bottom-up: combine each measurement with the sum of its children's estimates.
+    let mut estimates = values.to_vec();
+    let mut estimate_variances = variances.to_vec();
+    for &node in order.iter().rev() {
+        if !children[node].is_empty() {
+            let sum: f64 = children[node].iter().map(|&c| estimates[c]).sum();
+            let sum_variance: f64 = children[node].iter().map(|&c| estimate_variances[c]).sum();
+            let weight = 1.0 / variances[node] + 1.0 / sum_variance;
+            estimates[node] = (values[node] / variances[node] + sum / sum_variance) / weight;
+            estimate_variances[node] = 1.0 / weight;
+        }
+    }
+
+    // top-down: distribute each parent's discrepancy among its children.
+    let mut result = values.to_vec();
+    result[root] = estimates[root];
+    for &node in order.iter() {
+        if !children[node].is_empty() {
+            let sum: f64 = children[node].iter().map(|&c| estimates[c]).sum();
+            let sum_variance: f64 = children[node].iter().map(|&c| estimate_variances[c]).sum();
+            let discrepancy = result[node] - sum;
+            for &child in children[node].iter() {
+                result[child] = estimates[child] + discrepancy * estimate_variances[child] / sum_variance;
+            }
+        }
+    }
+
+    result
+}
+
+mod tests {
+    #[test]
+    fn test_isotonic() {
+        assert_eq!(super::isotonic_decreasing(&[5.0, 3.0, 4.0, 1.0]), vec![5.0, 3.5, 3.5, 1.0]);
+        assert_eq!(super::isotonic_decreasing(&[1.0, 2.0, 3.0]), vec![2.0, 2.0, 2.0]);
+        assert_eq!(super::isotonic_decreasing(&[3.0, 2.0, 1.0]), vec![3.0, 2.0, 1.0]);
+        assert_eq!(super::isotonic_decreasing(&[]), Vec::<f64>::new());
+    }
+
+    #[test]
+    fn test_hierarchical() {
+        let values = vec![10.0, 3.0, 3.0, 3.0];
+        let variances = vec![1.0; 4];
+        let children = vec![vec![1, 2, 3], vec![], vec![], vec![]];
+        let result = super::hierarchical_consistency(&values, &variances, &children, 0);
+        assert_eq!(result, vec![9.75, 3.25, 3.25, 3.25]);
+    }
+}
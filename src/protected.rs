@@ -0,0 +1,43 @@
+//! A wrapper for the stream of true records, which may only leave the crate through measurements.
+//!
+//! The true records of a `Dataset` are sensitive, and each is only disclosed through the noise of
+//! a measurement. Were the true stream available, a timely operator such as `inspect` could copy
+//! its records into a side channel, perhaps by accident when debugging. The true stream is instead
+//! held in a `ProtectedStream`, whose contents are only accessible within this crate, and the
+//! methods that reach them are named `expose` and `into_stream` so that each use is easily found.
+//! Code outside the crate may move, clone, and transform datasets, but may only observe their true
+//! records through the `measure` family of operators.
+//!
+//! This protects against accidental exposure only. The closures supplied to `map`, `filter`, `join`
+//! and the other transformations are applied to the true records, and see each of them exactly; a
+//! closure that writes its argument to a file or a shared variable exports the data as surely as
+//! `inspect` would. Privacy therefore also relies on those closures being free of side effects.
+
+use timely::Data;
+use timely::dataflow::{Scope, Stream};
+
+/// A stream of weighted true records, accessible only within this crate.
+pub struct ProtectedStream<G: Scope, D: Data> {
+    stream: Stream<G, (D, i64)>,
+}
+
+impl<G: Scope, D: Data> ProtectedStream<G, D> {
+    /// Protects `stream`.
+    pub(crate) fn new(stream: Stream<G, (D, i64)>) -> Self {
+        ProtectedStream { stream: stream }
+    }
+    /// The protected stream, for transformation or measurement.
+    pub(crate) fn expose(&self) -> &Stream<G, (D, i64)> {
+        &self.stream
+    }
+    /// The protected stream, consuming the protection.
+    pub(crate) fn into_stream(self) -> Stream<G, (D, i64)> {
+        self.stream
+    }
+}
+
+impl<G: Scope, D: Data> Clone for ProtectedStream<G, D> {
+    fn clone(&self) -> Self {
+        ProtectedStream { stream: self.stream.clone() }
+    }
+}
@@ -0,0 +1,87 @@
+//! Radix sorting of weighted records with integer data.
+//!
+//! Consolidation sorts records by their data, and in graph analyses the data are most often node
+//! identifiers or hashes. For such fixed-size unsigned integers a most-significant-digit radix
+//! sort is much faster than a comparison sort, and skips the high digits that small identifiers
+//! do not use. As specialization is not stable, the integer types are detected through `Any`, so
+//! that consolidation keeps its `Ord` bound and callers need not name or implement anything.
+
+use std::any::Any;
+
+// lists shorter than this are left to comparison sorting.
+const THRESHOLD: usize = 256;
+
+/// Sorts `list` by its data if they are unsigned integers, returning false if they are not.
+///
+/// Short lists are also left unsorted, as comparison sorting serves them as well.
+pub fn sort<T: 'static>(list: &mut Vec<(T, i64)>) -> bool {
+    if list.len() < THRESHOLD { return false; }
+    let list = list as &mut dyn Any;
+    if let Some(list) = list.downcast_mut::<Vec<(usize, i64)>>() { sort_by(list, |x| x as u64); return true; }
+    if let Some(list) = list.downcast_mut::<Vec<(u64, i64)>>() { sort_by(list, |x| x); return true; }
+    if let Some(list) = list.downcast_mut::<Vec<(u32, i64)>>() { sort_by(list, |x| x as u64); return true; }
+    if let Some(list) = list.downcast_mut::<Vec<(u16, i64)>>() { sort_by(list, |x| x as u64); return true; }
+    if let Some(list) = list.downcast_mut::<Vec<(u8, i64)>>() { sort_by(list, |x| x as u64); return true; }
+    false
+}
+
+// sorts `list` by `key` of its data, omitting the bytes above the largest key.
+fn sort_by<K: Copy, F: Fn(K)->u64+Copy>(list: &mut [(K, i64)], key: F) {
+    let max = list.iter().map(|x| key(x.0)).max().unwrap_or(0);
+    let bytes = (64 - max.leading_zeros() as usize + 7) / 8;
+    if bytes > 0 { radix(list, key, bytes - 1); }
+}
+
+// sorts `list` by `key` of its data, from the byte `digit` down, permuting records in place.
+fn radix<K: Copy, F: Fn(K)->u64+Copy>(list: &mut [(K, i64)], key: F, digit: usize) {
+
+    let shift = 8 * digit;
+    let bucket = |x: &(K, i64)| ((key(x.0) >> shift) & 0xFF) as usize;
+
+    let mut starts = [0usize; 256];
+    let mut ends = [0usize; 256];
+    for x in list.iter() {
+        ends[bucket(x)] += 1;
+    }
+    let mut total = 0;
+    for index in 0 .. 256 {
+        starts[index] = total;
+        total += ends[index];
+        ends[index] = total;
+    }
+
+    // swap each record into the next free position of its bucket, until every bucket is full.
+    let mut next = starts;
+    for index in 0 .. 256 {
+        while next[index] < ends[index] {
+            let target = bucket(&list[next[index]]);
+            if target == index { next[index] += 1; }
+            else {
+                list.swap(next[index], next[target]);
+                next[target] += 1;
+            }
+        }
+    }
+
+    if digit > 0 {
+        for index in 0 .. 256 {
+            let slice = &mut list[starts[index] .. ends[index]];
+            if slice.len() < THRESHOLD { slice.sort_unstable_by_key(|x| key(x.0)); }
+            else { radix(slice, key, digit - 1); }
+        }
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_sort() {
+        let mut list = (0 .. 1000usize).map(|x| ((x * 7919) % 1013 + (x % 3) * 100000, 1)).collect::<Vec<_>>();
+        let mut expected = list.clone();
+        expected.sort();
+        assert!(super::sort(&mut list));
+        assert_eq!(list.iter().map(|x| x.0).collect::<Vec<_>>(), expected.iter().map(|x| x.0).collect::<Vec<_>>());
+        let mut other = (0 .. 1000i64).map(|x| (500 - x, 1)).collect::<Vec<_>>();
+        assert!(!super::sort(&mut other));
+    }
+}
@@ -0,0 +1,102 @@
+//! Parsing of records from delimited lines of text.
+//!
+//! Tables such as those of TPC-H are stored one record per line, with fields separated by a
+//! delimiter. Rather than write a parser for each schema, `#[derive(WpinqRecord)]` implements
+//! `Record` for a struct by parsing each of its fields in turn, as described in `wpinq_derive`.
+//! Fields are parsed by the `Field` trait, which may be implemented for further types.
+
+use std::error::Error;
+use std::fmt;
+
+use arrayvec::{Array, ArrayString};
+
+/// A type parsed from one delimited line of text.
+pub trait Record: Sized {
+    /// Parses a record from `text`.
+    fn parse(text: &str) -> Result<Self, ParseError>;
+}
+
+/// A type parsed from the text of one field.
+pub trait Field: Sized {
+    /// Parses `text`, or returns `None` if it is not valid.
+    fn parse_field(text: &str) -> Option<Self>;
+}
+
+macro_rules! implement_field {
+    ($($ty:ty),*) => {
+        $(impl Field for $ty {
+            fn parse_field(text: &str) -> Option<Self> { text.parse().ok() }
+        })*
+    }
+}
+
+implement_field!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64, bool, char, String);
+
+impl<A: Array<Item=u8>+Copy> Field for ArrayString<A> {
+    fn parse_field(text: &str) -> Option<Self> { ArrayString::from(text).ok() }
+}
+
+/// The error of a field that is missing or invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    field: &'static str,
+    text: Option<String>,
+}
+
+impl ParseError {
+    /// The error for `field`, whose text `text` is invalid.
+    pub fn new(field: &'static str, text: &str) -> Self {
+        ParseError { field: field, text: Some(text.to_owned()) }
+    }
+    /// The error for `field`, which is missing.
+    pub fn missing(field: &'static str) -> Self {
+        ParseError { field: field, text: None }
+    }
+    /// The name of the field.
+    pub fn field(&self) -> &str { self.field }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.text {
+            Some(ref text) => write!(f, "invalid value {:?} for field `{}`", text, self.field),
+            None => write!(f, "missing field `{}`", self.field),
+        }
+    }
+}
+
+impl Error for ParseError { }
+
+/// The text of the next field, named `field`.
+pub fn next_field<'a, I: Iterator<Item=&'a str>>(fields: &mut I, field: &'static str) -> Result<&'a str, ParseError> {
+    fields.next().ok_or_else(|| ParseError::missing(field))
+}
+
+/// Copies the bytes of `text` into `buffer`, truncating `text` if it is longer.
+pub fn fill(text: &str, buffer: &mut [u8]) {
+    for (dst, src) in buffer.iter_mut().zip(text.as_bytes().iter()) {
+        *dst = *src;
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_fields() {
+        use super::Field;
+
+        let mut buffer = [0u8; 4];
+        super::fill("ab", &mut buffer);
+        assert_eq!(&buffer, b"ab\0\0");
+        super::fill("abcdef", &mut buffer);
+        assert_eq!(&buffer, b"abcd");
+
+        assert_eq!(i32::parse_field("-7"), Some(-7));
+        assert_eq!(usize::parse_field("x"), None);
+
+        let mut fields = "1|2".split("|");
+        assert_eq!(super::next_field(&mut fields, "a"), Ok("1"));
+        assert_eq!(super::next_field(&mut fields, "b"), Ok("2"));
+        assert_eq!(super::next_field(&mut fields, "c").unwrap_err().to_string(), "missing field `c`");
+    }
+}
@@ -0,0 +1,56 @@
+//! A registry of named measurements, queried by textual keys.
+//!
+//! Measurements are typed by their keys, which makes them awkward to collect and to query from
+//! outside of Rust. A `Registry` erases these types, pairing each measurement with a parser for
+//! its keys, so that measurements can be observed by name and key text alone.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use ::Measurement;
+
+/// Named measurements, each observed by parsing a textual key.
+pub struct Registry {
+    entries: HashMap<String, Box<dyn FnMut(&str)->Result<i64, String>>>,
+}
+
+impl Registry {
+
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Registry { entries: HashMap::new() }
+    }
+
+    /// Registers `measurement` under `name`, with keys parsed by `FromStr`.
+    pub fn register<D: FromStr+Hash+Eq+'static>(&mut self, name: &str, measurement: Measurement<D>) {
+        self.register_with(name, measurement, |text| text.parse().ok());
+    }
+
+    /// Registers `measurement` under `name`, with keys parsed by `parse`.
+    pub fn register_with<D, P>(&mut self, name: &str, mut measurement: Measurement<D>, parse: P)
+    where
+        D: Hash+Eq+'static,
+        P: Fn(&str)->Option<D>+'static,
+    {
+        let observe = move |text: &str| {
+            parse(text)
+                .map(|key| measurement.observe(key))
+                .ok_or_else(|| format!("malformed key: {:?}", text))
+        };
+        self.entries.insert(name.to_owned(), Box::new(observe));
+    }
+
+    /// The names of all registered measurements, in no particular order.
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Observes the noisy count of the key `text` in the measurement `name`.
+    pub fn observe(&mut self, name: &str, text: &str) -> Result<i64, String> {
+        match self.entries.get_mut(name) {
+            Some(observe) => observe(text),
+            None => Err(format!("unknown measurement: {:?}", name)),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! An interactive loop for observing measurements after a computation has stabilized.
+//!
+//! A `Repl` answers textual commands against a `Registry` of measurements, either read from an
+//! input stream or issued programmatically through `execute`. Additional commands may be added,
+//! each with a declared privacy cost charged against an optional `Odometer`; these are
+//! typically used to construct and register new dataflows against a context `C` (for example,
+//! the worker and dataset handles).
+//!
+//! The built-in commands are
+//!
+//! ```text
+//! observe <name> <key>    report the noisy count of <key> in measurement <name>
+//! list                    list the registered measurements
+//! budget                  report the remaining budget
+//! help                    list the available commands
+//! quit                    leave the loop
+//! ```
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, Result, Write};
+
+use budget::Odometer;
+use registry::Registry;
+
+/// A command acting on a context `C` and the registry, with whitespace-separated arguments.
+type Command<C> = Box<dyn FnMut(&mut C, &mut Registry, &[&str])->::std::result::Result<String, String>>;
+
+/// An interpreter of commands against a registry of measurements.
+pub struct Repl<C> {
+    registry: Registry,
+    odometer: Option<Rc<RefCell<Odometer>>>,
+    commands: HashMap<String, (f64, Command<C>)>,
+}
+
+impl<C> Repl<C> {
+
+    /// Creates an interpreter for `registry`, with no budget limit.
+    pub fn new(registry: Registry) -> Self {
+        Repl {
+            registry: registry,
+            odometer: None,
+            commands: HashMap::new(),
+        }
+    }
+
+    /// Limits the total cost of commands issued to `budget`.
+    pub fn set_budget(&mut self, budget: f64) {
+        self.odometer = Some(Rc::new(RefCell::new(Odometer::new(budget))));
+    }
+
+    /// Charges the cost of commands to `odometer`, which may be shared with other analyses.
+    ///
+    /// Commands whose actions charge the same odometer themselves (for example, by constructing
+    /// measurements with `Dataset::measure_charged`) should be declared with zero cost.
+    pub fn set_odometer(&mut self, odometer: &Rc<RefCell<Odometer>>) {
+        self.odometer = Some(odometer.clone());
+    }
+
+    /// The remaining budget, if limited.
+    pub fn remaining(&self) -> Option<f64> { self.odometer.as_ref().map(|o| o.borrow().remaining()) }
+
+    /// The registry of measurements.
+    pub fn registry(&mut self) -> &mut Registry { &mut self.registry }
+
+    /// Adds a command `name` with privacy cost `cost`, performed by `action`.
+    ///
+    /// The cost is charged before `action` runs, and is not refunded if it fails, as the action may
+    /// already have constructed and registered measurements.
+    pub fn command<F>(&mut self, name: &str, cost: f64, action: F)
+    where
+        F: FnMut(&mut C, &mut Registry, &[&str])->::std::result::Result<String, String>+'static,
+    {
+        self.commands.insert(name.to_owned(), (cost, Box::new(action)));
+    }
+
+    /// Executes one command line, returning its response.
+    pub fn execute(&mut self, context: &mut C, line: &str) -> ::std::result::Result<String, String> {
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.split_first() {
+            None => Ok(String::new()),
+            Some((&"observe", args)) => {
+                if args.len() != 2 { return Err("usage: observe <name> <key>".to_owned()); }
+                self.registry.observe(args[0], args[1]).map(|count| count.to_string())
+            },
+            Some((&"list", _)) => {
+                let mut names = self.registry.names();
+                names.sort();
+                Ok(names.join("\n"))
+            },
+            Some((&"budget", _)) => {
+                Ok(self.remaining().map(|b| b.to_string()).unwrap_or_else(|| "unlimited".to_owned()))
+            },
+            Some((&"help", _)) => {
+                let mut names = self.commands.keys().map(|x| x.as_str()).collect::<Vec<_>>();
+                names.sort();
+                names.extend(&["observe", "list", "budget", "help", "quit"]);
+                Ok(names.join(" "))
+            },
+            // only `run` has a loop to leave, and otherwise `quit` does nothing.
+            Some((&"quit", _)) => Ok(String::new()),
+            Some((name, args)) => {
+                let (cost, ref mut action) = *self.commands.get_mut(*name).ok_or_else(|| format!("unknown command: {}", name))?;
+                // charge before acting, so that actions failing after registering measurements are paid for.
+                if let Some(ref odometer) = self.odometer {
+                    odometer.borrow_mut().charge(name, cost).map_err(|e| e.to_string())?;
+                }
+                action(context, &mut self.registry, args)
+            },
+        }
+    }
+
+    /// Reads and executes commands from `input` until it ends or `quit` is read.
+    pub fn run<R: BufRead, W: Write>(&mut self, context: &mut C, input: R, mut output: W) -> Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if line.split_whitespace().next() == Some("quit") { break; }
+            match self.execute(context, &line) {
+                Ok(response) => { if !response.is_empty() { writeln!(output, "{}", response)?; } },
+                Err(error) => { writeln!(output, "error: {}", error)?; },
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_charge_failed() {
+        use registry::Registry;
+        let mut repl = super::Repl::new(Registry::new());
+        repl.set_budget(1.0);
+        repl.command("fail", 0.75, |_context: &mut (), _registry, _args| Err("failed".to_owned()));
+        assert_eq!(repl.execute(&mut (), "fail"), Err("failed".to_owned()));
+        assert_eq!(repl.remaining(), Some(0.25));
+        assert!(repl.execute(&mut (), "fail").is_err());
+        assert_eq!(repl.remaining(), Some(0.25));
+        assert_eq!(repl.execute(&mut (), "quit"), Ok(String::new()));
+    }
+}
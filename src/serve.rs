@@ -0,0 +1,76 @@
+//! An HTTP endpoint answering observations of registered measurements.
+//!
+//! This module is compiled with the `serve` feature. Measurements are not thread-safe, and so
+//! the server runs on the worker thread, answering one request at a time, and should only be
+//! started once the computation has stabilized. Requests have the form
+//!
+//! ```text
+//! GET /observe/<name>/<key>
+//! ```
+//!
+//! where `<key>` is percent-encoded, and responses carry the noisy count as plain text.
+
+use std::io::{BufRead, BufReader, Result, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use registry::Registry;
+
+/// Serves requests against `registry` at `address`, until an error occurs.
+pub fn serve<A: ToSocketAddrs>(registry: &mut Registry, address: A) -> Result<()> {
+    let listener = TcpListener::bind(address)?;
+    for stream in listener.incoming() {
+        handle(registry, stream?)?;
+    }
+    Ok(())
+}
+
+/// Answers the single request available on `stream`.
+pub fn handle(registry: &mut Registry, mut stream: TcpStream) -> Result<()> {
+
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+
+    let (status, body) = respond(registry, &request);
+    write!(stream, "HTTP/1.0 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body)?;
+    stream.flush()
+}
+
+/// Produces a status line and body for a request line.
+fn respond(registry: &mut Registry, request: &str) -> (&'static str, String) {
+
+    let mut words = request.split_whitespace();
+    if words.next() != Some("GET") {
+        return ("405 Method Not Allowed", "only GET is supported\n".to_owned());
+    }
+
+    let path = words.next().unwrap_or("");
+    let mut parts = path.trim_start_matches('/').splitn(3, '/');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("observe"), Some(name), Some(key)) => {
+            match registry.observe(&decode(name), &decode(key)) {
+                Ok(count) => ("200 OK", format!("{}\n", count)),
+                Err(error) => ("404 Not Found", format!("{}\n", error)),
+            }
+        },
+        _ => ("400 Bad Request", "expected /observe/<name>/<key>\n".to_owned()),
+    }
+}
+
+/// Decodes percent-encoded text, leaving malformed escapes in place.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped =
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            ::std::str::from_utf8(&bytes[index+1 .. index+3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        }
+        else { None };
+        match escaped {
+            Some(byte) => { result.push(byte); index += 3; },
+            None => { result.push(bytes[index]); index += 1; },
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
@@ -0,0 +1,179 @@
+//! Ingestion of records from external streams.
+//!
+//! Static files are loaded once, but live event data arrive continually, from a message queue, a
+//! channel, or a socket. A `Source` yields such records one at a time, waiting a bounded time for
+//! each, and a `Feed` reads a source on a background thread, batches its records into epochs by
+//! count and by time, and moves each batch into a `DatasetHandle` from the worker thread, advancing
+//! the handle's epoch after each batch. Consumers of message queues, such as Kafka, implement
+//! `Source` by polling with the supplied timeout.
+
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use timely::Data;
+
+use DatasetHandle;
+
+/// The error returned once a source has no further records.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Closed;
+
+/// A stream of records from outside the computation.
+pub trait Source {
+    /// The type of record produced.
+    type Item;
+    /// Waits at most `timeout` for the next record.
+    ///
+    /// Returns `Ok(None)` if no record arrived in time, and `Err(Closed)` once the stream has ended
+    /// or failed.
+    fn next(&mut self, timeout: Duration) -> Result<Option<Self::Item>, Closed>;
+}
+
+impl<D> Source for Receiver<D> {
+    type Item = D;
+    fn next(&mut self, timeout: Duration) -> Result<Option<D>, Closed> {
+        match self.recv_timeout(timeout) {
+            Ok(record) => Ok(Some(record)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(Closed),
+        }
+    }
+}
+
+/// Records parsed from the lines of a TCP connection.
+///
+/// Lines that are empty or that `parse` rejects are skipped.
+pub struct LineSource<D, F: Fn(&str)->Option<D>> {
+    reader: BufReader<TcpStream>,
+    buffer: Vec<u8>,
+    parse: F,
+}
+
+impl<D, F: Fn(&str)->Option<D>> LineSource<D, F> {
+    /// Connects to `address`, parsing each line with `parse`.
+    pub fn connect<A: ToSocketAddrs>(address: A, parse: F) -> ::std::io::Result<Self> {
+        Ok(LineSource::new(TcpStream::connect(address)?, parse))
+    }
+    /// Reads lines from `stream`, parsing each with `parse`.
+    pub fn new(stream: TcpStream, parse: F) -> Self {
+        LineSource { reader: BufReader::new(stream), buffer: Vec::new(), parse: parse }
+    }
+}
+
+impl<D, F: Fn(&str)->Option<D>> Source for LineSource<D, F> {
+    type Item = D;
+    fn next(&mut self, timeout: Duration) -> Result<Option<D>, Closed> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline { return Ok(None); }
+            self.reader.get_ref().set_read_timeout(Some(deadline - now)).map_err(|_| Closed)?;
+            // a partial line remains in the buffer if the read times out.
+            match self.reader.read_until(b'\n', &mut self.buffer) {
+                Ok(0) => return Err(Closed),
+                // a line without a newline ends the stream, and is parsed before it closes.
+                Ok(_) => {
+                    let record = {
+                        let line = String::from_utf8_lossy(&self.buffer[..]);
+                        let line = line.trim_end();
+                        if line.is_empty() { None } else { (self.parse)(line) }
+                    };
+                    self.buffer.clear();
+                    if record.is_some() { return Ok(record); }
+                },
+                Err(ref error) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => return Ok(None),
+                Err(ref error) if error.kind() == ErrorKind::Interrupted => { },
+                Err(_) => return Err(Closed),
+            }
+        }
+    }
+}
+
+/// Records of a source, read on a background thread and batched into epochs.
+pub struct Feed<D> {
+    batches: Receiver<Vec<D>>,
+    thread: Option<JoinHandle<()>>,
+    weight: i64,
+}
+
+impl<D: Data+Send> Feed<D> {
+    /// Reads `source` on a new thread, giving each record weight `weight`.
+    ///
+    /// A batch ends once it holds `batch` records, or once `interval` has passed since it began,
+    /// whichever comes first, and so epochs advance even when no records arrive.
+    pub fn spawn<S: Source<Item=D>+Send+'static>(source: S, weight: i64, batch: usize, interval: Duration) -> Self {
+        assert!(batch > 0);
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || read(source, batch, interval, sender));
+        Feed { batches: receiver, thread: Some(thread), weight: weight }
+    }
+
+    /// Moves the available batches into the true input of `handle`, advancing it after each.
+    ///
+    /// The synthetic input is advanced with the true input. Returns the number of batches moved,
+    /// or `None` once the source has closed and all of its batches have been moved, after which
+    /// the handle may be closed.
+    pub fn pump(&mut self, handle: &mut DatasetHandle<usize, D>) -> Option<usize> {
+        let mut moved = 0;
+        loop {
+            match self.batches.try_recv() {
+                Ok(batch) => {
+                    for record in batch.into_iter() {
+                        handle.truth.send((record, self.weight));
+                    }
+                    let next = handle.truth.time() + 1;
+                    handle.truth.advance_to(next);
+                    handle.synth.advance_to(next);
+                    moved += 1;
+                },
+                Err(TryRecvError::Empty) => return Some(moved),
+                Err(TryRecvError::Disconnected) => {
+                    if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+                    return if moved > 0 { Some(moved) } else { None };
+                },
+            }
+        }
+    }
+}
+
+// reads `source` into batches sent to `sender`, until the source closes or `sender` disconnects.
+fn read<S: Source>(mut source: S, batch: usize, interval: Duration, sender: Sender<Vec<S::Item>>) {
+    let mut records = Vec::with_capacity(batch);
+    let mut start = Instant::now();
+    loop {
+        let elapsed = start.elapsed();
+        let closed = if elapsed < interval {
+            match source.next(interval - elapsed) {
+                Ok(Some(record)) => { records.push(record); false },
+                Ok(None) => false,
+                Err(Closed) => true,
+            }
+        }
+        else { false };
+        if closed || records.len() >= batch || start.elapsed() >= interval {
+            let full = ::std::mem::replace(&mut records, Vec::with_capacity(batch));
+            if sender.send(full).is_err() || closed { return; }
+            start = Instant::now();
+        }
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_read() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let (records, source) = mpsc::channel();
+        for record in 0 .. 5 { records.send(record).unwrap(); }
+        drop(records);
+
+        let (sender, batches) = mpsc::channel();
+        super::read(source, 2, Duration::from_secs(60), sender);
+        assert_eq!(batches.iter().collect::<Vec<_>>(), vec![vec![0, 1], vec![2, 3], vec![4]]);
+    }
+}
@@ -0,0 +1,222 @@
+//! A minimal SQL-like frontend.
+//!
+//! Queries of the form `SELECT .. FROM .. [JOIN .. ON ..] [WHERE ..] [GROUP BY ..]` are parsed and
+//! lowered to `Dataset` operators over rows of textual fields, concluding in a `measure` whose
+//! keys are the selected (or grouped) columns. Comparisons are numeric when both sides parse as
+//! numbers, and lexicographic otherwise, which orders ISO dates correctly.
+//!
+//! `SELECT DISTINCT` and `COUNT(DISTINCT ..)` are lowered to `shave`, retaining the first slice of
+//! each distinct value, so that each contributes at most the catalog's distinct width.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use timely::dataflow::{Scope, ProbeHandle};
+
+use ::{Dataset, Measurement};
+
+mod parse;
+
+pub use self::parse::{parse, Comparison, Operand, Query};
+
+/// A row of textual field values.
+pub type Row = Vec<String>;
+
+/// An operand resolved against a schema.
+#[derive(Clone)]
+enum Resolved {
+    Column(usize),
+    Literal(String),
+}
+
+impl Resolved {
+    fn value<'a>(&'a self, row: &'a Row) -> &'a str {
+        match *self {
+            Resolved::Column(index) => &row[index],
+            Resolved::Literal(ref value) => value,
+        }
+    }
+}
+
+fn compare(value1: &str, value2: &str) -> Ordering {
+    match (value1.parse::<f64>(), value2.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        _ => value1.cmp(value2),
+    }
+}
+
+fn evaluate(row: &Row, left: &Resolved, comparison: Comparison, right: &Resolved) -> bool {
+    let order = compare(left.value(row), right.value(row));
+    match comparison {
+        Comparison::Eq => order == Ordering::Equal,
+        Comparison::Ne => order != Ordering::Equal,
+        Comparison::Lt => order == Ordering::Less,
+        Comparison::Le => order != Ordering::Greater,
+        Comparison::Gt => order == Ordering::Greater,
+        Comparison::Ge => order != Ordering::Less,
+    }
+}
+
+/// An error planning a query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlanError {
+    /// The query text does not parse.
+    Parse(String),
+    /// The named table is not registered.
+    UnknownTable(String),
+    /// The named column is in no table of the query.
+    UnknownColumn(String),
+    /// The named column is the suffix of columns of several tables.
+    AmbiguousColumn(String),
+    /// The named column is selected but not grouped.
+    Ungrouped(String),
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlanError::Parse(ref message) => write!(f, "parse error: {}", message),
+            PlanError::UnknownTable(ref name) => write!(f, "unknown table: {}", name),
+            PlanError::UnknownColumn(ref name) => write!(f, "unknown column: {}", name),
+            PlanError::AmbiguousColumn(ref name) => write!(f, "ambiguous column: {}", name),
+            PlanError::Ungrouped(ref name) => write!(f, "selected column not grouped: {}", name),
+        }
+    }
+}
+
+impl Error for PlanError { }
+
+/// Resolves `name` against `columns`, either exactly or as the unique column with suffix `.name`.
+fn resolve(columns: &[String], name: &str) -> Result<usize, PlanError> {
+    if let Some(index) = columns.iter().position(|c| c == name) {
+        return Ok(index);
+    }
+    let suffix = format!(".{}", name);
+    let matches = columns.iter().enumerate().filter(|&(_, c)| c.ends_with(&suffix)).map(|(i, _)| i).collect::<Vec<_>>();
+    match matches.len() {
+        1 => Ok(matches[0]),
+        0 => Err(PlanError::UnknownColumn(name.to_owned())),
+        _ => Err(PlanError::AmbiguousColumn(name.to_owned())),
+    }
+}
+
+/// Registered datasets of rows, against which queries are planned.
+pub struct Catalog<G: Scope> {
+    tables: HashMap<String, (Vec<String>, Dataset<G, Row>)>,
+    width: i64,
+}
+
+impl<G: Scope> Catalog<G> {
+
+    /// Creates an empty catalog, with a distinct width of one.
+    pub fn new() -> Self {
+        Catalog { tables: HashMap::new(), width: 1 }
+    }
+
+    /// Sets the weight `width` that each distinct value contributes to `DISTINCT` measurements.
+    ///
+    /// The width is typically the weight of one row, so that each distinct value counts as one row.
+    pub fn distinct_width(mut self, width: i64) -> Self {
+        assert!(width > 0);
+        self.width = width;
+        self
+    }
+
+    /// Registers `dataset` as table `name`, whose rows have fields named by `columns`.
+    pub fn register(&mut self, name: &str, columns: &[&str], dataset: Dataset<G, Row>) {
+        let columns = columns.iter().map(|c| format!("{}.{}", name, c)).collect();
+        self.tables.insert(name.to_owned(), (columns, dataset));
+    }
+
+    // the columns and rows of table `name`; rows with fewer fields than columns are discarded, and
+    // additional fields truncated, so that columns resolved against the schema index every row.
+    fn table(&self, name: &str) -> Result<(Vec<String>, Dataset<G, Row>), PlanError> {
+        let &(ref columns, ref dataset) = self.tables.get(name).ok_or_else(|| PlanError::UnknownTable(name.to_owned()))?;
+        let arity = columns.len();
+        let dataset = dataset.clone().filter_map(move |mut row: Row| {
+            if row.len() < arity { None } else { row.truncate(arity); Some(row) }
+        });
+        Ok((columns.clone(), dataset))
+    }
+
+    /// Lowers `query` to a dataset of measured keys, and the names of their columns.
+    ///
+    /// Rows with fewer fields than their table has columns are discarded, as by `filter`.
+    pub fn plan(&self, query: &Query) -> Result<(Vec<String>, Dataset<G, Row>), PlanError> {
+
+        let (mut columns, mut dataset) = self.table(&query.from)?;
+
+        if let Some((ref table, ref left, ref right)) = query.join {
+            let (other_columns, other) = self.table(table)?;
+            // allow the equated columns to be named in either order.
+            let (left, right) = match (resolve(&columns, left), resolve(&other_columns, right)) {
+                (Ok(l), Ok(r)) => (l, r),
+                _ => (resolve(&columns, right)?, resolve(&other_columns, left)?),
+            };
+            dataset =
+            dataset
+                .join_by(other, move |row| row[left].clone(), move |row| row[right].clone())
+                .map(|(mut row1, row2)| { row1.extend(row2); row1 });
+            columns.extend(other_columns);
+        }
+
+        if !query.filter.is_empty() {
+            let mut conditions = Vec::new();
+            for &(ref left, comparison, ref right) in query.filter.iter() {
+                let resolve_operand = |operand: &Operand| match *operand {
+                    Operand::Column(ref name) => resolve(&columns, name).map(Resolved::Column),
+                    Operand::Literal(ref value) => Ok(Resolved::Literal(value.clone())),
+                };
+                conditions.push((resolve_operand(left)?, comparison, resolve_operand(right)?));
+            }
+            dataset = dataset.filter(move |row| conditions.iter().all(|&(ref l, c, ref r)| evaluate(row, l, c, r)));
+        }
+
+        if !query.group.is_empty() {
+            for name in query.select.iter() {
+                if !query.group.contains(name) {
+                    return Err(PlanError::Ungrouped(name.clone()));
+                }
+            }
+        }
+
+        let keys = if query.select.is_empty() { &query.group } else { &query.select };
+        let indices = keys.iter().map(|name| resolve(&columns, name)).collect::<Result<Vec<_>, _>>()?;
+        let names = indices.iter().map(|&i| columns[i].clone()).collect();
+
+        // distinct values are shaved into slices of `width`, of which only the first is retained.
+        let width = self.width;
+        let dataset =
+        if !query.count_distinct.is_empty() {
+            let arity = indices.len();
+            let counted = query.count_distinct.iter().map(|name| resolve(&columns, name)).collect::<Result<Vec<_>, _>>()?;
+            let indices = indices.into_iter().chain(counted).collect::<Vec<_>>();
+            dataset
+                .map_ref(move |row| indices.iter().map(|&i| row[i].clone()).collect::<Row>())
+                .shave(width)
+                .filter_map(move |(mut row, index)| if index == 0 { row.truncate(arity); Some(row) } else { None })
+        }
+        else if query.distinct {
+            dataset
+                .map_ref(move |row| indices.iter().map(|&i| row[i].clone()).collect::<Row>())
+                .shave(width)
+                .filter_map(|(row, index)| if index == 0 { Some(row) } else { None })
+        }
+        else {
+            dataset.map_ref(move |row| indices.iter().map(|&i| row[i].clone()).collect::<Row>())
+        };
+
+        Ok((names, dataset))
+    }
+
+    /// Parses, plans, and measures `text`, returning noisy counts keyed by the selected columns.
+    pub fn query(&self, text: &str, probe: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>) -> Result<Measurement<Row>, PlanError> {
+        let query = parse(text).map_err(PlanError::Parse)?;
+        let (_names, dataset) = self.plan(&query)?;
+        Ok(dataset.measure(probe, total))
+    }
+}
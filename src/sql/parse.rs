@@ -0,0 +1,249 @@
+//! Tokenizing and parsing of queries.
+
+/// A comparison operator in a `WHERE` clause.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Comparison { Eq, Ne, Lt, Le, Gt, Ge }
+
+/// An operand of a comparison: a column reference or a literal value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Column(String),
+    Literal(String),
+}
+
+/// A parsed query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Query {
+    /// The selected columns; empty for `COUNT(*)` and `COUNT(DISTINCT ..)`.
+    pub select: Vec<String>,
+    /// Indicates `SELECT DISTINCT`, which measures each distinct selection at most once.
+    pub distinct: bool,
+    /// The columns of `COUNT(DISTINCT ..)`, whose distinct values are counted; empty otherwise.
+    pub count_distinct: Vec<String>,
+    /// The first table named in the `FROM` clause.
+    pub from: String,
+    /// An optional joined table, with the pair of columns equated by `ON`.
+    pub join: Option<(String, String, String)>,
+    /// Conjunctive `WHERE` conditions.
+    pub filter: Vec<(Operand, Comparison, Operand)>,
+    /// The `GROUP BY` columns.
+    pub group: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Literal(String),
+    Symbol(&'static str),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+
+    let mut tokens = Vec::new();
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        }
+        else if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_' || chars[index] == '.' || chars[index] == '-') {
+                index += 1;
+            }
+            let word = chars[start .. index].iter().collect::<String>();
+            if word.parse::<f64>().is_ok() { tokens.push(Token::Literal(word)); }
+            else { tokens.push(Token::Word(word)); }
+        }
+        else if c == '\'' {
+            let start = index + 1;
+            index = start;
+            while index < chars.len() && chars[index] != '\'' { index += 1; }
+            if index == chars.len() { return Err("unterminated string literal".to_owned()); }
+            tokens.push(Token::Literal(chars[start .. index].iter().collect()));
+            index += 1;
+        }
+        else {
+            let next = if index + 1 < chars.len() { Some(chars[index+1]) } else { None };
+            let symbol = match (c, next) {
+                ('<', Some('=')) => "<=",
+                ('>', Some('=')) => ">=",
+                ('!', Some('=')) => "!=",
+                ('<', Some('>')) => "!=",
+                ('<', _) => "<",
+                ('>', _) => ">",
+                ('=', _) => "=",
+                (',', _) => ",",
+                ('(', _) => "(",
+                (')', _) => ")",
+                ('*', _) => "*",
+                _ => { return Err(format!("unexpected character: {:?}", c)); }
+            };
+            index += symbol.len();
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    cursor: usize,
+}
+
+impl Parser {
+
+    fn peek(&self) -> Option<&Token> { self.tokens.get(self.cursor) }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = match self.peek() {
+            Some(&Token::Word(ref word)) => word.eq_ignore_ascii_case(keyword),
+            _ => false,
+        };
+        if found { self.cursor += 1; }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.keyword(keyword) { Ok(()) }
+        else { Err(format!("expected {}, found {:?}", keyword, self.peek())) }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let found = match self.peek() {
+            Some(&Token::Symbol(found)) => found == symbol,
+            _ => false,
+        };
+        if found { self.cursor += 1; }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.symbol(symbol) { Ok(()) }
+        else { Err(format!("expected {:?}, found {:?}", symbol, self.peek())) }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        let result = match self.peek() {
+            Some(&Token::Word(ref word)) => Ok(word.clone()),
+            other => Err(format!("expected identifier, found {:?}", other)),
+        };
+        if result.is_ok() { self.cursor += 1; }
+        result
+    }
+
+    fn identifiers(&mut self) -> Result<Vec<String>, String> {
+        let mut result = vec![self.identifier()?];
+        while self.symbol(",") {
+            result.push(self.identifier()?);
+        }
+        Ok(result)
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let result = match self.peek() {
+            Some(&Token::Word(ref word)) => Ok(Operand::Column(word.clone())),
+            Some(&Token::Literal(ref literal)) => Ok(Operand::Literal(literal.clone())),
+            other => Err(format!("expected column or literal, found {:?}", other)),
+        };
+        if result.is_ok() { self.cursor += 1; }
+        result
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, String> {
+        let result = match self.peek() {
+            Some(&Token::Symbol("=")) => Ok(Comparison::Eq),
+            Some(&Token::Symbol("!=")) => Ok(Comparison::Ne),
+            Some(&Token::Symbol("<")) => Ok(Comparison::Lt),
+            Some(&Token::Symbol("<=")) => Ok(Comparison::Le),
+            Some(&Token::Symbol(">")) => Ok(Comparison::Gt),
+            Some(&Token::Symbol(">=")) => Ok(Comparison::Ge),
+            other => Err(format!("expected comparison, found {:?}", other)),
+        };
+        if result.is_ok() { self.cursor += 1; }
+        result
+    }
+}
+
+/// Parses a query of the form
+///
+/// ```text
+/// SELECT [DISTINCT] (COUNT(*) | COUNT(DISTINCT col, ..) | col, ..) FROM table [JOIN table ON col = col]
+///     [WHERE col op value [AND ..]] [GROUP BY col, ..]
+/// ```
+pub fn parse(text: &str) -> Result<Query, String> {
+
+    let mut parser = Parser { tokens: tokenize(text)?, cursor: 0 };
+
+    parser.expect_keyword("SELECT")?;
+    let distinct = parser.keyword("DISTINCT");
+    let mut count_distinct = Vec::new();
+    let select =
+    if parser.keyword("COUNT") {
+        parser.expect_symbol("(")?;
+        if parser.keyword("DISTINCT") { count_distinct = parser.identifiers()?; }
+        else { parser.expect_symbol("*")?; }
+        parser.expect_symbol(")")?;
+        Vec::new()
+    }
+    else {
+        parser.identifiers()?
+    };
+
+    parser.expect_keyword("FROM")?;
+    let from = parser.identifier()?;
+
+    let join =
+    if parser.keyword("JOIN") {
+        let table = parser.identifier()?;
+        parser.expect_keyword("ON")?;
+        let left = parser.identifier()?;
+        parser.expect_symbol("=")?;
+        let right = parser.identifier()?;
+        Some((table, left, right))
+    }
+    else { None };
+
+    let mut filter = Vec::new();
+    if parser.keyword("WHERE") {
+        loop {
+            let left = parser.operand()?;
+            let comparison = parser.comparison()?;
+            let right = parser.operand()?;
+            filter.push((left, comparison, right));
+            if !parser.keyword("AND") { break; }
+        }
+    }
+
+    let group =
+    if parser.keyword("GROUP") {
+        parser.expect_keyword("BY")?;
+        parser.identifiers()?
+    }
+    else { Vec::new() };
+
+    if let Some(token) = parser.peek() {
+        return Err(format!("unexpected trailing token: {:?}", token));
+    }
+
+    Ok(Query { select: select, distinct: distinct, count_distinct: count_distinct, from: from, join: join, filter: filter, group: group })
+}
+
+mod tests {
+    #[test]
+    fn test_parse() {
+        use super::{parse, Comparison, Operand};
+        let query = parse("SELECT o.priority FROM orders JOIN lineitem ON o.key = l.key WHERE o.date >= '1993-07-01' AND l.qty < 5 GROUP BY o.priority").unwrap();
+        assert_eq!(query.select, vec!["o.priority".to_owned()]);
+        assert_eq!(query.join, Some(("lineitem".to_owned(), "o.key".to_owned(), "l.key".to_owned())));
+        assert_eq!(query.filter[0], (Operand::Column("o.date".to_owned()), Comparison::Ge, Operand::Literal("1993-07-01".to_owned())));
+        assert_eq!(query.filter[1], (Operand::Column("l.qty".to_owned()), Comparison::Lt, Operand::Literal("5".to_owned())));
+        assert!(parse("select count(*) from orders").unwrap().select.is_empty());
+        assert!(parse("SELECT DISTINCT o.custkey FROM orders").unwrap().distinct);
+        assert_eq!(parse("SELECT COUNT(DISTINCT o.custkey) FROM orders").unwrap().count_distinct, vec!["o.custkey".to_owned()]);
+        assert!(parse("SELECT FROM orders").is_err());
+    }
+}
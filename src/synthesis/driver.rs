@@ -0,0 +1,65 @@
+//! Incremental scoring of proposed changes to synthetic data.
+//!
+//! Evaluating a proposed change ordinarily advances the synthetic input and steps the worker until
+//! a probe shared by all measurements reaches the new timestamp, and reads a total error shared by
+//! all measurements. A `Driver` instead owns the synthetic input along with a probe and a total
+//! attached only to the measurements of dataflows that read the input, so that scoring a change
+//! waits only on those dataflows, and reflects only their measurements.
+//!
+//! Timely schedules every dataflow of a worker each time it is stepped, and so the other dataflows
+//! are still scheduled while a change is scored. Those without pending input do little work, and
+//! their progress does not delay the score.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use timely::{Allocate, Data};
+use timely::dataflow::{InputHandle, ProbeHandle};
+use timely::dataflow::scopes::Root;
+use timely::progress::nested::product::Product;
+use timely::progress::timestamp::RootTimestamp;
+
+/// Drives one synthetic input, scoring changes by their effect on the total error.
+///
+/// The probe returned by `probe`, and the total returned by `total_handle`, should be supplied to
+/// each measurement of a dataflow reading the input (and to no others), in place of any globally
+/// shared probe and total.
+pub struct Driver<D: Data> {
+    input: InputHandle<usize, (D, i64)>,
+    probe: ProbeHandle<Product<RootTimestamp, usize>>,
+    total: Rc<RefCell<i64>>,
+}
+
+impl<D: Data> Driver<D> {
+    /// Creates a driver for `input`.
+    pub fn new(input: InputHandle<usize, (D, i64)>) -> Self {
+        Driver {
+            input: input,
+            probe: ProbeHandle::new(),
+            total: Rc::new(RefCell::new(0)),
+        }
+    }
+    /// The synthetic input, for attaching to dataflows.
+    pub fn input(&mut self) -> &mut InputHandle<usize, (D, i64)> { &mut self.input }
+    /// The probe to be supplied to measurements of dataflows reading the input.
+    pub fn probe(&mut self) -> &mut ProbeHandle<Product<RootTimestamp, usize>> { &mut self.probe }
+    /// The total error to be supplied to measurements of dataflows reading the input.
+    pub fn total_handle(&self) -> &Rc<RefCell<i64>> { &self.total }
+    /// The current total error of the measurements reading the input.
+    pub fn total(&self) -> i64 { *self.total.borrow() }
+    /// Applies `deltas` to the synthetic input and returns the resulting change in total error.
+    ///
+    /// The worker is stepped only until the measurements reading this input reflect the change,
+    /// and the change is that of their total error alone. The deltas remain applied; they can be
+    /// reverted by scoring their negation.
+    pub fn score_delta<A: Allocate>(&mut self, worker: &mut Root<A>, deltas: &[(D, i64)]) -> i64 {
+        let before = self.total();
+        for update in deltas.iter() {
+            self.input.send(update.clone());
+        }
+        let next = self.input.time().inner + 1;
+        self.input.advance_to(next);
+        while self.probe.less_than(self.input.time()) { worker.step(); }
+        self.total() - before
+    }
+}
@@ -0,0 +1,177 @@
+//! Initial synthetic graphs, for subsequent refinement or as lightweight synthetic output.
+//!
+//! Refinement converges much more quickly from a graph that already matches the coarse structure
+//! of the measurements than from a uniformly random graph. The initializers here produce graphs
+//! fitted to post-processed measurements, and so consume no additional privacy.
+
+use rand::{Rng, RngCore};
+
+/// Builds a directed graph whose out- and in-degree sequences both equal `degrees`.
+///
+/// Node `i` is assigned `degrees[i]` outgoing and `degrees[i]` incoming edge stubs, and outgoing
+/// stubs are matched to a uniformly random permutation of incoming stubs (the configuration
+/// model). The result may contain self-loops and repeated edges, which refinement is free to
+/// remove. The `degrees` are typically the fitted sequence produced by `degrees::fit_cdf_seq`.
+pub fn from_degree_sequence(degrees: &[usize], rng: &mut dyn RngCore) -> Vec<(usize, usize)> {
+
+    let mut stubs = Vec::with_capacity(degrees.iter().sum());
+    for (node, &degree) in degrees.iter().enumerate() {
+        for _ in 0 .. degree {
+            stubs.push(node);
+        }
+    }
+
+    let mut targets = stubs.clone();
+    for index in (1 .. targets.len()).rev() {
+        let other = rng.gen_range(0, index + 1);
+        targets.swap(index, other);
+    }
+
+    stubs.into_iter().zip(targets.into_iter()).collect()
+}
+
+/// Builds a directed graph approximating the joint degree distribution `joint`.
+///
+/// Each entry `((d1, d2), count)` asks for `count` edges from nodes of degree `d1` to nodes of
+/// degree `d2` (the dK-2 statistics), where a node of degree `d` has `d` outgoing and `d` incoming
+/// edges. The number of nodes of each degree is inferred from the number of edge endpoints that
+/// require that degree, and endpoints are assigned by drawing without replacement from the stubs
+/// of the nodes of each degree. If the noisy counts demand more stubs than a degree class has,
+/// further endpoints are assigned to uniformly chosen nodes of the class.
+///
+/// The counts are typically rounded and clamped noisy measurements, as produced from a
+/// `degrees::joint` measurement by `degrees::joint_degrees`, and the result is meant to be loaded
+/// into a synthesis `Table` as the starting point for refinement.
+pub fn from_joint_degrees(joint: &[((usize, usize), usize)], rng: &mut dyn RngCore) -> Vec<(usize, usize)> {
+
+    // count the outgoing and incoming endpoints required of each degree.
+    let mut endpoints = Vec::new();
+    for &((d1, d2), count) in joint.iter() {
+        let max = ::std::cmp::max(d1, d2);
+        if endpoints.len() <= max { endpoints.resize(max + 1, (0, 0)); }
+        endpoints[d1].0 += count;
+        endpoints[d2].1 += count;
+    }
+
+    // allocate nodes to each non-zero degree, and their stubs in random order.
+    let mut nodes = Vec::new();
+    let mut out_stubs = Vec::new();
+    let mut in_stubs = Vec::new();
+    let mut next = 0;
+    for (degree, &(outs, ins)) in endpoints.iter().enumerate() {
+        let count = ::std::cmp::max(outs, ins);
+        let number = if degree > 0 { (count + degree - 1) / degree } else { 0 };
+        nodes.push((next, number));
+        out_stubs.push(shuffled_stubs(next, number, degree, rng));
+        in_stubs.push(shuffled_stubs(next, number, degree, rng));
+        next += number;
+    }
+
+    let mut graph = Vec::new();
+    for &((d1, d2), count) in joint.iter() {
+        if d1 > 0 && d2 > 0 {
+            for _ in 0 .. count {
+                let src = out_stubs[d1].pop().unwrap_or_else(|| nodes[d1].0 + rng.gen_range(0, nodes[d1].1));
+                let dst = in_stubs[d2].pop().unwrap_or_else(|| nodes[d2].0 + rng.gen_range(0, nodes[d2].1));
+                graph.push((src, dst));
+            }
+        }
+    }
+
+    graph
+}
+
+/// Samples a directed Chung-Lu random graph whose expected out- and in-degrees are `weights`.
+///
+/// The number of edges is the rounded sum of the weights, and each edge has its source and its
+/// destination drawn independently, each node with probability proportional to its weight. This
+/// takes a single pass and needs no refinement, and reproduces the degree distribution but not the
+/// clustering of the original graph. The result may contain self-loops and repeated edges. The
+/// `weights` are typically produced by `degrees::expected_degrees`.
+pub fn chung_lu(weights: &[f64], rng: &mut dyn RngCore) -> Vec<(usize, usize)> {
+
+    let mut cumulative = Vec::with_capacity(weights.len());
+    let mut sum = 0.0;
+    for &weight in weights.iter() {
+        assert!(weight >= 0.0);
+        sum += weight;
+        cumulative.push(sum);
+    }
+
+    // draws the first node whose cumulative weight exceeds a uniform target.
+    let last = weights.len().saturating_sub(1);
+    let mut draw = || {
+        let target = rng.gen::<f64>() * sum;
+        let index = match cumulative.binary_search_by(|x| x.partial_cmp(&target).unwrap()) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
+        ::std::cmp::min(index, last)
+    };
+
+    let edges = sum.round() as usize;
+    let mut graph = Vec::with_capacity(edges);
+    for _ in 0 .. edges {
+        let src = draw();
+        let dst = draw();
+        graph.push((src, dst));
+    }
+    graph
+}
+
+// produces `degree` stubs for each of `number` nodes starting from `first`, in random order.
+fn shuffled_stubs(first: usize, number: usize, degree: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+    let mut stubs = Vec::with_capacity(number * degree);
+    for node in first .. first + number {
+        for _ in 0 .. degree {
+            stubs.push(node);
+        }
+    }
+    for index in (1 .. stubs.len()).rev() {
+        let other = rng.gen_range(0, index + 1);
+        stubs.swap(index, other);
+    }
+    stubs
+}
+
+mod tests {
+    #[test]
+    fn test_degrees() {
+        let degrees = vec![5, 3, 2, 2, 1, 1, 0];
+        let graph = super::from_degree_sequence(&degrees[..], &mut ::rand::thread_rng());
+
+        let mut out_degrees = vec![0; degrees.len()];
+        let mut in_degrees = vec![0; degrees.len()];
+        for &(src, dst) in graph.iter() {
+            out_degrees[src] += 1;
+            in_degrees[dst] += 1;
+        }
+
+        assert_eq!(out_degrees, degrees);
+        assert_eq!(in_degrees, degrees);
+    }
+
+    #[test]
+    fn test_chung_lu() {
+        let weights = vec![10.0, 5.0, 4.6, 0.0];
+        let graph = super::chung_lu(&weights[..], &mut ::rand::thread_rng());
+        assert_eq!(graph.len(), 20);
+        assert!(graph.iter().all(|&(src, dst)| src < 3 && dst < 3));
+    }
+
+    #[test]
+    fn test_joint() {
+        // a star with three leaves, in both directions.
+        let joint = vec![((3, 1), 3), ((1, 3), 3)];
+        let graph = super::from_joint_degrees(&joint[..], &mut ::rand::thread_rng());
+
+        let mut degrees = vec![0; 4];
+        for &(src, _dst) in graph.iter() {
+            degrees[src] += 1;
+        }
+        degrees.sort();
+
+        assert_eq!(graph.len(), 6);
+        assert_eq!(degrees, vec![1, 1, 1, 3]);
+    }
+}
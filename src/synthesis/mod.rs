@@ -0,0 +1,80 @@
+//! Synthesis of datasets fitted to noisy measurements.
+//!
+//! Synthetic records are introduced through the `synth` inputs of measured datasets, and each
+//! measurement accumulates the error between its synthetic and true counts into a shared total.
+//! Synthesis repeatedly proposes changes to the synthetic records, and retains those changes
+//! that do not increase the total error.
+//!
+//! For data over a small enumerable domain, the `mwem` module instead maintains an explicit
+//! synthetic distribution, fitted by multiplicative weights, and the `pmw` module serves
+//! interactive queries from such a distribution.
+
+use timely::Data;
+use timely::dataflow::InputHandle;
+use timely::progress::Timestamp;
+
+pub mod tabular;
+pub mod driver;
+pub mod graph;
+pub mod mwem;
+pub mod pmw;
+pub mod synthesizer;
+
+pub use self::driver::Driver;
+pub use self::synthesizer::Synthesizer;
+
+/// A table of synthetic records, each introduced with a common weight.
+///
+/// Changes to the table are recorded as pending weighted updates, and are only sent to a
+/// synthetic input when `flush` is called.
+pub struct Table<R: Data> {
+    records: Vec<R>,
+    weight: i64,
+    pending: Vec<(R, i64)>,
+}
+
+impl<R: Data> Table<R> {
+    /// Creates an empty table whose records will each have weight `weight`.
+    pub fn new(weight: i64) -> Self {
+        Table::from(Vec::new(), weight)
+    }
+    /// Creates a table from `records`, all of which are pending introduction.
+    pub fn from(records: Vec<R>, weight: i64) -> Self {
+        let pending = records.iter().map(|r| (r.clone(), weight)).collect();
+        Table {
+            records: records,
+            weight: weight,
+            pending: pending,
+        }
+    }
+    /// Creates a table of `count` records produced by `constructor` from their index.
+    pub fn generate<F: FnMut(usize)->R>(count: usize, weight: i64, constructor: F) -> Self {
+        Table::from((0 .. count).map(constructor).collect(), weight)
+    }
+    /// The current synthetic records.
+    pub fn records(&self) -> &[R] { &self.records[..] }
+    /// The weight with which each record is introduced.
+    pub fn weight(&self) -> i64 { self.weight }
+    /// The number of records.
+    pub fn len(&self) -> usize { self.records.len() }
+    /// Indicates whether the table has no records.
+    pub fn is_empty(&self) -> bool { self.records.is_empty() }
+    /// Adds `record` to the table.
+    pub fn push(&mut self, record: R) {
+        self.pending.push((record.clone(), self.weight));
+        self.records.push(record);
+    }
+    /// Replaces the record at `index` with `record`, returning the prior record.
+    pub fn replace(&mut self, index: usize, record: R) -> R {
+        self.pending.push((record.clone(), self.weight));
+        let prior = ::std::mem::replace(&mut self.records[index], record);
+        self.pending.push((prior.clone(), -self.weight));
+        prior
+    }
+    /// Sends all pending updates to `input`.
+    pub fn flush<T: Timestamp>(&mut self, input: &mut InputHandle<T, (R, i64)>) {
+        for update in self.pending.drain(..) {
+            input.send(update);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Multiplicative weights synthesis over an explicit domain (MWEM).
+//!
+//! For low-dimensional data whose domain can be enumerated, the synthetic data can be maintained
+//! as a distribution with a weight for each domain element, rather than as records. Each round
+//! privately selects a query the distribution answers poorly, measures it, and multiplicatively
+//! reweights the distribution towards the measurement. This converges much faster than refining
+//! synthetic records, but requires memory and time proportional to the size of the domain.
+//!
+//! Queries are linear, given as lists of domain elements and coefficients in `[-1, 1]`, so that
+//! each record changes the answer to each query by at most one. They are answered on the sensitive
+//! data by a `Histogram`, gathered from a dataset by `Dataset::histogram`, and each round is charged
+//! to an odometer.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use rand::{Rng, RngCore};
+
+use budget::{Exhausted, Odometer};
+use noise;
+
+/// The sensitive counts of a dataset of domain elements, gathered at worker zero.
+///
+/// The counts are not exposed, and are only consulted by `Mwem::run` and `Pmw::answer`, which
+/// reveal them through noise and charge an odometer for doing so. Counts are in units of input
+/// records, the weight of each record having been divided by the weight of an input record times
+/// the stability of the dataset. All counts are held by worker zero, and mechanisms should only be
+/// run there once the probe indicates completion; other workers hold no counts.
+pub struct Histogram {
+    weights: Rc<RefCell<HashMap<usize, i64>>>,
+    domain: usize,
+    unit: f64,
+}
+
+impl Histogram {
+    /// Wraps gathered `weights` over `0 .. domain`, each input record contributing at most `unit`.
+    pub(crate) fn new(weights: Rc<RefCell<HashMap<usize, i64>>>, domain: usize, unit: f64) -> Self {
+        assert!(domain > 0);
+        assert!(unit > 0.0);
+        Histogram { weights: weights, domain: domain, unit: unit }
+    }
+    /// The number of domain elements.
+    pub fn domain(&self) -> usize { self.domain }
+    /// The true answer to `query`, in units of input records.
+    pub(crate) fn answer(&self, query: &[(usize, f64)]) -> f64 {
+        let weights = self.weights.borrow();
+        query.iter().map(|&(x, c)| c * (weights.get(&x).cloned().unwrap_or(0) as f64)).sum::<f64>() / self.unit
+    }
+}
+
+/// A synthetic distribution over the domain `0 .. domain`, scaled to a total count.
+pub struct Mwem {
+    distribution: Vec<f64>,
+    total: f64,
+    measurements: Vec<(usize, f64)>,
+}
+
+impl Mwem {
+
+    /// Creates a uniform distribution over `domain` elements with total count `total`.
+    pub fn new(domain: usize, total: f64) -> Self {
+        assert!(domain > 0);
+        Mwem {
+            distribution: vec![total / domain as f64; domain],
+            total: total,
+            measurements: Vec::new(),
+        }
+    }
+
+    /// The synthetic count of each domain element.
+    pub fn distribution(&self) -> &[f64] { &self.distribution[..] }
+    /// The queries measured by `run`, by index, and their noisy answers.
+    pub fn measurements(&self) -> &[(usize, f64)] { &self.measurements[..] }
+
+    /// The answer to `query` on the synthetic distribution.
+    pub fn answer(&self, query: &[(usize, f64)]) -> f64 {
+        query.iter().map(|&(x, c)| c * self.distribution[x]).sum()
+    }
+
+    /// Reweights the distribution towards answering `query` with `measured`.
+    ///
+    /// Each element's weight is multiplied by `exp(c * (measured - answer) / (2 * total))`, where
+    /// `c` is its coefficient in `query`, and the distribution is rescaled to its total.
+    pub fn update(&mut self, query: &[(usize, f64)], measured: f64) {
+        let error = measured - self.answer(query);
+        for &(x, c) in query.iter() {
+            self.distribution[x] *= (c * error / (2.0 * self.total)).exp();
+        }
+        let sum: f64 = self.distribution.iter().sum();
+        if sum > 0.0 {
+            for weight in self.distribution.iter_mut() {
+                *weight *= self.total / sum;
+            }
+        }
+    }
+
+    /// Repeatedly reweights the distribution towards each of the `measured` answers to `queries`.
+    ///
+    /// This only post-processes the measurements, which may come from any source, such as the
+    /// noisy counts of a `Measurement`.
+    pub fn fit(&mut self, queries: &[Vec<(usize, f64)>], measured: &[(usize, f64)], iterations: usize) {
+        for _ in 0 .. iterations {
+            for &(index, value) in measured.iter() {
+                self.update(&queries[index][..], value);
+            }
+        }
+    }
+
+    /// Refines the distribution towards `histogram` over `rounds` rounds, at a total cost of `epsilon`.
+    ///
+    /// Each round charges `epsilon / rounds` to `odometer`, and spends half of it selecting the
+    /// worst-fit query by the exponential mechanism, and half measuring it with Laplace noise,
+    /// after which the distribution is refit to all measurements made so far. If a round cannot be
+    /// afforded the error is returned, and the distribution reflects the rounds completed. The
+    /// total count of the distribution is that given to `new`, which should be public or noisy.
+    pub fn run(&mut self, histogram: &Histogram, queries: &[Vec<(usize, f64)>], rounds: usize, epsilon: f64, odometer: &mut Odometer, rng: &mut dyn RngCore) -> Result<(), Exhausted> {
+
+        assert!(epsilon > 0.0);
+        assert!(histogram.domain() == self.distribution.len());
+        assert!(queries.iter().all(|q| q.iter().all(|x| x.1.abs() <= 1.0)));
+
+        let answers = queries.iter().map(|query| histogram.answer(&query[..])).collect::<Vec<_>>();
+        let epsilon = epsilon / (rounds as f64);
+
+        for _ in 0 .. rounds {
+
+            odometer.charge("MwemRound", epsilon)?;
+
+            // select a poorly answered query, and measure it.
+            let index = self.select(&answers[..], queries, epsilon / 2.0, rng);
+            self.measurements.push((index, answers[index] + noise::laplace_scaled(2.0 / epsilon, rng)));
+
+            // refit the distribution to all measurements.
+            let measured = self.measurements.clone();
+            self.fit(queries, &measured[..], 1);
+        }
+
+        Ok(())
+    }
+
+    // selects a query with probability proportional to `exp(epsilon * error / 2)`.
+    fn select(&self, answers: &[f64], queries: &[Vec<(usize, f64)>], epsilon: f64, rng: &mut dyn RngCore) -> usize {
+
+        // work with logarithms of the weights, to avoid overflow for large errors.
+        let logs = queries.iter().zip(answers.iter()).map(|(query, truth)| {
+            epsilon * (truth - self.answer(&query[..])).abs() / 2.0
+        }).collect::<Vec<_>>();
+
+        let max = logs.iter().cloned().fold(::std::f64::NEG_INFINITY, f64::max);
+        let weights = logs.iter().map(|l| (l - max).exp()).collect::<Vec<_>>();
+        let total: f64 = weights.iter().sum();
+
+        let mut target = rng.gen::<f64>() * total;
+        for (index, weight) in weights.iter().enumerate() {
+            if target < *weight { return index; }
+            target -= weight;
+        }
+        weights.len() - 1
+    }
+}
+
+mod tests {
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use super::Histogram;
+
+    // a histogram of 100 records at 2 and 300 at 6, each of weight 10.
+    fn histogram() -> Histogram {
+        let weights = vec![(2, 1000), (6, 3000)].into_iter().collect();
+        Histogram::new(Rc::new(RefCell::new(weights)), 8, 10.0)
+    }
+
+    #[test]
+    fn test_mwem() {
+        use budget::Odometer;
+        let counts = vec![0.0, 0.0, 100.0, 0.0, 0.0, 0.0, 300.0, 0.0];
+        let mut queries = Vec::new();
+        for lower in 0 .. 8 {
+            for upper in lower + 1 .. 9 {
+                queries.push((lower .. upper).map(|x| (x, 1.0)).collect::<Vec<_>>());
+            }
+        }
+        let mut odometer = Odometer::new(100.0);
+        let mut mwem = super::Mwem::new(8, 400.0);
+        mwem.run(&histogram(), &queries[..], 20, 100.0, &mut odometer, &mut ::rand::thread_rng()).unwrap();
+        assert_eq!(odometer.ledger().len(), 20);
+        assert!(mwem.run(&histogram(), &queries[..], 1, 1.0, &mut odometer, &mut ::rand::thread_rng()).is_err());
+        let error = |d: &[f64]| d.iter().zip(counts.iter()).map(|(x, y)| (x - y).abs()).sum::<f64>();
+        assert!(error(mwem.distribution()) < error(super::Mwem::new(8, 400.0).distribution()) / 2.0);
+    }
+}
@@ -0,0 +1,123 @@
+//! Interactive query answering by private multiplicative weights (PMW).
+//!
+//! Queries are answered from a synthetic distribution maintained by multiplicative weights, and
+//! the sensitive data are only consulted to check whether the synthetic answer is far from the
+//! truth. The check is the sparse vector technique, whose cost is only incurred when a check
+//! fails: the query is then measured with Laplace noise, the measurement is returned, and the
+//! distribution is refit to all measurements so far. As the distribution improves, checks fail
+//! less often, and many queries can be answered at the cost of few measurements.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use rand::RngCore;
+
+use budget::{Exhausted, Odometer};
+use noise;
+use super::mwem::{Histogram, Mwem};
+
+// the number of passes over all measurements made after each new measurement.
+const FIT_ITERATIONS: usize = 10;
+
+/// An interactive query answerer over a histogram with domain `0 .. histogram.domain()`.
+///
+/// Queries are lists of domain elements and coefficients in `[-1, 1]`, as for `Mwem`.
+pub struct Pmw {
+    histogram: Histogram,
+    mwem: Mwem,
+    threshold: f64,
+    epsilon: f64,
+    noisy_threshold: Option<f64>,
+    odometer: Rc<RefCell<Odometer>>,
+    queries: Vec<Vec<(usize, f64)>>,
+    measurements: Vec<(usize, f64)>,
+}
+
+impl Pmw {
+
+    /// Creates an answerer for `histogram` that consults it when answers may be off by `threshold`.
+    ///
+    /// Each sparse vector instance and each measurement costs `epsilon`, charged to `odometer`.
+    /// The synthetic distribution has total count `total`, which should be public or noisy.
+    pub fn new(histogram: Histogram, total: f64, threshold: f64, epsilon: f64, odometer: &Rc<RefCell<Odometer>>) -> Self {
+        assert!(epsilon > 0.0);
+        let domain = histogram.domain();
+        Pmw {
+            histogram: histogram,
+            mwem: Mwem::new(domain, total),
+            threshold: threshold,
+            epsilon: epsilon,
+            noisy_threshold: None,
+            odometer: odometer.clone(),
+            queries: Vec::new(),
+            measurements: Vec::new(),
+        }
+    }
+
+    /// The synthetic distribution from which answers are served.
+    pub fn distribution(&self) -> &[f64] { self.mwem.distribution() }
+    /// The number of queries that required a measurement.
+    pub fn updates(&self) -> usize { self.measurements.len() }
+
+    /// Answers `query`, from the synthetic distribution if its answer appears accurate.
+    ///
+    /// Otherwise the query is measured, the distribution refit, and the noisy measurement
+    /// returned. An error is returned if the odometer cannot afford the sparse vector instance
+    /// or measurement that the query requires, in which case the query is not answered.
+    pub fn answer(&mut self, query: &[(usize, f64)], rng: &mut dyn RngCore) -> Result<f64, Exhausted> {
+
+        assert!(query.iter().all(|x| x.1.abs() <= 1.0));
+
+        // start a sparse vector instance, if one is not active.
+        if self.noisy_threshold.is_none() {
+            self.odometer.borrow_mut().charge("PmwThreshold", self.epsilon)?;
+            self.noisy_threshold = Some(self.threshold + noise::laplace_scaled(2.0 / self.epsilon, rng));
+        }
+
+        let synth = self.mwem.answer(query);
+        let truth = self.histogram.answer(query);
+        let noisy_error = (truth - synth).abs() + noise::laplace_scaled(4.0 / self.epsilon, rng);
+
+        if noisy_error < self.noisy_threshold.unwrap() {
+            Ok(synth)
+        }
+        else {
+            // the instance halts; measure the query and refit to all measurements.
+            self.noisy_threshold = None;
+            self.odometer.borrow_mut().charge("PmwMeasure", self.epsilon)?;
+            let measured = truth + noise::laplace_scaled(1.0 / self.epsilon, rng);
+            self.measurements.push((self.queries.len(), measured));
+            self.queries.push(query.to_vec());
+            self.mwem.fit(&self.queries[..], &self.measurements[..], FIT_ITERATIONS);
+            Ok(measured)
+        }
+    }
+}
+
+mod tests {
+
+    #[test]
+    fn test_pmw() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use budget::Odometer;
+        use super::Histogram;
+
+        // 100 records at 2 and 300 at 6, each of weight 10.
+        let weights = vec![(2, 1000), (6, 3000)].into_iter().collect();
+        let histogram = Histogram::new(Rc::new(RefCell::new(weights)), 8, 10.0);
+        let odometer = Rc::new(RefCell::new(Odometer::new(100.0)));
+        let mut pmw = super::Pmw::new(histogram, 400.0, 20.0, 1.0, &odometer);
+        let rng = &mut ::rand::thread_rng();
+        for _ in 0 .. 10 {
+            for lower in 0 .. 8 {
+                for upper in lower + 1 .. 9 {
+                    let query = (lower .. upper).map(|x| (x, 1.0)).collect::<Vec<_>>();
+                    pmw.answer(&query[..], rng).unwrap();
+                }
+            }
+        }
+        assert!(pmw.updates() < 50);
+        assert!(odometer.borrow().spent() <= 2.0 * pmw.updates() as f64 + 1.0);
+    }
+}
@@ -0,0 +1,216 @@
+//! A refinement loop that owns the stepping of the worker.
+//!
+//! Each round of refinement proposes a change to one synthetic record, sends the change to the
+//! synthetic input, advances the input, steps the worker until the measurements reflect the
+//! change, and reads the total error, reverting the change if the error increased. A
+//! `Synthesizer` performs these rounds, counts them, reports progress, and stops when the error
+//! reaches a target or stops improving. It also records the trajectory of the error, sampled
+//! periodically, so that convergence can be inspected after a run, and may adapt the magnitudes
+//! of its moves towards a target acceptance rate.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rand::RngCore;
+
+use timely::{Allocate, Data};
+use timely::dataflow::{InputHandle, ProbeHandle};
+use timely::dataflow::scopes::Root;
+use timely::progress::nested::product::Product;
+use timely::progress::timestamp::RootTimestamp;
+
+use super::Table;
+use super::tabular::{self, Move};
+
+/// The progress of a run of refinement.
+#[derive(Copy, Clone, Debug)]
+pub struct Progress {
+    /// The number of rounds performed.
+    pub rounds: usize,
+    /// The number of proposed changes that were retained.
+    pub accepted: usize,
+    /// The total error after the most recent round.
+    pub error: i64,
+    /// The time since the run started.
+    pub elapsed: Duration,
+}
+
+/// When to stop refinement, and how to report progress.
+pub struct Config {
+    rounds: usize,
+    patience: Option<usize>,
+    convergence: Option<(usize, f64)>,
+    target: i64,
+    sample: usize,
+    adaptation: Option<(f64, usize)>,
+    block: usize,
+    report: Option<(usize, Box<dyn FnMut(&Progress)>)>,
+}
+
+impl Config {
+    /// Stops after at most `rounds` rounds.
+    pub fn new(rounds: usize) -> Self {
+        Config { rounds: rounds, patience: None, convergence: None, target: 0, sample: 100, adaptation: None, block: 1, report: None }
+    }
+    /// Stops once `rounds` consecutive rounds have failed to reduce the error.
+    pub fn patience(mut self, rounds: usize) -> Self {
+        self.patience = Some(rounds);
+        self
+    }
+    /// Stops once the error has improved by less than the fraction `threshold` over `window` rounds.
+    ///
+    /// Unlike `patience`, this stops runs that continue to improve, but too slowly to be worth
+    /// continuing.
+    pub fn converge(mut self, window: usize, threshold: f64) -> Self {
+        assert!(window > 0);
+        self.convergence = Some((window, threshold));
+        self
+    }
+    /// Records the error in the trajectory after every `every` rounds, rather than every 100.
+    pub fn sample(mut self, every: usize) -> Self {
+        assert!(every > 0);
+        self.sample = every;
+        self
+    }
+    /// Adapts the magnitude of each move towards the acceptance rate `target`, after every
+    /// `every` proposals of the move. See `Move::adapt`.
+    pub fn adapt(mut self, target: f64, every: usize) -> Self {
+        assert!(target > 0.0 && target < 1.0);
+        assert!(every > 0);
+        self.adaptation = Some((target, every));
+        self
+    }
+    /// Proposes `block` changes in each round, evaluated together. See `tabular::refine_block`.
+    pub fn block(mut self, block: usize) -> Self {
+        assert!(block > 0);
+        self.block = block;
+        self
+    }
+    /// Stops once the total error is at most `error`.
+    pub fn target(mut self, error: i64) -> Self {
+        self.target = error;
+        self
+    }
+    /// Calls `report` after every `every` rounds, and once the run completes.
+    pub fn report<F: FnMut(&Progress)+'static>(mut self, every: usize, report: F) -> Self {
+        assert!(every > 0);
+        self.report = Some((every, Box::new(report)));
+        self
+    }
+}
+
+/// Refines a table of synthetic records, sent to one synthetic input.
+pub struct Synthesizer<D: Data> {
+    table: Table<D>,
+    input: InputHandle<usize, (D, i64)>,
+    total: Rc<RefCell<i64>>,
+    moves: Vec<Move<D>>,
+    trajectory: Vec<(usize, i64)>,
+}
+
+impl<D: Data> Synthesizer<D> {
+    /// Creates a synthesizer refining `table` with `moves`, whose records are sent to `input`.
+    ///
+    /// The measurements of dataflows reading `input` should accumulate their error into `total`.
+    pub fn new(table: Table<D>, input: InputHandle<usize, (D, i64)>, total: &Rc<RefCell<i64>>, moves: Vec<Move<D>>) -> Self {
+        Synthesizer {
+            table: table,
+            input: input,
+            total: total.clone(),
+            moves: moves,
+            trajectory: Vec::new(),
+        }
+    }
+    /// The current synthetic records.
+    pub fn table(&self) -> &Table<D> { &self.table }
+    /// The total error after sampled rounds of the most recent run, with the round numbers.
+    ///
+    /// The trajectory starts with the error before the first round, at round zero, and ends with
+    /// the error after the final round.
+    pub fn trajectory(&self) -> &[(usize, i64)] { &self.trajectory[..] }
+
+    /// Refines the table until `config` indicates it should stop, returning the final progress.
+    ///
+    /// The `probe` must be attached to the measurements reading the synthetic input, and the
+    /// worker is stepped until it passes the input's timestamp in each round.
+    pub fn run<A: Allocate>(&mut self, worker: &mut Root<A>, probe: &ProbeHandle<Product<RootTimestamp, usize>>, mut config: Config, rng: &mut dyn RngCore) -> Progress {
+
+        let start = Instant::now();
+        let input = &mut self.input;
+        let total = &self.total;
+        let mut evaluate = |table: &mut Table<D>| {
+            table.flush(input);
+            let next = input.time().inner + 1;
+            input.advance_to(next);
+            while probe.less_than(input.time()) { worker.step(); }
+            *total.borrow()
+        };
+
+        let mut progress = Progress { rounds: 0, accepted: 0, error: evaluate(&mut self.table), elapsed: Duration::from_secs(0) };
+        let mut unimproved = 0;
+
+        // the errors of the most recent rounds, to assess convergence.
+        let mut recent = VecDeque::new();
+        recent.push_back(progress.error);
+        let convergence = config.convergence;
+        let converged = |recent: &VecDeque<i64>| match convergence {
+            Some((window, threshold)) => {
+                recent.len() > window && ((recent[0] - recent[window]) as f64) < threshold * (recent[0].abs() as f64)
+            },
+            None => false,
+        };
+
+        self.trajectory.clear();
+        self.trajectory.push((0, progress.error));
+
+        while progress.rounds < config.rounds && progress.error > config.target && config.patience.map(|p| unimproved < p).unwrap_or(true) && !converged(&recent) {
+
+            let (accepted, error) = if config.block > 1 {
+                let block = ::std::cmp::min(config.block, self.table.len());
+                tabular::refine_block(&mut self.table, &self.moves[..], block, progress.error, rng, &mut evaluate)
+            }
+            else {
+                let (_choice, accepted, error) = tabular::refine(&mut self.table, &self.moves[..], progress.error, rng, &mut evaluate);
+                (if accepted { 1 } else { 0 }, error)
+            };
+
+            if let Some((target, every)) = config.adaptation {
+                for proposal in self.moves.iter() {
+                    if proposal.recent() >= every { proposal.adapt(target); }
+                }
+            }
+
+            unimproved = if error < progress.error { 0 } else { unimproved + 1 };
+            progress.rounds += 1;
+            progress.accepted += accepted;
+            progress.error = error;
+
+            if let Some((window, _)) = convergence {
+                recent.push_back(error);
+                if recent.len() > window + 1 { recent.pop_front(); }
+            }
+            if progress.rounds % config.sample == 0 {
+                self.trajectory.push((progress.rounds, error));
+            }
+
+            if let Some((every, ref mut report)) = config.report {
+                if progress.rounds % every == 0 {
+                    progress.elapsed = start.elapsed();
+                    report(&progress);
+                }
+            }
+        }
+
+        if self.trajectory.last().map(|x| x.0) != Some(progress.rounds) {
+            self.trajectory.push((progress.rounds, progress.error));
+        }
+
+        progress.elapsed = start.elapsed();
+        if let Some((_every, ref mut report)) = config.report {
+            report(&progress);
+        }
+        progress
+    }
+}
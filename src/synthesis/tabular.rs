@@ -0,0 +1,223 @@
+//! Record-swap synthesis of relational tables.
+//!
+//! Synthetic rows are produced by record constructors and then refined by moves, each of which
+//! proposes a modified copy of one record, for example by redrawing a single field. Moves across
+//! related tables preserve foreign keys, by only ever assigning a child record the key of some
+//! existing parent record.
+//!
+//! Moves may have a magnitude, for example how far a numeric field is perturbed, and track how
+//! often their proposals are accepted. Magnitudes can then be adapted towards a target acceptance
+//! rate, rather than tuned by hand for each dataset.
+
+use std::cell::Cell;
+
+use rand::{Rng, RngCore};
+
+use timely::Data;
+
+use super::Table;
+
+/// A named proposal that produces a modified copy of a record.
+pub struct Move<R> {
+    name: String,
+    proposal: Box<dyn Fn(&R, f64, &mut dyn RngCore)->R>,
+    magnitude: Cell<f64>,
+    // proposals and acceptances, in total and since the last adaptation.
+    attempts: Cell<(usize, usize)>,
+    accepted: Cell<(usize, usize)>,
+}
+
+impl<R> Move<R> {
+    /// Creates a move from a function producing a modified copy of a record.
+    pub fn new<F: Fn(&R, &mut dyn RngCore)->R+'static>(name: &str, proposal: F) -> Self {
+        Move::scaled(name, 1.0, move |record, _magnitude, rng| proposal(record, rng))
+    }
+    /// Creates a move from a function producing a modified copy of a record, given a magnitude.
+    ///
+    /// The magnitude starts at `initial`, and may be changed by `adapt`.
+    pub fn scaled<F: Fn(&R, f64, &mut dyn RngCore)->R+'static>(name: &str, initial: f64, proposal: F) -> Self {
+        Move {
+            name: name.to_owned(),
+            proposal: Box::new(proposal),
+            magnitude: Cell::new(initial),
+            attempts: Cell::new((0, 0)),
+            accepted: Cell::new((0, 0)),
+        }
+    }
+    /// The name of the move, for reporting.
+    pub fn name(&self) -> &str { &self.name }
+    /// The current magnitude of the move.
+    pub fn magnitude(&self) -> f64 { self.magnitude.get() }
+    /// Applies the move to `record`.
+    pub fn propose(&self, record: &R, rng: &mut dyn RngCore) -> R {
+        (self.proposal)(record, self.magnitude.get(), rng)
+    }
+    /// Records whether a proposal of the move was accepted.
+    pub fn record(&self, accepted: bool) {
+        let (total, recent) = self.attempts.get();
+        self.attempts.set((total + 1, recent + 1));
+        if accepted {
+            let (total, recent) = self.accepted.get();
+            self.accepted.set((total + 1, recent + 1));
+        }
+    }
+    /// The number of proposals recorded, and the fraction of them accepted.
+    pub fn acceptance(&self) -> (usize, f64) {
+        let attempts = self.attempts.get().0;
+        (attempts, (self.accepted.get().0 as f64) / (attempts.max(1) as f64))
+    }
+    /// The number of proposals recorded since the last adaptation.
+    pub fn recent(&self) -> usize { self.attempts.get().1 }
+    /// Adapts the magnitude towards the acceptance rate `target`.
+    ///
+    /// The magnitude is multiplied by `exp(rate - target)`, where `rate` is the fraction of the
+    /// proposals since the last adaptation that were accepted: frequently accepted moves become
+    /// bolder, and rarely accepted moves more cautious.
+    pub fn adapt(&self, target: f64) {
+        let (total, recent) = self.attempts.get();
+        if recent > 0 {
+            let rate = (self.accepted.get().1 as f64) / (recent as f64);
+            self.magnitude.set(self.magnitude.get() * (rate - target).exp());
+            self.attempts.set((total, 0));
+            self.accepted.set((self.accepted.get().0, 0));
+        }
+    }
+}
+
+/// A move that redraws one field of a record with `draw`, leaving other fields unchanged.
+pub fn field<R, V, G, S>(name: &str, draw: G, set: S) -> Move<R>
+where
+    R: Clone+'static,
+    G: Fn(&mut dyn RngCore)->V+'static,
+    S: Fn(&mut R, V)+'static,
+{
+    Move::new(name, move |record: &R, rng: &mut dyn RngCore| {
+        let mut result = record.clone();
+        set(&mut result, draw(rng));
+        result
+    })
+}
+
+/// A move that reassigns a child record to a uniformly chosen parent in `parents`.
+///
+/// The parent keys are captured when the move is constructed, and so the move should be rebuilt
+/// if the parent table changes its keys. Children are only ever assigned existing keys, and so
+/// the foreign key relationship is preserved.
+pub fn foreign_key<P, C, K, F, S>(name: &str, parents: &Table<P>, key: F, set: S) -> Move<C>
+where
+    P: Data,
+    C: Clone+'static,
+    K: Clone+'static,
+    F: Fn(&P)->K,
+    S: Fn(&mut C, K)+'static,
+{
+    let keys = parents.records().iter().map(|p| key(p)).collect::<Vec<_>>();
+    assert!(!keys.is_empty());
+    Move::new(name, move |record: &C, rng: &mut dyn RngCore| {
+        let mut result = record.clone();
+        set(&mut result, keys[rng.gen_range(0, keys.len())].clone());
+        result
+    })
+}
+
+/// Performs one round of refinement of `table` using a randomly chosen move.
+///
+/// A uniformly chosen record is replaced by the result of a uniformly chosen move, after which
+/// `evaluate` is called. It should flush the table to its synthetic input, complete the round of
+/// computation, and return the new total error. If the total error exceeds `current`, the change
+/// is reverted, and `evaluate` is called again to confirm the reversion.
+///
+/// The outcome is recorded with the move. Returns the index of the move applied, whether it was
+/// accepted, and the resulting total error.
+pub fn refine<R, E>(table: &mut Table<R>, moves: &[Move<R>], current: i64, rng: &mut dyn RngCore, mut evaluate: E) -> (usize, bool, i64)
+where
+    R: Data,
+    E: FnMut(&mut Table<R>)->i64,
+{
+    assert!(!table.is_empty());
+    assert!(!moves.is_empty());
+
+    let index = rng.gen_range(0, table.len());
+    let choice = rng.gen_range(0, moves.len());
+    let proposed = moves[choice].propose(&table.records()[index], rng);
+    let prior = table.replace(index, proposed);
+
+    let error = evaluate(table);
+    if error <= current {
+        moves[choice].record(true);
+        (choice, true, error)
+    }
+    else {
+        table.replace(index, prior);
+        moves[choice].record(false);
+        (choice, false, evaluate(table))
+    }
+}
+
+/// Performs one round of refinement of `table`, applying `block` moves at once.
+///
+/// Each of `block` distinct uniformly chosen records is replaced by the result of a uniformly
+/// chosen move, and `evaluate` is called once for all of them. If the total error does not exceed
+/// `current`, all changes are retained. Otherwise they are reverted, and each half of the block is
+/// attempted in the same way, so that additional evaluations are only spent on blocks containing
+/// harmful changes. This is most effective when the changes are independent, affecting different
+/// measured elements. Reverted changes are confirmed by a final call to `evaluate`, as in `refine`.
+///
+/// The outcomes are recorded with the moves. Returns the number of changes accepted, and the
+/// resulting total error.
+pub fn refine_block<R, E>(table: &mut Table<R>, moves: &[Move<R>], block: usize, current: i64, rng: &mut dyn RngCore, mut evaluate: E) -> (usize, i64)
+where
+    R: Data,
+    E: FnMut(&mut Table<R>)->i64,
+{
+    assert!(!moves.is_empty());
+    assert!(block > 0 && block <= table.len());
+
+    let mut indices = Vec::with_capacity(block);
+    while indices.len() < block {
+        let index = rng.gen_range(0, table.len());
+        if !indices.contains(&index) { indices.push(index); }
+    }
+
+    let changes = indices.into_iter().map(|index| {
+        let choice = rng.gen_range(0, moves.len());
+        let proposed = moves[choice].propose(&table.records()[index], rng);
+        (index, choice, proposed)
+    }).collect::<Vec<_>>();
+
+    let (accepted, reverted, error) = attempt(table, moves, &changes[..], current, &mut evaluate);
+    if reverted { (accepted, evaluate(table)) } else { (accepted, error) }
+}
+
+// applies `changes`, retaining them if the error does not exceed `current`, and otherwise
+// attempting each half in turn. returns the number of changes accepted, whether changes were
+// last reverted without evaluation, and the resulting error, which after a reversion is that
+// already known for the restored records.
+fn attempt<R, E>(table: &mut Table<R>, moves: &[Move<R>], changes: &[(usize, usize, R)], current: i64, evaluate: &mut E) -> (usize, bool, i64)
+where
+    R: Data,
+    E: FnMut(&mut Table<R>)->i64,
+{
+    let priors = changes.iter().map(|&(index, _, ref proposed)| table.replace(index, proposed.clone())).collect::<Vec<_>>();
+
+    let error = evaluate(table);
+    if error <= current {
+        for change in changes.iter() { moves[change.1].record(true); }
+        return (changes.len(), false, error);
+    }
+
+    for (change, prior) in changes.iter().zip(priors.into_iter()) {
+        table.replace(change.0, prior);
+    }
+
+    if changes.len() == 1 {
+        moves[changes[0].1].record(false);
+        (0, true, current)
+    }
+    else {
+        let middle = changes.len() / 2;
+        let (accepted1, _, error1) = attempt(table, moves, &changes[.. middle], current, evaluate);
+        let (accepted2, reverted, error2) = attempt(table, moves, &changes[middle ..], error1, evaluate);
+        (accepted1 + accepted2, reverted, error2)
+    }
+}
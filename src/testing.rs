@@ -0,0 +1,306 @@
+//! Executable checks of the stability of pipelines.
+//!
+//! The privacy guarantee of a measurement rests on the claim that a change to the weight of one
+//! input record changes the weights of the measured dataset by at most a bounded multiple of that
+//! change. The harness here runs a pipeline on a base dataset, and then applies and retracts a
+//! series of single-record changes, checking that the total change in output weight and in the
+//! measurement error stay within the claimed bound, and that each retraction exactly restores the
+//! prior output.
+//!
+//! A second harness runs a measurement across a cluster of spawned processes, checking that each
+//! worker observes the same noisy counts and total error, whichever worker owns each record.
+
+use std::env;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+
+use timely::{Configuration, Data, ExchangeData};
+use timely::communication::allocator::Thread;
+use timely::dataflow::{InputHandle, ProbeHandle};
+use timely::dataflow::operators::{Inspect, Probe};
+use timely::dataflow::scopes::{Child, Root};
+
+use {Dataset, DatasetHandle, consolidate};
+use noise::{self, NoiseSource};
+
+// a noise scale small enough that noise truncates to zero, and errors are exact multiples of its inverse.
+const SCALE: f64 = 1.0 / ((1u64 << 40) as f64);
+
+/// The changes to apply to a base dataset, one at a time.
+///
+/// Each record of the base dataset is removed, and has its weight halved, and each of the
+/// `additions` is added. Each change is retracted before the next is applied.
+pub fn neighbors<D: Clone>(base: &[(D, i64)], additions: &[(D, i64)]) -> Vec<(D, i64)> {
+    let mut changes = Vec::new();
+    for &(ref datum, weight) in base.iter() {
+        changes.push((datum.clone(), -weight));
+        changes.push((datum.clone(), -weight / 2));
+    }
+    changes.extend(additions.iter().cloned());
+    changes
+}
+
+/// Checks that `pipeline` is `bound`-stable on the neighbors of `base`.
+///
+/// For each change produced by `neighbors(base, additions)`, the sum of absolute changes in output
+/// weights, and the absolute change in the total (absolute) measurement error, must not exceed
+/// `bound` times the absolute weight of the change, plus `tolerance` to account for rounding. The
+/// measurement is made with negligible noise, as the absolute error of an element entering or
+/// leaving the measurement otherwise includes its noise. Retracting each change must restore the
+/// output exactly.
+///
+/// Returns a description of the first violation found, if any.
+pub fn check_stability<D, R, F>(base: &[(D, i64)], additions: &[(D, i64)], bound: f64, tolerance: i64, mut pipeline: F) -> Result<(), String>
+where
+    D: Data+Debug,
+    R: ExchangeData+Ord+Hash+Debug,
+    F: for<'a> FnMut(Dataset<Child<'a, Root<Thread>, usize>, D>)->Dataset<Child<'a, Root<Thread>, usize>, R>,
+{
+    let mut worker = Root::new(Thread);
+    let mut input = DatasetHandle::new();
+    let mut probe = ProbeHandle::new();
+    let total = Rc::new(RefCell::new(0i64));
+    let output = Rc::new(RefCell::new(Vec::new()));
+
+    let _measurement = worker.dataflow(|scope| {
+        let result = pipeline(input.enter(scope));
+        let output = output.clone();
+        result.truth.expose().inspect(move |x| output.borrow_mut().push(x.clone())).probe_with(&mut probe);
+        result.measure_scaled(&mut probe, &total, SCALE)
+    });
+
+    let mut round = 0;
+    let mut step = |input: &mut DatasetHandle<usize, D>, worker: &mut Root<Thread>, updates: &[(D, i64)]| {
+        for update in updates.iter() { input.truth.send(update.clone()); }
+        round += 1;
+        input.truth.advance_to(round);
+        input.synth.advance_to(round);
+        while probe.less_than(input.truth.time()) { worker.step(); }
+        let mut changes = output.borrow_mut().drain(..).collect::<Vec<_>>();
+        consolidate(&mut changes);
+        changes
+    };
+
+    step(&mut input, &mut worker, base);
+
+    for change in neighbors(base, additions) {
+
+        let limit = bound * (change.1.abs() as f64) + (tolerance as f64);
+        let error = *total.borrow();
+
+        let forward = step(&mut input, &mut worker, &[change.clone()]);
+        let norm: i64 = forward.iter().map(|x| x.1.abs()).sum();
+        if norm as f64 > limit {
+            return Err(format!("change {:?} altered output weight by {:?}, exceeding {:?}: {:?}", change, norm, limit, forward));
+        }
+        let error_change = (((*total.borrow() - error).abs() as f64) * SCALE) as i64;
+        if error_change as f64 > limit {
+            return Err(format!("change {:?} altered measurement error by {:?}, exceeding {:?}", change, error_change, limit));
+        }
+
+        let mut backward = step(&mut input, &mut worker, &[(change.0.clone(), -change.1)]);
+        backward.extend(forward.into_iter());
+        consolidate(&mut backward);
+        if !backward.is_empty() {
+            return Err(format!("retraction of {:?} did not restore output: {:?}", change, backward));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a measurement of `records` across a cluster of processes is observed alike by all.
+///
+/// The check spawns `processes` copies of the running executable, each with `threads` workers,
+/// connected as a timely cluster over local TCP ports. Each copy must run the same check: when
+/// called from a test, the copies run only the calling test, identified by the name of its thread.
+/// Each worker sends a share of `records`, and queries every record through `measure_cluster`.
+/// Every answer must be the true count plus the noise of a measurement seeded by `seed`, and every
+/// worker must report the total error of the whole measurement, with empty synthetic data.
+///
+/// Returns a description of the first discrepancy found, if any.
+pub fn check_cluster<D>(records: Vec<(D, i64)>, processes: usize, threads: usize, seed: [u8; 32]) -> Result<(), String>
+where
+    D: ExchangeData+Ord+Hash+Debug,
+{
+    match (env::var(PROCESS), env::var(ADDRESSES)) {
+        (Ok(process), Ok(addresses)) => {
+            let process = process.parse().map_err(|_| format!("invalid process index {:?}", process))?;
+            let addresses = addresses.split(',').map(|x| x.to_owned()).collect();
+            check_process(records, Configuration::Cluster(threads, process, addresses, false), seed)
+        },
+        _ => spawn_cluster(processes),
+    }
+}
+
+// the environment variables naming a spawned process's index and the addresses of all processes.
+const PROCESS: &str = "WPINQ_CLUSTER_PROCESS";
+const ADDRESSES: &str = "WPINQ_CLUSTER_ADDRESSES";
+
+// runs `processes` copies of this executable as a cluster, and reports any that fail.
+fn spawn_cluster(processes: usize) -> Result<(), String> {
+    // ports are found by binding to port zero, and released for the cluster to bind.
+    let mut addresses = Vec::new();
+    for _ in 0 .. processes {
+        let listener = TcpListener::bind("127.0.0.1:0").map_err(|x| x.to_string())?;
+        addresses.push(listener.local_addr().map_err(|x| x.to_string())?);
+    }
+    let addresses = addresses.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",");
+
+    // within a test, only the calling test is run; otherwise the executable is run as it was.
+    let arguments = match thread::current().name() {
+        Some(name) if name != "main" => vec![name.to_owned(), "--exact".to_owned(), "--test-threads=1".to_owned()],
+        _ => env::args().skip(1).collect(),
+    };
+    let executable = env::current_exe().map_err(|x| x.to_string())?;
+    let children = (0 .. processes).map(|process| {
+        Command::new(&executable)
+            .args(&arguments)
+            .env(PROCESS, process.to_string())
+            .env(ADDRESSES, addresses.clone())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|x| x.to_string())
+    }).collect::<Result<Vec<_>, _>>()?;
+
+    let mut result = Ok(());
+    for (process, child) in children.into_iter().enumerate() {
+        let output = child.wait_with_output().map_err(|x| x.to_string())?;
+        if !output.status.success() && result.is_ok() {
+            result = Err(format!("process {} failed ({}): {}{}", process, output.status, String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)));
+        }
+    }
+    result
+}
+
+// runs this process's share of the cluster check.
+fn check_process<D>(records: Vec<(D, i64)>, configuration: Configuration, seed: [u8; 32]) -> Result<(), String>
+where
+    D: ExchangeData+Ord+Hash+Debug,
+{
+    let mut counts = records.clone();
+    consolidate(&mut counts);
+    let source = NoiseSource::from_seed(seed).derive(0);
+    let expected: i64 = counts.iter().map(|&(ref datum, count)| {
+        (-count - source.laplace(datum)).abs()
+    }).sum();
+
+    let records = Arc::new(records);
+    let results = ::timely::execute(configuration, move |worker| {
+
+        noise::seed(seed);
+        let index = worker.index();
+        let peers = worker.peers();
+        let mut input = DatasetHandle::new();
+        let mut queries = InputHandle::new();
+        let mut probe = ProbeHandle::new();
+        let total = Rc::new(RefCell::new(0i64));
+
+        let measurement = worker.dataflow(|scope| {
+            let queries = queries.to_stream(scope);
+            input.enter(scope).measure_cluster(&queries, &mut probe, &total)
+        });
+
+        for (position, record) in records.iter().enumerate() {
+            if position % peers == index { input.truth.send(record.clone()); }
+            queries.send(record.0.clone());
+        }
+        input.close();
+        queries.close();
+        while worker.step() { }
+
+        let answers = records.iter().map(|x| (x.0.clone(), measurement.answer(&x.0))).collect::<Vec<_>>();
+        let total = *total.borrow();
+        (index, answers, total)
+    })?.join();
+
+    for result in results.into_iter() {
+        let (index, answers, total) = result?;
+        for (datum, answer) in answers.into_iter() {
+            let count = counts.iter().find(|x| x.0 == datum).map(|x| x.1).unwrap_or(0);
+            if answer != Some(count + source.laplace(&datum)) {
+                return Err(format!("worker {} observed {:?} for {:?}, with true count {:?}", index, answer, datum, count));
+            }
+        }
+        if total != expected {
+            return Err(format!("worker {} reported total error {:?}, expected {:?}", index, total, expected));
+        }
+    }
+
+    Ok(())
+}
+
+mod tests {
+
+    #[test]
+    fn test_flat_map() {
+        let base = vec![(1u64, 1000), (2, 1000), (3, 2000)];
+        let result = super::check_stability(&base[..], &[(4, 1000)], 1.0, 4, |data| data.flat_map(|x| vec![x, x + 1, x * 2]));
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_fused() {
+        let base = vec![(1u64, 1000), (2, 1000), (3, 2000), (6, 500)];
+        let result = super::check_stability(&base[..], &[(4, 1000), (5, 1000)], 1.0, 0, |data| {
+            data.fuse()
+                .map(|x| x * 3)
+                .filter(|x| x % 2 == 0)
+                .filter_map(|x| if x < 15 { Some((x, x / 2)) } else { None })
+                .map_ref(|&(x, y)| x + y)
+                .dataset()
+        });
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_shave() {
+        let base = vec![(1u64, 1000), (2, 2500), (1, 700)];
+        let result = super::check_stability(&base[..], &[(3, 400)], 1.0, 0, |data| data.shave(300));
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_join() {
+        let base = vec![((0u64, 1u64), 1000), ((0, 2), 1000), ((1, 2), 1000), ((2, 0), 1000)];
+        // the join reads its input twice, and so is 2-stable in it.
+        let result = super::check_stability(&base[..], &[((1, 0), 1000)], 2.0, 16, |data| {
+            let other = data.clone().map(|(src, dst)| (dst, src));
+            data.join(other)
+        });
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_join_by() {
+        let base = vec![((0u64, 1u64), 1000), ((0, 2), 1000), ((1, 2), 1000), ((2, 0), 1000)];
+        // paths of length two, from edges joined on the destination of the first.
+        let result = super::check_stability(&base[..], &[((1, 0), 1000)], 2.0, 16, |data| {
+            data.clone().join_by(data, |edge| edge.1, |edge| edge.0)
+        });
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_join_sorted() {
+        let base = vec![((0u64, 1u64), 1000), ((0, 2), 1000), ((1, 2), 1000), ((2, 0), 1000)];
+        let result = super::check_stability(&base[..], &[((1, 0), 1000), ((0, 3), 500)], 2.0, 16, |data| {
+            let other = data.clone().map(|(src, dst)| (dst, src));
+            data.join_sorted(other, |key| *key)
+        });
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_cluster() {
+        let records = vec![(0u64, 1000), (1, 500), (0, 300), (2, -200), (3, 700), (1, -500)];
+        assert_eq!(super::check_cluster(records, 2, 2, [7u8; 32]), Ok(()));
+    }
+}
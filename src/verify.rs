@@ -0,0 +1,396 @@
+//! Exact-arithmetic reference semantics for verification.
+//!
+//! The dataflow operators track weights as `i64` and round whenever they divide. This module
+//! provides an in-memory `Collection` whose weights are exact rationals, with methods mirroring
+//! those of `Dataset`, for use in tests that check operator stability and that retractions cancel
+//! exactly. Results convert back to `i64` weights with `Collection::weights`.
+//!
+//! The model is checked against the operators themselves by `check_dataflow`, which runs a
+//! pipeline as a dataflow with input weights scaled so that its divisions are exact, and returns
+//! the exact difference between its output and the model's.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::{Add, Sub, Mul, Div, Neg};
+
+use timely::{Data, ExchangeData};
+use timely::communication::allocator::Thread;
+use timely::dataflow::ProbeHandle;
+use timely::dataflow::operators::{Inspect, Probe};
+use timely::dataflow::scopes::{Child, Root};
+
+use {Dataset, DatasetHandle, Rounding};
+
+/// An exact rational number, kept in lowest terms with a positive denominator.
+///
+/// Arithmetic panics on overflow rather than losing precision.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Rational {
+    /// Constructs `numerator / denominator` in lowest terms.
+    pub fn new(numerator: i128, denominator: i128) -> Self {
+        assert!(denominator != 0);
+        let divisor = gcd(numerator.abs(), denominator.abs());
+        let sign = if denominator < 0 { -1 } else { 1 };
+        Rational {
+            numerator: sign * numerator / divisor,
+            denominator: sign * denominator / divisor,
+        }
+    }
+    /// The rational zero.
+    pub fn zero() -> Self { Rational::from(0) }
+    /// The numerator, in lowest terms.
+    pub fn numerator(&self) -> i128 { self.numerator }
+    /// The denominator, in lowest terms and positive.
+    pub fn denominator(&self) -> i128 { self.denominator }
+    /// Indicates whether the value is zero.
+    pub fn is_zero(&self) -> bool { self.numerator == 0 }
+    /// The absolute value.
+    pub fn abs(&self) -> Self { Rational { numerator: self.numerator.abs(), denominator: self.denominator } }
+    /// Converts to an `i64` using `rounding`, with `salt` identifying the record for stochastic rounding.
+    pub fn round(&self, rounding: Rounding, salt: u64) -> i64 {
+        rounding.divide(salt, self.numerator, self.denominator)
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(value: i64) -> Self { Rational { numerator: value as i128, denominator: 1 } }
+}
+
+impl Add for Rational {
+    type Output = Rational;
+    fn add(self, other: Rational) -> Rational {
+        let numerator = checked(self.numerator.checked_mul(other.denominator))
+            .checked_add(checked(other.numerator.checked_mul(self.denominator)));
+        Rational::new(checked(numerator), checked(self.denominator.checked_mul(other.denominator)))
+    }
+}
+
+impl Sub for Rational {
+    type Output = Rational;
+    fn sub(self, other: Rational) -> Rational { self + (-other) }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+    fn mul(self, other: Rational) -> Rational {
+        Rational::new(
+            checked(self.numerator.checked_mul(other.numerator)),
+            checked(self.denominator.checked_mul(other.denominator)))
+    }
+}
+
+impl Div for Rational {
+    type Output = Rational;
+    fn div(self, other: Rational) -> Rational {
+        assert!(!other.is_zero());
+        Rational::new(
+            checked(self.numerator.checked_mul(other.denominator)),
+            checked(self.denominator.checked_mul(other.numerator)))
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational { Rational { numerator: -self.numerator, denominator: self.denominator } }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        let this = checked(self.numerator.checked_mul(other.denominator));
+        let that = checked(other.numerator.checked_mul(self.denominator));
+        this.cmp(&that)
+    }
+}
+
+fn checked(value: Option<i128>) -> i128 {
+    value.expect("overflow in exact rational arithmetic")
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    if a == 0 { 1 } else { a }
+}
+
+/// A collection of elements of type `D` with exact rational weights.
+///
+/// Elements whose weight is zero are not retained, and so two collections are equal exactly when
+/// they assign equal weights to all elements.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Collection<D: Hash+Eq> {
+    weights: HashMap<D, Rational>,
+}
+
+impl<D: Hash+Eq+Clone> Collection<D> {
+    /// Creates an empty collection.
+    pub fn new() -> Self {
+        Collection { weights: HashMap::new() }
+    }
+    /// Creates a collection from integer weighted elements, accumulating repeated elements.
+    pub fn from_weights<I: IntoIterator<Item=(D, i64)>>(iter: I) -> Self {
+        let mut result = Collection::new();
+        for (datum, weight) in iter {
+            result.update(datum, Rational::from(weight));
+        }
+        result
+    }
+    /// Adds `weight` to the weight of `datum`.
+    pub fn update(&mut self, datum: D, weight: Rational) {
+        let total = *self.weights.get(&datum).unwrap_or(&Rational::zero()) + weight;
+        if total.is_zero() { self.weights.remove(&datum); }
+        else { self.weights.insert(datum, total); }
+    }
+    /// The weight of `datum`.
+    pub fn weight(&self, datum: &D) -> Rational {
+        *self.weights.get(datum).unwrap_or(&Rational::zero())
+    }
+    /// The number of elements with non-zero weight.
+    pub fn len(&self) -> usize { self.weights.len() }
+    /// Indicates whether no element has non-zero weight.
+    pub fn is_empty(&self) -> bool { self.weights.is_empty() }
+    /// The sum of the absolute values of all weights.
+    pub fn norm(&self) -> Rational {
+        self.weights.values().fold(Rational::zero(), |sum, w| sum + w.abs())
+    }
+    /// Converts the weights to `i64`, rounding as indicated by `rounding`.
+    ///
+    /// Elements whose weights round to zero are omitted. The results are sorted, for comparison.
+    pub fn weights(&self, rounding: Rounding) -> Vec<(D, i64)> where D: Ord {
+        let mut result = self.weights
+            .iter()
+            .map(|(d, w)| (d.clone(), w.round(rounding, ::fnv_hash(d))))
+            .filter(|x| x.1 != 0)
+            .collect::<Vec<_>>();
+        result.sort_by(|x, y| x.0.cmp(&y.0));
+        result
+    }
+
+    /// Transforms each element using `function`.
+    pub fn map<R: Hash+Eq+Clone, F: Fn(D)->R>(&self, function: F) -> Collection<R> {
+        let mut result = Collection::new();
+        for (datum, weight) in self.weights.iter() {
+            result.update(function(datum.clone()), *weight);
+        }
+        result
+    }
+    /// Transforms references to each element using `function`.
+    pub fn map_ref<R: Hash+Eq+Clone, F: Fn(&D)->R>(&self, function: F) -> Collection<R> {
+        let mut result = Collection::new();
+        for (datum, weight) in self.weights.iter() {
+            result.update(function(datum), *weight);
+        }
+        result
+    }
+    /// Restricts the collection to elements satisfying `predicate`.
+    pub fn filter<P: Fn(&D)->bool>(&self, predicate: P) -> Self {
+        let mut result = Collection::new();
+        for (datum, weight) in self.weights.iter().filter(|x| predicate(x.0)) {
+            result.update(datum.clone(), *weight);
+        }
+        result
+    }
+    /// Transforms each element using `function`, retaining only the elements it returns results for.
+    pub fn filter_map<R: Hash+Eq+Clone, F: Fn(D)->Option<R>>(&self, function: F) -> Collection<R> {
+        let mut result = Collection::new();
+        for (datum, weight) in self.weights.iter() {
+            if let Some(output) = function(datum.clone()) {
+                result.update(output, *weight);
+            }
+        }
+        result
+    }
+    /// Merges two collections, accumulating their weights.
+    pub fn concat(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for (datum, weight) in other.weights.iter() {
+            result.update(datum.clone(), *weight);
+        }
+        result
+    }
+    /// Merges two collections, subtracting their weights.
+    pub fn except(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for (datum, weight) in other.weights.iter() {
+            result.update(datum.clone(), -*weight);
+        }
+        result
+    }
+    /// Maps each element to a list of elements, dividing its weight evenly among them.
+    pub fn flat_map<I: IntoIterator, F: Fn(D)->I>(&self, function: F) -> Collection<I::Item>
+    where
+        I::Item: Hash+Eq+Clone,
+    {
+        let mut result = Collection::new();
+        for (datum, weight) in self.weights.iter() {
+            let list = function(datum.clone()).into_iter().collect::<Vec<_>>();
+            let length = Rational::from(list.len() as i64);
+            for item in list {
+                result.update(item, *weight / length);
+            }
+        }
+        result
+    }
+    /// Transforms each element into a sequence of indexed elements, each of weight at most `width`.
+    ///
+    /// Only positive weights are supported, as for `Dataset::shave`.
+    pub fn shave(&self, width: Rational) -> Collection<(D, usize)> {
+        assert!(width > Rational::zero());
+        let mut result = Collection::new();
+        for (datum, weight) in self.weights.iter() {
+            assert!(*weight > Rational::zero());
+            let mut remaining = *weight;
+            let mut index = 0;
+            while remaining > Rational::zero() {
+                let part = if remaining < width { remaining } else { width };
+                result.update((datum.clone(), index), part);
+                remaining = remaining - part;
+                index += 1;
+            }
+        }
+        result
+    }
+    /// Returns collections of the minimum and maximum weights of each element, respectively.
+    pub fn min_max(&self, other: &Self) -> (Self, Self) {
+        let mut min = Collection::new();
+        let mut max = Collection::new();
+        for datum in self.weights.keys().chain(other.weights.keys()) {
+            if min.weights.contains_key(datum) || max.weights.contains_key(datum) { continue; }
+            let (w1, w2) = (self.weight(datum), other.weight(datum));
+            min.update(datum.clone(), ::std::cmp::min(w1, w2));
+            max.update(datum.clone(), ::std::cmp::max(w1, w2));
+        }
+        (min, max)
+    }
+}
+
+impl<K: Hash+Eq+Clone, V1: Hash+Eq+Clone> Collection<(K, V1)> {
+    /// Joins two keyed collections, scaling the product of weights by the total weight of each key.
+    pub fn join<V2: Hash+Eq+Clone>(&self, other: &Collection<(K, V2)>) -> Collection<(K, (V1, V2))> {
+        let mut groups1 = HashMap::new();
+        for (&(ref key, ref val), weight) in self.weights.iter() {
+            groups1.entry(key.clone()).or_insert(Vec::new()).push((val.clone(), *weight));
+        }
+        let mut groups2 = HashMap::new();
+        for (&(ref key, ref val), weight) in other.weights.iter() {
+            groups2.entry(key.clone()).or_insert(Vec::new()).push((val.clone(), *weight));
+        }
+        let mut result = Collection::new();
+        for (key, list1) in groups1.iter() {
+            if let Some(list2) = groups2.get(key) {
+                let total1 = list1.iter().fold(Rational::zero(), |sum, x| sum + x.1.abs());
+                let total2 = list2.iter().fold(Rational::zero(), |sum, x| sum + x.1.abs());
+                let total = total1 + total2;
+                for &(ref val1, weight1) in list1.iter() {
+                    for &(ref val2, weight2) in list2.iter() {
+                        result.update((key.clone(), (val1.clone(), val2.clone())), weight1 * weight2 / total);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Runs `pipeline` as a dataflow on `base`, and returns the exact difference between `model`
+/// applied to `base` and the dataflow's output.
+///
+/// The input weights of the dataflow are multiplied by `scale`, and its output weights divided by
+/// `scale`. Operators round when they divide, but a `scale` divisible by the denominators of the
+/// exact weights makes each division exact, and the result is then empty exactly when the dataflow
+/// computes the weights the model defines. Parameters of the pipeline given in units of weight,
+/// such as the width of `shave`, should be multiplied by `scale` as well.
+pub fn check_dataflow<D, R, F, M>(base: &[(D, i64)], scale: i64, mut pipeline: F, model: M) -> Collection<R>
+where
+    D: Data+Hash+Eq,
+    R: ExchangeData+Hash+Eq,
+    F: for<'a> FnMut(Dataset<Child<'a, Root<Thread>, usize>, D>)->Dataset<Child<'a, Root<Thread>, usize>, R>,
+    M: Fn(&Collection<D>)->Collection<R>,
+{
+    assert!(scale > 0);
+
+    let mut worker = Root::new(Thread);
+    let mut input = DatasetHandle::new();
+    let mut probe = ProbeHandle::new();
+    let output = Rc::new(RefCell::new(Vec::new()));
+
+    worker.dataflow(|scope| {
+        let output = output.clone();
+        let result = pipeline(input.enter(scope));
+        result.truth.expose().inspect(move |x| output.borrow_mut().push(x.clone())).probe_with(&mut probe);
+    });
+
+    for &(ref datum, weight) in base.iter() {
+        input.truth.send((datum.clone(), weight * scale));
+    }
+    input.close();
+    while worker.step() { }
+
+    let mut difference = model(&Collection::from_weights(base.iter().cloned()));
+    for (datum, weight) in output.borrow_mut().drain(..) {
+        difference.update(datum, Rational::new(-(weight as i128), scale as i128));
+    }
+    difference
+}
+
+mod tests {
+
+    #[test]
+    fn test_rational() {
+        use super::Rational;
+        assert_eq!(Rational::new(2, -4), Rational::new(-1, 2));
+        assert_eq!(Rational::new(1, 3) + Rational::new(1, 6), Rational::new(1, 2));
+        assert!(Rational::new(1, 3) < Rational::new(1, 2));
+    }
+
+    #[test]
+    fn test_join_stability() {
+        use super::{Collection, Rational};
+        let edges = Collection::from_weights(vec![((0, 1), 3), ((0, 2), 3), ((1, 2), 3)]);
+        let nodes = Collection::from_weights(vec![((0, ()), 3), ((1, ()), 3)]);
+        let before = edges.join(&nodes);
+
+        // adding weight to one input changes the output by at most that weight.
+        let change = Collection::from_weights(vec![((0, 3), 5)]);
+        let after = edges.concat(&change).join(&nodes);
+        assert!(after.except(&before).norm() <= Rational::from(5));
+
+        // retracting the change restores the output exactly.
+        let restored = edges.concat(&change).except(&change).join(&nodes);
+        assert_eq!(restored, before);
+    }
+
+    #[test]
+    fn test_shave() {
+        use super::{Collection, Rational};
+        use Rounding;
+        let data = Collection::from_weights(vec![("a", 7)]);
+        let shaved = data.shave(Rational::from(3));
+        assert_eq!(shaved.weights(Rounding::Truncate), vec![(("a", 0), 3), (("a", 1), 3), (("a", 2), 1)]);
+    }
+
+    #[test]
+    fn test_check_dataflow() {
+        use super::check_dataflow;
+        let edges = vec![((0u64, 1u64), 3), ((0, 2), 3), ((1, 2), 3)];
+        // the self-join has weights `3/4` and `3/2`, which are exact for weights scaled by four.
+        let exact = check_dataflow(&edges[..], 1024, |data| data.clone().join(data), |data| data.join(data));
+        assert!(exact.is_empty());
+        let rounded = check_dataflow(&edges[..], 1, |data| data.clone().join(data), |data| data.join(data));
+        assert!(!rounded.is_empty());
+    }
+}
@@ -0,0 +1,342 @@
+//! Linear query workloads answered from optimized measurement strategies.
+//!
+//! A `Linear` workload is a set of linear queries over a histogram domain `0 .. domain`, each a
+//! weighted sum of the counts of domain elements. Rather than measuring each query directly, the
+//! workload is answered from noisy measurements of a `Strategy`, a set of signed sums of domain
+//! elements, from which the least-squares estimate of the histogram is reconstructed (the "matrix
+//! mechanism"). Strategies are compared by the total expected squared error of the workload's answers.
+//!
+//! Reconstruction inverts a dense matrix with one row and column per domain element, and so is
+//! best suited to domains of at most a few thousand elements.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::ops::Range;
+
+use timely::dataflow::{Scope, ProbeHandle};
+
+use ::{Dataset, Measurement};
+use noise;
+
+/// A set of signed sums of domain elements whose values are measured.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Measures the count of each domain element.
+    Identity,
+    /// Measures the counts of a tree of ranges with the indicated branching factor, whose leaves
+    /// are the domain elements.
+    Hierarchical(usize),
+    /// Measures the unnormalized Haar wavelet coefficients of the domain: the total count, and for
+    /// each node of a binary tree over the domain the count of its left half less that of its right.
+    ///
+    /// This is the "Privelet" mechanism, whose noise on each coefficient is inversely proportional
+    /// to the coefficient's support, and whose range queries have error polylogarithmic in the domain.
+    Wavelet,
+}
+
+impl Strategy {
+    /// The sums measured by the strategy, for a domain of `domain` elements.
+    ///
+    /// Each sum is a list of domain elements, each with a sign of `1` or `-1`.
+    pub fn rows(&self, domain: usize) -> Vec<Vec<(usize, i64)>> {
+        let leaves: Vec<Range<usize>> = (0 .. domain).map(|x| x .. x + 1).collect();
+        let mut rows = Vec::new();
+        match *self {
+            Strategy::Identity => {
+                rows.extend(leaves.into_iter().map(|r| signed(r, 1)));
+            },
+            Strategy::Hierarchical(branching) => {
+                assert!(branching > 1);
+                let mut level = leaves;
+                rows.extend(level.iter().cloned().map(|r| signed(r, 1)));
+                while level.len() > 1 {
+                    level = level.chunks(branching).map(|c| c[0].start .. c[c.len()-1].end).collect();
+                    rows.extend(level.iter().cloned().map(|r| signed(r, 1)));
+                }
+            },
+            Strategy::Wavelet => {
+                let mut level = leaves;
+                while level.len() > 1 {
+                    for pair in level.chunks(2).filter(|c| c.len() == 2) {
+                        let mut row = signed(pair[0].clone(), 1);
+                        row.extend(signed(pair[1].clone(), -1));
+                        rows.push(row);
+                    }
+                    level = level.chunks(2).map(|c| c[0].start .. c[c.len()-1].end).collect();
+                }
+                if domain > 0 {
+                    rows.push(signed(0 .. domain, 1));
+                }
+            },
+        }
+        rows
+    }
+}
+
+// the elements of `range`, each with sign `sign`.
+fn signed(range: Range<usize>, sign: i64) -> Vec<(usize, i64)> {
+    range.map(|x| (x, sign)).collect()
+}
+
+/// A workload of linear queries over the histogram domain `0 .. domain`.
+pub struct Linear {
+    domain: usize,
+    queries: Vec<Vec<(usize, f64)>>,
+}
+
+impl Linear {
+    /// Creates an empty workload over `domain` elements.
+    pub fn new(domain: usize) -> Self {
+        Linear { domain: domain, queries: Vec::new() }
+    }
+    /// The number of domain elements.
+    pub fn domain(&self) -> usize { self.domain }
+    /// The number of queries.
+    pub fn len(&self) -> usize { self.queries.len() }
+    /// Indicates whether the workload has no queries.
+    pub fn is_empty(&self) -> bool { self.queries.is_empty() }
+
+    /// Adds the query summing the counts of each element scaled by its coefficient, returning its index.
+    pub fn query(&mut self, coefficients: Vec<(usize, f64)>) -> usize {
+        assert!(coefficients.iter().all(|x| x.0 < self.domain));
+        self.queries.push(coefficients);
+        self.queries.len() - 1
+    }
+    /// Adds the query counting the elements in `range`, returning its index.
+    pub fn range(&mut self, range: Range<usize>) -> usize {
+        self.query(range.map(|x| (x, 1.0)).collect())
+    }
+
+    /// The expected total squared error of the workload's answers using `strategy`.
+    ///
+    /// The error is in units of squared weight, for a single measurement of the strategy.
+    pub fn expected_error(&self, strategy: Strategy) -> f64 {
+        match Reconstruction::new(self.domain, strategy) {
+            Some(reconstruction) => self.queries.iter().map(|q| reconstruction.variance(q)).sum(),
+            None => ::std::f64::INFINITY,
+        }
+    }
+
+    /// Selects the strategy from `candidates` with least expected error.
+    pub fn optimize(&self, candidates: &[Strategy]) -> Strategy {
+        assert!(!candidates.is_empty());
+        let mut best = (candidates[0], self.expected_error(candidates[0]));
+        for &candidate in candidates[1..].iter() {
+            let error = self.expected_error(candidate);
+            if error < best.1 { best = (candidate, error); }
+        }
+        best.0
+    }
+
+    /// Selects between the identity and wavelet strategies, and hierarchical strategies of several
+    /// branching factors.
+    pub fn strategy(&self) -> Strategy {
+        let mut candidates = vec![Strategy::Identity, Strategy::Wavelet];
+        for &branching in [2, 4, 8, 16].iter() {
+            if branching < self.domain {
+                candidates.push(Strategy::Hierarchical(branching));
+            }
+        }
+        self.optimize(&candidates[..])
+    }
+
+    /// Measures `dataset`, whose records are domain elements, using the strategy from `strategy()`.
+    ///
+    /// Each record's weight is divided equally among the sums that contain it, negated for those
+    /// in which it has a negative sign, so that the measurement costs the same as measuring
+    /// `dataset` directly. Records outside the domain are discarded.
+    pub fn measure<G: Scope>(self, dataset: Dataset<G, usize>, handle: &mut ProbeHandle<G::Timestamp>, total: &Rc<RefCell<i64>>) -> LinearMeasurement {
+        let strategy = self.strategy();
+        let reconstruction = Reconstruction::new(self.domain, strategy).expect("strategy does not determine the histogram");
+        let membership = reconstruction.membership.clone();
+        let measurement =
+        dataset
+            .flat_map_weighted(move |x| membership.get(x).cloned().unwrap_or_default())
+            .measure(handle, total);
+
+        LinearMeasurement {
+            strategy: strategy,
+            queries: self.queries,
+            reconstruction: reconstruction,
+            measurement: measurement,
+        }
+    }
+}
+
+/// A noisy measurement of a strategy, from which the queries of a workload are answered.
+pub struct LinearMeasurement {
+    strategy: Strategy,
+    queries: Vec<Vec<(usize, f64)>>,
+    reconstruction: Reconstruction,
+    measurement: Measurement<usize>,
+}
+
+impl LinearMeasurement {
+    /// The strategy that was measured.
+    pub fn strategy(&self) -> Strategy { self.strategy }
+    /// The least-squares estimates of the weight of each domain element.
+    pub fn estimates(&mut self) -> Vec<f64> {
+        let measurement = &mut self.measurement;
+        let counts: Vec<f64> = (0 .. self.reconstruction.rows).map(|q| measurement.observe(q) as f64).collect();
+        self.reconstruction.estimate(&counts[..])
+    }
+    /// The estimated answer to each query of the workload, in order.
+    pub fn answers(&mut self) -> Vec<f64> {
+        let estimates = self.estimates();
+        self.queries
+            .iter()
+            .map(|q| q.iter().map(|&(x, c)| c * estimates[x]).sum())
+            .collect()
+    }
+    /// The variance of the answer to the `index`-th query.
+    pub fn variance(&self, index: usize) -> f64 {
+        self.reconstruction.variance(&self.queries[index][..])
+    }
+}
+
+/// The least-squares reconstruction of a histogram from the measured sums of a strategy.
+struct Reconstruction {
+    // the number of sums of the strategy.
+    rows: usize,
+    // for each domain element, the indices of the sums that contain it, and its signs.
+    membership: Vec<Vec<(usize, i64)>>,
+    // the inverse of the Gram matrix of the strategy.
+    inverse: Vec<Vec<f64>>,
+}
+
+impl Reconstruction {
+
+    // returns `None` if the strategy does not determine the histogram.
+    fn new(domain: usize, strategy: Strategy) -> Option<Self> {
+
+        let rows = strategy.rows(domain);
+        let mut membership = vec![Vec::new(); domain];
+        for (index, row) in rows.iter().enumerate() {
+            for &(x, sign) in row.iter() {
+                membership[x].push((index, sign));
+            }
+        }
+
+        // each element contributes +/- 1 / k to each of the k sums containing it.
+        let mut gram = vec![vec![0.0; domain]; domain];
+        for row in rows.iter() {
+            for &(x1, s1) in row.iter() {
+                for &(x2, s2) in row.iter() {
+                    gram[x1][x2] += (s1 * s2) as f64 / (membership[x1].len() * membership[x2].len()) as f64;
+                }
+            }
+        }
+
+        invert(gram).map(|inverse| Reconstruction {
+            rows: rows.len(),
+            membership: membership,
+            inverse: inverse,
+        })
+    }
+
+    // the variance of the answer to `query`, from Laplace noise with scale `noise::SCALE`.
+    fn variance(&self, query: &[(usize, f64)]) -> f64 {
+        let mut sum = 0.0;
+        for &(x1, c1) in query.iter() {
+            for &(x2, c2) in query.iter() {
+                sum += c1 * c2 * self.inverse[x1][x2];
+            }
+        }
+        2.0 * (noise::SCALE as f64) * (noise::SCALE as f64) * sum
+    }
+
+    // the least-squares estimate of the histogram from the measured value of each sum.
+    fn estimate(&self, counts: &[f64]) -> Vec<f64> {
+        let projected: Vec<f64> =
+        self.membership
+            .iter()
+            .map(|m| m.iter().map(|&(q, sign)| sign as f64 * counts[q]).sum::<f64>() / m.len() as f64)
+            .collect();
+
+        self.inverse
+            .iter()
+            .map(|row| row.iter().zip(projected.iter()).map(|(a, b)| a * b).sum())
+            .collect()
+    }
+}
+
+// inverts a square matrix by Gauss-Jordan elimination, returning `None` if it is singular.
+fn invert(mut matrix: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+
+    let size = matrix.len();
+    let mut inverse: Vec<Vec<f64>> = (0 .. size).map(|i| (0 .. size).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+
+    for column in 0 .. size {
+
+        // select the row with the largest entry in `column` as the pivot.
+        let pivot = (column .. size).max_by(|&a, &b| matrix[a][column].abs().partial_cmp(&matrix[b][column].abs()).unwrap()).unwrap();
+        if matrix[pivot][column].abs() < 1e-12 { return None; }
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+
+        let scale = matrix[column][column];
+        for j in 0 .. size {
+            matrix[column][j] /= scale;
+            inverse[column][j] /= scale;
+        }
+
+        for row in 0 .. size {
+            if row != column && matrix[row][column] != 0.0 {
+                let factor = matrix[row][column];
+                for j in 0 .. size {
+                    matrix[row][j] -= factor * matrix[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+    }
+
+    Some(inverse)
+}
+
+mod tests {
+    #[test]
+    fn test_rows() {
+        use super::Strategy;
+        assert_eq!(Strategy::Identity.rows(2), vec![vec![(0, 1)], vec![(1, 1)]]);
+        assert_eq!(Strategy::Hierarchical(2).rows(3).len(), 6);
+        assert_eq!(Strategy::Wavelet.rows(3), vec![vec![(0, 1), (1, -1)], vec![(0, 1), (1, 1), (2, -1)], vec![(0, 1), (1, 1), (2, 1)]]);
+        assert_eq!(Strategy::Wavelet.rows(16).len(), 16);
+    }
+
+    #[test]
+    fn test_reconstruction() {
+        use super::{Reconstruction, Strategy};
+        let histogram = vec![3.0, 0.0, 5.0, 1.0, 2.0];
+        for &strategy in [Strategy::Hierarchical(2), Strategy::Wavelet].iter() {
+            let reconstruction = Reconstruction::new(5, strategy).unwrap();
+            let counts: Vec<f64> =
+            strategy
+                .rows(5)
+                .into_iter()
+                .map(|r| r.into_iter().map(|(x, s)| s as f64 * histogram[x] / reconstruction.membership[x].len() as f64).sum())
+                .collect();
+            let estimate = reconstruction.estimate(&counts[..]);
+            for (x, y) in estimate.iter().zip(histogram.iter()) {
+                assert!((x - y).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimize() {
+        use super::{Linear, Strategy};
+        let mut points = Linear::new(32);
+        for index in 0 .. 32 {
+            points.range(index .. index + 1);
+        }
+        assert_eq!(points.strategy(), Strategy::Identity);
+
+        let mut halves = Linear::new(32);
+        halves.range(0 .. 16);
+        halves.range(16 .. 32);
+        halves.range(0 .. 32);
+        assert!(halves.strategy() != Strategy::Identity);
+        assert!(halves.expected_error(halves.strategy()) < halves.expected_error(Strategy::Identity));
+    }
+}
@@ -0,0 +1,214 @@
+//! Declarative construction of measurement workloads.
+//!
+//! A `Workload` collects named analyses, each a function from input datasets to a dataset to be
+//! measured, along with the privacy budget each is meant to consume. Deploying the workload to a
+//! worker constructs one dataflow for each analysis, all sharing a probe and a total error.
+//! Alternatively, the workload may be deployed with a total budget, which is divided among the
+//! analyses according to their stabilities and declared importance.
+//!
+//! The `linear` module answers workloads of linear queries over a histogram domain from a single
+//! optimized measurement.
+
+use std::any::Any;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+use timely::{Allocate, ExchangeData};
+use timely::progress::Timestamp;
+use timely::progress::nested::product::Product;
+use timely::progress::timestamp::RootTimestamp;
+use timely::dataflow::ProbeHandle;
+use timely::dataflow::scopes::{Child, Root};
+
+use ::{Dataset, Measurement};
+use {budget, plan};
+
+pub mod linear;
+
+pub use self::linear::{Linear, LinearMeasurement, Strategy};
+
+/// Constructs a measured dataflow, returning its type-erased `Measurement` and its stability.
+type Builder<A, T, I> = Box<dyn for<'a> FnMut(&mut Child<'a, Root<A>, T>, &mut I, &mut ProbeHandle<Product<RootTimestamp, T>>, &Rc<RefCell<i64>>)->(Box<dyn Any>, f64)>;
+
+/// Sets the noise scale of a type-erased `Measurement`.
+type Rescale = Box<dyn Fn(&mut Box<dyn Any>, f64)>;
+
+/// A collection of named analyses over inputs `I`, to be deployed together.
+///
+/// The inputs `I` are supplied at deployment, and are typically a struct or tuple of
+/// `DatasetHandle`s that each analysis may `enter` into its scope.
+pub struct Workload<A: Allocate, T: Timestamp, I> {
+    analyses: Vec<(String, f64, f64, Builder<A, T, I>, Rescale)>,
+}
+
+impl<A: Allocate, T: Timestamp, I: 'static> Workload<A, T, I> {
+
+    /// Creates a new empty workload.
+    pub fn new() -> Self {
+        Workload { analyses: Vec::new() }
+    }
+
+    /// Registers an analysis under `name`, with a declared privacy `budget`.
+    ///
+    /// The `analysis` function is called once at deployment, with the dataflow scope and the
+    /// inputs, and should return the dataset to measure. The analysis has importance one.
+    pub fn register<R, F>(&mut self, name: &str, budget: f64, mut analysis: F) -> &mut Self
+    where
+        R: ExchangeData+Ord+Hash,
+        F: for<'a> FnMut(&mut Child<'a, Root<A>, T>, &mut I)->Dataset<Child<'a, Root<A>, T>, R>+'static,
+    {
+        let builder = move |scope: &mut Child<Root<A>, T>, inputs: &mut I, probe: &mut ProbeHandle<Product<RootTimestamp, T>>, total: &Rc<RefCell<i64>>| {
+            let dataset = analysis(scope, inputs);
+            let stability = dataset.stability();
+            let measurement: Box<dyn Any> = Box::new(dataset.measure(probe, total));
+            (measurement, stability)
+        };
+        let rescale = |measurement: &mut Box<dyn Any>, scale: f64| {
+            measurement.downcast_mut::<Measurement<R>>().unwrap().set_scale(scale);
+        };
+        self.analyses.push((name.to_owned(), budget, 1.0, Box::new(builder), Box::new(rescale)));
+        self
+    }
+
+    /// Sets the importance of the analysis registered under `name`.
+    ///
+    /// Importance only affects `deploy_allocated`, which minimizes the sum of the expected errors
+    /// of the analyses, each multiplied by its importance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no analysis is registered under `name`, or if `importance` is not positive.
+    pub fn importance(&mut self, name: &str, importance: f64) -> &mut Self {
+        assert!(importance > 0.0);
+        match self.analyses.iter_mut().find(|x| x.0 == name) {
+            Some(analysis) => analysis.2 = importance,
+            None => panic!("no analysis registered as {:?}", name),
+        }
+        self
+    }
+
+    /// The sum of the declared budgets of all registered analyses.
+    pub fn total_budget(&self) -> f64 {
+        self.analyses.iter().map(|x| x.1).sum()
+    }
+
+    /// Explains the plan of each registered analysis, with the stability of each operator.
+    ///
+    /// Plans are only recorded as datasets are constructed, and so this constructs a dataflow for
+    /// each analysis in `worker`, whose measurements are discarded; `inputs` should be handles that
+    /// are otherwise unused, so that no records reach these dataflows. Each analysis is listed with
+    /// its declared budget, followed by its plan nodes as written by `plan::explain`.
+    pub fn explain(&mut self, worker: &mut Root<A>, inputs: &mut I) -> String {
+        let mut probe = ProbeHandle::new();
+        let total = Rc::new(RefCell::new(0i64));
+        let mut text = Vec::new();
+        for &mut (ref name, budget, _, ref mut builder, _) in self.analyses.iter_mut() {
+            let start = plan::next();
+            worker.dataflow(|scope| { builder(scope, inputs, &mut probe, &total); });
+            text.extend(format!("{}\tbudget {}\n", name, budget).into_bytes());
+            plan::explain(&mut text, start .. plan::next()).expect("writing to memory failed");
+        }
+        String::from_utf8(text).expect("plan is not utf8")
+    }
+
+    /// Constructs a dataflow for each registered analysis in `worker`.
+    pub fn deploy(self, worker: &mut Root<A>, inputs: &mut I) -> Deployment<T> {
+        self.build(worker, inputs).0
+    }
+
+    /// Constructs a dataflow for each registered analysis, dividing `epsilon` among them.
+    ///
+    /// The division is made by `budget::allocate`, from the stability of each measured dataset
+    /// and the importance of each analysis, and the declared budgets are replaced by the shares.
+    /// Each measurement's noise is scaled by `budget::scale` so that it costs its share for input
+    /// records of weight at most `weight`. No records should be sent to the inputs before this
+    /// method returns.
+    pub fn deploy_allocated(self, worker: &mut Root<A>, inputs: &mut I, epsilon: f64, weight: i64) -> Deployment<T> {
+
+        let (mut deployment, built) = self.build(worker, inputs);
+
+        let demands = built.iter().map(|x| (x.0, x.1)).collect::<Vec<_>>();
+        let shares = budget::allocate(epsilon, &demands[..]);
+
+        for (index, ((stability, _importance, rescale), share)) in built.into_iter().zip(shares.into_iter()).enumerate() {
+            let name = deployment.budgets[index].0.clone();
+            rescale(deployment.measurements.get_mut(&name).unwrap(), budget::scale(stability, weight, share));
+            deployment.budgets[index].1 = share;
+        }
+
+        deployment
+    }
+
+    // constructs the dataflows, returning the stability, importance, and rescaling of each.
+    fn build(self, worker: &mut Root<A>, inputs: &mut I) -> (Deployment<T>, Vec<(f64, f64, Rescale)>) {
+
+        let mut probe = ProbeHandle::new();
+        let total = Rc::new(RefCell::new(0i64));
+        let mut measurements = HashMap::new();
+        let mut budgets = Vec::new();
+        let mut plans = Vec::new();
+        let mut built = Vec::new();
+
+        for (name, budget, importance, mut builder, rescale) in self.analyses.into_iter() {
+            let start = plan::next();
+            let (measurement, stability) = worker.dataflow(|scope| builder(scope, inputs, &mut probe, &total));
+            plans.push(start .. plan::next());
+            measurements.insert(name.clone(), measurement);
+            budgets.push((name, budget));
+            built.push((stability, importance, rescale));
+        }
+
+        let deployment = Deployment {
+            probe: probe,
+            total: total,
+            measurements: measurements,
+            budgets: budgets,
+            plans: plans,
+        };
+
+        (deployment, built)
+    }
+}
+
+/// The deployed dataflows of a workload.
+pub struct Deployment<T: Timestamp> {
+    /// Indicates when all measurements reflect inputs up to a timestamp.
+    pub probe: ProbeHandle<Product<RootTimestamp, T>>,
+    /// The total error of synthetic data across all measurements.
+    pub total: Rc<RefCell<i64>>,
+    measurements: HashMap<String, Box<dyn Any>>,
+    budgets: Vec<(String, f64)>,
+    plans: Vec<Range<usize>>,
+}
+
+impl<T: Timestamp> Deployment<T> {
+    /// Returns the measurement registered under `name`, if it exists and measures `D`.
+    pub fn measurement<D: Hash+Eq+'static>(&mut self, name: &str) -> Option<&mut Measurement<D>> {
+        self.measurements
+            .get_mut(name)
+            .and_then(|m| m.downcast_mut::<Measurement<D>>())
+    }
+    /// The names and budgets of the deployed analyses, in registration order.
+    ///
+    /// The budgets are those declared, or the shares allocated by `deploy_allocated`.
+    pub fn budgets(&self) -> &[(String, f64)] {
+        &self.budgets[..]
+    }
+
+    /// Explains the plan of each deployed analysis, with the stability of each operator.
+    ///
+    /// Each analysis is listed with its budget, followed by its plan nodes as written by
+    /// `plan::explain`. Enabling `instrument` before deployment also reports the number of synthetic
+    /// records each operator has produced so far.
+    pub fn explain(&self) -> String {
+        let mut text = Vec::new();
+        for (&(ref name, budget), nodes) in self.budgets.iter().zip(self.plans.iter()) {
+            text.extend(format!("{}\tbudget {}\n", name, budget).into_bytes());
+            plan::explain(&mut text, nodes.clone()).expect("writing to memory failed");
+        }
+        String::from_utf8(text).expect("plan is not utf8")
+    }
+}
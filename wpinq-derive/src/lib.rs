@@ -0,0 +1,143 @@
+//! Derives parsers for records stored as delimited lines of text.
+//!
+//! `#[derive(WpinqRecord)]` implements `wpinq::record::Record` and `From<&str>` for a struct with
+//! named fields, reading one field from each delimited column in the order the fields are declared.
+//! The delimiter is `|` unless set with `#[wpinq(delimiter = ",")]` on the struct. Fields of type
+//! `[u8; N]` are filled from the bytes of their column, truncated or padded with zeros, and other
+//! fields are parsed by `wpinq::record::Field`. Individual fields may instead be annotated:
+//!
+//! * `#[wpinq(scale = 100)]` parses the column as `f64` and casts its product with the scale to the
+//!   field's type, as for prices stored as integer cents.
+//! * `#[wpinq(with = "path")]` calls the function `path` with the text of the column.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use syn::{Data, DeriveInput, Error, Fields, Lit, Meta, NestedMeta, Type};
+use syn::spanned::Spanned;
+
+#[proc_macro_derive(WpinqRecord, attributes(wpinq))]
+pub fn derive_record(input: TokenStream) -> TokenStream {
+    let input = match syn::parse::<DeriveInput>(input) {
+        Ok(input) => input,
+        Err(error) => return error.to_compile_error().into(),
+    };
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> Result<Tokens, Error> {
+
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(input.generics.span(), "WpinqRecord does not support generic records"));
+    }
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(Error::new(name.span(), "WpinqRecord requires named fields")),
+        },
+        _ => return Err(Error::new(name.span(), "WpinqRecord only supports structs")),
+    };
+
+    let mut delimiter = "|".to_owned();
+    for (key, value) in options(&input.attrs)? {
+        match (&key[..], value) {
+            ("delimiter", Lit::Str(text)) => delimiter = text.value(),
+            (_, value) => return Err(Error::new(value.span(), format!("unknown record option `{}`", key))),
+        }
+    }
+
+    let mut parsed = Vec::new();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let label = ident.to_string();
+        let ty = &field.ty;
+        let mut parse = default_parse(ty);
+        for (key, value) in options(&field.attrs)? {
+            parse = match (&key[..], value) {
+                ("scale", Lit::Int(scale)) => {
+                    let scale = scale.base10_parse::<i64>()? as f64;
+                    quote! { text.parse::<f64>().ok().map(|x| (x * #scale) as #ty) }
+                },
+                ("scale", Lit::Float(scale)) => {
+                    let scale = scale.base10_parse::<f64>()?;
+                    quote! { text.parse::<f64>().ok().map(|x| (x * #scale) as #ty) }
+                },
+                ("with", Lit::Str(path)) => {
+                    let path = path.parse::<syn::ExprPath>()?;
+                    quote! { Some(#path(text)) }
+                },
+                (_, value) => return Err(Error::new(value.span(), format!("unknown field option `{}`", key))),
+            };
+        }
+        parsed.push(quote! {
+            #ident: {
+                let text = ::wpinq::record::next_field(&mut fields, #label)?;
+                match #parse {
+                    Some(value) => value,
+                    None => return Err(::wpinq::record::ParseError::new(#label, text)),
+                }
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl ::wpinq::record::Record for #name {
+            fn parse(text: &str) -> Result<Self, ::wpinq::record::ParseError> {
+                let mut fields = text.split(#delimiter);
+                Ok(#name { #(#parsed,)* })
+            }
+        }
+        impl<'a> From<&'a str> for #name {
+            fn from(text: &'a str) -> Self {
+                match <#name as ::wpinq::record::Record>::parse(text) {
+                    Ok(record) => record,
+                    Err(error) => panic!("{}", error),
+                }
+            }
+        }
+    })
+}
+
+// the parse of a field of type `ty` without options, as an expression of type `Option<ty>`.
+fn default_parse(ty: &Type) -> Tokens {
+    if let Type::Array(ref array) = *ty {
+        if let Type::Path(ref path) = *array.elem {
+            if path.path.is_ident("u8") {
+                let len = &array.len;
+                return quote! { { let mut buffer = [0u8; #len]; ::wpinq::record::fill(text, &mut buffer); Some(buffer) } };
+            }
+        }
+    }
+    quote! { <#ty as ::wpinq::record::Field>::parse_field(text) }
+}
+
+// the `key = value` pairs of `#[wpinq(..)]` attributes.
+fn options(attrs: &[syn::Attribute]) -> Result<Vec<(String, Lit)>, Error> {
+    let mut result = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("wpinq")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested.into_iter() {
+                    match nested {
+                        NestedMeta::Meta(Meta::NameValue(pair)) => {
+                            let key = pair.path.get_ident().map(|i| i.to_string()).unwrap_or_default();
+                            result.push((key, pair.lit));
+                        },
+                        other => return Err(Error::new(other.span(), "expected `key = value`")),
+                    }
+                }
+            },
+            other => return Err(Error::new(other.span(), "expected `#[wpinq(key = value)]`")),
+        }
+    }
+    Ok(result)
+}